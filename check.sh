@@ -11,4 +11,8 @@ cargo test --workspace --all-targets --all-features
 cargo clippy --workspace --all-targets --all-features --  -D warnings -W clippy::all
 cargo fmt --all -- --check
 
+# The C header of eterm_ffi must match its API:
+cbindgen --config eterm_ffi/cbindgen.toml --crate eterm_ffi --output eterm_ffi/include/eterm.h
+git diff --exit-code eterm_ffi/include/eterm.h
+
 cargo doc -p eterm --lib --no-deps --all-features
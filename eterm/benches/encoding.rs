@@ -0,0 +1,112 @@
+//! Benchmarks for the hot path of the server and client:
+//! converting shapes to/from [`eterm::net_shape::NetShape`], serializing and compressing.
+//!
+//! Run with `cargo bench -p eterm`.
+//! See `examples/print.rs` for the resulting sizes.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use egui::{epaint, Rect};
+
+fn example_shapes() -> Vec<epaint::ClippedShape> {
+    let mut ctx = egui::CtxRef::default();
+    let raw_input = egui::RawInput::default();
+    let mut demo_windows = egui_demo_lib::DemoWindows::default();
+    let (_output, shapes) = ctx.run(raw_input, |ctx| demo_windows.ui(ctx));
+    shapes
+}
+
+fn bincode<S: ?Sized + serde::Serialize>(data: &S) -> Vec<u8> {
+    use bincode::Options as _;
+    bincode::options().serialize(data).unwrap()
+}
+
+fn zstd(data: &[u8], level: i32) -> Vec<u8> {
+    zstd::encode_all(std::io::Cursor::new(data), level).unwrap()
+}
+
+fn net_shapes(c: &mut Criterion) {
+    let shapes = example_shapes();
+    let net_shapes = eterm::net_shape::to_clipped_net_shapes(shapes.clone(), Rect::EVERYTHING);
+    let fonts = epaint::text::Fonts::new(1.0, egui::FontDefinitions::default());
+
+    c.bench_function("to_clipped_net_shapes", |b| {
+        b.iter_batched(
+            || shapes.clone(),
+            |shapes| eterm::net_shape::to_clipped_net_shapes(shapes, Rect::EVERYTHING),
+            BatchSize::SmallInput,
+        );
+    });
+
+    c.bench_function("from_clipped_net_shapes", |b| {
+        b.iter(|| eterm::net_shape::from_clipped_net_shapes(&fonts, &net_shapes));
+    });
+}
+
+fn serialization(c: &mut Criterion) {
+    let net_shapes = eterm::net_shape::to_clipped_net_shapes(example_shapes(), Rect::EVERYTHING);
+    let bincoded = bincode(&net_shapes);
+
+    c.bench_function("bincode_serialize", |b| {
+        b.iter(|| bincode(black_box(&net_shapes)));
+    });
+
+    c.bench_function("bincode_deserialize", |b| {
+        use bincode::Options as _;
+        b.iter(|| {
+            let net_shapes: Vec<eterm::net_shape::ClippedNetShape> = bincode::options()
+                .deserialize(black_box(&bincoded))
+                .unwrap();
+            net_shapes
+        });
+    });
+}
+
+fn compression(c: &mut Criterion) {
+    let bincoded = bincode(&eterm::net_shape::to_clipped_net_shapes(
+        example_shapes(),
+        Rect::EVERYTHING,
+    ));
+
+    let mut group = c.benchmark_group("zstd");
+    for level in [0, 1, 5, 10] {
+        group.bench_function(format!("compress_level_{}", level), |b| {
+            b.iter(|| zstd(black_box(&bincoded), level));
+        });
+
+        let compressed = zstd(&bincoded, level);
+        group.bench_function(format!("decompress_level_{}", level), |b| {
+            b.iter(|| zstd::decode_all(black_box(&compressed[..])).unwrap());
+        });
+    }
+    group.finish();
+}
+
+fn full_frame(c: &mut Criterion) {
+    let message = eterm::ServerToClientMessage::Frame {
+        frame_index: 0,
+        output: Default::default(),
+        clipped_net_shapes: eterm::net_shape::to_clipped_net_shapes(
+            example_shapes(),
+            Rect::EVERYTHING,
+        ),
+        client_time: None,
+        server_time: 0.0,
+        server_frame_time: None,
+    };
+    // The first byte says the packet is zstd-compressed (see `eterm::codec`):
+    let packet = [&[1_u8][..], &zstd(&bincode(&message), 5)].concat();
+
+    c.bench_function("decode_frame_with_limits", |b| {
+        b.iter(|| {
+            let message: eterm::ServerToClientMessage = eterm::wire::decode_message(
+                black_box(&packet),
+                &eterm::DecodeLimits::SERVER_TO_CLIENT,
+            )
+            .unwrap();
+            message
+        });
+    });
+}
+
+criterion_group!(benches, net_shapes, serialization, compression, full_frame);
+criterion_main!(benches);
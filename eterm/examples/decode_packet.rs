@@ -0,0 +1,34 @@
+//! Pretty-print packets dumped with `--dump-packets` (see `eterm::packet_dump`).
+//!
+//! ``` sh
+//! cargo run -p eterm --example decode_packet -- eterm_dump/000042_12.345s_s2c.bin
+//! ```
+
+use eterm::packet_dump::{Direction, DumpedPacket};
+
+fn main() -> anyhow::Result<()> {
+    let paths: Vec<std::path::PathBuf> = std::env::args_os().skip(1).map(Into::into).collect();
+    if paths.is_empty() {
+        anyhow::bail!("Usage: decode_packet <dumped packet>…");
+    }
+
+    for path in paths {
+        let dumped = DumpedPacket::read(&path)?;
+        let direction = match dumped.direction {
+            Direction::ClientToServer => "client -> server",
+            Direction::ServerToClient => "server -> client",
+        };
+        println!(
+            "# Packet {} at {:.3} s, {}, {} bytes",
+            dumped.index,
+            dumped.time,
+            direction,
+            dumped.packet.len()
+        );
+        match dumped.decode() {
+            Ok(message) => println!("{}", serde_yaml::to_string(&message)?),
+            Err(err) => println!("# Failed to decode: {:#}\n", err),
+        }
+    }
+    Ok(())
+}
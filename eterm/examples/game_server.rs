@@ -7,6 +7,12 @@ fn main() {
 
     let mut eterm_server = eterm::Server::new("0.0.0.0:8505").unwrap();
     eterm_server.set_minimum_update_interval(1.0);
+    eterm_server.set_show_remote_cursors(true);
+    eterm_server.set_app_info(eterm::AppInfo {
+        name: "eterm game server".to_owned(),
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        icon: None,
+    });
 
     let mut demo_windows = egui_demo_lib::DemoWindows::default();
 
@@ -17,6 +23,11 @@ fn main() {
                     ui.horizontal(|ui| {
                         ui.label("Server time:");
                         ui_clock(ui);
+                        ui.separator();
+                        if ui.button("Save clock log…").clicked() {
+                            let log = format!("Server time: {:.3} s\n", seconds_since_midnight());
+                            eterm::download::send_file(egui_ctx, "clock.log", log.into_bytes());
+                        }
                     });
                 });
                 demo_windows.ui(egui_ctx);
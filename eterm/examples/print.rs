@@ -111,7 +111,7 @@ fn main() {
     println!();
 
     let (_, shapes) = example_shapes();
-    let net_shapes = eterm::net_shape::to_clipped_net_shapes(shapes);
+    let net_shapes = eterm::net_shape::to_clipped_net_shapes(shapes, egui::Rect::EVERYTHING);
     println!("Shapes:");
     print_encodings(&net_shapes);
     println!();
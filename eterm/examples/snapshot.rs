@@ -0,0 +1,22 @@
+//! Render the egui demo without any network, e.g. for golden-image tests:
+//!
+//! ``` sh
+//! cargo run -p eterm --example snapshot --features snapshot -- demo.png [input.ron]
+//! ```
+
+fn main() -> anyhow::Result<()> {
+    // Log to stdout (if you run with `RUST_LOG=debug`).
+    tracing_subscriber::fmt::init();
+
+    let mut args = std::env::args().skip(1);
+    let png = args.next().unwrap_or_else(|| "demo.png".to_owned());
+    let options = eterm::snapshot::Options {
+        input: args.next().map(Into::into),
+        png: Some(png.into()),
+        ..Default::default()
+    };
+
+    let mut demo_windows = egui_demo_lib::DemoWindows::default();
+    eterm::snapshot::run(&options, |egui_ctx| demo_windows.ui(egui_ctx))?;
+    Ok(())
+}
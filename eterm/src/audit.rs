@@ -0,0 +1,149 @@
+//! A record of who connected and what they did, for deployments where remote control must be traceable.
+//!
+//! Set a sink with [`crate::Server::set_audit_sink`].
+
+use std::net::SocketAddr;
+
+use crate::ClientId;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuditEvent {
+    /// A new connection.
+    Connected,
+    /// The client presented a session resumption token. `accepted` is `false` if it was forged or expired.
+    Resumption { accepted: bool },
+    /// The connection was closed, by either side.
+    Disconnected,
+    /// A mouse button was pressed (or the screen touched) here, in points.
+    Click {
+        button: egui::PointerButton,
+        pos: egui::Pos2,
+    },
+    /// A key was pressed.
+    Key {
+        key: egui::Key,
+        modifiers: egui::Modifiers,
+    },
+    /// Text typed, collected from one input message.
+    Text(String),
+    /// The ui panicked while being shown to this client. See [`crate::Server::set_catch_panics`].
+    UiPanicked { message: String },
+}
+
+/// One thing that happened. See [`crate::Server::set_audit_sink`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditRecord {
+    pub time: std::time::SystemTime,
+    pub client_id: ClientId,
+    /// Where the client connected from.
+    pub addr: SocketAddr,
+    pub event: AuditEvent,
+}
+
+impl std::fmt::Display for AuditRecord {
+    /// E.g. `client 3 (10.0.0.7:51234): clicked Primary at [120 48]`, without the time.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "client {} ({}): ", self.client_id.as_u64(), self.addr)?;
+        match &self.event {
+            AuditEvent::Connected => write!(f, "connected"),
+            AuditEvent::Resumption { accepted } => {
+                if *accepted {
+                    write!(f, "resumed a session")
+                } else {
+                    write!(f, "presented an invalid resumption token")
+                }
+            }
+            AuditEvent::Disconnected => write!(f, "disconnected"),
+            AuditEvent::Click { button, pos } => {
+                write!(f, "clicked {:?} at [{:.0} {:.0}]", button, pos.x, pos.y)
+            }
+            AuditEvent::Key { key, modifiers } => {
+                write!(f, "pressed ")?;
+                if modifiers.ctrl {
+                    write!(f, "Ctrl+")?;
+                }
+                if modifiers.mac_cmd {
+                    write!(f, "Cmd+")?;
+                }
+                if modifiers.alt {
+                    write!(f, "Alt+")?;
+                }
+                if modifiers.shift {
+                    write!(f, "Shift+")?;
+                }
+                write!(f, "{:?}", key)
+            }
+            AuditEvent::Text(text) => write!(f, "typed {:?}", text),
+            AuditEvent::UiPanicked { message } => write!(f, "the ui panicked: {}", message),
+        }
+    }
+}
+
+/// The clicks, key presses and text of some input, leaving out everything else (e.g. pointer moves).
+pub(crate) fn summarize_input(raw_input: &egui::RawInput) -> Vec<AuditEvent> {
+    let mut summary = vec![];
+    let mut text = String::new();
+    for event in &raw_input.events {
+        match event {
+            egui::Event::PointerButton {
+                pos,
+                button,
+                pressed: true,
+                ..
+            } => summary.push(AuditEvent::Click {
+                button: *button,
+                pos: *pos,
+            }),
+            egui::Event::Key {
+                key,
+                pressed: true,
+                modifiers,
+            } => summary.push(AuditEvent::Key {
+                key: *key,
+                modifiers: *modifiers,
+            }),
+            egui::Event::Text(new_text) => text.push_str(new_text),
+            _ => {}
+        }
+    }
+    if !text.is_empty() {
+        summary.push(AuditEvent::Text(text));
+    }
+    summary
+}
+
+#[test]
+fn test_summarize_input() {
+    let pos = egui::pos2(10.0, 20.0);
+    let raw_input = egui::RawInput {
+        events: vec![
+            egui::Event::PointerMoved(pos),
+            egui::Event::PointerButton {
+                pos,
+                button: egui::PointerButton::Primary,
+                pressed: true,
+                modifiers: Default::default(),
+            },
+            egui::Event::PointerButton {
+                pos,
+                button: egui::PointerButton::Primary,
+                pressed: false,
+                modifiers: Default::default(),
+            },
+            egui::Event::Text("h".to_owned()),
+            egui::Event::Text("i".to_owned()),
+        ],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        summarize_input(&raw_input),
+        vec![
+            AuditEvent::Click {
+                button: egui::PointerButton::Primary,
+                pos
+            },
+            AuditEvent::Text("hi".to_owned()),
+        ]
+    );
+}
@@ -1,34 +1,307 @@
 use std::sync::{
     atomic::{AtomicBool, Ordering::SeqCst},
-    mpsc::{self},
-    Arc,
+    mpsc, Arc,
 };
 
 use egui::{text::Fonts, util::History, RawInput};
 use parking_lot::Mutex;
 
-use crate::{ClientToServerMessage, EguiFrame, ServerToClientMessage, TcpEndpoint};
+use crate::{
+    clock::{now, ClockSync},
+    download::ReceivedFile,
+    notification::Notification,
+    ClientPreferences, ClientToServerMessage, EguiFrame, GoodbyeReason, ServerToClientMessage,
+    TcpEndpoint,
+};
+
+/// A file we are in the process of receiving.
+struct IncomingFile {
+    file_name: String,
+    size: usize,
+    bytes: Vec<u8>,
+}
+
+impl IncomingFile {
+    fn finish(self) -> ReceivedFile {
+        tracing::info!("Received {:?} ({} bytes)", self.file_name, self.bytes.len());
+        ReceivedFile {
+            file_name: self.file_name,
+            bytes: self.bytes,
+        }
+    }
+}
+
+/// Messages from the network thread, waiting for [`Client::update`].
+///
+/// If `update` isn't called for a while, only the newest frame is kept,
+/// so this doesn't grow with every frame received.
+#[derive(Default)]
+struct IncomingQueue {
+    messages: std::collections::VecDeque<ServerToClientMessage>,
+}
+
+impl IncomingQueue {
+    fn push(&mut self, message: ServerToClientMessage) {
+        if let ServerToClientMessage::Frame { .. } = &message {
+            if let Some(older_frame) = self.take_latest_frame() {
+                self.messages.push_back(merge_frames(older_frame, message));
+                return;
+            }
+        }
+        self.messages.push_back(message);
+    }
+
+    /// Remove the latest queued frame, unless it is from an earlier connection.
+    fn take_latest_frame(&mut self) -> Option<ServerToClientMessage> {
+        for i in (0..self.messages.len()).rev() {
+            match &self.messages[i] {
+                ServerToClientMessage::Frame { .. } => return self.messages.remove(i),
+                ServerToClientMessage::Hello { .. } => return None,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn take_all(&mut self) -> std::collections::VecDeque<ServerToClientMessage> {
+        std::mem::take(&mut self.messages)
+    }
+}
+
+/// Lay out the shapes and run the frame hook.
+fn lay_out(
+    fonts: &Fonts,
+    clipped_net_shapes: &[crate::net_shape::ClippedNetShape],
+    frame_hook: Option<&mut FrameHook>,
+) -> Vec<egui::epaint::ClippedShape> {
+    let mut clipped_shapes = crate::net_shape::from_clipped_net_shapes(fonts, clipped_net_shapes);
+    if let Some(frame_hook) = frame_hook {
+        frame_hook(&mut clipped_shapes);
+    }
+    clipped_shapes
+}
+
+/// Lay out the shapes, run the frame hook, tessellate them and move them into the window.
+fn tessellate(
+    fonts: &Fonts,
+    clipped_net_shapes: &[crate::net_shape::ClippedNetShape],
+    frame_hook: Option<&mut FrameHook>,
+    screen_transform: crate::ScreenTransform,
+) -> Vec<egui::ClippedMesh> {
+    let clipped_shapes = lay_out(fonts, clipped_net_shapes, frame_hook);
+    let tesselator_options = egui::epaint::tessellator::TessellationOptions::from_pixels_per_point(
+        fonts.pixels_per_point(),
+    );
+    let tex_size = fonts.font_image().size();
+    let mut clipped_meshes =
+        egui::epaint::tessellator::tessellate_shapes(clipped_shapes, tesselator_options, tex_size);
+    screen_transform.transform_meshes(&mut clipped_meshes);
+    clipped_meshes
+}
+
+/// The newer frame, but with the one-off commands in the output of the older frame too.
+fn merge_frames(
+    older: ServerToClientMessage,
+    mut newer: ServerToClientMessage,
+) -> ServerToClientMessage {
+    if let (
+        ServerToClientMessage::Frame {
+            output: mut merged_output,
+            ..
+        },
+        ServerToClientMessage::Frame { output, .. },
+    ) = (older, &mut newer)
+    {
+        crate::output::merge(&mut merged_output, std::mem::take(output));
+        *output = merged_output;
+    }
+    newer
+}
+
+#[test]
+fn test_incoming_queue() {
+    let frame = |frame_index: u64, copied_text: &str| ServerToClientMessage::Frame {
+        frame_index,
+        output: egui::Output {
+            copied_text: copied_text.to_owned(),
+            ..Default::default()
+        },
+        clipped_net_shapes: vec![],
+        client_time: None,
+        server_time: 0.0,
+        server_frame_time: None,
+    };
+
+    let mut queue = IncomingQueue::default();
+    queue.push(frame(0, "copied"));
+    queue.push(ServerToClientMessage::Pong {
+        client_time: 0.0,
+        server_time: 0.0,
+    });
+    queue.push(frame(1, ""));
+    queue.push(frame(2, ""));
+
+    let messages = queue.take_all();
+    assert_eq!(messages.len(), 2, "only the newest frame is kept");
+    match &messages[1] {
+        ServerToClientMessage::Frame {
+            frame_index,
+            output,
+            ..
+        } => {
+            assert_eq!(*frame_index, 2);
+            assert_eq!(output.copied_text, "copied", "commands are kept");
+        }
+        _ => panic!("Expected a frame"),
+    }
+}
+
+/// Makes a new connection to the server. See [`Client::with_connector`].
+type Connector = dyn FnMut() -> std::io::Result<std::net::TcpStream> + Send;
+
+type WakeUpCallback = Arc<Mutex<Option<Box<dyn Fn() + Send>>>>;
+
+/// Time series of (time in seconds, value). See [`Client::stats_samples`].
+#[derive(Clone, Debug, Default)]
+pub struct StatsSamples {
+    /// Round-trip times, in seconds.
+    pub latency: Vec<(f64, f32)>,
+    /// Server-to-client delays of frames, in seconds.
+    pub downstream_delay: Vec<(f64, f32)>,
+    /// Sizes of all received packets, in bytes.
+    pub packet_size: Vec<(f64, f32)>,
+    /// Sizes of received frame packets, in bytes.
+    pub frame_size: Vec<(f64, f32)>,
+}
+
+impl StatsSamples {
+    /// One line per sample: `series,time,value`, after a header line.
+    ///
+    /// # Errors
+    /// On I/O errors.
+    pub fn write_csv(&self, writer: &mut impl std::io::Write) -> std::io::Result<()> {
+        writeln!(writer, "series,time,value")?;
+        for (name, series) in [
+            ("latency", &self.latency),
+            ("downstream_delay", &self.downstream_delay),
+            ("packet_size", &self.packet_size),
+            ("frame_size", &self.frame_size),
+        ] {
+            for (time, value) in series {
+                writeln!(writer, "{},{},{}", name, time, value)?;
+            }
+        }
+        writer.flush()
+    }
+}
+
+/// Changes the shapes of each frame before they are tessellated. See [`Client::set_frame_hook`].
+type FrameHook = dyn FnMut(&mut Vec<egui::epaint::ClippedShape>) + Send;
+
+/// How often we tell the server about our vsync, at most. See [`Client::on_vsync`].
+const DISPLAY_TIMING_INTERVAL: f64 = 1.0;
+
+/// Frames should arrive this long before our vsync, in seconds.
+const VSYNC_MARGIN: f64 = 0.002;
+
+/// Set in the versions of the font images from the server, so painters notice
+/// when we switch between our own font image and the server's.
+const SERVER_FONT_IMAGE_VERSION: u64 = 1 << 63;
+
+/// When the server has several addresses, wait this long for one before also trying the next.
+const CONNECTION_ATTEMPT_DELAY: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Give up on connecting to an address after this long.
+pub(crate) const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// When dropped, the [`Client`] waits at most this long for the goodbye to be sent.
+const GOODBYE_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// [`mio`] tokens used by the network thread.
+const SOCKET: mio::Token = mio::Token(0);
+const NETWORK_WAKER: mio::Token = mio::Token(1);
 
 pub struct Client {
     addr: String,
     connected: Arc<AtomicBool>,
+    /// Cleared when the client is dropped, or the network thread gives up.
     alive: Arc<AtomicBool>,
+    /// See [`Self::set_reconnect`].
+    reconnect: Arc<AtomicBool>,
+    /// Why we lost (or never got) the connection. Cleared when we connect.
+    disconnect_reason: Arc<Mutex<Option<DisconnectReason>>>,
+    /// Sent to the server when connecting. See [`Self::set_preferences`].
+    preferences: Arc<Mutex<ClientPreferences>>,
+    /// See [`Self::set_packet_dump_dir`].
+    packet_dumper: Arc<Mutex<Option<Arc<crate::packet_dump::PacketDumper>>>>,
+    /// Is the viewer window visible? See [`Self::set_visible`].
+    visible: Arc<AtomicBool>,
     outgoing_msg_tx: mpsc::Sender<ClientToServerMessage>,
-    incoming_msg_rx: mpsc::Receiver<ServerToClientMessage>,
+    /// Wakes up the network thread, e.g. to send what we put in `outgoing_msg_tx`.
+    network_waker: mio::Waker,
+    /// Disconnected when the network thread exits.
+    network_done: mpsc::Receiver<()>,
+    incoming: Arc<Mutex<IncomingQueue>>,
+    wake_up: WakeUpCallback,
 
     font_definitions: egui::FontDefinitions,
     fonts: Option<Fonts>,
+    /// Have we received [`ServerToClientMessage::Fonts`] since we connected?
+    fonts_from_server: bool,
+    /// Have we sent [`ClientToServerMessage::RequestFonts`] since we connected?
+    fonts_requested: bool,
+    /// Set when the server sends laid-out text. See [`crate::Server::set_laid_out_text`].
+    server_font_image: Option<Arc<egui::FontImage>>,
+    glyph_coverage: crate::font_fallback::GlyphCoverage,
     latest_frame: Option<EguiFrame>,
+    latest_clipped_net_shapes: Vec<crate::net_shape::ClippedNetShape>,
+    frame_hook: Option<Box<FrameHook>>,
+    screen_transform: crate::ScreenTransform,
+    /// The frame hook, screen transform or fonts have changed, so the latest frame should be painted again.
+    refilter: bool,
+    incoming_files: std::collections::HashMap<u64, IncomingFile>,
+    received_files: Vec<ReceivedFile>,
+    notifications: Vec<Notification>,
+    notice: Option<crate::notice::Notice>,
+    motd: Option<String>,
+    app_info: Option<crate::AppInfo>,
+    server_log: crate::remote_log::ReceivedLog,
+    telemetry: Option<crate::Telemetry>,
+    theme: Option<crate::Theme>,
 
     bandwidth_history: Arc<Mutex<History<f32>>>,
     frame_size_history: Arc<Mutex<History<f32>>>,
     latency_history: History<f32>,
+    downstream_delay_history: History<f32>,
+    server_frame_time_history: History<f32>,
     frame_history: History<()>,
+    /// Frame index over server time, to see how fast the server makes frames.
+    server_frame_history: History<f32>,
+    server_frame_index: Option<u64>,
+    clock_sync: Arc<Mutex<ClockSync>>,
+    /// When we last sent [`ClientToServerMessage::DisplayTiming`].
+    last_display_timing: Option<f64>,
+
+    /// Kept alive for as long as the client is.
+    #[cfg(feature = "ssh")]
+    _ssh_tunnel: Option<crate::ssh::SshTunnel>,
 }
 
 impl Drop for Client {
     fn drop(&mut self) {
+        self.outgoing_msg_tx
+            .send(ClientToServerMessage::Goodbye {
+                reason: GoodbyeReason::UserQuit,
+            })
+            .ok();
         self.alive.store(false, SeqCst);
+        self.network_waker.wake().ok();
+        if self.connected.load(SeqCst) {
+            // Give the network thread a chance to get the goodbye out,
+            // or the server won't know we're gone until a send fails,
+            // e.g. if the process exits right after this.
+            self.network_done.recv_timeout(GOODBYE_TIMEOUT).ok();
+        }
     }
 }
 
@@ -39,64 +312,240 @@ impl Client {
     /// eterm::Client::new("127.0.0.1:8580".to_owned());
     /// ```
     pub fn new(addr: String) -> Self {
+        let connector = {
+            let addr = addr.clone();
+            Box::new(move || connect(&addr))
+        };
+        Self::with_connector(addr, connector)
+    }
+
+    /// `addr` is only for showing. `connector` is called each time we (re)connect.
+    fn with_connector(addr: String, mut connector: Box<Connector>) -> Self {
         let alive = Arc::new(AtomicBool::new(true));
         let connected = Arc::new(AtomicBool::new(false));
+        let visible = Arc::new(AtomicBool::new(true));
+        let reconnect = Arc::new(AtomicBool::new(true));
+        let disconnect_reason = Arc::new(Mutex::new(None));
+        let preferences = Arc::new(Mutex::new(ClientPreferences {
+            locale: system_locale(),
+            ..Default::default()
+        }));
+        let packet_dumper = Arc::new(Mutex::new(None));
         let mut bandwidth_history = Arc::new(Mutex::new(History::new(0..200, 2.0)));
         let mut frame_size_history = Arc::new(Mutex::new(History::new(1..100, 0.5)));
 
         let (outgoing_msg_tx, mut outgoing_msg_rx) = mpsc::channel();
-        let (mut incoming_msg_tx, incoming_msg_rx) = mpsc::channel();
+        let (network_done_tx, network_done) = mpsc::channel::<()>();
+        let incoming = Arc::new(Mutex::new(IncomingQueue::default()));
+        let wake_up = WakeUpCallback::default();
+        let clock_sync = Arc::new(Mutex::new(ClockSync::default()));
+
+        let mut poll = mio::Poll::new().expect("Failed to create mio::Poll");
+        let network_waker =
+            mio::Waker::new(poll.registry(), NETWORK_WAKER).expect("Failed to create mio::Waker");
 
         let client = Self {
             addr: addr.clone(),
             connected: connected.clone(),
             alive: alive.clone(),
+            reconnect: reconnect.clone(),
+            disconnect_reason: disconnect_reason.clone(),
+            preferences: preferences.clone(),
+            packet_dumper: packet_dumper.clone(),
+            visible: visible.clone(),
             outgoing_msg_tx,
-            incoming_msg_rx,
+            network_waker,
+            network_done,
+            incoming: incoming.clone(),
+            wake_up: wake_up.clone(),
             font_definitions: Default::default(),
             fonts: None,
+            fonts_from_server: false,
+            fonts_requested: false,
+            server_font_image: None,
+            glyph_coverage: Default::default(),
             latest_frame: Default::default(),
+            latest_clipped_net_shapes: Default::default(),
+            frame_hook: None,
+            screen_transform: Default::default(),
+            refilter: false,
+            incoming_files: Default::default(),
+            received_files: Default::default(),
+            notifications: Default::default(),
+            notice: None,
+            motd: None,
+            app_info: None,
+            server_log: Default::default(),
+            telemetry: None,
+            theme: None,
             bandwidth_history: bandwidth_history.clone(),
             frame_size_history: frame_size_history.clone(),
             latency_history: History::new(1..100, 1.0),
+            downstream_delay_history: History::new(1..100, 1.0),
+            server_frame_time_history: History::new(1..100, 1.0),
             frame_history: History::new(2..100, 1.0),
+            server_frame_history: History::new(2..100, 1.0),
+            server_frame_index: None,
+            clock_sync: clock_sync.clone(),
+            last_display_timing: None,
+            #[cfg(feature = "ssh")]
+            _ssh_tunnel: None,
         };
 
         std::thread::spawn(move || {
+            let _network_done_tx = network_done_tx; // dropped when we return
+
+            // Lets us get our session back when we reconnect:
+            let mut resumption_token = None;
+
             tracing::info!("Connecting to {}…", addr);
             while alive.load(SeqCst) {
-                match std::net::TcpStream::connect(&addr) {
+                match connector() {
                     Ok(tcp_stream) => {
                         tracing::info!("Connected!");
                         connected.store(true, SeqCst);
-                        if let Err(err) = run(
+                        *disconnect_reason.lock() = None;
+                        call_wake_up(&wake_up);
+                        let result = run(
                             tcp_stream,
+                            &mut poll,
+                            &alive,
+                            &visible,
+                            &preferences,
+                            &packet_dumper,
+                            &mut resumption_token,
                             &mut outgoing_msg_rx,
-                            &mut incoming_msg_tx,
+                            &incoming,
                             &mut bandwidth_history,
                             &mut frame_size_history,
-                        ) {
-                            tracing::info!(
-                                "Connection lost: {}",
-                                crate::error_display_chain(err.as_ref())
-                            );
-                        } else {
-                            tracing::info!("Connection closed.",);
-                        }
+                            &clock_sync,
+                            &wake_up,
+                        );
                         connected.store(false, SeqCst);
+                        let reason = match result {
+                            Ok(Some(reason)) => {
+                                tracing::info!("The server hung up: {}", reason);
+                                DisconnectReason::Goodbye(reason)
+                            }
+                            Ok(None) => {
+                                tracing::info!("Connection closed.",);
+                                DisconnectReason::ConnectionLost
+                            }
+                            Err(err) => {
+                                tracing::info!(
+                                    "Connection lost: {}",
+                                    crate::error_display_chain(err.as_ref())
+                                );
+                                if err.downcast_ref::<crate::VersionMismatch>().is_some() {
+                                    DisconnectReason::VersionMismatch
+                                } else {
+                                    DisconnectReason::ConnectionLost
+                                }
+                            }
+                        };
+                        let give_up = !reason.should_reconnect() || !reconnect.load(SeqCst);
+                        *disconnect_reason.lock() = Some(reason);
+                        if give_up {
+                            break;
+                        }
+                        call_wake_up(&wake_up);
                     }
                     Err(err) => {
                         tracing::debug!("Failed to connect to {}: {}", addr, err);
+                        // Don't hide why we lost an earlier connection:
+                        let first_failure = {
+                            let mut reason = disconnect_reason.lock();
+                            let first_failure = reason.is_none();
+                            reason.get_or_insert(DisconnectReason::ConnectionRefused);
+                            first_failure
+                        };
+                        if !reconnect.load(SeqCst) {
+                            break;
+                        }
+                        if first_failure {
+                            call_wake_up(&wake_up);
+                        }
                         std::thread::sleep(std::time::Duration::from_secs(1));
                     }
                 }
             }
+
+            alive.store(false, SeqCst);
+            call_wake_up(&wake_up);
         });
 
         client
     }
 
-    /// The address we are connected to or trying to connect to.
+    /// Connects to the eterm server at `remote_addr` through an SSH tunnel to `ssh_target`.
+    ///
+    /// This spawns the system `ssh` command with a local port forward, so your usual
+    /// keys, agent and `~/.ssh/config` all apply. The tunnel is closed when the client is dropped.
+    ///
+    /// ``` no_run
+    /// eterm::Client::new_via_ssh("user@example.com", "127.0.0.1:8505")?;
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    ///
+    /// # Errors
+    /// If `ssh` could not be started.
+    #[cfg(feature = "ssh")]
+    pub fn new_via_ssh(ssh_target: &str, remote_addr: &str) -> anyhow::Result<Self> {
+        let ssh_tunnel = crate::ssh::SshTunnel::new(ssh_target, remote_addr)?;
+        let mut client = Self::new(ssh_tunnel.local_addr().to_owned());
+        client._ssh_tunnel = Some(ssh_tunnel);
+        Ok(client)
+    }
+
+    /// Connects to an eterm server speaking over the stdin and stdout of `command`,
+    /// e.g. one started with [`crate::Server::serve_stdio`] at the other end of an SSH connection.
+    /// No extra ports needed. See [`crate::stdio`].
+    ///
+    /// ``` no_run
+    /// eterm::Client::new_command("ssh user@example.com my-server --stdio")?;
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    ///
+    /// The command is split on whitespace (no quoting), and run again each time we reconnect.
+    /// It is killed when the client is dropped.
+    ///
+    /// # Errors
+    /// If the command is empty.
+    pub fn new_command(command: &str) -> anyhow::Result<Self> {
+        let command_tunnel = crate::stdio::CommandTunnel::new(command)?;
+        // The commands are killed when the network thread is done with them:
+        let connector = Box::new(move || command_tunnel.connect());
+        Ok(Self::with_connector(command.to_owned(), connector))
+    }
+
+    /// Called from the network thread whenever something new has arrived,
+    /// or the connection status changed.
+    ///
+    /// Use this to wake up your event loop so that you can call [`Self::update`]
+    /// without polling.
+    pub fn set_wake_up_callback(&self, callback: impl Fn() + Send + 'static) {
+        *self.wake_up.lock() = Some(Box::new(callback));
+    }
+
+    /// Write every packet sent and received to a file in `dir`.
+    /// See [`crate::packet_dump`] for how to read them.
+    ///
+    /// Takes effect from the next time we connect,
+    /// so call this right after [`Self::new`] to also get the hello.
+    ///
+    /// # Errors
+    /// If `dir` can't be created.
+    pub fn set_packet_dump_dir(&self, dir: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        let packet_dumper = crate::packet_dump::PacketDumper::new(
+            dir.as_ref(),
+            crate::packet_dump::Direction::ClientToServer,
+        )?;
+        *self.packet_dumper.lock() = Some(Arc::new(packet_dumper));
+        Ok(())
+    }
+
+    /// The address we are connected to or trying to connect to,
+    /// or the command given to [`Self::new_command`].
     pub fn addr(&self) -> &str {
         &self.addr
     }
@@ -106,13 +555,124 @@ impl Client {
         self.connected.load(SeqCst)
     }
 
-    pub fn send_input(&self, raw_input: RawInput) {
-        self.outgoing_msg_tx
-            .send(ClientToServerMessage::Input {
-                raw_input,
-                client_time: now(),
-            })
-            .ok();
+    /// Send input to the server. Pointer positions are mapped into the remote ui
+    /// with the [`Self::set_screen_transform`].
+    pub fn send_input(&self, mut raw_input: RawInput) {
+        self.screen_transform.inverse_input(&mut raw_input);
+        self.send_message(ClientToServerMessage::Input {
+            raw_input,
+            client_time: now(),
+        });
+    }
+
+    /// The message of the day the server sent when we connected, if any.
+    ///
+    /// Returns it only once per connection. See [`crate::Server::set_motd`].
+    pub fn take_motd(&mut self) -> Option<String> {
+        self.motd.take()
+    }
+
+    /// What the server is serving, once it has said hello.
+    ///
+    /// See [`crate::Server::set_app_info`].
+    pub fn app_info(&self) -> Option<&crate::AppInfo> {
+        self.app_info.as_ref()
+    }
+
+    /// The log of the server, if it sends it. See [`crate::Server::log_layer`].
+    pub fn server_log(&self) -> &crate::remote_log::ReceivedLog {
+        &self.server_log
+    }
+
+    /// The latest numbers on how the server encodes our frames.
+    ///
+    /// `None` unless the server enables it with [`crate::Server::set_telemetry_interval`].
+    pub fn telemetry(&self) -> Option<crate::Telemetry> {
+        self.telemetry
+    }
+
+    /// The look of the served ui, e.g. for making the rest of the viewer match it.
+    ///
+    /// `None` until the server has told us.
+    pub fn theme(&self) -> Option<crate::Theme> {
+        self.theme
+    }
+
+    /// The banner the server wants shown on top of its ui, if any.
+    ///
+    /// See [`crate::Server::broadcast_notice`].
+    pub fn notice(&self) -> Option<&crate::notice::Notice> {
+        self.notice.as_ref()
+    }
+
+    /// If the server hung up on us, this says why.
+    ///
+    /// Cleared when we connect again. We don't reconnect after [`GoodbyeReason::Kicked`],
+    /// [`GoodbyeReason::AuthFailed`], [`GoodbyeReason::SessionExpired`] or [`GoodbyeReason::ServerError`].
+    pub fn goodbye_reason(&self) -> Option<GoodbyeReason> {
+        match *self.disconnect_reason.lock() {
+            Some(DisconnectReason::Goodbye(reason)) => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// Why we are not connected, if we have tried.
+    ///
+    /// Cleared when we connect again.
+    pub fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        *self.disconnect_reason.lock()
+    }
+
+    /// Should we try again when we fail to connect or lose the connection? On by default.
+    ///
+    /// When off, the client gives up after the first failure. See [`Self::has_given_up`].
+    pub fn set_reconnect(&self, reconnect: bool) {
+        self.reconnect.store(reconnect, SeqCst);
+    }
+
+    /// Has the client stopped trying to connect?
+    ///
+    /// See [`Self::set_reconnect`] and [`DisconnectReason::should_reconnect`].
+    pub fn has_given_up(&self) -> bool {
+        !self.alive.load(SeqCst)
+    }
+
+    pub fn preferences(&self) -> ClientPreferences {
+        self.preferences.lock().clone()
+    }
+
+    /// Tell the server what we'd like the ui to look like, e.g. dark or light.
+    ///
+    /// The server may or may not respect it.
+    pub fn set_preferences(&self, preferences: ClientPreferences) {
+        let mut current = self.preferences.lock();
+        if *current != preferences {
+            *current = preferences.clone();
+            self.send_message(ClientToServerMessage::Preferences(preferences));
+        }
+    }
+
+    /// Tell the server whether the viewer window is visible.
+    ///
+    /// While it is not (e.g. minimized), the server will stop painting and sending frames to us.
+    pub fn set_visible(&self, visible: bool) {
+        if self.visible.swap(visible, SeqCst) != visible {
+            self.send_message(ClientToServerMessage::Visibility { visible });
+        }
+    }
+
+    /// Forward gamepad input to the server.
+    pub fn send_gamepad_events(&self, events: Vec<crate::gamepad::GamepadEvent>) {
+        if !events.is_empty() {
+            self.send_message(ClientToServerMessage::Gamepad { events });
+        }
+    }
+
+    /// Hand the message to the network thread, which sends it right away.
+    fn send_message(&self, message: ClientToServerMessage) {
+        if self.outgoing_msg_tx.send(message).is_ok() {
+            self.network_waker.wake().ok();
+        }
     }
 
     /// Estimated bandwidth use (downstream).
@@ -130,51 +690,176 @@ impl Client {
         self.latency_history.average()
     }
 
+    /// Smoothed estimate of the time it takes for a frame to travel
+    /// from the server to us, in seconds.
+    ///
+    /// Measured against the server clock, as estimated by [`Self::clock_offset`].
+    pub fn downstream_delay(&self) -> Option<f32> {
+        self.downstream_delay_history.average()
+    }
+
+    /// Smoothed time the server spends running its ui for each frame, in seconds.
+    ///
+    /// If this is high, the served ui is what makes things sluggish, not the network.
+    pub fn server_frame_time(&self) -> Option<f32> {
+        self.server_frame_time_history.average()
+    }
+
+    /// Estimated server clock minus our clock, in seconds.
+    ///
+    /// `None` until we have heard back from the server.
+    pub fn clock_offset(&self) -> Option<f64> {
+        self.clock_sync.lock().offset()
+    }
+
     /// Smoothed estimate of the adaptive frames per second.
+    ///
+    /// This is how many frames we receive each second.
     pub fn adaptive_fps(&self) -> Option<f32> {
         self.frame_history.rate()
     }
 
+    /// The index of the latest frame we showed. Frames older than this are discarded.
+    pub fn server_frame_index(&self) -> Option<u64> {
+        self.server_frame_index
+    }
+
+    /// How many frames per second the server produces, measured with the server clock.
+    ///
+    /// If this is much higher than [`Self::adaptive_fps`], the network is the bottleneck.
+    /// If they are both low, the server is.
+    pub fn server_fps(&self) -> Option<f32> {
+        self.server_frame_history.velocity()
+    }
+
+    /// The raw samples behind the statistics above, e.g. for a performance report.
+    ///
+    /// Only the last few seconds are kept, so call this regularly to collect longer series.
+    pub fn stats_samples(&self) -> StatsSamples {
+        let samples = |history: &History<f32>| history.iter().collect();
+        StatsSamples {
+            latency: samples(&self.latency_history),
+            downstream_delay: samples(&self.downstream_delay_history),
+            packet_size: samples(&*self.bandwidth_history.lock()),
+            frame_size: samples(&*self.frame_size_history.lock()),
+        }
+    }
+
+    /// Write [`Self::stats_samples`] to a CSV file.
+    ///
+    /// # Errors
+    /// On I/O errors.
+    pub fn export_stats(&self, path: &std::path::Path) -> anyhow::Result<()> {
+        use anyhow::Context as _;
+        let file = std::fs::File::create(path).with_context(|| format!("{:?}", path))?;
+        self.stats_samples()
+            .write_csv(&mut std::io::BufWriter::new(file))
+            .with_context(|| format!("{:?}", path))
+    }
+
+    /// Call right after presenting a frame with vsync on, with the refresh rate of the display in Hz.
+    ///
+    /// The server then times its frames to arrive just before our next vsync,
+    /// which judders less than frames arriving at random times.
+    pub fn on_vsync(&mut self, refresh_rate: f32) {
+        let now = now();
+        if let Some(last_display_timing) = self.last_display_timing {
+            if now - last_display_timing < DISPLAY_TIMING_INTERVAL {
+                return;
+            }
+        }
+        let (clock_offset, downstream_delay) = match (self.clock_offset(), self.downstream_delay())
+        {
+            (Some(clock_offset), Some(downstream_delay)) => (clock_offset, downstream_delay),
+            _ => return, // We don't know when to tell the server to send yet
+        };
+        self.last_display_timing = Some(now);
+        self.send_message(ClientToServerMessage::DisplayTiming {
+            refresh_rate,
+            send_phase: now + clock_offset - downstream_delay as f64 - VSYNC_MARGIN,
+        });
+    }
+
     /// Retrieved new events, and gives back what to do.
     ///
     /// Return `None` when there is nothing new.
     pub fn update(&mut self, pixels_per_point: f32) -> Option<EguiFrame> {
+        if !self.is_connected() {
+            self.notice = None; // The server will tell us again when we reconnect
+        }
+
         if self.fonts.is_none() {
             self.fonts = Some(Fonts::new(pixels_per_point, self.font_definitions.clone()));
         }
         let fonts = self.fonts.as_mut().unwrap();
         if pixels_per_point != fonts.pixels_per_point() {
             *fonts = Fonts::new(pixels_per_point, self.font_definitions.clone());
+            self.refilter = true; // the latest frame refers to the old font texture
         }
 
-        while let Ok(msg) = self.incoming_msg_rx.try_recv() {
+        let mut request_fonts = false;
+        let messages = self.incoming.lock().take_all();
+        for msg in messages {
             match msg {
                 ServerToClientMessage::Fonts { font_definitions } => {
-                    self.font_definitions = font_definitions;
+                    self.font_definitions =
+                        crate::font_fallback::repair_font_definitions(font_definitions);
                     *fonts = Fonts::new(pixels_per_point, self.font_definitions.clone());
+                    self.glyph_coverage.clear();
+                    self.fonts_from_server = true;
                 }
                 ServerToClientMessage::Frame {
                     frame_index,
                     output,
                     clipped_net_shapes,
                     client_time,
+                    server_time,
+                    server_frame_time,
                 } => {
-                    let clipped_shapes =
-                        crate::net_shape::from_clipped_net_shapes(fonts, clipped_net_shapes);
-                    let tesselator_options =
-                        egui::epaint::tessellator::TessellationOptions::from_pixels_per_point(
-                            pixels_per_point,
-                        );
-                    let tex_size = fonts.font_image().size();
-                    let clipped_meshes = egui::epaint::tessellator::tessellate_shapes(
-                        clipped_shapes,
-                        tesselator_options,
-                        tex_size,
+                    match frame_order(self.server_frame_index, frame_index) {
+                        FrameOrder::Next => {}
+                        FrameOrder::Gap { missing } => {
+                            tracing::debug!(
+                                "Missed {} frame(s) before frame {}",
+                                missing,
+                                frame_index
+                            );
+                        }
+                        FrameOrder::Stale => {
+                            tracing::warn!(
+                                "Discarding frame {}: already showing frame {:?}",
+                                frame_index,
+                                self.server_frame_index
+                            );
+                            continue;
+                        }
+                    }
+
+                    // Until the server's fonts arrive, the text is laid out with our defaults.
+                    // If those are missing glyphs, maybe we missed the fonts, so ask (once):
+                    if !self.fonts_from_server
+                        && !self.fonts_requested
+                        && self
+                            .glyph_coverage
+                            .has_missing_glyphs(fonts, &clipped_net_shapes)
+                    {
+                        tracing::debug!("Frame {} has text our fonts can't show", frame_index);
+                        self.fonts_requested = true;
+                        request_fonts = true;
+                    }
+
+                    self.latest_clipped_net_shapes = clipped_net_shapes;
+                    let clipped_meshes = tessellate(
+                        fonts,
+                        &self.latest_clipped_net_shapes,
+                        self.frame_hook.as_deref_mut(),
+                        self.screen_transform,
                     );
+                    self.refilter = false;
 
                     let latest_frame = self.latest_frame.get_or_insert_with(EguiFrame::default);
                     latest_frame.frame_index = frame_index;
-                    latest_frame.output.append(output);
+                    crate::output::merge(&mut latest_frame.output, output);
                     latest_frame.clipped_meshes = clipped_meshes;
 
                     if let Some(client_time) = client_time {
@@ -182,22 +867,225 @@ impl Client {
                         self.latency_history.add(now(), rtt);
                     }
 
+                    if let Some(clock_offset) = self.clock_sync.lock().offset() {
+                        let delay = (now() + clock_offset - server_time) as f32;
+                        self.downstream_delay_history.add(now(), delay.max(0.0));
+                    }
+
+                    if let Some(server_frame_time) = server_frame_time {
+                        self.server_frame_time_history.add(now(), server_frame_time);
+                    }
+
                     self.frame_history.add(now(), ());
+
+                    self.server_frame_index = Some(frame_index);
+                    self.server_frame_history
+                        .add(server_time, frame_index as f32);
+                }
+                ServerToClientMessage::FileStart {
+                    transfer_id,
+                    file_name,
+                    size,
+                } => {
+                    let file = IncomingFile {
+                        file_name: crate::download::sanitize_file_name(&file_name),
+                        size: size as usize,
+                        bytes: Default::default(),
+                    };
+                    if file.size == 0 {
+                        self.received_files.push(file.finish());
+                    } else {
+                        self.incoming_files.insert(transfer_id, file);
+                    }
+                }
+                ServerToClientMessage::FileChunk { transfer_id, bytes } => {
+                    if let Some(file) = self.incoming_files.get_mut(&transfer_id) {
+                        file.bytes.extend_from_slice(&bytes);
+                        if file.bytes.len() >= file.size {
+                            if let Some(file) = self.incoming_files.remove(&transfer_id) {
+                                self.received_files.push(file.finish());
+                            }
+                        }
+                    } else {
+                        tracing::warn!("Received a chunk of an unknown file");
+                    }
+                }
+                ServerToClientMessage::Notification(notification) => {
+                    self.notifications.push(notification);
+                }
+                ServerToClientMessage::Notice { text, severity } => {
+                    self.notice = if text.is_empty() {
+                        None
+                    } else {
+                        Some(crate::notice::Notice { text, severity })
+                    };
+                }
+                ServerToClientMessage::Hello { motd, app_info, .. } => {
+                    // A new connection, maybe to a new session with its own frame indices:
+                    self.server_frame_index = None;
+                    self.server_frame_history.clear();
+                    // The server sends its fonts again if they are not the defaults:
+                    if self.font_definitions != egui::FontDefinitions::default() {
+                        self.font_definitions = Default::default();
+                        *fonts = Fonts::new(pixels_per_point, self.font_definitions.clone());
+                        self.glyph_coverage.clear();
+                    }
+                    self.fonts_from_server = false;
+                    self.fonts_requested = false;
+                    self.server_font_image = None;
+                    if !motd.is_empty() {
+                        self.motd = Some(motd);
+                    }
+                    self.app_info = Some(app_info);
+                    self.telemetry = None;
+                }
+                ServerToClientMessage::Log { records } => {
+                    self.server_log.extend(records);
+                }
+                ServerToClientMessage::Telemetry(telemetry) => {
+                    self.telemetry = Some(telemetry);
+                }
+                ServerToClientMessage::Theme(theme) => {
+                    self.theme = Some(theme);
+                }
+                ServerToClientMessage::FontImage {
+                    version,
+                    width,
+                    height,
+                    pixels,
+                } => {
+                    self.server_font_image = Some(Arc::new(egui::FontImage {
+                        version: version | SERVER_FONT_IMAGE_VERSION,
+                        width,
+                        height,
+                        pixels,
+                    }));
+                }
+                ServerToClientMessage::Pong { .. } | ServerToClientMessage::Goodbye { .. } => {
+                    // Handled by the network thread
                 }
             }
         }
 
+        if self.refilter {
+            self.refilter = false;
+            if self.server_frame_index.is_some() {
+                let clipped_meshes = tessellate(
+                    fonts,
+                    &self.latest_clipped_net_shapes,
+                    self.frame_hook.as_deref_mut(),
+                    self.screen_transform,
+                );
+                let latest_frame = self.latest_frame.get_or_insert_with(EguiFrame::default);
+                latest_frame.frame_index = self.server_frame_index.unwrap_or_default();
+                latest_frame.clipped_meshes = clipped_meshes;
+            }
+        }
+
         fonts.end_frame(); // make sure to evict galley cache
 
+        if request_fonts {
+            self.send_message(ClientToServerMessage::RequestFonts);
+        }
+
         self.bandwidth_history.lock().flush(now());
         self.frame_size_history.lock().flush(now());
         self.latency_history.flush(now());
+        self.downstream_delay_history.flush(now());
+        self.server_frame_time_history.flush(now());
         self.frame_history.flush(now());
 
         self.latest_frame.take()
     }
 
+    /// Files the server has sent us (see [`crate::download::send_file`]), received in full since last call.
+    ///
+    /// Call after [`Self::update`], and ask the user where to save them.
+    pub fn take_received_files(&mut self) -> Vec<ReceivedFile> {
+        std::mem::take(&mut self.received_files)
+    }
+
+    /// Notifications from the server (see [`crate::notification::notify`]) since last call.
+    ///
+    /// Call after [`Self::update`], and show them to the user.
+    pub fn take_notifications(&mut self) -> Vec<Notification> {
+        std::mem::take(&mut self.notifications)
+    }
+
+    /// Change the shapes of each frame before they are painted: add overlays (crosshairs, annotations),
+    /// remove shapes (e.g. a debug layer) or apply a high-contrast palette with [`crate::recolor::map_colors`].
+    ///
+    /// The shapes are in the coordinates of the remote ui, i.e. before the [`Self::set_screen_transform`].
+    ///
+    /// Also repaints the latest frame on the next [`Self::update`].
+    pub fn set_frame_hook(
+        &mut self,
+        frame_hook: impl FnMut(&mut Vec<egui::epaint::ClippedShape>) + Send + 'static,
+    ) {
+        self.frame_hook = Some(Box::new(frame_hook));
+        self.refilter = true;
+    }
+
+    /// Where to paint the remote ui in the viewer window, e.g. [`crate::ScreenTransform::letterbox`].
+    ///
+    /// Applied to the meshes from [`Self::update`] and inversely to the pointer input of [`Self::send_input`].
+    pub fn set_screen_transform(&mut self, screen_transform: crate::ScreenTransform) {
+        if self.screen_transform != screen_transform {
+            self.screen_transform = screen_transform;
+            self.refilter = true;
+        }
+    }
+
+    pub fn screen_transform(&self) -> crate::ScreenTransform {
+        self.screen_transform
+    }
+
+    /// Paint the shapes as the server sent them again.
+    pub fn clear_frame_hook(&mut self) {
+        if self.frame_hook.take().is_some() {
+            self.refilter = true;
+        }
+    }
+
+    /// The shapes of the latest frame received by [`Self::update`], after the frame hook
+    /// but before tessellation.
+    ///
+    /// Useful if you want to paint the remote ui without a GPU.
+    /// The shapes are laid out anew on each call, which runs the frame hook again.
+    pub fn latest_clipped_shapes(&mut self) -> Vec<egui::epaint::ClippedShape> {
+        match &self.fonts {
+            Some(fonts) => lay_out(
+                fonts,
+                &self.latest_clipped_net_shapes,
+                self.frame_hook.as_deref_mut(),
+            ),
+            None => vec![],
+        }
+    }
+
+    /// The text of the latest frame received by [`Self::update`], as sent by the server.
+    ///
+    /// For checking what the remote ui shows, without painting it.
+    pub fn visible_text(&self) -> crate::visible_text::TextIndex {
+        match &self.fonts {
+            Some(fonts) => crate::visible_text::TextIndex::from_clipped_net_shapes(
+                fonts,
+                &self.latest_clipped_net_shapes,
+            ),
+            None => Default::default(),
+        }
+    }
+
+    /// The shapes of the latest frame, as sent by the server.
+    pub(crate) fn latest_clipped_net_shapes(&self) -> &[crate::net_shape::ClippedNetShape] {
+        &self.latest_clipped_net_shapes
+    }
+
+    /// The font texture to paint the meshes of [`Self::update`] with.
     pub fn font_image(&self) -> Arc<egui::FontImage> {
+        if let Some(server_font_image) = &self.server_font_image {
+            return server_font_image.clone();
+        }
         self.fonts
             .as_ref()
             .expect("Call update() first")
@@ -205,22 +1093,143 @@ impl Client {
     }
 }
 
+/// Why a [`Client`] is not connected. See [`Client::disconnect_reason`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// We could not connect, e.g. because no server is listening at that address.
+    ConnectionRefused,
+    /// The server speaks another version of the eterm protocol.
+    VersionMismatch,
+    /// The server hung up on us, and said why.
+    Goodbye(GoodbyeReason),
+    /// The connection was lost without a goodbye.
+    ConnectionLost,
+}
+
+impl DisconnectReason {
+    /// Is it worth reconnecting after this?
+    pub fn should_reconnect(self) -> bool {
+        match self {
+            Self::ConnectionRefused | Self::ConnectionLost => true,
+            Self::VersionMismatch => false,
+            Self::Goodbye(reason) => reason.should_reconnect(),
+        }
+    }
+}
+
+impl std::fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ConnectionRefused => f.write_str("connection refused"),
+            Self::VersionMismatch => f.write_str("the server runs another version of eterm"),
+            Self::Goodbye(reason) => reason.fmt(f),
+            Self::ConnectionLost => f.write_str("connection lost"),
+        }
+    }
+}
+
+/// Talk to the server until the connection is lost or the [`Client`] is dropped.
+///
+/// Returns the reason the server gave if it hung up on us.
+#[allow(clippy::too_many_arguments)]
 fn run(
     tcp_stream: std::net::TcpStream,
+    poll: &mut mio::Poll,
+    alive: &AtomicBool,
+    visible: &AtomicBool,
+    preferences: &Mutex<ClientPreferences>,
+    packet_dumper: &Mutex<Option<Arc<crate::packet_dump::PacketDumper>>>,
+    resumption_token: &mut Option<Vec<u8>>,
     outgoing_msg_rx: &mut mpsc::Receiver<ClientToServerMessage>,
-    incoming_msg_tx: &mut mpsc::Sender<ServerToClientMessage>,
+    incoming: &Mutex<IncomingQueue>,
     bandwidth_history: &mut Arc<Mutex<History<f32>>>,
     frame_size_history: &mut Arc<Mutex<History<f32>>>,
-) -> anyhow::Result<()> {
+    clock_sync: &Mutex<ClockSync>,
+    wake_up: &WakeUpCallback,
+) -> anyhow::Result<Option<GoodbyeReason>> {
     use anyhow::Context as _;
 
     tcp_stream
         .set_nonblocking(true)
         .context("TCP set_nonblocking")?;
 
-    let mut tcp_endpoint = TcpEndpoint { tcp_stream };
+    let mut tcp_stream = mio::net::TcpStream::from_std(tcp_stream);
+    poll.registry()
+        .register(
+            &mut tcp_stream,
+            SOCKET,
+            mio::Interest::READABLE | mio::Interest::WRITABLE,
+        )
+        .context("registering TCP socket")?;
+
+    let mut tcp_endpoint = TcpEndpoint::new(tcp_stream);
+    tcp_endpoint.packet_dumper = packet_dumper.lock().clone();
+    let result = run_connected(
+        &mut tcp_endpoint,
+        poll,
+        alive,
+        visible,
+        preferences,
+        resumption_token,
+        outgoing_msg_rx,
+        incoming,
+        bandwidth_history,
+        frame_size_history,
+        clock_sync,
+        wake_up,
+    );
+    poll.registry()
+        .deregister(&mut tcp_endpoint.tcp_stream)
+        .ok();
+    result
+}
+
+/// Sleeps until there is something to receive, something to send (see [`Client::send_message`]),
+/// or it is time to ping.
+#[allow(clippy::too_many_arguments)]
+fn run_connected(
+    tcp_endpoint: &mut TcpEndpoint,
+    poll: &mut mio::Poll,
+    alive: &AtomicBool,
+    visible: &AtomicBool,
+    preferences: &Mutex<ClientPreferences>,
+    resumption_token: &mut Option<Vec<u8>>,
+    outgoing_msg_rx: &mut mpsc::Receiver<ClientToServerMessage>,
+    incoming: &Mutex<IncomingQueue>,
+    bandwidth_history: &mut Arc<Mutex<History<f32>>>,
+    frame_size_history: &mut Arc<Mutex<History<f32>>>,
+    clock_sync: &Mutex<ClockSync>,
+    wake_up: &WakeUpCallback,
+) -> anyhow::Result<Option<GoodbyeReason>> {
+    use anyhow::Context as _;
+
+    tcp_endpoint.send_message(&ClientToServerMessage::Hello {
+        compressions: crate::codec::Compression::supported(),
+        resumption_token: resumption_token.clone(),
+        preferences: preferences.lock().clone(),
+    })?;
+    if !visible.load(SeqCst) {
+        // A new connection starts out visible:
+        tcp_endpoint.send_message(&ClientToServerMessage::Visibility { visible: false })?;
+    }
+
+    clock_sync.lock().clear(); // We may be talking to a new server
+    let mut last_ping: Option<std::time::Instant> = None;
+    let mut num_acked = 0;
+    let limits = crate::DecodeLimits::SERVER_TO_CLIENT;
+    let mut events = mio::Events::with_capacity(16);
 
     loop {
+        if last_ping.map_or(true, |last_ping| {
+            last_ping.elapsed() >= crate::clock::PING_INTERVAL
+        }) {
+            tcp_endpoint.send_message(&ClientToServerMessage::Ping { client_time: now() })?;
+            last_ping = Some(std::time::Instant::now());
+        }
+
+        // Check before sending, so we don't miss the goodbye sent by `Client::drop`:
+        let is_alive = alive.load(SeqCst);
+
         loop {
             match outgoing_msg_rx.try_recv() {
                 Ok(message) => {
@@ -228,24 +1237,222 @@ fn run(
                 }
                 Err(mpsc::TryRecvError::Empty) => break,
                 Err(mpsc::TryRecvError::Disconnected) => {
-                    return Ok(());
+                    return Ok(None);
                 }
             }
         }
 
-        while let Some(packet) = tcp_endpoint.try_receive_packet().context("receive")? {
+        if !is_alive {
+            // The client was dropped. Try to get the goodbye out before we close:
+            let deadline = std::time::Instant::now() + GOODBYE_TIMEOUT;
+            while tcp_endpoint.flush().is_ok() && tcp_endpoint.has_pending_output() {
+                let timeout = deadline.saturating_duration_since(std::time::Instant::now());
+                if timeout == std::time::Duration::ZERO {
+                    break;
+                }
+                poll.poll(&mut events, Some(timeout)).ok();
+            }
+            return Ok(None);
+        }
+
+        while let Some(packet) = tcp_endpoint
+            .try_receive_packet(&limits)
+            .context("receive")?
+        {
             bandwidth_history.lock().add(now(), packet.len() as f32);
-            let message = crate::decode_message(&packet).context("decode")?;
+            let mut message = crate::wire::decode_message(&packet, &limits).context("decode")?;
+            match message {
+                ServerToClientMessage::Pong {
+                    client_time,
+                    server_time,
+                } => {
+                    clock_sync
+                        .lock()
+                        .add_sample(client_time, server_time, now());
+                    continue;
+                }
+                ServerToClientMessage::Hello {
+                    compression,
+                    resumption_token: ref mut new_token,
+                    ..
+                } => {
+                    tracing::debug!("Using {:?} compression", compression);
+                    tcp_endpoint.compression = compression;
+                    if !new_token.is_empty() {
+                        *resumption_token = Some(std::mem::take(new_token));
+                    }
+                    // Forwarded for the motd
+                }
+                ServerToClientMessage::Goodbye { reason } => {
+                    return Ok(Some(reason));
+                }
+                _ => {}
+            }
             if let ServerToClientMessage::Frame { .. } = &message {
                 frame_size_history.lock().add(now(), packet.len() as f32);
             }
-            incoming_msg_tx.send(message)?;
+            incoming.lock().push(message);
+            call_wake_up(wake_up);
         }
 
-        std::thread::sleep(std::time::Duration::from_millis(5));
+        let num_received = tcp_endpoint.sequencer.num_received();
+        if num_received != num_acked {
+            tcp_endpoint.send_message(&ClientToServerMessage::Ack { num_received })?;
+            num_acked = num_received;
+        }
+
+        tcp_endpoint.flush().context("send")?;
+
+        // Which event woke us up doesn't matter - we check everything each time.
+        let time_to_ping = last_ping.map_or(std::time::Duration::ZERO, |last_ping| {
+            crate::clock::PING_INTERVAL.saturating_sub(last_ping.elapsed())
+        });
+        match poll.poll(&mut events, Some(time_to_ping)) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err).context("mio poll"),
+        }
     }
 }
 
-fn now() -> f64 {
-    std::time::UNIX_EPOCH.elapsed().unwrap().as_secs_f64()
+/// Connect to whichever address of `addr` answers first.
+///
+/// If it resolves to several addresses (e.g. both IPv6 and IPv4), they are tried in parallel,
+/// each one [`CONNECTION_ATTEMPT_DELAY`] after the previous one ("happy eyeballs", RFC 8305).
+/// That way a broken IPv6 route doesn't hold us up until it times out.
+fn connect(addr: &str) -> std::io::Result<std::net::TcpStream> {
+    use std::net::ToSocketAddrs as _;
+
+    let mut addrs = interleave_families(addr.to_socket_addrs()?.collect())
+        .into_iter()
+        .peekable();
+    let (result_tx, result_rx) = mpsc::channel();
+    let mut num_pending = 0;
+    let mut last_err = None;
+    loop {
+        if let Some(socket_addr) = addrs.next() {
+            let result_tx = result_tx.clone();
+            std::thread::spawn(move || {
+                // If another address wins, this connection is closed as soon as it's made:
+                result_tx
+                    .send(std::net::TcpStream::connect_timeout(
+                        &socket_addr,
+                        CONNECT_TIMEOUT,
+                    ))
+                    .ok();
+            });
+            num_pending += 1;
+        }
+        if num_pending == 0 {
+            return Err(last_err.unwrap_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("{:?} has no addresses", addr),
+                )
+            }));
+        }
+
+        let result = if addrs.peek().is_some() {
+            result_rx.recv_timeout(CONNECTION_ATTEMPT_DELAY).ok()
+        } else {
+            result_rx.recv().ok()
+        };
+        match result {
+            Some(Ok(tcp_stream)) => return Ok(tcp_stream),
+            Some(Err(err)) => {
+                // Start on the next address right away:
+                num_pending -= 1;
+                last_err = Some(err);
+            }
+            None => {} // Time to also try the next address
+        }
+    }
+}
+
+/// Alternate between the address families, starting with the one of the first address.
+fn interleave_families(addrs: Vec<std::net::SocketAddr>) -> Vec<std::net::SocketAddr> {
+    let first_is_ipv6 = addrs.first().map_or(false, |addr| addr.is_ipv6());
+    let (first, second): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == first_is_ipv6);
+    itertools::interleave(first, second).collect()
+}
+
+#[test]
+fn test_interleave_families() {
+    let addrs: Vec<std::net::SocketAddr> = vec![
+        "[::1]:8505".parse().unwrap(),
+        "[::2]:8505".parse().unwrap(),
+        "127.0.0.1:8505".parse().unwrap(),
+        "127.0.0.2:8505".parse().unwrap(),
+        "127.0.0.3:8505".parse().unwrap(),
+    ];
+    let interleaved: Vec<String> = interleave_families(addrs)
+        .iter()
+        .map(|addr| addr.to_string())
+        .collect();
+    assert_eq!(
+        interleaved,
+        vec![
+            "[::1]:8505",
+            "127.0.0.1:8505",
+            "[::2]:8505",
+            "127.0.0.2:8505",
+            "127.0.0.3:8505"
+        ]
+    );
+}
+
+/// Where a received frame falls relative to the last one we showed.
+#[derive(Debug, PartialEq)]
+enum FrameOrder {
+    /// The one we expected.
+    Next,
+    /// Newer than expected: frames were skipped or lost.
+    Gap { missing: u64 },
+    /// A duplicate, or older than what we already show.
+    Stale,
+}
+
+fn frame_order(last_shown: Option<u64>, frame_index: u64) -> FrameOrder {
+    match last_shown {
+        None => FrameOrder::Next,
+        Some(last_shown) if frame_index <= last_shown => FrameOrder::Stale,
+        Some(last_shown) if frame_index == last_shown + 1 => FrameOrder::Next,
+        Some(last_shown) => FrameOrder::Gap {
+            missing: frame_index - last_shown - 1,
+        },
+    }
+}
+
+#[test]
+fn test_frame_order() {
+    assert_eq!(frame_order(None, 17), FrameOrder::Next);
+    assert_eq!(frame_order(Some(16), 17), FrameOrder::Next);
+    assert_eq!(frame_order(Some(14), 17), FrameOrder::Gap { missing: 2 });
+    assert_eq!(frame_order(Some(17), 17), FrameOrder::Stale);
+    assert_eq!(frame_order(Some(18), 17), FrameOrder::Stale);
+}
+
+/// E.g. `"en-US"`, from the POSIX locale environment variables.
+pub(crate) fn system_locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .filter(|value| value != "C" && value != "POSIX")
+        .map(|value| {
+            // "en_US.UTF-8" -> "en-US"
+            let value = value
+                .split(|c| c == '.' || c == '@')
+                .next()
+                .unwrap_or_default();
+            value.replace('_', "-")
+        })
+}
+
+fn call_wake_up(wake_up: &WakeUpCallback) {
+    if let Some(wake_up) = &*wake_up.lock() {
+        wake_up();
+    }
 }
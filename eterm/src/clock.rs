@@ -0,0 +1,78 @@
+//! NTP-style estimation of the offset between the client and server clocks.
+//!
+//! The client sends [`crate::ClientToServerMessage::Ping`] with its own time,
+//! and the server immediately answers with [`crate::ServerToClientMessage::Pong`]
+//! containing both that time and the server time.
+//! Assuming the delay is the same both ways, the server time was read
+//! half-way through the round trip. The samples with the shortest round-trip
+//! have the least room for asymmetric delays, so we trust those the most.
+
+/// Seconds since this process started. Monotonic, unlike the wall clock.
+pub(crate) fn now() -> f64 {
+    static START: parking_lot::Mutex<Option<std::time::Instant>> = parking_lot::const_mutex(None);
+    START
+        .lock()
+        .get_or_insert_with(std::time::Instant::now)
+        .elapsed()
+        .as_secs_f64()
+}
+
+/// How often the client pings the server.
+pub(crate) const PING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// How many of the latest samples to consider.
+const MAX_SAMPLES: usize = 16;
+
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    round_trip_time: f64,
+    /// server clock minus client clock
+    offset: f64,
+}
+
+/// Estimates the server clock from the client side.
+#[derive(Default)]
+pub(crate) struct ClockSync {
+    samples: std::collections::VecDeque<Sample>,
+}
+
+impl ClockSync {
+    /// `client_send_time` and `client_receive_time` are [`now`] on the client,
+    /// `server_time` is [`now`] on the server.
+    pub fn add_sample(
+        &mut self,
+        client_send_time: f64,
+        server_time: f64,
+        client_receive_time: f64,
+    ) {
+        let round_trip_time = client_receive_time - client_send_time;
+        if round_trip_time < 0.0 {
+            return; // Bogus
+        }
+        let offset = server_time - (client_send_time + client_receive_time) / 2.0;
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            round_trip_time,
+            offset,
+        });
+    }
+
+    /// Server clock minus client clock, in seconds.
+    pub fn offset(&self) -> Option<f64> {
+        self.best_sample().map(|sample| sample.offset)
+    }
+
+    fn best_sample(&self) -> Option<Sample> {
+        self.samples.iter().copied().min_by(|a, b| {
+            a.round_trip_time
+                .partial_cmp(&b.round_trip_time)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
@@ -0,0 +1,290 @@
+//! Packet compression.
+//!
+//! Each packet starts with a byte saying which [`Compression`] was used, so any packet can be
+//! decoded as long as the receiver supports that compression.
+//! Which compression to use when sending is negotiated when connecting:
+//! the client lists what it supports in [`crate::ClientToServerMessage::Hello`],
+//! and the server picks one and tells the client in [`crate::ServerToClientMessage::Hello`].
+//! Until then, packets are uncompressed.
+//!
+//! Each compression is behind a cargo feature (`zstd` and `lz4`), so that e.g. embedded
+//! targets can skip the zstd C dependency.
+
+/// The compression level we start each client at.
+/// See [`LevelControl`] for how it then adapts.
+pub const DEFAULT_LEVEL: i32 = 5;
+
+/// How a packet is compressed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Compression {
+    None,
+    Zstd,
+    Lz4,
+}
+
+impl Compression {
+    fn tag(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Zstd => 1,
+            Self::Lz4 => 2,
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Self::None),
+            1 => Some(Self::Zstd),
+            2 => Some(Self::Lz4),
+            _ => None,
+        }
+    }
+
+    /// The compressions this build of eterm supports, most preferred first.
+    pub fn supported() -> Vec<Self> {
+        let mut supported = vec![];
+        if cfg!(feature = "zstd") {
+            supported.push(Self::Zstd);
+        }
+        if cfg!(feature = "lz4") {
+            supported.push(Self::Lz4);
+        }
+        supported.push(Self::None);
+        supported
+    }
+
+    /// Pick the compression we prefer out of the ones the other side supports.
+    pub fn negotiate(theirs: &[Self]) -> Self {
+        Self::supported()
+            .into_iter()
+            .find(|compression| theirs.contains(compression))
+            .unwrap_or(Self::None)
+    }
+
+    pub fn codec(self) -> Option<&'static dyn PacketCodec> {
+        match self {
+            Self::None => Some(&NoCompression),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Some(&Zstd),
+            #[cfg(feature = "lz4")]
+            Self::Lz4 => Some(&Lz4),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+}
+
+/// Compresses and decompresses packets.
+pub trait PacketCodec: Sync {
+    /// Higher `level` means smaller but slower. Codecs without levels ignore it.
+    ///
+    /// # Errors
+    /// If the underlying compressor fails.
+    fn compress(&self, data: &[u8], level: i32) -> anyhow::Result<Vec<u8>>;
+
+    /// # Errors
+    /// If the data is corrupt, or decompresses to more than `max_size` bytes.
+    fn decompress(&self, data: &[u8], max_size: usize) -> anyhow::Result<Vec<u8>>;
+}
+
+struct NoCompression;
+
+impl PacketCodec for NoCompression {
+    fn compress(&self, data: &[u8], _level: i32) -> anyhow::Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+
+    fn decompress(&self, data: &[u8], max_size: usize) -> anyhow::Result<Vec<u8>> {
+        anyhow::ensure!(data.len() <= max_size, "Packet too large");
+        Ok(data.to_vec())
+    }
+}
+
+#[cfg(feature = "zstd")]
+struct Zstd;
+
+#[cfg(feature = "zstd")]
+impl PacketCodec for Zstd {
+    fn compress(&self, data: &[u8], level: i32) -> anyhow::Result<Vec<u8>> {
+        Ok(zstd::encode_all(std::io::Cursor::new(data), level)?)
+    }
+
+    fn decompress(&self, data: &[u8], max_size: usize) -> anyhow::Result<Vec<u8>> {
+        use std::io::Read as _;
+        let mut decompressed = vec![];
+        zstd::stream::read::Decoder::new(data)?
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut decompressed)?;
+        anyhow::ensure!(
+            decompressed.len() <= max_size,
+            "Packet decompresses to more than {:.1} MB",
+            max_size as f32 * 1e-6
+        );
+        Ok(decompressed)
+    }
+}
+
+#[cfg(feature = "lz4")]
+struct Lz4;
+
+#[cfg(feature = "lz4")]
+impl PacketCodec for Lz4 {
+    fn compress(&self, data: &[u8], _level: i32) -> anyhow::Result<Vec<u8>> {
+        Ok(lz4_flex::compress_prepend_size(data))
+    }
+
+    fn decompress(&self, data: &[u8], max_size: usize) -> anyhow::Result<Vec<u8>> {
+        // Check the size before lz4_flex allocates it:
+        anyhow::ensure!(data.len() >= 4, "lz4 packet too short");
+        let size = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        anyhow::ensure!(
+            size <= max_size,
+            "Packet decompresses to more than {:.1} MB",
+            max_size as f32 * 1e-6
+        );
+        Ok(lz4_flex::decompress_size_prepended(data)?)
+    }
+}
+
+/// Compress `data` and prefix it with the compression tag.
+pub(crate) fn compress(
+    compression: Compression,
+    level: i32,
+    data: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let codec = compression.codec().ok_or_else(|| {
+        anyhow::format_err!("eterm was compiled without support for {:?}", compression)
+    })?;
+    let mut packet = vec![compression.tag()];
+    packet.extend_from_slice(&codec.compress(data, level)?);
+    Ok(packet)
+}
+
+/// Decompress a packet from [`compress`].
+pub(crate) fn decompress(packet: &[u8], max_size: usize) -> anyhow::Result<Vec<u8>> {
+    let (&tag, data) = packet
+        .split_first()
+        .ok_or_else(|| anyhow::format_err!("Empty packet"))?;
+    let compression = Compression::from_tag(tag)
+        .ok_or_else(|| anyhow::format_err!("Unknown compression: {}", tag))?;
+    let codec = compression.codec().ok_or_else(|| {
+        anyhow::format_err!("eterm was compiled without support for {:?}", compression)
+    })?;
+    codec.decompress(data, max_size)
+}
+
+#[test]
+fn test_negotiate_per_client() {
+    // E.g. a web viewer without zstd:
+    assert_eq!(
+        Compression::negotiate(&[Compression::None]),
+        Compression::None
+    );
+    assert_eq!(Compression::negotiate(&[]), Compression::None);
+
+    // A native viewer gets our favorite:
+    let favorite = Compression::supported()[0];
+    assert_eq!(Compression::negotiate(&Compression::supported()), favorite);
+
+    for compression in Compression::supported() {
+        let packet = compress(compression, DEFAULT_LEVEL, b"hello hello hello").unwrap();
+        assert_eq!(decompress(&packet, 1024).unwrap(), b"hello hello hello");
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// Picks the compression level for one client.
+///
+/// If the connection can't keep up we compress harder to save bytes.
+/// If it can, we compress less to save CPU.
+/// If even the hardest compression isn't enough, we also quantize the shapes.
+pub(crate) struct LevelControl {
+    level: i32,
+    /// Frames in a row where the connection kept up.
+    frames_without_backlog: u32,
+    /// See [`Self::should_quantize`].
+    quantize: bool,
+}
+
+impl Default for LevelControl {
+    fn default() -> Self {
+        Self {
+            level: DEFAULT_LEVEL,
+            frames_without_backlog: 0,
+            quantize: false,
+        }
+    }
+}
+
+impl LevelControl {
+    const MIN_LEVEL: i32 = 1;
+    const MAX_LEVEL: i32 = 15;
+
+    /// Don't spend more than this many seconds compressing one frame.
+    const MAX_ENCODE_TIME: f32 = 0.010;
+
+    /// How many frames the connection must keep up before we lower the level.
+    const CALM_FRAMES: u32 = 30;
+
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    /// Are we at the highest level, and still can't keep up?
+    /// Then round the shapes too (see [`crate::net_shape::quantize`]), until the level goes down again.
+    pub fn should_quantize(&self) -> bool {
+        self.quantize
+    }
+
+    /// Call after each sent frame.
+    ///
+    /// `backlogged`: did we have to wait for the socket since the last frame?
+    pub fn on_frame(&mut self, encode_time: f32, backlogged: bool) {
+        if backlogged {
+            self.frames_without_backlog = 0;
+            if encode_time < Self::MAX_ENCODE_TIME {
+                self.level = (self.level + 1).min(Self::MAX_LEVEL);
+            }
+        } else {
+            self.frames_without_backlog += 1;
+            if self.frames_without_backlog >= Self::CALM_FRAMES {
+                self.frames_without_backlog = 0;
+                self.level = (self.level - 1).max(Self::MIN_LEVEL);
+            }
+        }
+
+        if encode_time > Self::MAX_ENCODE_TIME {
+            self.level = (self.level - 1).max(Self::MIN_LEVEL);
+        }
+
+        if self.level < Self::MAX_LEVEL {
+            self.quantize = false;
+        } else if backlogged {
+            self.quantize = true;
+        }
+    }
+}
+
+#[test]
+fn test_level_control() {
+    let mut control = LevelControl::default();
+    for _ in 0..100 {
+        control.on_frame(0.001, true);
+    }
+    assert_eq!(control.level(), LevelControl::MAX_LEVEL, "slow link");
+    assert!(control.should_quantize(), "very slow link");
+    control.on_frame(0.001, false);
+    assert!(control.should_quantize(), "hysteresis");
+
+    for _ in 0..100 {
+        control.on_frame(0.050, true);
+    }
+    assert!(control.level() < LevelControl::MAX_LEVEL, "slow encoder");
+    assert!(!control.should_quantize());
+
+    for _ in 0..10_000 {
+        control.on_frame(0.001, false);
+    }
+    assert_eq!(control.level(), LevelControl::MIN_LEVEL, "fast link");
+}
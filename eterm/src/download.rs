@@ -0,0 +1,74 @@
+//! Send files from the server to the viewer, e.g. for a "Save log file…" button.
+//!
+//! On the server, call [`send_file`] from within [`crate::Server::show`].
+//! On the client, collect the files with [`crate::Client::take_received_files`].
+
+use std::sync::Arc;
+
+/// Files are sent in chunks of this size, so they don't block frames for too long.
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How many chunks to send to each client per call to [`crate::Server::show`].
+pub(crate) const CHUNKS_PER_UPDATE: usize = 4;
+
+/// A file sent from the server, received in full.
+#[derive(Clone, Debug)]
+pub struct ReceivedFile {
+    /// Suggested file name, without any directories.
+    pub file_name: String,
+    pub bytes: Vec<u8>,
+}
+
+/// A file queued for sending to a client.
+#[derive(Clone)]
+pub(crate) struct OutgoingFile {
+    pub file_name: String,
+    pub bytes: Arc<[u8]>,
+}
+
+fn outgoing_files_id() -> egui::Id {
+    egui::Id::new("eterm_outgoing_files")
+}
+
+/// Send a file to the client currently being shown.
+///
+/// The viewer will ask the user where to save it.
+///
+/// Call this from within the closure given to [`crate::Server::show`].
+pub fn send_file(
+    egui_ctx: &egui::CtxRef,
+    file_name: impl Into<String>,
+    bytes: impl Into<Arc<[u8]>>,
+) {
+    egui_ctx
+        .memory()
+        .data
+        .get_temp_mut_or_default::<Vec<OutgoingFile>>(outgoing_files_id())
+        .push(OutgoingFile {
+            file_name: file_name.into(),
+            bytes: bytes.into(),
+        });
+}
+
+/// The files queued with [`send_file`] since last call.
+pub(crate) fn take_outgoing_files(egui_ctx: &egui::CtxRef) -> Vec<OutgoingFile> {
+    std::mem::take(
+        egui_ctx
+            .memory()
+            .data
+            .get_temp_mut_or_default::<Vec<OutgoingFile>>(outgoing_files_id()),
+    )
+}
+
+/// Remove any directories from a file name we got over the network.
+pub(crate) fn sanitize_file_name(file_name: &str) -> String {
+    let file_name = file_name
+        .rsplit(&['/', '\\'][..])
+        .next()
+        .unwrap_or_default();
+    if file_name.is_empty() || file_name == "." || file_name == ".." {
+        "download".to_owned()
+    } else {
+        file_name.to_owned()
+    }
+}
@@ -0,0 +1,137 @@
+//! Keeping the client's text layout working whatever fonts the server sends, or doesn't send.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use egui::epaint::text::{FontDefinitions, Fonts};
+use egui::TextStyle;
+
+use crate::net_shape::{ClippedNetShape, NetShape};
+
+/// [`Fonts::new`] panics on font definitions that refer to fonts they don't have,
+/// and laying out text panics for text styles without a font.
+/// Fall back to the defaults for anything like that.
+pub(crate) fn repair_font_definitions(mut definitions: FontDefinitions) -> FontDefinitions {
+    let defaults = FontDefinitions::default();
+
+    for (family, font_names) in &mut definitions.fonts_for_family {
+        let font_data = &definitions.font_data;
+        font_names.retain(|font_name| {
+            let known = font_data.contains_key(font_name);
+            if !known {
+                tracing::warn!(
+                    "The server fonts for {:?} refer to a missing font {:?}",
+                    family,
+                    font_name
+                );
+            }
+            known
+        });
+    }
+
+    for (&text_style, &family_and_size) in &defaults.family_and_size {
+        definitions
+            .family_and_size
+            .entry(text_style)
+            .or_insert_with(|| {
+                tracing::warn!("The server fonts have no {:?} text style", text_style);
+                family_and_size
+            });
+    }
+
+    let families: BTreeSet<_> = definitions
+        .family_and_size
+        .values()
+        .map(|&(family, _)| family)
+        .collect();
+    for family in families {
+        let has_fonts = definitions
+            .fonts_for_family
+            .get(&family)
+            .map_or(false, |font_names| !font_names.is_empty());
+        if has_fonts {
+            continue;
+        }
+        tracing::warn!(
+            "The server fonts have no {:?} fonts: using the defaults",
+            family
+        );
+        let font_names = defaults
+            .fonts_for_family
+            .get(&family)
+            .cloned()
+            .unwrap_or_default();
+        for font_name in &font_names {
+            if let Some(font_data) = defaults.font_data.get(font_name) {
+                definitions
+                    .font_data
+                    .entry(font_name.clone())
+                    .or_insert_with(|| font_data.clone());
+            }
+        }
+        definitions.fonts_for_family.insert(family, font_names);
+    }
+
+    definitions
+}
+
+/// Which characters the fonts have glyphs for, to notice when we're
+/// laying out text with other fonts than the server.
+#[derive(Default)]
+pub(crate) struct GlyphCoverage {
+    /// Filled in lazily, since it is slow to compute.
+    characters: BTreeMap<TextStyle, BTreeSet<char>>,
+}
+
+impl GlyphCoverage {
+    /// Call when the fonts change.
+    pub fn clear(&mut self) {
+        self.characters.clear();
+    }
+
+    /// Is there text in these shapes that `fonts` has no glyphs for?
+    pub fn has_missing_glyphs(&mut self, fonts: &Fonts, shapes: &[ClippedNetShape]) -> bool {
+        for ClippedNetShape(_, shape) in shapes {
+            if let NetShape::Text(text_shape) = shape {
+                let job = &text_shape.job;
+                for section in &job.sections {
+                    let characters = self
+                        .characters
+                        .entry(section.format.style)
+                        .or_insert_with(|| fonts[section.format.style].characters());
+                    let text = job.text.get(section.byte_range.clone()).unwrap_or_default();
+                    if text
+                        .chars()
+                        .any(|c| !c.is_whitespace() && !characters.contains(&c))
+                    {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+#[test]
+fn test_repair_font_definitions() {
+    let defaults = FontDefinitions::default();
+
+    let mut definitions = FontDefinitions::default();
+    definitions.family_and_size.remove(&TextStyle::Heading);
+    definitions.fonts_for_family.insert(
+        egui::FontFamily::Monospace,
+        vec!["not a font we have".to_owned()],
+    );
+    let repaired = repair_font_definitions(definitions);
+
+    assert_eq!(
+        repaired.family_and_size[&TextStyle::Heading],
+        defaults.family_and_size[&TextStyle::Heading]
+    );
+    assert_eq!(
+        repaired.fonts_for_family[&egui::FontFamily::Monospace],
+        defaults.fonts_for_family[&egui::FontFamily::Monospace]
+    );
+
+    assert_eq!(repair_font_definitions(defaults.clone()), defaults);
+}
@@ -0,0 +1,421 @@
+//! Cutting fonts down to the characters a client has been shown, see [`crate::Server::set_font_subsetting`].
+//!
+//! Only TrueType outlines (the `glyf` table) are subset. The glyphs keep their ids,
+//! so the metrics and kerning tables stay valid: the unused glyphs are emptied,
+//! and their characters dropped from the character map.
+//! Other fonts (e.g. `.otf` with CFF outlines) are sent whole.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+use anyhow::Context as _;
+use egui::epaint::text::FontDefinitions;
+
+use crate::net_shape::{ClippedNetShape, NetShape};
+
+// Flags of the components of composite glyphs:
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+/// Always included: printable ASCII, and what egui shows for missing glyphs and passwords.
+pub(crate) fn base_chars() -> BTreeSet<char> {
+    let mut chars: BTreeSet<char> = (' '..='~').collect();
+    chars.extend(['◻', '•']);
+    chars
+}
+
+/// Add the characters of all the text in `shapes`.
+pub(crate) fn collect_chars(shapes: &[ClippedNetShape], chars: &mut BTreeSet<char>) {
+    for ClippedNetShape(_, shape) in shapes {
+        if let NetShape::Text(text_shape) = shape {
+            chars.extend(text_shape.job.text.chars());
+        }
+    }
+}
+
+/// Subset all the fonts, sending whole the ones we can't subset.
+pub(crate) fn subset_font_definitions(
+    definitions: &FontDefinitions,
+    chars: &BTreeSet<char>,
+) -> FontDefinitions {
+    let mut subset = definitions.clone();
+    for (name, font_data) in &mut subset.font_data {
+        match subset_font(&font_data.font, chars) {
+            Ok(Some(font)) => font_data.font = font.into(),
+            Ok(None) => {}
+            Err(err) => tracing::warn!("Failed to subset the font {:?}: {:#}", name, err),
+        }
+    }
+    subset
+}
+
+/// Returns `None` if the font doesn't have TrueType outlines.
+pub(crate) fn subset_font(font: &[u8], chars: &BTreeSet<char>) -> anyhow::Result<Option<Vec<u8>>> {
+    let sfnt_version = read_u32(font, 0)?;
+    if sfnt_version != 0x0001_0000 && sfnt_version != u32::from_be_bytes(*b"true") {
+        return Ok(None); // e.g. CFF outlines, or a font collection
+    }
+
+    let num_tables = read_u16(font, 4)? as usize;
+    let mut tables = Vec::with_capacity(num_tables);
+    for i in 0..num_tables {
+        let record = 12 + 16 * i;
+        let tag: [u8; 4] = font
+            .get(record..record + 4)
+            .context("truncated table directory")?
+            .try_into()?;
+        let offset = read_u32(font, record + 8)? as usize;
+        let length = read_u32(font, record + 12)? as usize;
+        let data = font.get(offset..offset + length).with_context(|| {
+            format!(
+                "the {:?} table is out of bounds",
+                String::from_utf8_lossy(&tag)
+            )
+        })?;
+        tables.push((tag, data));
+    }
+
+    let table = |tag: &[u8; 4]| {
+        tables
+            .iter()
+            .find(|(table_tag, _)| table_tag == tag)
+            .map(|(_, data)| *data)
+    };
+    let (glyf, loca, head, maxp, cmap) = match (
+        table(b"glyf"),
+        table(b"loca"),
+        table(b"head"),
+        table(b"maxp"),
+        table(b"cmap"),
+    ) {
+        (Some(glyf), Some(loca), Some(head), Some(maxp), Some(cmap)) => {
+            (glyf, loca, head, maxp, cmap)
+        }
+        _ => return Ok(None),
+    };
+
+    let num_glyphs = read_u16(maxp, 4)? as usize;
+    let long_loca = read_u16(head, 50)? != 0;
+    let glyph_ranges = (0..num_glyphs)
+        .map(|i| {
+            let (start, end) = if long_loca {
+                (
+                    read_u32(loca, 4 * i)? as usize,
+                    read_u32(loca, 4 * i + 4)? as usize,
+                )
+            } else {
+                (
+                    2 * read_u16(loca, 2 * i)? as usize,
+                    2 * read_u16(loca, 2 * i + 2)? as usize,
+                )
+            };
+            anyhow::ensure!(start <= end && end <= glyf.len(), "bad loca entry");
+            Ok(start..end)
+        })
+        .collect::<anyhow::Result<Vec<Range<usize>>>>()?;
+
+    let char_map = read_cmap(cmap, chars)?;
+
+    // The glyph for missing characters, the mapped glyphs, and the parts of composite glyphs:
+    let mut keep = BTreeSet::new();
+    let mut todo: Vec<u16> = std::iter::once(0)
+        .chain(char_map.values().copied())
+        .collect();
+    while let Some(glyph_id) = todo.pop() {
+        if let Some(range) = glyph_ranges.get(glyph_id as usize) {
+            if keep.insert(glyph_id) {
+                todo.extend(composite_components(&glyf[range.clone()])?);
+            }
+        }
+    }
+
+    let mut new_glyf = vec![];
+    let mut new_loca = Vec::with_capacity(4 * (num_glyphs + 1));
+    for (glyph_id, range) in glyph_ranges.iter().enumerate() {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        if keep.contains(&(glyph_id as u16)) {
+            new_glyf.extend_from_slice(&glyf[range.clone()]);
+            pad_to_4(&mut new_glyf);
+        }
+    }
+    new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+
+    let mut new_head = head.to_vec();
+    anyhow::ensure!(new_head.len() >= 54, "head table too short");
+    new_head[8..12].copy_from_slice(&[0; 4]); // checkSumAdjustment, set by `write_font`
+    new_head[50..52].copy_from_slice(&1_u16.to_be_bytes()); // long loca
+
+    let new_cmap = write_cmap(&char_map);
+
+    let new_tables: Vec<([u8; 4], Vec<u8>)> = tables
+        .iter()
+        .filter(|(tag, _)| tag != b"DSIG") // the signature no longer matches
+        .map(|&(tag, data)| {
+            let data = match &tag {
+                b"glyf" => new_glyf.clone(),
+                b"loca" => new_loca.clone(),
+                b"head" => new_head.clone(),
+                b"cmap" => new_cmap.clone(),
+                _ => data.to_vec(),
+            };
+            (tag, data)
+        })
+        .collect();
+    Ok(Some(write_font(sfnt_version, new_tables)))
+}
+
+/// The glyph ids of the components, if this is a composite glyph.
+fn composite_components(glyph: &[u8]) -> anyhow::Result<Vec<u16>> {
+    if glyph.is_empty() || read_u16(glyph, 0)? as i16 >= 0 {
+        return Ok(vec![]); // empty or simple glyph
+    }
+    let mut components = vec![];
+    let mut offset = 10;
+    loop {
+        let flags = read_u16(glyph, offset)?;
+        components.push(read_u16(glyph, offset + 2)?);
+        offset += 4;
+        offset += if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+            4
+        } else {
+            2
+        };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            offset += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            offset += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            offset += 8;
+        }
+        if flags & MORE_COMPONENTS == 0 {
+            return Ok(components);
+        }
+    }
+}
+
+/// The glyph ids of those of `chars` the font has.
+fn read_cmap(cmap: &[u8], chars: &BTreeSet<char>) -> anyhow::Result<BTreeMap<char, u16>> {
+    let mut format_4 = None;
+    let mut format_12 = None;
+    for i in 0..read_u16(cmap, 2)? as usize {
+        let record = 4 + 8 * i;
+        let platform_id = read_u16(cmap, record)?;
+        let encoding_id = read_u16(cmap, record + 2)?;
+        let is_unicode = platform_id == 0 || (platform_id == 3 && matches!(encoding_id, 1 | 10));
+        if is_unicode {
+            let offset = read_u32(cmap, record + 4)? as usize;
+            let subtable = cmap.get(offset..).context("cmap subtable out of bounds")?;
+            match read_u16(subtable, 0)? {
+                4 => format_4 = Some(subtable),
+                12 => format_12 = Some(subtable),
+                _ => {}
+            }
+        }
+    }
+
+    let mut char_map = BTreeMap::new();
+    for &c in chars {
+        let glyph_id = match (format_12, format_4) {
+            (Some(subtable), _) => lookup_format_12(subtable, c as u32)?,
+            (None, Some(subtable)) => lookup_format_4(subtable, c as u32)?,
+            (None, None) => anyhow::bail!("no supported unicode cmap"),
+        };
+        if glyph_id != 0 {
+            char_map.insert(c, glyph_id);
+        }
+    }
+    Ok(char_map)
+}
+
+/// Segment mapping to delta values, for the Basic Multilingual Plane.
+fn lookup_format_4(subtable: &[u8], c: u32) -> anyhow::Result<u16> {
+    if c > 0xFFFF {
+        return Ok(0);
+    }
+    let c = c as u16;
+    let seg_count = read_u16(subtable, 6)? as usize / 2;
+    let end_codes = 14;
+    let start_codes = end_codes + 2 * seg_count + 2;
+    let id_deltas = start_codes + 2 * seg_count;
+    let id_range_offsets = id_deltas + 2 * seg_count;
+
+    // The first segment that ends at or after `c`:
+    let (mut low, mut high) = (0, seg_count);
+    while low < high {
+        let mid = (low + high) / 2;
+        if read_u16(subtable, end_codes + 2 * mid)? < c {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    let segment = low;
+    if segment == seg_count || c < read_u16(subtable, start_codes + 2 * segment)? {
+        return Ok(0);
+    }
+
+    let start = read_u16(subtable, start_codes + 2 * segment)?;
+    let id_delta = read_u16(subtable, id_deltas + 2 * segment)?;
+    let id_range_offset_pos = id_range_offsets + 2 * segment;
+    let id_range_offset = read_u16(subtable, id_range_offset_pos)? as usize;
+    if id_range_offset == 0 {
+        Ok(c.wrapping_add(id_delta))
+    } else {
+        let glyph_id = read_u16(
+            subtable,
+            id_range_offset_pos + id_range_offset + 2 * (c - start) as usize,
+        )?;
+        Ok(if glyph_id == 0 {
+            0
+        } else {
+            glyph_id.wrapping_add(id_delta)
+        })
+    }
+}
+
+/// Segmented coverage, for all of Unicode.
+fn lookup_format_12(subtable: &[u8], c: u32) -> anyhow::Result<u16> {
+    let num_groups = read_u32(subtable, 12)? as usize;
+    let (mut low, mut high) = (0, num_groups);
+    while low < high {
+        let mid = (low + high) / 2;
+        let group = 16 + 12 * mid;
+        let start_char = read_u32(subtable, group)?;
+        let end_char = read_u32(subtable, group + 4)?;
+        if c < start_char {
+            high = mid;
+        } else if c > end_char {
+            low = mid + 1;
+        } else {
+            let start_glyph_id = read_u32(subtable, group + 8)?;
+            return Ok(u16::try_from(start_glyph_id + (c - start_char)).unwrap_or(0));
+        }
+    }
+    Ok(0)
+}
+
+/// A cmap with a single format 12 subtable.
+fn write_cmap(char_map: &BTreeMap<char, u16>) -> Vec<u8> {
+    // (start char, end char, start glyph id):
+    let mut groups: Vec<(u32, u32, u32)> = vec![];
+    for (&c, &glyph_id) in char_map {
+        let (c, glyph_id) = (c as u32, glyph_id as u32);
+        if let Some((start_char, end_char, start_glyph_id)) = groups.last_mut() {
+            if c == *end_char + 1 && glyph_id == *start_glyph_id + (c - *start_char) {
+                *end_char = c;
+                continue;
+            }
+        }
+        groups.push((c, c, glyph_id));
+    }
+
+    let mut cmap = vec![];
+    cmap.extend_from_slice(&0_u16.to_be_bytes()); // version
+    cmap.extend_from_slice(&1_u16.to_be_bytes()); // number of subtables
+    cmap.extend_from_slice(&3_u16.to_be_bytes()); // platform: Windows
+    cmap.extend_from_slice(&10_u16.to_be_bytes()); // encoding: Unicode full repertoire
+    cmap.extend_from_slice(&12_u32.to_be_bytes()); // offset
+    cmap.extend_from_slice(&12_u16.to_be_bytes()); // format
+    cmap.extend_from_slice(&0_u16.to_be_bytes()); // reserved
+    cmap.extend_from_slice(&(16 + 12 * groups.len() as u32).to_be_bytes()); // length
+    cmap.extend_from_slice(&0_u32.to_be_bytes()); // language
+    cmap.extend_from_slice(&(groups.len() as u32).to_be_bytes());
+    for (start_char, end_char, start_glyph_id) in groups {
+        cmap.extend_from_slice(&start_char.to_be_bytes());
+        cmap.extend_from_slice(&end_char.to_be_bytes());
+        cmap.extend_from_slice(&start_glyph_id.to_be_bytes());
+    }
+    cmap
+}
+
+fn write_font(sfnt_version: u32, mut tables: Vec<([u8; 4], Vec<u8>)>) -> Vec<u8> {
+    tables.sort_by_key(|(tag, _)| *tag);
+    let num_tables = tables.len() as u16;
+    let entry_selector = 15 - num_tables.leading_zeros() as u16;
+    let search_range = 16 << entry_selector;
+
+    let mut font = vec![];
+    font.extend_from_slice(&sfnt_version.to_be_bytes());
+    font.extend_from_slice(&num_tables.to_be_bytes());
+    font.extend_from_slice(&search_range.to_be_bytes());
+    font.extend_from_slice(&entry_selector.to_be_bytes());
+    font.extend_from_slice(&(16 * num_tables - search_range).to_be_bytes());
+
+    let mut offset = 12 + 16 * tables.len();
+    let mut head_offset = None;
+    for (tag, data) in &tables {
+        if tag == b"head" {
+            head_offset = Some(offset);
+        }
+        font.extend_from_slice(tag);
+        font.extend_from_slice(&checksum(data).to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += (data.len() + 3) / 4 * 4;
+    }
+    for (_, data) in &tables {
+        font.extend_from_slice(data);
+        pad_to_4(&mut font);
+    }
+
+    if let Some(head_offset) = head_offset {
+        let check_sum_adjustment = 0xB1B0_AFBA_u32.wrapping_sub(checksum(&font));
+        font[head_offset + 8..head_offset + 12]
+            .copy_from_slice(&check_sum_adjustment.to_be_bytes());
+    }
+    font
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0_u32, |sum, chunk| {
+        let mut word = [0_u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+fn pad_to_4(data: &mut Vec<u8>) {
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> anyhow::Result<u16> {
+    let bytes = data
+        .get(offset..offset + 2)
+        .context("unexpected end of font data")?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> anyhow::Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .context("unexpected end of font data")?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+#[test]
+fn test_subset_font_definitions() {
+    let definitions = FontDefinitions::default();
+    let mut chars = base_chars();
+    chars.insert('ä');
+    let subset = subset_font_definitions(&definitions, &chars);
+
+    let size = |definitions: &FontDefinitions| -> usize {
+        definitions
+            .font_data
+            .values()
+            .map(|data| data.font.len())
+            .sum()
+    };
+    assert!(size(&subset) < size(&definitions) / 2);
+
+    let fonts = egui::epaint::text::Fonts::new(1.0, subset);
+    let characters = fonts[egui::TextStyle::Body].characters();
+    assert!(characters.contains(&'A'));
+    assert!(characters.contains(&'ä'));
+    assert!(!characters.contains(&'ö'));
+}
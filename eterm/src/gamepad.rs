@@ -0,0 +1,214 @@
+//! Gamepad input forwarded from the viewer to the server.
+//!
+//! The viewer reads its local gamepads and sends [`GamepadEvent`]:s to the server,
+//! which accumulates them into a [`GamepadState`] per gamepad and client.
+//! Read them from within [`crate::Server::show`] using [`gamepads`].
+
+use std::collections::BTreeSet;
+
+/// Identifies a gamepad connected to a viewer. Only unique per client.
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub struct GamepadId(pub u32);
+
+/// A button on a gamepad, using the layout of an Xbox controller.
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+/// An analog axis on a gamepad.
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftZ,
+    RightZ,
+}
+
+/// Something happened to a gamepad on the viewer side.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum GamepadEvent {
+    Connected {
+        id: GamepadId,
+        name: String,
+    },
+    Disconnected {
+        id: GamepadId,
+    },
+    Button {
+        id: GamepadId,
+        button: GamepadButton,
+        pressed: bool,
+    },
+    /// The axis is in the range `[-1, 1]`.
+    Axis {
+        id: GamepadId,
+        axis: GamepadAxis,
+        value: f32,
+    },
+}
+
+/// The current state of one gamepad.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GamepadState {
+    pub name: String,
+    /// Buttons currently held down.
+    pub down: BTreeSet<GamepadButton>,
+    /// Buttons pressed since last frame.
+    pub pressed: BTreeSet<GamepadButton>,
+    /// Latest value of each axis, in the range `[-1, 1]`.
+    pub axes: Vec<(GamepadAxis, f32)>,
+}
+
+impl GamepadState {
+    pub fn is_down(&self, button: GamepadButton) -> bool {
+        self.down.contains(&button)
+    }
+
+    /// Was the button pressed since the last frame?
+    pub fn was_pressed(&self, button: GamepadButton) -> bool {
+        self.pressed.contains(&button)
+    }
+
+    /// Zero if unknown.
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes
+            .iter()
+            .find(|(a, _)| *a == axis)
+            .map_or(0.0, |(_, value)| *value)
+    }
+
+    fn set_axis(&mut self, axis: GamepadAxis, value: f32) {
+        match self.axes.iter_mut().find(|(a, _)| *a == axis) {
+            Some((_, existing)) => *existing = value,
+            None => self.axes.push((axis, value)),
+        }
+    }
+}
+
+/// All gamepads of one client.
+pub type Gamepads = std::collections::BTreeMap<GamepadId, GamepadState>;
+
+pub(crate) fn apply_event(gamepads: &mut Gamepads, event: GamepadEvent) {
+    match event {
+        GamepadEvent::Connected { id, name } => {
+            gamepads.entry(id).or_default().name = name;
+        }
+        GamepadEvent::Disconnected { id } => {
+            gamepads.remove(&id);
+        }
+        GamepadEvent::Button {
+            id,
+            button,
+            pressed,
+        } => {
+            let state = gamepads.entry(id).or_default();
+            if pressed {
+                state.down.insert(button);
+                state.pressed.insert(button);
+            } else {
+                state.down.remove(&button);
+            }
+        }
+        GamepadEvent::Axis { id, axis, value } => {
+            gamepads.entry(id).or_default().set_axis(axis, value);
+        }
+    }
+}
+
+/// Clear the per-frame state (e.g. [`GamepadState::pressed`]).
+pub(crate) fn end_frame(gamepads: &mut Gamepads) {
+    for state in gamepads.values_mut() {
+        state.pressed.clear();
+    }
+}
+
+#[test]
+fn test_apply_event() {
+    let id = GamepadId(7);
+    let mut gamepads = Gamepads::default();
+
+    apply_event(
+        &mut gamepads,
+        GamepadEvent::Connected {
+            id,
+            name: "Xbox".to_owned(),
+        },
+    );
+    assert_eq!(gamepads[&id].name, "Xbox");
+
+    let button = |pressed| GamepadEvent::Button {
+        id,
+        button: GamepadButton::South,
+        pressed,
+    };
+    apply_event(&mut gamepads, button(true));
+    apply_event(&mut gamepads, button(false));
+    assert!(!gamepads[&id].is_down(GamepadButton::South));
+    assert!(
+        gamepads[&id].was_pressed(GamepadButton::South),
+        "a press and release within one frame should still count"
+    );
+    end_frame(&mut gamepads);
+    assert!(!gamepads[&id].was_pressed(GamepadButton::South));
+
+    let axis = |value| GamepadEvent::Axis {
+        id,
+        axis: GamepadAxis::LeftStickX,
+        value,
+    };
+    apply_event(&mut gamepads, axis(0.5));
+    apply_event(&mut gamepads, axis(-0.25));
+    assert_eq!(gamepads[&id].axis(GamepadAxis::LeftStickX), -0.25);
+    assert_eq!(gamepads[&id].axes.len(), 1);
+    assert_eq!(gamepads[&id].axis(GamepadAxis::RightStickY), 0.0);
+
+    apply_event(&mut gamepads, GamepadEvent::Disconnected { id });
+    assert!(gamepads.is_empty());
+}
+
+fn gamepads_id() -> egui::Id {
+    egui::Id::new("eterm_gamepads")
+}
+
+pub(crate) fn set_gamepads(egui_ctx: &egui::CtxRef, gamepads: &Gamepads) {
+    egui_ctx
+        .memory()
+        .data
+        .insert_temp(gamepads_id(), gamepads.clone());
+}
+
+/// The gamepads of the client currently being shown.
+///
+/// Call this from within the closure given to [`crate::Server::show`].
+pub fn gamepads(egui_ctx: &egui::CtxRef) -> Gamepads {
+    egui_ctx
+        .memory()
+        .data
+        .get_temp(gamepads_id())
+        .unwrap_or_default()
+}
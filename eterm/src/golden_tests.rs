@@ -0,0 +1,303 @@
+//! Golden packets, checked into `tests/golden/`, that the current code must be able to decode.
+//!
+//! If one of these tests fail you have changed the wire format, which breaks old viewers.
+//! If that was intentional, bump [`crate::wire::PROTOCOL_HEADER`] and re-generate the packets with:
+//!
+//! ``` sh
+//! ETERM_BLESS=1 cargo test -p eterm golden
+//! ```
+//!
+//! Each file starts with the [`crate::wire::PROTOCOL_HEADER`] it was generated with,
+//! and re-generating a packet that has changed fails unless the header has been bumped.
+
+use egui::{
+    epaint::{
+        text::{LayoutJob, LayoutSection, TextFormat},
+        CircleShape, PathShape, RectShape,
+    },
+    pos2, Color32, Rect, Stroke, TextureId,
+};
+
+use crate::{
+    codec::Compression,
+    gamepad::{GamepadAxis, GamepadButton, GamepadEvent, GamepadId},
+    net_shape::{ClippedNetShape, NetMesh, NetShape, NetTextShape, NetWhiteMesh},
+    wire::PROTOCOL_HEADER,
+    ClientToServerMessage, ServerToClientMessage,
+};
+
+fn golden_path(name: &str) -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.bin", name))
+}
+
+fn bincode<M: serde::Serialize>(message: &M) -> Vec<u8> {
+    use bincode::Options as _;
+    bincode::options().serialize(message).unwrap()
+}
+
+/// `expected` must encode to the same bytes as the golden packet,
+/// and the golden packet must decode to something that encodes to the same bytes.
+fn check_golden<M: serde::Serialize + serde::de::DeserializeOwned>(name: &str, expected: &M) {
+    let path = golden_path(name);
+    let golden = std::fs::read(&path);
+
+    if std::env::var("ETERM_BLESS").is_ok() {
+        if let Ok(golden) = &golden {
+            if golden.starts_with(&PROTOCOL_HEADER) {
+                let packet = &golden[PROTOCOL_HEADER.len()..];
+                assert_eq!(
+                    bincode(expected),
+                    crate::codec::decompress(packet, usize::MAX).unwrap(),
+                    "{}: the encoding has changed. Bump PROTOCOL_HEADER before re-generating",
+                    name
+                );
+            }
+        }
+        let packet = crate::encode_message(
+            expected,
+            crate::codec::Compression::Zstd,
+            crate::codec::DEFAULT_LEVEL,
+        )
+        .unwrap();
+        std::fs::write(&path, [&PROTOCOL_HEADER[..], &packet[..]].concat()).unwrap();
+        return;
+    }
+
+    let golden = golden.unwrap_or_else(|err| panic!("{:?}: {}", path, err));
+    assert!(
+        golden.starts_with(&PROTOCOL_HEADER),
+        "{}: made with protocol {:?}, but we are at {:?}. Re-generate it",
+        name,
+        &golden[..PROTOCOL_HEADER.len().min(golden.len())],
+        PROTOCOL_HEADER
+    );
+    let packet = &golden[PROTOCOL_HEADER.len()..];
+    let golden_bincode = crate::codec::decompress(packet, usize::MAX).unwrap();
+
+    assert_eq!(
+        bincode(expected),
+        golden_bincode,
+        "{}: the encoding has changed",
+        name
+    );
+
+    let decoded: M = crate::wire::decode_message(packet, &Default::default())
+        .unwrap_or_else(|err| panic!("{}: failed to decode: {:#}", name, err));
+    assert_eq!(
+        bincode(&decoded),
+        golden_bincode,
+        "{}: decoding is lossy",
+        name
+    );
+}
+
+#[test]
+fn golden_client_to_server() {
+    check_golden(
+        "client_hello",
+        &ClientToServerMessage::Hello {
+            compressions: vec![Compression::Zstd, Compression::None],
+            resumption_token: Some(vec![1, 2, 3]),
+            preferences: crate::ClientPreferences {
+                dark_mode: Some(true),
+                locale: Some("sv-SE".to_owned()),
+            },
+        },
+    );
+    check_golden(
+        "client_input",
+        &ClientToServerMessage::Input {
+            raw_input: egui::RawInput {
+                scroll_delta: egui::vec2(0.0, -20.0),
+                screen_rect: Some(Rect::from_min_max(pos2(0.0, 0.0), pos2(800.0, 600.0))),
+                pixels_per_point: Some(2.0),
+                time: Some(3.5),
+                predicted_dt: 0.25,
+                modifiers: egui::Modifiers {
+                    ctrl: true,
+                    command: true,
+                    ..Default::default()
+                },
+                events: vec![egui::Event::Copy, egui::Event::Cut],
+                ..Default::default()
+            },
+            client_time: 12.5,
+        },
+    );
+    check_golden(
+        "client_goodbye",
+        &ClientToServerMessage::Goodbye {
+            reason: crate::GoodbyeReason::UserQuit,
+        },
+    );
+    check_golden(
+        "client_ping",
+        &ClientToServerMessage::Ping { client_time: 12.5 },
+    );
+    check_golden(
+        "client_gamepad",
+        &ClientToServerMessage::Gamepad {
+            events: vec![
+                GamepadEvent::Button {
+                    id: GamepadId(1),
+                    button: GamepadButton::East,
+                    pressed: true,
+                },
+                GamepadEvent::Axis {
+                    id: GamepadId(1),
+                    axis: GamepadAxis::LeftStickY,
+                    value: 0.5,
+                },
+            ],
+        },
+    );
+}
+
+#[test]
+fn golden_server_to_client() {
+    check_golden(
+        "server_hello",
+        &ServerToClientMessage::Hello {
+            compression: Compression::Zstd,
+            motd: "Welcome".to_owned(),
+            resumption_token: vec![4, 5],
+            app_info: crate::AppInfo {
+                name: "Game".to_owned(),
+                version: "1.2.0".to_owned(),
+                icon: None,
+            },
+        },
+    );
+    check_golden(
+        "server_frame_shapes",
+        &ServerToClientMessage::Frame {
+            frame_index: 2,
+            output: Default::default(),
+            clipped_net_shapes: every_net_shape(),
+            client_time: Some(12.5),
+            server_time: 1000.25,
+            server_frame_time: Some(0.5),
+        },
+    );
+    check_golden(
+        "server_pong",
+        &ServerToClientMessage::Pong {
+            client_time: 12.5,
+            server_time: 1000.25,
+        },
+    );
+    check_golden(
+        "server_frame_empty",
+        &ServerToClientMessage::Frame {
+            frame_index: 1,
+            output: Default::default(),
+            clipped_net_shapes: vec![],
+            client_time: None,
+            server_time: 0.0,
+            server_frame_time: None,
+        },
+    );
+    check_golden(
+        "server_file_start",
+        &ServerToClientMessage::FileStart {
+            transfer_id: 7,
+            file_name: "log.txt".to_owned(),
+            size: 300,
+        },
+    );
+    check_golden(
+        "server_file_chunk",
+        &ServerToClientMessage::FileChunk {
+            transfer_id: 7,
+            bytes: vec![1, 2, 3],
+        },
+    );
+    check_golden(
+        "server_notification",
+        &ServerToClientMessage::Notification(crate::notification::Notification {
+            title: "Done".to_owned(),
+            body: String::new(),
+            sound: true,
+        }),
+    );
+}
+
+/// One of each [`NetShape`] variant.
+fn every_net_shape() -> Vec<ClippedNetShape> {
+    let red = Color32::from_rgb(255, 0, 0);
+    let blue = Color32::from_rgb(0, 0, 255);
+    let gray = Color32::from_rgb(160, 160, 160);
+    let stroke = Stroke::new(1.0, blue);
+    let triangle = vec![pos2(0.0, 0.0), pos2(10.0, 0.0), pos2(0.0, 10.0)];
+
+    let shapes = vec![
+        NetShape::Circle(CircleShape {
+            center: pos2(10.0, 20.0),
+            radius: 5.0,
+            fill: red,
+            stroke,
+        }),
+        NetShape::LineSegment {
+            points: [pos2(0.0, 0.0), pos2(10.0, 10.0)],
+            stroke,
+        },
+        NetShape::Path(PathShape {
+            points: vec![pos2(0.0, 0.0), pos2(10.0, 0.0), pos2(5.0, 8.0)],
+            closed: true,
+            fill: red,
+            stroke,
+        }),
+        NetShape::Rect(RectShape {
+            rect: Rect::from_min_max(pos2(20.0, 20.0), pos2(40.0, 30.0)),
+            corner_radius: 2.0,
+            fill: red,
+            stroke,
+        }),
+        NetShape::Text(NetTextShape {
+            pos: pos2(50.0, 10.0),
+            job: LayoutJob {
+                text: "Hi".to_owned(),
+                sections: vec![LayoutSection {
+                    leading_space: 0.0,
+                    byte_range: 0..2,
+                    format: TextFormat {
+                        style: egui::TextStyle::Body,
+                        color: gray,
+                        background: Color32::TRANSPARENT,
+                        italics: false,
+                        underline: Stroke::default(),
+                        strikethrough: Stroke::default(),
+                        valign: egui::Align::BOTTOM,
+                    },
+                }],
+                wrap_width: f32::INFINITY,
+                first_row_min_height: 0.0,
+                break_on_newline: true,
+                halign: egui::Align::LEFT,
+                justify: false,
+            },
+            underline: Stroke::default(),
+            override_text_color: Some(red),
+            angle: 0.5,
+        }),
+        NetShape::Mesh(NetMesh {
+            texture_id: TextureId::User(7),
+            indices: vec![0, 1, 2],
+            pos: triangle.clone(),
+            uv: vec![pos2(0.0, 0.0), pos2(1.0, 0.0), pos2(0.0, 1.0)],
+            color: vec![red, blue, gray],
+        }),
+        NetShape::WhiteMesh(NetWhiteMesh {
+            indices: vec![0, 1, 2],
+            pos: triangle,
+            color: vec![red, blue, gray],
+        }),
+    ];
+    let clip_rect = Rect::from_min_max(pos2(0.0, 0.0), pos2(100.0, 50.0));
+    shapes
+        .into_iter()
+        .map(|shape| ClippedNetShape(clip_rect, shape))
+        .collect()
+}
@@ -70,19 +70,73 @@
 #![allow(clippy::float_cmp)]
 #![allow(clippy::manual_range_contains)]
 
+/// Like [`tracing::info_span!`], but only if the `tracing_spans` feature is enabled.
+///
+/// Use it to add spans to things that can be slow, e.g. running the ui or sending a frame.
+macro_rules! profile_span {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing_spans")]
+        let span = tracing::info_span!($($arg)*);
+        #[cfg(not(feature = "tracing_spans"))]
+        let span = tracing::Span::none();
+        span
+    }};
+}
+
+pub mod audit;
 mod client;
+mod clock;
+pub mod codec;
+pub mod download;
+mod font_fallback;
+mod font_subset;
+pub mod gamepad;
+#[cfg(all(test, feature = "zstd"))]
+mod golden_tests;
+mod mjpeg;
 pub mod net_shape;
+pub mod net_sim;
+pub mod notice;
+pub mod notification;
+mod output;
+pub mod packet_dump;
+mod polling_client;
+mod presence;
+pub mod preview;
+pub mod raster;
+mod rate_limit;
+pub mod recolor;
+pub mod recording;
+pub mod remote_log;
+mod remote_view;
+mod resume;
+pub mod role;
+#[cfg(test)]
+mod schema_tests;
+mod screen_transform;
+mod sequence;
 mod server;
-
-pub use client::Client;
-pub use server::{ClientId, Server};
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+#[cfg(feature = "ssh")]
+mod ssh;
+pub mod stdio;
+mod test_client;
+#[cfg(feature = "upnp")]
+mod upnp;
+pub mod visible_text;
+pub mod wire;
+
+pub use client::{Client, DisconnectReason, StatsSamples};
+pub use polling_client::{ClientEvent, PollingClient};
+pub use remote_view::RemoteView;
+pub use screen_transform::ScreenTransform;
+pub use server::{ClientId, ClientInfo, ClientStats, Profile, Server, ServerEvent};
+pub use test_client::TestClient;
 
 use std::sync::Arc;
 
-/// All TCP packets are prefixed with this.
-///
-/// b"eterm", major, minor, patch
-pub(crate) const PROTOCOL_HEADER: [u8; 8] = [b'e', b't', b'e', b'r', b'm', 0, 0, 1];
+use wire::{encode_message, PROTOCOL_HEADER};
 
 #[test]
 fn test_version() {
@@ -94,6 +148,30 @@ fn test_version() {
     );
 }
 
+/// The other side speaks another version of the eterm protocol.
+#[derive(Debug)]
+pub(crate) struct VersionMismatch {
+    /// major, minor, patch
+    pub theirs: [u8; 3],
+}
+
+impl std::fmt::Display for VersionMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "This side uses eterm {}.{}.{}, the other side is on {}.{}.{}",
+            PROTOCOL_HEADER[5],
+            PROTOCOL_HEADER[6],
+            PROTOCOL_HEADER[7],
+            self.theirs[0],
+            self.theirs[1],
+            self.theirs[2],
+        )
+    }
+}
+
+impl std::error::Error for VersionMismatch {}
+
 pub type Packet = Arc<[u8]>;
 
 #[derive(Default)]
@@ -107,16 +185,145 @@ pub struct EguiFrame {
 pub enum ClientToServerMessage {
     Input {
         raw_input: egui::RawInput,
-        /// Seconds since epoch. Used to measure latency.
+        /// Client clock, in seconds. Used to measure latency.
         client_time: f64,
     },
-    Goodbye,
+    /// Sent regularly to estimate the offset between the client and server clocks.
+    /// The server answers with [`ServerToClientMessage::Pong`].
+    Ping {
+        /// Client clock, in seconds.
+        client_time: f64,
+    },
+    /// Gamepad input from the viewer.
+    Gamepad { events: Vec<gamepad::GamepadEvent> },
+    /// The client is about to disconnect.
+    Goodbye { reason: GoodbyeReason },
+    /// Sent first, uncompressed. The server answers with [`ServerToClientMessage::Hello`].
+    Hello {
+        /// What compressions the client can decode, most preferred first.
+        compressions: Vec<codec::Compression>,
+        /// From the last [`ServerToClientMessage::Hello`], if we are reconnecting.
+        resumption_token: Option<Vec<u8>>,
+        preferences: ClientPreferences,
+    },
+    /// The viewer window was minimized (`false`) or restored (`true`).
+    /// The server sends no frames while the viewer is hidden.
+    Visibility { visible: bool },
+    /// The viewer changed its [`ClientPreferences`] after connecting.
+    Preferences(ClientPreferences),
+    /// The client has received this many packets on this connection.
+    /// Optional, but lets the server know how many bytes are in flight.
+    Ack { num_received: u64 },
+    /// The viewer presents at `refresh_rate` Hz. A frame sent at `send_phase` (server clock, in seconds),
+    /// or any multiple of `1 / refresh_rate` from it, arrives just before a vsync of the viewer.
+    /// Sent regularly by viewers that call [`Client::on_vsync`].
+    DisplayTiming { refresh_rate: f32, send_phase: f64 },
+    /// The frames have text the client's fonts have no glyphs for, so maybe we missed
+    /// a [`ServerToClientMessage::Fonts`]. The server answers by sending its fonts again.
+    RequestFonts,
+}
+
+/// What the viewer would like the served ui to look like.
+///
+/// Set with [`Client::set_preferences`], read with [`Server::client_preferences`].
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ClientPreferences {
+    /// Does the viewer prefer a dark theme (`true`) or a light theme (`false`)?
+    pub dark_mode: Option<bool>,
+    /// The language and region of the viewer, e.g. `"sv-SE"`, so the ui can be localized.
+    /// [`Client`] fills this in from the environment (`LC_ALL`, `LC_MESSAGES` or `LANG`).
+    pub locale: Option<String>,
+}
+
+/// Describes the served app, so viewers can tell several servers apart,
+/// e.g. in their window title.
+///
+/// Set with [`Server::set_app_info`], read with [`Client::app_info`].
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct AppInfo {
+    /// E.g. `"MyGame dev server"`. Can be empty.
+    pub name: String,
+    /// E.g. `"1.2.0"`. Can be empty.
+    pub version: String,
+    pub icon: Option<AppIcon>,
+}
+
+impl AppInfo {
+    /// E.g. `"eterm – MyGame dev server 1.2.0"`.
+    pub fn window_title(&self) -> String {
+        match (self.name.is_empty(), self.version.is_empty()) {
+            (true, _) => "eterm viewer".to_owned(),
+            (false, true) => format!("eterm – {}", self.name),
+            (false, false) => format!("eterm – {} {}", self.name, self.version),
+        }
+    }
+}
+
+/// How the server encodes the frames it sends to a client, averaged over the last few seconds.
+///
+/// See [`ServerToClientMessage::Telemetry`] and [`Client::telemetry`].
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Telemetry {
+    /// Time to serialize and compress a frame, in seconds.
+    pub encode_time: f32,
+    /// Size of a frame before compression, in bytes.
+    pub uncompressed_size: f32,
+    /// Size of a frame after compression, in bytes.
+    pub compressed_size: f32,
+    pub compression: codec::Compression,
+    pub compression_level: i32,
+}
+
+/// The key colors of the served ui. See [`Client::theme`].
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Theme {
+    pub dark_mode: bool,
+    /// Background of panels and windows.
+    pub panel_fill: egui::Color32,
+    pub text_color: egui::Color32,
+    /// Background of selected things.
+    pub selection_color: egui::Color32,
+}
+
+impl Theme {
+    pub fn from_visuals(visuals: &egui::Visuals) -> Self {
+        Self {
+            dark_mode: visuals.dark_mode,
+            panel_fill: visuals.widgets.noninteractive.bg_fill,
+            text_color: visuals.widgets.noninteractive.fg_stroke.color,
+            selection_color: visuals.selection.bg_fill,
+        }
+    }
+
+    /// The default visuals of the same mode, with these colors.
+    pub fn to_visuals(&self) -> egui::Visuals {
+        let mut visuals = if self.dark_mode {
+            egui::Visuals::dark()
+        } else {
+            egui::Visuals::light()
+        };
+        visuals.widgets.noninteractive.bg_fill = self.panel_fill;
+        visuals.widgets.noninteractive.fg_stroke.color = self.text_color;
+        visuals.selection.bg_fill = self.selection_color;
+        visuals
+    }
+}
+
+/// An icon for the viewer window, e.g. 32x32 pixels.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct AppIcon {
+    pub width: u32,
+    pub height: u32,
+    /// Unmultiplied RGBA, row by row, top to bottom.
+    pub rgba: Vec<u8>,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
 pub enum ServerToClientMessage {
-    /// Sent first to all clients so they know how to paint
-    /// the [`crate::net_shape::NetShape`]:s.
+    /// Sent when the fonts of the served ui differ from the defaults, so the client knows
+    /// how to paint the [`crate::net_shape::NetShape`]:s. Again whenever they change.
+    ///
+    /// After each [`Self::Hello`] the client uses [`egui::FontDefinitions::default`] until told otherwise.
     Fonts {
         font_definitions: egui::FontDefinitions,
     },
@@ -129,33 +336,160 @@ pub enum ServerToClientMessage {
         /// If this frame is a response to a `ClientToServerMessage::Input`.
         /// Used to measure latency.
         client_time: Option<f64>,
+        /// Server clock when the frame was sent, in seconds.
+        /// Used to measure the one-way delay.
+        server_time: f64,
+        /// How long the server spent running the ui for this frame, in seconds.
+        server_frame_time: Option<f32>,
+    },
+
+    /// Answer to [`ClientToServerMessage::Ping`].
+    Pong {
+        /// Copied from the ping.
+        client_time: f64,
+        /// Server clock when the ping was received, in seconds.
+        server_time: f64,
+    },
+
+    /// The server wants the user to save a file.
+    /// Followed by [`Self::FileChunk`]:s until `size` bytes have been sent.
+    FileStart {
+        transfer_id: u64,
+        file_name: String,
+        size: u64,
     },
-}
 
-fn encode_message<M: ?Sized + serde::Serialize>(message: &M) -> anyhow::Result<Packet> {
-    use anyhow::Context as _;
-    use bincode::Options as _;
+    /// The next part of a file started with [`Self::FileStart`].
+    FileChunk { transfer_id: u64, bytes: Vec<u8> },
+
+    /// Get the attention of the user.
+    Notification(notification::Notification),
+
+    /// Answer to [`ClientToServerMessage::Hello`].
+    /// From now on both sides compress their packets with `compression`.
+    Hello {
+        compression: codec::Compression,
+        /// Message of the day, shown once by the viewer. Can be empty.
+        motd: String,
+        /// Present this when reconnecting to get the same session back.
+        /// Empty if the server could not issue one.
+        resumption_token: Vec<u8>,
+        /// What we are looking at.
+        app_info: AppInfo,
+    },
+
+    /// The server is about to disconnect the client.
+    Goodbye { reason: GoodbyeReason },
 
-    let bincoded = bincode::options().serialize(message).context("bincode")?;
+    /// Show a banner on top of the ui, e.g. "server restarting in 30 s".
+    /// An empty `text` removes the banner.
+    Notice {
+        text: String,
+        severity: notice::Severity,
+    },
 
-    const ZSTD_LEVEL: i32 = 5;
-    let compressed =
-        zstd::encode_all(std::io::Cursor::new(&bincoded), ZSTD_LEVEL).context("zstd")?;
+    /// New records from the server log. See [`remote_log`].
+    Log { records: Vec<remote_log::LogRecord> },
+
+    /// How the server encodes our frames.
+    /// Sent periodically if enabled with [`Server::set_telemetry_interval`].
+    Telemetry(Telemetry),
+
+    /// The look of the served ui, so the viewer can match it.
+    /// Sent after [`Self::Hello`] and whenever it changes.
+    Theme(Theme),
+
+    /// The font texture of the server, sampled by the text meshes it sends.
+    /// Sent before the frames that need it, when enabled with [`Server::set_laid_out_text`].
+    FontImage {
+        version: u64,
+        width: usize,
+        height: usize,
+        /// Coverage, one byte per texel.
+        pixels: Vec<u8>,
+    },
+}
 
-    Ok(compressed.into())
+/// Why one side is hanging up, sent in the `Goodbye` messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum GoodbyeReason {
+    /// The user closed the viewer.
+    UserQuit,
+    /// The server kicked the client, e.g. with [`Server::kick`].
+    Kicked,
+    /// The server is shutting down.
+    ServerShutdown,
+    /// The client was not allowed to connect.
+    AuthFailed,
+    /// The other side sent something we couldn't understand.
+    ProtocolError,
+    /// The session lasted longer than [`Server::set_max_session_duration`] allows.
+    /// Start a new one to continue.
+    SessionExpired,
+    /// The served ui failed (panicked) for this client. See [`Server::set_catch_panics`].
+    ServerError,
 }
 
-fn decode_message<M: serde::de::DeserializeOwned>(packet: &[u8]) -> anyhow::Result<M> {
-    use anyhow::Context as _;
-    use bincode::Options as _;
+impl GoodbyeReason {
+    /// Is it worth reconnecting after this?
+    pub fn should_reconnect(self) -> bool {
+        match self {
+            Self::UserQuit | Self::ServerShutdown | Self::ProtocolError => true,
+            Self::Kicked | Self::AuthFailed | Self::SessionExpired | Self::ServerError => false,
+        }
+    }
+}
+
+impl std::fmt::Display for GoodbyeReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::UserQuit => "the user quit",
+            Self::Kicked => "kicked by the server",
+            Self::ServerShutdown => "the server shut down",
+            Self::AuthFailed => "authentication failed",
+            Self::ProtocolError => "protocol error",
+            Self::SessionExpired => "the session expired",
+            Self::ServerError => "the server failed to show the ui",
+        })
+    }
+}
 
-    let bincoded = zstd::decode_all(packet).context("zstd")?;
+/// Limits on what we accept from the other side.
+///
+/// These protect against a hostile (or buggy) peer making us allocate unbounded
+/// amounts of memory, e.g. by sending a small compression bomb.
+///
+/// None of the message types are recursive, so there is no need for a depth limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Refuse packets larger than this (before decompression).
+    pub max_packet_size: usize,
+
+    /// Stop decompressing when we reach this size.
+    ///
+    /// This also bounds the number of elements in any list, since each element is at least one byte,
+    /// and length prefixes that claim more than what is left are rejected before allocating.
+    pub max_decompressed_size: usize,
+}
 
-    let message = bincode::options()
-        .deserialize(&bincoded)
-        .context("bincode")?;
+impl DecodeLimits {
+    /// Used by the client for frames, fonts etc.
+    pub const SERVER_TO_CLIENT: Self = Self {
+        max_packet_size: 32_000_000,
+        max_decompressed_size: 64_000_000,
+    };
+
+    /// Used by the server for input from clients, which is small.
+    pub const CLIENT_TO_SERVER: Self = Self {
+        max_packet_size: 1_000_000,
+        max_decompressed_size: 8_000_000,
+    };
+}
 
-    Ok(message)
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self::SERVER_TO_CLIENT
+    }
 }
 
 /// Show full cause chain in a single line
@@ -170,20 +504,74 @@ pub(crate) fn error_display_chain(error: &dyn std::error::Error) -> String {
 
 // ----------------------------------------------------------------------------
 
-/// Wrapper around a non-blocking [`std::net::TcpStream`].
+/// Wrapper around a non-blocking [`mio::net::TcpStream`].
 pub(crate) struct TcpEndpoint {
-    tcp_stream: std::net::TcpStream,
+    tcp_stream: mio::net::TcpStream,
+    /// What we compress outgoing packets with. Negotiated when connecting.
+    compression: codec::Compression,
+    /// Packets the socket wasn't ready to take yet. Written by [`Self::flush`].
+    outgoing: Vec<u8>,
+    /// Counts the packets sent and received.
+    sequencer: sequence::Sequencer,
+    /// Writes every packet to disk, if turned on.
+    packet_dumper: Option<Arc<packet_dump::PacketDumper>>,
 }
 
 impl TcpEndpoint {
+    fn new(tcp_stream: mio::net::TcpStream) -> Self {
+        Self {
+            tcp_stream,
+            compression: codec::Compression::None,
+            outgoing: Default::default(),
+            sequencer: Default::default(),
+            packet_dumper: None,
+        }
+    }
+
+    /// If the other side is a web browser (or some other HTTP client),
+    /// return the requested path.
+    ///
+    /// Returns `None` if we don't know yet.
+    fn peek_http_request_path(&self) -> Option<String> {
+        let mut start = [0_u8; 1024];
+        let num_bytes = self.tcp_stream.peek(&mut start).ok()?;
+        let start = &start[..num_bytes];
+        if !start.starts_with(b"GET ") {
+            return None;
+        }
+        let request_line = String::from_utf8_lossy(start);
+        let path = request_line.split(' ').nth(1).unwrap_or("/");
+        Some(path.to_owned())
+    }
+
+    /// Read and throw away whatever has been received so far.
+    fn discard_incoming(&mut self) {
+        use std::io::Read as _;
+        let mut buffer = [0_u8; 1024];
+        while matches!(self.tcp_stream.read(&mut buffer), Ok(n) if n > 0) {}
+    }
+
+    /// Respond to an HTTP request with a static page.
+    fn send_http_page(&mut self, html: &str) -> anyhow::Result<()> {
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            html.len(),
+            html
+        );
+        self.send_raw(response.as_bytes())
+    }
+
     /// returns immediately if there is nothing to read
-    fn try_receive_packet(&mut self) -> anyhow::Result<Option<Packet>> {
+    fn try_receive_packet(&mut self, limits: &DecodeLimits) -> anyhow::Result<Option<Packet>> {
         use std::io::Read as _;
 
         // All messages are length-prefixed by PROTOCOL_HEADER and u32 (LE).
-        let mut header = [0_u8; 12];
+        let mut header = [0_u8; wire::FRAME_HEADER_LEN];
         match self.tcp_stream.peek(&mut header) {
-            Ok(12) => {}
+            Ok(wire::FRAME_HEADER_LEN) => {}
+            Ok(0) => {
+                anyhow::bail!("Connection closed");
+            }
             Ok(_) => {
                 return Ok(None);
             }
@@ -196,29 +584,7 @@ impl TcpEndpoint {
             }
         }
 
-        let protocol = &header[..PROTOCOL_HEADER.len()];
-        let length = &header[PROTOCOL_HEADER.len()..];
-        let length = u32::from_le_bytes([length[0], length[1], length[2], length[3]]) as usize;
-
-        if protocol[0..5] != PROTOCOL_HEADER[0..5] {
-            anyhow::bail!("The other side is not eterm");
-        }
-
-        if protocol != PROTOCOL_HEADER {
-            anyhow::bail!(
-                "This side uses eterm {}.{}.{}, the other side is on {}.{}.{}",
-                PROTOCOL_HEADER[5],
-                PROTOCOL_HEADER[6],
-                PROTOCOL_HEADER[7],
-                protocol[5],
-                protocol[6],
-                protocol[7],
-            );
-        }
-
-        if length > 32_000_000 {
-            anyhow::bail!("Refusing packet of {:.1} MB", length as f32 * 1e-6);
-        }
+        let length = wire::parse_frame_header(&header, limits)?;
 
         // See if we have the whole packet yet:
         let mut length_and_packet = vec![0_u8; header.len() + length];
@@ -241,51 +607,75 @@ impl TcpEndpoint {
         self.tcp_stream.read_exact(&mut length_and_packet)?;
 
         let packet = &length_and_packet[header.len()..];
+        self.sequencer.on_receive();
+        if let Some(packet_dumper) = &self.packet_dumper {
+            packet_dumper.dump_received(packet);
+        }
 
         Ok(Some(packet.into()))
     }
 
     /// returns immediately if there is nothing to read
-    fn try_receive_message<M: serde::de::DeserializeOwned>(&mut self) -> anyhow::Result<Option<M>> {
+    fn try_receive_message<M: serde::de::DeserializeOwned>(
+        &mut self,
+        limits: &DecodeLimits,
+    ) -> anyhow::Result<Option<M>> {
         use anyhow::Context as _;
-        match self.try_receive_packet().context("receive")? {
+        match self.try_receive_packet(limits).context("receive")? {
             Some(packet) => {
-                let message = crate::decode_message(&packet).context("decode")?;
+                let _span = profile_span!("decode", bytes = packet.len()).entered();
+                let message = wire::decode_message(&packet, limits).context("decode")?;
                 Ok(Some(message))
             }
             None => Ok(None),
         }
     }
 
+    /// Queue the packet and write as much of it as the socket will take right now.
     fn send_packet(&mut self, packet: &[u8]) -> anyhow::Result<()> {
-        let length = packet.len() as u32;
-        let length = length.to_le_bytes();
-        self.write_all_with_retry(&PROTOCOL_HEADER)?;
-        self.write_all_with_retry(&length)?;
-        self.write_all_with_retry(packet)?;
-        Ok(())
+        if let Some(packet_dumper) = &self.packet_dumper {
+            packet_dumper.dump_sent(packet);
+        }
+        wire::frame_packet(packet, &mut self.outgoing);
+        self.sequencer
+            .on_send(wire::FRAME_HEADER_LEN + packet.len());
+        self.flush()
+    }
+
+    /// Are there queued packets waiting for the socket to become writable?
+    fn has_pending_output(&self) -> bool {
+        !self.outgoing.is_empty()
     }
 
-    fn write_all_with_retry(&mut self, chunk: &[u8]) -> anyhow::Result<()> {
+    /// Write queued packets until done, or until the socket would block.
+    fn flush(&mut self) -> anyhow::Result<()> {
         use std::io::Write as _;
-        loop {
-            match self.tcp_stream.write_all(chunk) {
-                Ok(()) => {
-                    return Ok(());
-                }
-                Err(err) => {
-                    if err.kind() == std::io::ErrorKind::WouldBlock {
-                        // doesn't seem to help
-                        std::thread::sleep(std::time::Duration::from_millis(5));
-                    } else {
-                        anyhow::bail!("{:?}", err);
-                    }
+        while !self.outgoing.is_empty() {
+            match self.tcp_stream.write(&self.outgoing) {
+                Ok(0) => anyhow::bail!("Connection closed"),
+                Ok(num_bytes) => {
+                    self.outgoing.drain(..num_bytes);
                 }
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => anyhow::bail!("{:?}", err),
             }
         }
+        Ok(())
+    }
+
+    /// Like [`Self::send_packet`], but without the eterm framing, e.g. for HTTP.
+    fn send_raw(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        self.outgoing.extend_from_slice(bytes);
+        self.flush()
     }
 
     fn send_message<M: serde::Serialize>(&mut self, message: &M) -> anyhow::Result<()> {
-        self.send_packet(&encode_message(message)?)
+        let packet = {
+            let _span = profile_span!("encode").entered();
+            encode_message(message, self.compression, codec::DEFAULT_LEVEL)?
+        };
+        let _span = profile_span!("send", bytes = packet.len()).entered();
+        self.send_packet(&packet)
     }
 }
@@ -0,0 +1,79 @@
+//! Serve frames as an MJPEG stream, for clients that can't speak the eterm protocol
+//! (e.g. a web browser on a phone). Requires the `mjpeg` feature.
+//!
+//! Point a browser to `http://host:port/mjpeg` to see the ui. There is no input.
+
+/// The HTTP path of the stream.
+pub(crate) const PATH: &str = "/mjpeg";
+
+const BOUNDARY: &str = "etermframe";
+
+#[cfg(feature = "mjpeg")]
+const JPEG_QUALITY: u8 = 80;
+
+/// Sent once, before the first frame.
+pub(crate) fn response_header() -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        BOUNDARY
+    )
+}
+
+/// Rasterize the shapes and encode them as one part of the multipart stream.
+#[cfg(feature = "mjpeg")]
+pub(crate) fn encode_frame(
+    egui_ctx: &egui::CtxRef,
+    clipped_shapes: Vec<egui::epaint::ClippedShape>,
+) -> anyhow::Result<Vec<u8>> {
+    use anyhow::Context as _;
+
+    let pixels_per_point = egui_ctx.pixels_per_point();
+    let screen_size = egui_ctx.input().screen_rect().size() * pixels_per_point;
+    let size = [
+        screen_size.x.round() as usize,
+        screen_size.y.round() as usize,
+    ];
+    anyhow::ensure!(
+        size[0] <= u16::MAX as usize && size[1] <= u16::MAX as usize,
+        "Screen too large for JPEG: {:?}",
+        size
+    );
+
+    let clipped_meshes = egui_ctx.tessellate(clipped_shapes);
+    let image = crate::raster::rasterize(
+        size,
+        egui::Color32::BLACK,
+        pixels_per_point,
+        &clipped_meshes,
+        &egui_ctx.font_image(),
+    );
+
+    let mut jpeg = vec![];
+    jpeg_encoder::Encoder::new(&mut jpeg, JPEG_QUALITY)
+        .encode(
+            &image.as_rgba_bytes(),
+            size[0] as u16,
+            size[1] as u16,
+            jpeg_encoder::ColorType::Rgba,
+        )
+        .context("jpeg")?;
+
+    let mut part = format!(
+        "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+        BOUNDARY,
+        jpeg.len()
+    )
+    .into_bytes();
+    part.extend_from_slice(&jpeg);
+    part.extend_from_slice(b"\r\n");
+    Ok(part)
+}
+
+#[cfg(not(feature = "mjpeg"))]
+#[allow(clippy::needless_pass_by_value)]
+pub(crate) fn encode_frame(
+    _egui_ctx: &egui::CtxRef,
+    _clipped_shapes: Vec<egui::epaint::ClippedShape>,
+) -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!("eterm was compiled without the 'mjpeg' feature")
+}
@@ -34,6 +34,49 @@ impl From<&NetMesh> for epaint::Mesh {
     }
 }
 
+/// A [`NetMesh`] that only samples the white texel of the font texture, like most ui meshes.
+///
+/// All its uvs would be [`epaint::WHITE_UV`], so they are not sent.
+#[derive(Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct NetWhiteMesh {
+    pub indices: Vec<u32>,
+    pub pos: Vec<Pos2>,
+    pub color: Vec<Color32>,
+}
+
+impl NetWhiteMesh {
+    /// `None` if the mesh samples anything but the white texel.
+    pub fn try_from_mesh(mesh: &epaint::Mesh) -> Option<Self> {
+        let is_white = mesh.texture_id == TextureId::Egui
+            && mesh.vertices.iter().all(|v| v.uv == epaint::WHITE_UV);
+        if is_white {
+            Some(Self {
+                indices: mesh.indices.clone(),
+                pos: mesh.vertices.iter().map(|v| v.pos).collect(),
+                color: mesh.vertices.iter().map(|v| v.color).collect(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl From<&NetWhiteMesh> for epaint::Mesh {
+    fn from(mesh: &NetWhiteMesh) -> epaint::Mesh {
+        epaint::Mesh {
+            texture_id: TextureId::Egui,
+            indices: mesh.indices.clone(),
+            vertices: itertools::izip!(&mesh.pos, &mesh.color)
+                .map(|(&pos, &color)| epaint::Vertex {
+                    pos,
+                    uv: epaint::WHITE_UV,
+                    color,
+                })
+                .collect(),
+        }
+    }
+}
+
 // ----------------------------------------------------------------------------
 
 /// Like [`epaint::Shape`], but optimized for transport over a network.
@@ -45,6 +88,7 @@ pub enum NetShape {
     Rect(epaint::RectShape),
     Text(NetTextShape),
     Mesh(NetMesh),
+    WhiteMesh(NetWhiteMesh),
 }
 
 /// How to draw some text on screen.
@@ -58,12 +102,17 @@ pub struct NetTextShape {
 }
 
 #[derive(Clone, PartialEq, serde::Serialize, serde::Deserialize)]
-pub struct ClippedNetShape(Rect, NetShape);
+pub struct ClippedNetShape(pub Rect, pub NetShape);
 
-pub fn to_clipped_net_shapes(in_shapes: Vec<epaint::ClippedShape>) -> Vec<ClippedNetShape> {
+/// Shapes outside of `screen_rect` (the screen of the viewer) are dropped.
+/// Use [`Rect::EVERYTHING`] to keep them all.
+pub fn to_clipped_net_shapes(
+    in_shapes: Vec<epaint::ClippedShape>,
+    screen_rect: Rect,
+) -> Vec<ClippedNetShape> {
     let mut net_shapes = vec![];
     for epaint::ClippedShape(clip_rect, shape) in in_shapes {
-        to_net_shapes(clip_rect, shape, &mut net_shapes)
+        to_net_shapes(clip_rect.intersect(screen_rect), shape, &mut net_shapes)
     }
     net_shapes
 }
@@ -130,35 +179,233 @@ fn to_net_shapes(
         }
         epaint::Shape::Mesh(mesh) => {
             if clip_rect.intersects(mesh.calc_bounds()) {
-                out_net_shapes.push(ClippedNetShape(
-                    clip_rect,
-                    NetShape::Mesh(NetMesh::from(&mesh)),
-                ));
+                let net_shape = match NetWhiteMesh::try_from_mesh(&mesh) {
+                    Some(white_mesh) => NetShape::WhiteMesh(white_mesh),
+                    None => NetShape::Mesh(NetMesh::from(&mesh)),
+                };
+                out_net_shapes.push(ClippedNetShape(clip_rect, net_shape));
+            }
+        }
+    }
+}
+
+/// What changed between two frames. See [`crate::Server::set_frame_diagnostics`].
+///
+/// Shapes are compared by their position in the list,
+/// so a shape added early in a frame makes all later shapes count as changed.
+pub(crate) struct FrameDiff {
+    pub num_shapes: usize,
+    /// Includes removed shapes.
+    pub num_changed: usize,
+    /// The distinct clip rects of the changed shapes, i.e. roughly which windows and panels changed.
+    pub changed_clip_rects: Vec<Rect>,
+}
+
+impl FrameDiff {
+    /// How many of [`Self::changed_clip_rects`] to show.
+    const MAX_SHOWN_CLIP_RECTS: usize = 5;
+
+    pub fn new(old: &[ClippedNetShape], new: &[ClippedNetShape]) -> Self {
+        let mut num_changed = old.len().saturating_sub(new.len());
+        let mut changed_clip_rects = vec![];
+        for (i, new_shape) in new.iter().enumerate() {
+            if old.get(i) != Some(new_shape) {
+                num_changed += 1;
+                if !changed_clip_rects.contains(&new_shape.0) {
+                    changed_clip_rects.push(new_shape.0);
+                }
+            }
+        }
+        Self {
+            num_shapes: new.len(),
+            num_changed,
+            changed_clip_rects,
+        }
+    }
+}
+
+impl std::fmt::Display for FrameDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{} shapes changed", self.num_changed, self.num_shapes)?;
+        for (i, clip_rect) in self
+            .changed_clip_rects
+            .iter()
+            .take(Self::MAX_SHOWN_CLIP_RECTS)
+            .enumerate()
+        {
+            let separator = if i == 0 { " in clip rects " } else { ", " };
+            write!(f, "{}{:?}", separator, clip_rect)?;
+        }
+        if self.changed_clip_rects.len() > Self::MAX_SHOWN_CLIP_RECTS {
+            write!(
+                f,
+                " and {} more",
+                self.changed_clip_rects.len() - Self::MAX_SHOWN_CLIP_RECTS
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Round all positions to whole points.
+///
+/// Small movements then often don't change anything, and what's left compresses better.
+pub(crate) fn quantize(shapes: &mut [ClippedNetShape]) {
+    for ClippedNetShape(_, shape) in shapes {
+        match shape {
+            NetShape::Circle(circle_shape) => {
+                circle_shape.center = circle_shape.center.round();
+            }
+            NetShape::LineSegment { points, .. } => {
+                for point in points {
+                    *point = point.round();
+                }
+            }
+            NetShape::Path(path_shape) => {
+                for point in &mut path_shape.points {
+                    *point = point.round();
+                }
+            }
+            NetShape::Rect(rect_shape) => {
+                rect_shape.rect =
+                    Rect::from_min_max(rect_shape.rect.min.round(), rect_shape.rect.max.round());
+            }
+            NetShape::Text(text_shape) => {
+                text_shape.pos = text_shape.pos.round();
+            }
+            NetShape::Mesh(net_mesh) => {
+                for pos in &mut net_mesh.pos {
+                    *pos = pos.round();
+                }
+            }
+            NetShape::WhiteMesh(white_mesh) => {
+                for pos in &mut white_mesh.pos {
+                    *pos = pos.round();
+                }
             }
         }
     }
 }
 
+/// Replace all text with meshes of its glyphs, so the client needs to do no text layout.
+///
+/// The meshes sample the font image of `fonts`, so the client must paint with that.
+/// See [`crate::Server::set_laid_out_text`].
+pub(crate) fn text_to_meshes(shapes: &mut [epaint::ClippedShape], fonts: &epaint::text::Fonts) {
+    let tesselator_options =
+        epaint::tessellator::TessellationOptions::from_pixels_per_point(fonts.pixels_per_point());
+    let tex_size = fonts.font_image().size();
+    for epaint::ClippedShape(clip_rect, shape) in shapes {
+        text_to_mesh(*clip_rect, shape, tesselator_options, tex_size);
+    }
+}
+
+fn text_to_mesh(
+    clip_rect: Rect,
+    shape: &mut epaint::Shape,
+    tesselator_options: epaint::tessellator::TessellationOptions,
+    tex_size: [usize; 2],
+) {
+    match shape {
+        epaint::Shape::Vec(shapes) => {
+            for shape in shapes {
+                text_to_mesh(clip_rect, shape, tesselator_options, tex_size);
+            }
+        }
+        epaint::Shape::Text(_) => {
+            let text_shape = std::mem::replace(shape, epaint::Shape::Noop);
+            let clipped_meshes = epaint::tessellator::tessellate_shapes(
+                vec![epaint::ClippedShape(clip_rect, text_shape)],
+                tesselator_options,
+                tex_size,
+            );
+            *shape = epaint::Shape::Vec(
+                clipped_meshes
+                    .into_iter()
+                    .map(|epaint::ClippedMesh(_, mesh)| epaint::Shape::Mesh(mesh))
+                    .collect(),
+            );
+        }
+        _ => {}
+    }
+}
+
+/// Roughly how many bytes of memory the shapes take.
+pub(crate) fn memory_size(shapes: &[ClippedNetShape]) -> usize {
+    use std::mem::{size_of, size_of_val};
+    let mut size = size_of_val(shapes);
+    for ClippedNetShape(_, shape) in shapes {
+        size += match shape {
+            NetShape::Circle(_) | NetShape::LineSegment { .. } | NetShape::Rect(_) => 0,
+            NetShape::Path(path_shape) => size_of_val(path_shape.points.as_slice()),
+            NetShape::Text(text_shape) => {
+                text_shape.job.text.len()
+                    + text_shape.job.sections.len() * size_of::<epaint::text::LayoutSection>()
+            }
+            NetShape::Mesh(net_mesh) => {
+                size_of_val(net_mesh.indices.as_slice())
+                    + size_of_val(net_mesh.pos.as_slice())
+                    + size_of_val(net_mesh.uv.as_slice())
+                    + size_of_val(net_mesh.color.as_slice())
+            }
+            NetShape::WhiteMesh(white_mesh) => {
+                size_of_val(white_mesh.indices.as_slice())
+                    + size_of_val(white_mesh.pos.as_slice())
+                    + size_of_val(white_mesh.color.as_slice())
+            }
+        };
+    }
+    size
+}
+
+/// A hash of the shapes, for noticing that they changed without keeping a copy of them.
+pub(crate) fn fingerprint(shapes: &[ClippedNetShape]) -> u64 {
+    use bincode::Options as _;
+    use std::hash::Hasher as _;
+
+    /// Hash the bincoded shapes as they are written, instead of allocating them all.
+    struct HashWriter(std::collections::hash_map::DefaultHasher);
+
+    impl std::io::Write for HashWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.write(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut writer = HashWriter(Default::default());
+    // Can only fail on I/O errors, and we have none:
+    bincode::options().serialize_into(&mut writer, shapes).ok();
+    writer.0.finish()
+}
+
 pub fn from_clipped_net_shapes(
     fonts: &epaint::text::Fonts,
-    in_shapes: Vec<ClippedNetShape>,
+    in_shapes: &[ClippedNetShape],
 ) -> Vec<epaint::ClippedShape> {
     in_shapes
-        .into_iter()
+        .iter()
         .map(|ClippedNetShape(clip_rect, net_shape)| {
-            epaint::ClippedShape(clip_rect, to_epaint_shape(fonts, net_shape))
+            epaint::ClippedShape(*clip_rect, to_epaint_shape(fonts, net_shape))
         })
         .collect()
 }
 
-fn to_epaint_shape(fonts: &epaint::text::Fonts, net_shape: NetShape) -> epaint::Shape {
+fn to_epaint_shape(fonts: &epaint::text::Fonts, net_shape: &NetShape) -> epaint::Shape {
     match net_shape {
-        NetShape::Circle(circle_shape) => epaint::Shape::Circle(circle_shape),
-        NetShape::LineSegment { points, stroke } => epaint::Shape::LineSegment { points, stroke },
-        NetShape::Path(path_shape) => epaint::Shape::Path(path_shape),
-        NetShape::Rect(rect_shape) => epaint::Shape::Rect(rect_shape),
+        NetShape::Circle(circle_shape) => epaint::Shape::Circle(*circle_shape),
+        NetShape::LineSegment { points, stroke } => epaint::Shape::LineSegment {
+            points: *points,
+            stroke: *stroke,
+        },
+        NetShape::Path(path_shape) => epaint::Shape::Path(path_shape.clone()),
+        NetShape::Rect(rect_shape) => epaint::Shape::Rect(*rect_shape),
         NetShape::Text(text_shape) => {
-            let galley = fonts.layout_job(text_shape.job);
+            let galley = fonts.layout_job(text_shape.job.clone());
             epaint::Shape::Text(epaint::TextShape {
                 pos: text_shape.pos,
                 galley,
@@ -167,6 +414,108 @@ fn to_epaint_shape(fonts: &epaint::text::Fonts, net_shape: NetShape) -> epaint::
                 angle: text_shape.angle,
             })
         }
-        NetShape::Mesh(net_mesh) => epaint::Shape::Mesh(epaint::Mesh::from(&net_mesh)),
+        NetShape::Mesh(net_mesh) => epaint::Shape::Mesh(epaint::Mesh::from(net_mesh)),
+        NetShape::WhiteMesh(white_mesh) => epaint::Shape::Mesh(epaint::Mesh::from(white_mesh)),
     }
 }
+
+/// A red square at `x`, for the tests.
+#[cfg(test)]
+fn shape_at(x: f32) -> epaint::ClippedShape {
+    epaint::ClippedShape(
+        Rect::EVERYTHING,
+        epaint::Shape::rect_filled(
+            Rect::from_min_size(epaint::pos2(x, 100.0), epaint::vec2(50.0, 50.0)),
+            0.0,
+            Color32::RED,
+        ),
+    )
+}
+
+#[test]
+fn test_cull_against_screen_rect() {
+    let screen_rect = Rect::from_min_max(Pos2::ZERO, epaint::pos2(800.0, 600.0));
+
+    let net_shapes = to_clipped_net_shapes(vec![shape_at(100.0), shape_at(2000.0)], screen_rect);
+    assert_eq!(net_shapes.len(), 1, "the off-screen shape should be culled");
+    assert_eq!(net_shapes[0].0, screen_rect);
+
+    let net_shapes = to_clipped_net_shapes(vec![shape_at(2000.0)], Rect::EVERYTHING);
+    assert_eq!(net_shapes.len(), 1);
+}
+
+#[test]
+fn test_frame_diff() {
+    let panel = Rect::from_min_size(Pos2::ZERO, epaint::vec2(100.0, 100.0));
+    let window = Rect::from_min_size(epaint::pos2(200.0, 0.0), epaint::vec2(100.0, 100.0));
+    let rect_at = |clip_rect: Rect, color: Color32| {
+        ClippedNetShape(
+            clip_rect,
+            NetShape::Rect(epaint::RectShape::filled(clip_rect, 0.0, color)),
+        )
+    };
+
+    let old = vec![
+        rect_at(panel, Color32::RED),
+        rect_at(window, Color32::RED),
+        rect_at(window, Color32::RED),
+    ];
+    let new = vec![
+        rect_at(panel, Color32::RED),
+        rect_at(window, Color32::GREEN),
+    ];
+
+    let diff = FrameDiff::new(&old, &new);
+    assert_eq!(diff.num_shapes, 2);
+    assert_eq!(diff.num_changed, 2, "one changed, one removed");
+    assert_eq!(diff.changed_clip_rects, vec![window]);
+    assert!(diff
+        .to_string()
+        .starts_with("2/2 shapes changed in clip rects "));
+}
+
+#[test]
+fn test_white_mesh() {
+    let mut mesh = epaint::Mesh::default();
+    mesh.add_colored_rect(
+        Rect::from_min_size(Pos2::ZERO, epaint::vec2(10.0, 10.0)),
+        Color32::RED,
+    );
+    let white_mesh = NetWhiteMesh::try_from_mesh(&mesh).unwrap();
+    assert_eq!(epaint::Mesh::from(&white_mesh), mesh);
+
+    mesh.vertices[0].uv = epaint::pos2(0.5, 0.5);
+    assert!(NetWhiteMesh::try_from_mesh(&mesh).is_none());
+}
+
+#[test]
+fn test_fingerprint() {
+    let shapes = to_clipped_net_shapes(vec![shape_at(10.0), shape_at(20.0)], Rect::EVERYTHING);
+    let moved = to_clipped_net_shapes(vec![shape_at(10.0), shape_at(21.0)], Rect::EVERYTHING);
+
+    assert_eq!(fingerprint(&shapes), fingerprint(&shapes.clone()));
+    assert_ne!(fingerprint(&shapes), fingerprint(&moved));
+    assert_ne!(fingerprint(&shapes), fingerprint(&shapes[..1]));
+    assert!(memory_size(&shapes) > memory_size(&shapes[..1]));
+}
+
+#[test]
+fn test_text_to_meshes() {
+    let fonts = epaint::text::Fonts::new(1.0, Default::default());
+    let text = epaint::Shape::text(
+        &fonts,
+        epaint::pos2(10.0, 10.0),
+        egui::Align2::LEFT_TOP,
+        "Hello",
+        egui::TextStyle::Body,
+        Color32::WHITE,
+    );
+    let mut shapes = vec![epaint::ClippedShape(Rect::EVERYTHING, text)];
+    text_to_meshes(&mut shapes, &fonts);
+
+    let net_shapes = to_clipped_net_shapes(shapes, Rect::EVERYTHING);
+    assert!(!net_shapes.is_empty());
+    assert!(net_shapes
+        .iter()
+        .all(|ClippedNetShape(_, shape)| matches!(shape, NetShape::Mesh(_))));
+}
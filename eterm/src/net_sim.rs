@@ -0,0 +1,260 @@
+//! A proxy that makes the network worse, for testing how a served ui copes with
+//! latency, jitter and little bandwidth, e.g. the frame pacing and the client's prediction.
+//!
+//! ``` no_run
+//! # fn test() -> anyhow::Result<()> {
+//! use std::time::Duration;
+//! use eterm::net_sim::{NetworkConditions, NetworkSimulator};
+//!
+//! let simulator = NetworkSimulator::start(
+//!     "127.0.0.1:8505",
+//!     NetworkConditions {
+//!         latency: Duration::from_millis(150),
+//!         jitter: Duration::from_millis(30),
+//!         bandwidth: Some(50_000.0),
+//!     },
+//! )?;
+//! let mut client = eterm::TestClient::connect(simulator.addr().to_string());
+//! client.wait_for_frame_containing("Name")?;
+//! # Ok(()) }
+//! ```
+//!
+//! eterm runs over TCP, so the bytes always arrive in order:
+//! jitter delays them by different amounts, but never reorders them.
+
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+use parking_lot::Mutex;
+
+/// How bad the network is, in each direction.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetworkConditions {
+    /// Added to everything sent.
+    pub latency: Duration,
+    /// Up to this much is randomly added to the latency.
+    pub jitter: Duration,
+    /// Bytes per second, or `None` for unlimited.
+    pub bandwidth: Option<f32>,
+}
+
+/// Listens on a local port and forwards each connection to the server,
+/// with the [`NetworkConditions`] applied.
+///
+/// Stops, and closes all its connections, when dropped.
+pub struct NetworkSimulator {
+    addr: SocketAddr,
+    conditions: Arc<Mutex<NetworkConditions>>,
+    alive: Arc<AtomicBool>,
+    /// Both ends of every forwarded connection, so we can close them.
+    streams: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl NetworkSimulator {
+    /// Start forwarding to the server at `server_addr` (e.g. "127.0.0.1:8505").
+    /// Connect to [`Self::addr`] instead of the server.
+    ///
+    /// # Errors
+    /// If we can't listen on a local port.
+    pub fn start(server_addr: &str, conditions: NetworkConditions) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").context("binding simulator TCP socket")?;
+        let addr = listener.local_addr().context("local_addr")?;
+        let simulator = Self {
+            addr,
+            conditions: Arc::new(Mutex::new(conditions)),
+            alive: Arc::new(AtomicBool::new(true)),
+            streams: Default::default(),
+        };
+
+        let server_addr = server_addr.to_owned();
+        let conditions = simulator.conditions.clone();
+        let alive = simulator.alive.clone();
+        let streams = simulator.streams.clone();
+        std::thread::spawn(move || {
+            for client_stream in listener.incoming() {
+                if !alive.load(SeqCst) {
+                    break;
+                }
+                let result = client_stream.context("accept").and_then(|client_stream| {
+                    forward(client_stream, &server_addr, &conditions, &streams)
+                });
+                if let Err(err) = result {
+                    tracing::warn!(
+                        "Network simulator failed to forward a connection: {:#}",
+                        err
+                    );
+                }
+            }
+        });
+
+        Ok(simulator)
+    }
+
+    /// Where to connect to.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Change the conditions of all connections, e.g. to see how a client copes
+    /// with the network suddenly getting worse.
+    pub fn set_conditions(&self, conditions: NetworkConditions) {
+        *self.conditions.lock() = conditions;
+    }
+
+    pub fn conditions(&self) -> NetworkConditions {
+        *self.conditions.lock()
+    }
+}
+
+impl Drop for NetworkSimulator {
+    fn drop(&mut self) {
+        self.alive.store(false, SeqCst);
+        // Wake up the thread waiting for new connections, so it sees that we're gone:
+        TcpStream::connect(self.addr).ok();
+        for stream in self.streams.lock().drain(..) {
+            stream.shutdown(Shutdown::Both).ok();
+        }
+    }
+}
+
+/// Connect to the server and start forwarding in both directions.
+fn forward(
+    client_stream: TcpStream,
+    server_addr: &str,
+    conditions: &Arc<Mutex<NetworkConditions>>,
+    streams: &Mutex<Vec<TcpStream>>,
+) -> anyhow::Result<()> {
+    let server_stream = TcpStream::connect(server_addr)
+        .with_context(|| format!("connecting to {}", server_addr))?;
+    client_stream.set_nodelay(true).ok();
+    server_stream.set_nodelay(true).ok();
+
+    let seed = streams.lock().len() as u64 + 1;
+    streams.lock().extend([
+        client_stream.try_clone().context("try_clone")?,
+        server_stream.try_clone().context("try_clone")?,
+    ]);
+    start_link(
+        client_stream.try_clone().context("try_clone")?,
+        server_stream.try_clone().context("try_clone")?,
+        conditions.clone(),
+        seed,
+    );
+    start_link(server_stream, client_stream, conditions.clone(), seed + 1);
+    Ok(())
+}
+
+/// Forward what arrives on `from` to `to`, one thread reading and one writing.
+fn start_link(
+    mut from: TcpStream,
+    mut to: TcpStream,
+    conditions: Arc<Mutex<NetworkConditions>>,
+    seed: u64,
+) {
+    let (tx, rx) = mpsc::channel::<(Instant, Vec<u8>)>();
+
+    std::thread::spawn(move || {
+        use std::io::Read as _;
+        let mut rng = XorShift(seed);
+        // When the last chunk has been sent, at the given bandwidth:
+        let mut link_free = Instant::now();
+        // When the last chunk arrives. Later chunks may not overtake it.
+        let mut last_arrival = Instant::now();
+        let mut buffer = vec![0_u8; 64 * 1024];
+        loop {
+            let num_bytes = match from.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(num_bytes) => num_bytes,
+            };
+            let conditions = *conditions.lock();
+            let sent = match conditions.bandwidth {
+                Some(bandwidth) if bandwidth > 0.0 => {
+                    let transmit_time = Duration::from_secs_f32(num_bytes as f32 / bandwidth);
+                    link_free.max(Instant::now()) + transmit_time
+                }
+                _ => Instant::now(),
+            };
+            link_free = sent;
+            let jitter = conditions.jitter.mul_f64(rng.next_f64());
+            last_arrival = last_arrival.max(sent + conditions.latency + jitter);
+            if tx
+                .send((last_arrival, buffer[..num_bytes].to_vec()))
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    std::thread::spawn(move || {
+        use std::io::Write as _;
+        for (arrival, chunk) in rx {
+            let now = Instant::now();
+            if arrival > now {
+                std::thread::sleep(arrival - now);
+            }
+            if to.write_all(&chunk).is_err() {
+                break;
+            }
+        }
+        // Pass on that the connection was closed:
+        to.shutdown(Shutdown::Write).ok();
+    });
+}
+
+/// Good enough randomness for jitter.
+struct XorShift(u64);
+
+impl XorShift {
+    /// In `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+#[test]
+fn test_network_simulator() {
+    use std::io::{Read as _, Write as _};
+
+    let echo_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let echo_addr = echo_listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (mut stream, _) = echo_listener.accept().unwrap();
+        let mut buffer = [0_u8; 1024];
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(num_bytes) => stream.write_all(&buffer[..num_bytes]).unwrap(),
+            }
+        }
+    });
+
+    let latency = Duration::from_millis(50);
+    let simulator = NetworkSimulator::start(
+        &echo_addr.to_string(),
+        NetworkConditions {
+            latency,
+            jitter: Duration::from_millis(20),
+            bandwidth: Some(100_000.0),
+        },
+    )
+    .unwrap();
+
+    let mut stream = TcpStream::connect(simulator.addr()).unwrap();
+    let start = Instant::now();
+    let message: Vec<u8> = (0..=255).collect();
+    for chunk in message.chunks(16) {
+        stream.write_all(chunk).unwrap();
+    }
+    let mut echoed = vec![0_u8; message.len()];
+    stream.read_exact(&mut echoed).unwrap();
+
+    assert_eq!(echoed, message, "jitter must not reorder anything");
+    assert!(start.elapsed() >= 2 * latency, "there and back again");
+}
@@ -0,0 +1,47 @@
+//! Banners shown by the viewer on top of the served ui, e.g. "server restarting in 30 s".
+//!
+//! On the server, call [`crate::Server::broadcast_notice`].
+//! On the client, get the current banner with [`crate::Client::notice`].
+
+use egui::Color32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// Background and text color of the banner.
+    pub fn colors(self) -> (Color32, Color32) {
+        match self {
+            Self::Info => (Color32::from_rgb(30, 80, 160), Color32::WHITE),
+            Self::Warning => (Color32::from_rgb(240, 190, 0), Color32::BLACK),
+            Self::Error => (Color32::from_rgb(190, 30, 30), Color32::WHITE),
+        }
+    }
+}
+
+/// A message from the server itself, independent of the served ui.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Notice {
+    pub text: String,
+    pub severity: Severity,
+}
+
+impl Notice {
+    /// Show the notice as a colored banner.
+    pub fn ui(&self, ui: &mut egui::Ui) -> egui::Response {
+        let (background, text_color) = self.severity.colors();
+        egui::Frame::none()
+            .fill(background)
+            .corner_radius(4.0)
+            .margin(egui::vec2(12.0, 6.0))
+            .show(ui, |ui| {
+                ui.visuals_mut().override_text_color = Some(text_color);
+                ui.label(self.text.as_str());
+            })
+            .response
+    }
+}
@@ -0,0 +1,70 @@
+//! Let the server get the attention of the user, e.g. when a long-running job finishes.
+//!
+//! On the server, call [`notify`] from within [`crate::Server::show`].
+//! On the client, collect the notifications with [`crate::Client::take_notifications`].
+
+/// Something the viewer should tell the user about, even if they are not looking at the window.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Notification {
+    /// Shown as the headline of a desktop notification.
+    pub title: String,
+    /// Shown below the title. Can be empty.
+    pub body: String,
+    /// Also make a sound (e.g. a system beep).
+    pub sound: bool,
+}
+
+impl Notification {
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Also make a sound.
+    pub fn sound(mut self) -> Self {
+        self.sound = true;
+        self
+    }
+}
+
+fn notifications_id() -> egui::Id {
+    egui::Id::new("eterm_notifications")
+}
+
+/// Send a notification to the client currently being shown.
+///
+/// ```
+/// # let egui_ctx = egui::CtxRef::default();
+/// eterm::notification::notify(
+///     &egui_ctx,
+///     eterm::notification::Notification::new("Training done")
+///         .body("loss: 0.012")
+///         .sound(),
+/// );
+/// ```
+///
+/// Call this from within the closure given to [`crate::Server::show`].
+pub fn notify(egui_ctx: &egui::CtxRef, notification: Notification) {
+    egui_ctx
+        .memory()
+        .data
+        .get_temp_mut_or_default::<Vec<Notification>>(notifications_id())
+        .push(notification);
+}
+
+/// The notifications queued with [`notify`] since last call.
+pub(crate) fn take_notifications(egui_ctx: &egui::CtxRef) -> Vec<Notification> {
+    std::mem::take(
+        egui_ctx
+            .memory()
+            .data
+            .get_temp_mut_or_default::<Vec<Notification>>(notifications_id()),
+    )
+}
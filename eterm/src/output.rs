@@ -0,0 +1,139 @@
+//! Getting all of [`egui::Output`] from the server ui to the viewer.
+//!
+//! Some fields are state (e.g. `cursor_icon`) that the viewer should keep applying
+//! until it changes, while others are one-off commands (e.g. `copied_text`) that must
+//! be acted upon exactly once.
+
+use egui::{Output, Vec2};
+
+/// The parts of the output that describe state rather than one-off commands.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct OutputState {
+    cursor_icon: egui::CursorIcon,
+    mutable_text_under_cursor: bool,
+    text_cursor_pos: Option<egui::Pos2>,
+}
+
+impl OutputState {
+    pub fn from_output(output: &Output) -> Self {
+        Self {
+            cursor_icon: output.cursor_icon,
+            mutable_text_under_cursor: output.mutable_text_under_cursor,
+            text_cursor_pos: output.text_cursor_pos,
+        }
+    }
+}
+
+/// Does the viewer need to be told about this output?
+///
+/// `last_sent` is the state of the last output we sent.
+/// `needs_repaint` is ignored, since that is for the server to act on.
+pub(crate) fn has_news(output: &Output, last_sent: &OutputState) -> bool {
+    let has_commands =
+        output.open_url.is_some() || !output.copied_text.is_empty() || !output.events.is_empty();
+    has_commands || OutputState::from_output(output) != *last_sent
+}
+
+/// Merge the output of two frames, as if they were one.
+///
+/// Unlike [`Output::append`], a missing `text_cursor_pos` in the newer output
+/// means there no longer is a text cursor.
+pub(crate) fn merge(older: &mut Output, newer: Output) {
+    let text_cursor_pos = newer.text_cursor_pos;
+    older.append(newer);
+    older.text_cursor_pos = text_cursor_pos;
+}
+
+/// Move the positions in the output, e.g. when the remote ui is shown at an offset.
+pub(crate) fn translate(output: &mut Output, offset: Vec2) {
+    if let Some(text_cursor_pos) = &mut output.text_cursor_pos {
+        *text_cursor_pos += offset;
+    }
+}
+
+#[cfg(test)]
+fn output_with_everything() -> Output {
+    Output {
+        cursor_icon: egui::CursorIcon::Text,
+        open_url: Some(egui::output::OpenUrl::new_tab(
+            "https://github.com/emilk/eterm",
+        )),
+        copied_text: "copied".to_owned(),
+        needs_repaint: false,
+        events: vec![egui::output::OutputEvent::Clicked(
+            egui::WidgetInfo::labeled(egui::WidgetType::Button, "Click me"),
+        )],
+        mutable_text_under_cursor: true,
+        text_cursor_pos: Some(egui::pos2(12.0, 34.0)),
+    }
+}
+
+#[test]
+fn test_output_survives_the_network() {
+    let output = output_with_everything();
+    let message = crate::ServerToClientMessage::Frame {
+        frame_index: 0,
+        output: output.clone(),
+        clipped_net_shapes: vec![],
+        client_time: None,
+        server_time: 0.0,
+        server_frame_time: None,
+    };
+    let packet = crate::encode_message(&message, crate::codec::Compression::None, 0).unwrap();
+    match crate::wire::decode_message(&packet, &Default::default()).unwrap() {
+        crate::ServerToClientMessage::Frame {
+            output: received, ..
+        } => assert!(received == output, "Output changed in transit"),
+        _ => panic!("Expected a frame"),
+    }
+}
+
+#[test]
+fn test_output_has_news() {
+    let output = output_with_everything();
+    let state = OutputState::from_output(&output);
+    assert!(has_news(&output, &state), "one-off commands must be sent");
+
+    let same_state = Output {
+        cursor_icon: egui::CursorIcon::Text,
+        mutable_text_under_cursor: true,
+        text_cursor_pos: Some(egui::pos2(12.0, 34.0)),
+        ..Default::default()
+    };
+    assert!(!has_news(&same_state, &state));
+
+    // Going back to the default cursor must be sent, or the viewer will keep the old one:
+    assert!(has_news(&Output::default(), &state));
+}
+
+#[test]
+fn test_output_merge() {
+    let mut merged = output_with_everything();
+    merged.needs_repaint = true;
+    merge(
+        &mut merged,
+        Output {
+            events: output_with_everything().events,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        merged.cursor_icon,
+        egui::CursorIcon::Default,
+        "latest state wins"
+    );
+    assert_eq!(merged.text_cursor_pos, None, "latest state wins");
+    assert!(!merged.mutable_text_under_cursor, "latest state wins");
+    assert!(!merged.needs_repaint, "latest state wins");
+    assert_eq!(merged.copied_text, "copied", "commands are kept");
+    assert!(merged.open_url.is_some(), "commands are kept");
+    assert_eq!(merged.events.len(), 2, "events are accumulated");
+}
+
+#[test]
+fn test_output_translate() {
+    let mut output = output_with_everything();
+    translate(&mut output, egui::vec2(100.0, 200.0));
+    assert_eq!(output.text_cursor_pos, Some(egui::pos2(112.0, 234.0)));
+}
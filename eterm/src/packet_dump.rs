@@ -0,0 +1,169 @@
+//! Dumping every raw packet to disk, for debugging interop issues between versions.
+//!
+//! Each packet gets its own file, named by the order it was sent or received in,
+//! the time (seconds since dumping started) and the direction, e.g. `000042_12.345s_s2c.bin`.
+//! The file contains the packet as given to [`crate::wire::decode_message`],
+//! i.e. without the frame header.
+//!
+//! Turn it on with [`crate::Server::set_packet_dump_dir`] or [`crate::Client::set_packet_dump_dir`],
+//! and read the files back with [`DumpedPacket::read`]
+//! (or `cargo run --example decode_packet -- <file>`).
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering::SeqCst};
+
+use anyhow::Context as _;
+
+use crate::{wire, ClientToServerMessage, DecodeLimits, Packet, ServerToClientMessage};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+impl Direction {
+    fn suffix(self) -> &'static str {
+        match self {
+            Self::ClientToServer => "c2s",
+            Self::ServerToClient => "s2c",
+        }
+    }
+
+    fn from_suffix(suffix: &str) -> Option<Self> {
+        match suffix {
+            "c2s" => Some(Self::ClientToServer),
+            "s2c" => Some(Self::ServerToClient),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the packets of one side of one or more connections.
+pub(crate) struct PacketDumper {
+    dir: PathBuf,
+    /// The direction of the packets we send.
+    sent: Direction,
+    start: std::time::Instant,
+    next_index: AtomicU64,
+}
+
+impl PacketDumper {
+    /// Creates `dir` if needed. `sent` is the direction of the packets this side sends.
+    pub(crate) fn new(dir: &Path, sent: Direction) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {:?}", dir))?;
+        Ok(Self {
+            dir: dir.to_owned(),
+            sent,
+            start: std::time::Instant::now(),
+            next_index: AtomicU64::new(0),
+        })
+    }
+
+    pub(crate) fn dump_sent(&self, packet: &[u8]) {
+        self.dump(self.sent, packet);
+    }
+
+    pub(crate) fn dump_received(&self, packet: &[u8]) {
+        let received = match self.sent {
+            Direction::ClientToServer => Direction::ServerToClient,
+            Direction::ServerToClient => Direction::ClientToServer,
+        };
+        self.dump(received, packet);
+    }
+
+    /// Failing to dump is not worth dropping the connection over.
+    fn dump(&self, direction: Direction, packet: &[u8]) {
+        let index = self.next_index.fetch_add(1, SeqCst);
+        let time = self.start.elapsed().as_secs_f64();
+        let path = self.dir.join(format!(
+            "{:06}_{:.3}s_{}.bin",
+            index,
+            time,
+            direction.suffix()
+        ));
+        if let Err(err) = std::fs::write(&path, packet) {
+            tracing::warn!("Failed to dump packet to {:?}: {}", path, err);
+        }
+    }
+}
+
+/// A packet read back from a dump file.
+pub struct DumpedPacket {
+    /// The order it was sent or received in.
+    pub index: u64,
+    /// Seconds since dumping started.
+    pub time: f64,
+    pub direction: Direction,
+    pub packet: Packet,
+}
+
+/// A decoded [`DumpedPacket`].
+#[derive(serde::Serialize)]
+pub enum DumpedMessage {
+    ClientToServer(ClientToServerMessage),
+    ServerToClient(ServerToClientMessage),
+}
+
+impl DumpedPacket {
+    /// Read a file written by the packet dumper.
+    ///
+    /// # Errors
+    /// On I/O errors, or if the file name isn't one we write.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let (index, time, direction) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(parse_file_name)
+            .with_context(|| format!("{:?} is not named like a dumped packet", path))?;
+        let packet = std::fs::read(path).with_context(|| format!("reading {:?}", path))?;
+        Ok(Self {
+            index,
+            time,
+            direction,
+            packet: packet.into(),
+        })
+    }
+
+    /// # Errors
+    /// If the packet is corrupt, or from an incompatible version.
+    pub fn decode(&self) -> anyhow::Result<DumpedMessage> {
+        match self.direction {
+            Direction::ClientToServer => {
+                wire::decode_message(&self.packet, &DecodeLimits::CLIENT_TO_SERVER)
+                    .map(DumpedMessage::ClientToServer)
+            }
+            Direction::ServerToClient => {
+                wire::decode_message(&self.packet, &DecodeLimits::SERVER_TO_CLIENT)
+                    .map(DumpedMessage::ServerToClient)
+            }
+        }
+    }
+}
+
+/// `000042_12.345s_s2c.bin` -> `(42, 12.345, ServerToClient)`
+fn parse_file_name(name: &str) -> Option<(u64, f64, Direction)> {
+    let mut parts = name.strip_suffix(".bin")?.split('_');
+    let index = parts.next()?.parse().ok()?;
+    let time = parts.next()?.strip_suffix('s')?.parse().ok()?;
+    let direction = Direction::from_suffix(parts.next()?)?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((index, time, direction))
+}
+
+#[test]
+fn test_parse_file_name() {
+    assert_eq!(
+        parse_file_name("000042_12.345s_s2c.bin"),
+        Some((42, 12.345, Direction::ServerToClient))
+    );
+    assert_eq!(
+        parse_file_name("000000_0.000s_c2s.bin"),
+        Some((0, 0.0, Direction::ClientToServer))
+    );
+    assert_eq!(parse_file_name("000042_12.345s_s2c.txt"), None);
+    assert_eq!(parse_file_name("000042_12.345_s2c.bin"), None);
+    assert_eq!(parse_file_name("recording.bin"), None);
+}
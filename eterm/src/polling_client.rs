@@ -0,0 +1,331 @@
+//! A client that does all its networking in [`PollingClient::poll`], without a thread of its own.
+
+use std::time::Instant;
+
+use anyhow::Context as _;
+
+use crate::{
+    clock::{now, ClockSync},
+    ClientPreferences, ClientToServerMessage, DisconnectReason, GoodbyeReason,
+    ServerToClientMessage, TcpEndpoint,
+};
+
+/// What happened since the last [`PollingClient::poll`].
+pub enum ClientEvent {
+    /// The connection is up, and we said hello.
+    Connected,
+    /// Anything the server sent, except the pongs which the client handles itself.
+    Message(ServerToClientMessage),
+    /// No more events until [`PollingClient::reconnect`].
+    Disconnected(DisconnectReason),
+}
+
+enum State {
+    /// Waiting for the non-blocking connect to finish.
+    Connecting {
+        tcp_stream: mio::net::TcpStream,
+        start: Instant,
+    },
+    Connected {
+        tcp_endpoint: TcpEndpoint,
+        last_ping: Option<Instant>,
+        num_acked: u64,
+    },
+    Disconnected,
+}
+
+/// Like [`crate::Client`], but without a network thread: nothing happens until you call [`Self::poll`],
+/// which never blocks. For engines that don't allow extra threads, and for deterministic tests.
+///
+/// It only speaks the protocol: you get the messages from the server as they are,
+/// and it's up to you to paint the frames (see [`crate::net_shape::from_clipped_net_shapes`]).
+/// It doesn't reconnect by itself either.
+///
+/// ``` no_run
+/// # fn test() -> anyhow::Result<()> {
+/// let mut client = eterm::PollingClient::connect("127.0.0.1:8505")?;
+/// loop {
+///     for event in client.poll() {
+///         if let eterm::ClientEvent::Message(eterm::ServerToClientMessage::Frame { .. }) = event {
+///             // paint it
+///         }
+///     }
+///     // do other work, e.g. wait for vsync
+/// }
+/// # }
+/// ```
+pub struct PollingClient {
+    addr: std::net::SocketAddr,
+    state: State,
+    preferences: ClientPreferences,
+    /// Lets us get our session back when we reconnect.
+    resumption_token: Option<Vec<u8>>,
+    /// Sent on the next poll, once we are connected.
+    outgoing: Vec<ClientToServerMessage>,
+    clock_sync: ClockSync,
+}
+
+impl Drop for PollingClient {
+    fn drop(&mut self) {
+        if let State::Connected { tcp_endpoint, .. } = &mut self.state {
+            // Best effort: we won't wait around for it.
+            tcp_endpoint
+                .send_message(&ClientToServerMessage::Goodbye {
+                    reason: GoodbyeReason::UserQuit,
+                })
+                .ok();
+        }
+    }
+}
+
+impl PollingClient {
+    /// Start connecting to an eterm server. Call [`Self::poll`] to get on with it.
+    ///
+    /// # Errors
+    /// If `addr` can't be resolved, or the connect fails right away.
+    /// Resolving a host name blocks, so pass an IP address to be sure this doesn't.
+    pub fn connect(addr: &str) -> anyhow::Result<Self> {
+        use std::net::ToSocketAddrs as _;
+        let addr = addr
+            .to_socket_addrs()
+            .with_context(|| format!("resolving {:?}", addr))?
+            .next()
+            .with_context(|| format!("{:?} has no addresses", addr))?;
+        let mut client = Self {
+            addr,
+            state: State::Disconnected,
+            preferences: ClientPreferences {
+                locale: crate::client::system_locale(),
+                ..Default::default()
+            },
+            resumption_token: None,
+            outgoing: vec![],
+            clock_sync: Default::default(),
+        };
+        client.reconnect()?;
+        Ok(client)
+    }
+
+    /// Drop the current connection, if any, and connect again.
+    /// The server gives us our session back if it can.
+    ///
+    /// # Errors
+    /// If the connect fails right away.
+    pub fn reconnect(&mut self) -> anyhow::Result<()> {
+        self.state = State::Disconnected;
+        let tcp_stream = mio::net::TcpStream::connect(self.addr)
+            .with_context(|| format!("connecting to {}", self.addr))?;
+        self.state = State::Connecting {
+            tcp_stream,
+            start: Instant::now(),
+        };
+        Ok(())
+    }
+
+    /// Have we connected, and not lost the connection since?
+    pub fn is_connected(&self) -> bool {
+        matches!(self.state, State::Connected { .. })
+    }
+
+    /// Sent to the server when connecting, and right away if we already are.
+    pub fn set_preferences(&mut self, preferences: ClientPreferences) {
+        if self.preferences != preferences {
+            self.preferences = preferences.clone();
+            if self.is_connected() {
+                self.send(ClientToServerMessage::Preferences(preferences));
+            }
+        }
+    }
+
+    /// Send input to the server.
+    pub fn send_input(&mut self, raw_input: egui::RawInput) {
+        self.send(ClientToServerMessage::Input {
+            raw_input,
+            client_time: now(),
+        });
+    }
+
+    /// Sent on the next [`Self::poll`] when connected.
+    /// What's left when we lose the connection is thrown away.
+    pub fn send(&mut self, message: ClientToServerMessage) {
+        self.outgoing.push(message);
+    }
+
+    /// Server clock minus our clock, in seconds.
+    pub fn clock_offset(&self) -> Option<f64> {
+        self.clock_sync.offset()
+    }
+
+    /// Send and receive what we can without blocking.
+    ///
+    /// Call this often, e.g. once per frame: it also keeps the connection alive with pings.
+    pub fn poll(&mut self) -> Vec<ClientEvent> {
+        let mut events = vec![];
+        if let Err(reason) = self.poll_connection(&mut events) {
+            self.state = State::Disconnected;
+            self.outgoing.clear();
+            events.push(ClientEvent::Disconnected(reason));
+        }
+        events
+    }
+
+    fn poll_connection(&mut self, events: &mut Vec<ClientEvent>) -> Result<(), DisconnectReason> {
+        if let State::Connecting { tcp_stream, start } = &self.state {
+            match connect_status(tcp_stream) {
+                Ok(true) => {}
+                Ok(false) if start.elapsed() < crate::client::CONNECT_TIMEOUT => {
+                    return Ok(()); // Not yet
+                }
+                Ok(false) => {
+                    tracing::debug!("Timed out connecting to {}", self.addr);
+                    return Err(DisconnectReason::ConnectionRefused);
+                }
+                Err(err) => {
+                    tracing::debug!("Failed to connect to {}: {}", self.addr, err);
+                    return Err(DisconnectReason::ConnectionRefused);
+                }
+            }
+
+            if let State::Connecting { tcp_stream, .. } =
+                std::mem::replace(&mut self.state, State::Disconnected)
+            {
+                tracing::info!("Connected to {}", self.addr);
+                let mut tcp_endpoint = TcpEndpoint::new(tcp_stream);
+                tcp_endpoint
+                    .send_message(&ClientToServerMessage::Hello {
+                        compressions: crate::codec::Compression::supported(),
+                        resumption_token: self.resumption_token.clone(),
+                        preferences: self.preferences.clone(),
+                    })
+                    .map_err(|err| connection_lost(&err))?;
+                self.clock_sync.clear(); // We may be talking to a new server
+                self.state = State::Connected {
+                    tcp_endpoint,
+                    last_ping: None,
+                    num_acked: 0,
+                };
+                events.push(ClientEvent::Connected);
+            }
+        }
+
+        self.poll_connected(events).map_err(|err| {
+            if err.downcast_ref::<crate::VersionMismatch>().is_some() {
+                tracing::info!("Connection lost: {}", err);
+                DisconnectReason::VersionMismatch
+            } else {
+                connection_lost(&err)
+            }
+        })
+    }
+
+    /// Ping, send, receive and ack, like the network thread of [`crate::Client`] does.
+    fn poll_connected(&mut self, events: &mut Vec<ClientEvent>) -> anyhow::Result<()> {
+        let (tcp_endpoint, last_ping, num_acked) = match &mut self.state {
+            State::Connected {
+                tcp_endpoint,
+                last_ping,
+                num_acked,
+            } => (tcp_endpoint, last_ping, num_acked),
+            State::Connecting { .. } | State::Disconnected => return Ok(()),
+        };
+
+        if last_ping.map_or(true, |last_ping| {
+            last_ping.elapsed() >= crate::clock::PING_INTERVAL
+        }) {
+            tcp_endpoint.send_message(&ClientToServerMessage::Ping { client_time: now() })?;
+            *last_ping = Some(Instant::now());
+        }
+
+        for message in self.outgoing.drain(..) {
+            tcp_endpoint.send_message(&message)?;
+        }
+
+        let limits = crate::DecodeLimits::SERVER_TO_CLIENT;
+        while let Some(packet) = tcp_endpoint
+            .try_receive_packet(&limits)
+            .context("receive")?
+        {
+            let mut message = crate::wire::decode_message(&packet, &limits).context("decode")?;
+            match message {
+                ServerToClientMessage::Pong {
+                    client_time,
+                    server_time,
+                } => {
+                    self.clock_sync.add_sample(client_time, server_time, now());
+                    continue;
+                }
+                ServerToClientMessage::Hello {
+                    compression,
+                    resumption_token: ref mut new_token,
+                    ..
+                } => {
+                    tracing::debug!("Using {:?} compression", compression);
+                    tcp_endpoint.compression = compression;
+                    if !new_token.is_empty() {
+                        self.resumption_token = Some(std::mem::take(new_token));
+                    }
+                }
+                ServerToClientMessage::Goodbye { reason } => {
+                    tracing::info!("The server hung up: {}", reason);
+                    // Don't leave it to `Drop` to say goodbye to a server that is already gone:
+                    self.state = State::Disconnected;
+                    self.outgoing.clear();
+                    events.push(ClientEvent::Disconnected(DisconnectReason::Goodbye(reason)));
+                    return Ok(());
+                }
+                _ => {}
+            }
+            events.push(ClientEvent::Message(message));
+        }
+
+        let num_received = tcp_endpoint.sequencer.num_received();
+        if num_received != *num_acked {
+            tcp_endpoint.send_message(&ClientToServerMessage::Ack { num_received })?;
+            *num_acked = num_received;
+        }
+
+        tcp_endpoint.flush().context("send")
+    }
+}
+
+/// Has the non-blocking connect finished?
+fn connect_status(tcp_stream: &mio::net::TcpStream) -> std::io::Result<bool> {
+    if let Some(err) = tcp_stream.take_error()? {
+        return Err(err);
+    }
+    match tcp_stream.peer_addr() {
+        Ok(_) => Ok(true),
+        Err(err) if err.kind() == std::io::ErrorKind::NotConnected => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+fn connection_lost(err: &anyhow::Error) -> DisconnectReason {
+    tracing::info!(
+        "Connection lost: {}",
+        crate::error_display_chain(err.as_ref())
+    );
+    DisconnectReason::ConnectionLost
+}
+
+#[test]
+fn test_polling_client() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = PollingClient::connect(&listener.local_addr().unwrap().to_string()).unwrap();
+    let (server_stream, _) = listener.accept().unwrap();
+    drop(server_stream); // hang up right away
+
+    let mut events = vec![];
+    let start = Instant::now();
+    while !matches!(events.last(), Some(ClientEvent::Disconnected(_))) {
+        assert!(start.elapsed().as_secs() < 10, "Timed out");
+        events.extend(client.poll());
+    }
+
+    assert!(matches!(events[0], ClientEvent::Connected));
+    assert!(matches!(
+        events.last(),
+        Some(ClientEvent::Disconnected(DisconnectReason::ConnectionLost))
+    ));
+    assert!(!client.is_connected());
+}
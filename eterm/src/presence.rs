@@ -0,0 +1,48 @@
+//! Show each client where the other clients are pointing.
+//!
+//! Enable with [`crate::Server::set_show_remote_cursors`].
+
+use egui::{Align2, Color32, Pos2, Shape, Stroke, TextStyle};
+
+use crate::ClientId;
+
+/// Where another client is pointing.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RemoteCursor {
+    pub client_id: ClientId,
+    pub name: String,
+    pub pos: Pos2,
+}
+
+/// A color that is unique-ish for each client, and the same for all who see it.
+fn client_color(client_id: ClientId) -> Color32 {
+    let golden_ratio = (5.0_f32.sqrt() - 1.0) / 2.0;
+    let hue = (client_id.as_u64() as f32 * golden_ratio).fract();
+    egui::color::Hsva::new(hue, 0.85, 0.9, 1.0).into()
+}
+
+/// Paint the cursors on top of everything else.
+pub(crate) fn paint_remote_cursors(egui_ctx: &egui::CtxRef, cursors: &[RemoteCursor]) {
+    let painter = egui_ctx.layer_painter(egui::LayerId::new(
+        egui::Order::Debug,
+        egui::Id::new("eterm_remote_cursors"),
+    ));
+
+    for cursor in cursors {
+        let color = client_color(cursor.client_id);
+        painter.circle(cursor.pos, 5.0, color, Stroke::new(1.0, Color32::WHITE));
+
+        let background = painter.add(Shape::Noop);
+        let text_rect = painter.text(
+            cursor.pos + egui::vec2(8.0, 6.0),
+            Align2::LEFT_TOP,
+            &cursor.name,
+            TextStyle::Small,
+            Color32::WHITE,
+        );
+        painter.set(
+            background,
+            Shape::rect_filled(text_rect.expand(2.0), 2.0, color),
+        );
+    }
+}
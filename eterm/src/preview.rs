@@ -0,0 +1,246 @@
+//! Seeing what the viewers see, on the server machine, without starting a viewer.
+//!
+//! Get a [`ServerPreview`] with [`crate::Server::preview`], then either show it in your own
+//! egui app with [`ServerPreview::ui`], or (with the `preview` feature) in a window of its own
+//! with [`run_window`]:
+//!
+//! ``` no_run
+//! # #[cfg(feature = "preview")]
+//! # fn test() -> anyhow::Result<()> {
+//! let mut server = eterm::Server::new("0.0.0.0:8505")?;
+//! let preview = server.preview();
+//! std::thread::spawn(move || loop {
+//!     server
+//!         .show(|egui_ctx, _client_id| {
+//!             egui::CentralPanel::default().show(egui_ctx, |ui| ui.label("Hello"));
+//!         })
+//!         .unwrap();
+//!     std::thread::sleep(std::time::Duration::from_millis(10));
+//! });
+//! eterm::preview::run_window(preview); // Must be on the main thread
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use egui::{FontDefinitions, Rect};
+use parking_lot::Mutex;
+
+use crate::{net_shape::ClippedNetShape, ClientId};
+
+/// How often [`run_window`] looks for new frames.
+#[cfg(feature = "preview")]
+const WINDOW_REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(33);
+
+struct PreviewFrame {
+    /// The screen of the viewer.
+    screen_rect: Rect,
+    clipped_net_shapes: Vec<ClippedNetShape>,
+    font_definitions: FontDefinitions,
+    /// Bumped when the fonts change.
+    fonts_version: u64,
+}
+
+impl Default for PreviewFrame {
+    fn default() -> Self {
+        Self {
+            screen_rect: Rect::NOTHING,
+            clipped_net_shapes: vec![],
+            font_definitions: Default::default(),
+            fonts_version: 0,
+        }
+    }
+}
+
+/// The latest frame sent to each connected client.
+///
+/// Cheap to clone, and can be sent to another thread, e.g. the one with the preview window.
+#[derive(Clone, Default)]
+pub struct ServerPreview {
+    frames: Arc<Mutex<BTreeMap<ClientId, PreviewFrame>>>,
+}
+
+impl ServerPreview {
+    pub(crate) fn set_frame(
+        &self,
+        client_id: ClientId,
+        screen_rect: Rect,
+        clipped_net_shapes: &[ClippedNetShape],
+    ) {
+        let mut frames = self.frames.lock();
+        let frame = frames.entry(client_id).or_default();
+        frame.screen_rect = screen_rect;
+        frame.clipped_net_shapes = clipped_net_shapes.to_vec();
+    }
+
+    pub(crate) fn set_fonts(&self, client_id: ClientId, font_definitions: &FontDefinitions) {
+        let mut frames = self.frames.lock();
+        let frame = frames.entry(client_id).or_default();
+        frame.font_definitions = font_definitions.clone();
+        frame.fonts_version += 1;
+    }
+
+    pub(crate) fn remove_client(&self, client_id: ClientId) {
+        self.frames.lock().remove(&client_id);
+    }
+
+    /// The clients we have frames for, oldest first.
+    pub fn client_ids(&self) -> Vec<ClientId> {
+        self.frames.lock().keys().copied().collect()
+    }
+
+    /// The fonts of the ui shown to this client.
+    ///
+    /// Give them to the [`egui::Context::set_fonts`] of the app showing the preview,
+    /// so the text is laid out just like in the viewer.
+    pub fn font_definitions(&self, client_id: ClientId) -> Option<FontDefinitions> {
+        self.frames
+            .lock()
+            .get(&client_id)
+            .map(|frame| frame.font_definitions.clone())
+    }
+
+    #[cfg(feature = "preview")]
+    fn fonts_version(&self, client_id: ClientId) -> Option<u64> {
+        self.frames
+            .lock()
+            .get(&client_id)
+            .map(|frame| frame.fonts_version)
+    }
+
+    /// Paint the latest frame of this client, at the size of its screen.
+    /// Text is laid out with the fonts of `ui`: see [`Self::font_definitions`].
+    pub fn ui(&self, ui: &mut egui::Ui, client_id: ClientId) -> egui::Response {
+        let (screen_rect, clipped_net_shapes) = match self.frames.lock().get(&client_id) {
+            Some(frame) => (frame.screen_rect, frame.clipped_net_shapes.clone()),
+            None => (Rect::NOTHING, vec![]),
+        };
+        let size = screen_rect.size().max(egui::Vec2::ZERO);
+        let (rect, response) = ui.allocate_exact_size(size, egui::Sense::hover());
+        let offset = rect.min.to_vec2();
+
+        let clipped_shapes =
+            crate::net_shape::from_clipped_net_shapes(ui.fonts(), &clipped_net_shapes);
+        for egui::epaint::ClippedShape(clip_rect, mut shape) in clipped_shapes {
+            shape.translate(offset);
+            ui.painter()
+                .sub_region(clip_rect.translate(offset).intersect(rect))
+                .add(shape);
+        }
+        response
+    }
+}
+
+/// Open a window showing the [`ServerPreview`], and run until it is closed.
+///
+/// Must be called on the main thread, so run the server on another thread.
+#[cfg(feature = "preview")]
+pub fn run_window(preview: ServerPreview) -> ! {
+    use glium::glutin;
+
+    let event_loop = glutin::event_loop::EventLoop::with_user_event();
+    let window_builder = glutin::window::WindowBuilder::new()
+        .with_resizable(true)
+        .with_inner_size(glutin::dpi::LogicalSize {
+            width: 1024.0,
+            height: 768.0,
+        })
+        .with_title("eterm preview");
+    let context_builder = glutin::ContextBuilder::new()
+        .with_depth_buffer(0)
+        .with_double_buffer(Some(true))
+        .with_srgb(true)
+        .with_stencil_buffer(0)
+        .with_vsync(true);
+    let display = glium::Display::new(window_builder, context_builder, &event_loop)
+        .expect("Failed to create the preview window");
+
+    let mut egui_glium = egui_glium::EguiGlium::new(&display);
+    let mut selected: Option<ClientId> = None;
+    // Whose fonts we last gave to egui, and which version of them:
+    let mut applied_fonts: Option<(ClientId, u64)> = None;
+
+    event_loop.run(move |event, _, control_flow| {
+        let mut redraw = || {
+            let client_ids = preview.client_ids();
+            if selected.map_or(true, |client_id| !client_ids.contains(&client_id)) {
+                selected = client_ids.first().copied();
+            }
+            let fonts =
+                selected.and_then(|client_id| Some((client_id, preview.fonts_version(client_id)?)));
+            if fonts != applied_fonts {
+                applied_fonts = fonts;
+                let font_definitions = selected
+                    .and_then(|client_id| preview.font_definitions(client_id))
+                    .unwrap_or_default();
+                egui_glium.egui_ctx.set_fonts(font_definitions);
+            }
+
+            let raw_input = egui_glium
+                .egui_winit
+                .take_egui_input(display.gl_window().window());
+            let (egui_output, clipped_shapes) = egui_glium.egui_ctx.run(raw_input, |egui_ctx| {
+                egui::TopBottomPanel::top("clients").show(egui_ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        if client_ids.is_empty() {
+                            ui.label("No clients connected");
+                        }
+                        for &client_id in &client_ids {
+                            let text = format!("Client {}", client_id.as_u64());
+                            ui.selectable_value(&mut selected, Some(client_id), text);
+                        }
+                    });
+                });
+                egui::CentralPanel::default().show(egui_ctx, |ui| {
+                    if let Some(client_id) = selected {
+                        egui::ScrollArea::both().show(ui, |ui| {
+                            preview.ui(ui, client_id);
+                        });
+                    }
+                });
+            });
+            egui_glium.egui_winit.handle_output(
+                display.gl_window().window(),
+                &egui_glium.egui_ctx,
+                egui_output,
+            );
+
+            use glium::Surface as _;
+            let mut target = display.draw();
+            target.clear_color(0.0, 0.0, 0.0, 1.0);
+            egui_glium.paint(&display, &mut target, clipped_shapes);
+            target.finish().unwrap();
+
+            // We don't know when the server sends something, so keep looking:
+            *control_flow = glutin::event_loop::ControlFlow::WaitUntil(
+                std::time::Instant::now() + WINDOW_REFRESH_INTERVAL,
+            );
+        };
+
+        match event {
+            // Platform-dependent event handlers to workaround a winit bug
+            // See: https://github.com/rust-windowing/winit/issues/987
+            // See: https://github.com/rust-windowing/winit/issues/1619
+            glutin::event::Event::RedrawEventsCleared if cfg!(windows) => redraw(),
+            glutin::event::Event::RedrawRequested(_) if !cfg!(windows) => redraw(),
+
+            glutin::event::Event::NewEvents(glutin::event::StartCause::ResumeTimeReached {
+                ..
+            }) => {
+                display.gl_window().window().request_redraw();
+            }
+
+            glutin::event::Event::WindowEvent { event, .. } => {
+                use glutin::event::WindowEvent;
+                if matches!(event, WindowEvent::CloseRequested | WindowEvent::Destroyed) {
+                    *control_flow = glutin::event_loop::ControlFlow::Exit;
+                }
+                egui_glium.on_event(&event);
+                display.gl_window().window().request_redraw();
+            }
+
+            _ => (),
+        }
+    })
+}
@@ -0,0 +1,182 @@
+//! A simple software rasterizer for [`egui::ClippedMesh`]:es.
+//!
+//! Slow, but needs no GPU. Used for headless snapshots of what the server sends.
+
+use egui::{
+    epaint::{Color32, Mesh, Pos2, Rect, TextureId, Vertex},
+    ClippedMesh, FontImage,
+};
+
+/// An sRGBA image with premultiplied alpha, row by row, top to bottom.
+#[derive(Clone, PartialEq)]
+pub struct Image {
+    /// `[width, height]` in pixels.
+    pub size: [usize; 2],
+    pub pixels: Vec<Color32>,
+}
+
+impl Image {
+    pub fn new(size: [usize; 2], color: Color32) -> Self {
+        Self {
+            size,
+            pixels: vec![color; size[0] * size[1]],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.size[0]
+    }
+
+    pub fn height(&self) -> usize {
+        self.size[1]
+    }
+
+    /// The pixels as tightly packed `[r, g, b, a]`.
+    pub fn as_rgba_bytes(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|c| c.to_array()).collect()
+    }
+}
+
+/// Paint the meshes on top of the given image.
+///
+/// Meshes using [`TextureId::Egui`] sample the given font image.
+/// User textures are not available, so they are painted as if untextured.
+pub fn paint_meshes(
+    image: &mut Image,
+    pixels_per_point: f32,
+    clipped_meshes: &[ClippedMesh],
+    font_image: &FontImage,
+) {
+    for ClippedMesh(clip_rect, mesh) in clipped_meshes {
+        let clip_rect = Rect::from_min_max(
+            (clip_rect.min.to_vec2() * pixels_per_point).to_pos2(),
+            (clip_rect.max.to_vec2() * pixels_per_point).to_pos2(),
+        );
+        paint_mesh(image, pixels_per_point, clip_rect, mesh, font_image);
+    }
+}
+
+/// Rasterize meshes into a new image of the given size (in pixels).
+pub fn rasterize(
+    size: [usize; 2],
+    background: Color32,
+    pixels_per_point: f32,
+    clipped_meshes: &[ClippedMesh],
+    font_image: &FontImage,
+) -> Image {
+    let mut image = Image::new(size, background);
+    paint_meshes(&mut image, pixels_per_point, clipped_meshes, font_image);
+    image
+}
+
+fn paint_mesh(
+    image: &mut Image,
+    pixels_per_point: f32,
+    clip_rect: Rect,
+    mesh: &Mesh,
+    font_image: &FontImage,
+) {
+    let texture = match mesh.texture_id {
+        TextureId::Egui => Some(font_image),
+        TextureId::User(_) => None,
+    };
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        let v = [
+            &mesh.vertices[triangle[0] as usize],
+            &mesh.vertices[triangle[1] as usize],
+            &mesh.vertices[triangle[2] as usize],
+        ];
+        paint_triangle(image, pixels_per_point, clip_rect, v, texture);
+    }
+}
+
+fn paint_triangle(
+    image: &mut Image,
+    pixels_per_point: f32,
+    clip_rect: Rect,
+    v: [&Vertex; 3],
+    texture: Option<&FontImage>,
+) {
+    let p: [Pos2; 3] = [
+        (v[0].pos.to_vec2() * pixels_per_point).to_pos2(),
+        (v[1].pos.to_vec2() * pixels_per_point).to_pos2(),
+        (v[2].pos.to_vec2() * pixels_per_point).to_pos2(),
+    ];
+
+    let area = edge(p[0], p[1], p[2]);
+    if area == 0.0 {
+        return;
+    }
+
+    let bounds = Rect::from_points(&p).intersect(clip_rect);
+    let min_x = bounds.min.x.floor().max(0.0) as usize;
+    let min_y = bounds.min.y.floor().max(0.0) as usize;
+    let max_x = (bounds.max.x.ceil().max(0.0) as usize).min(image.width());
+    let max_y = (bounds.max.y.ceil().max(0.0) as usize).min(image.height());
+
+    for y in min_y..max_y {
+        for x in min_x..max_x {
+            // Sample the pixel center:
+            let s = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+            let w0 = edge(p[1], p[2], s) / area;
+            let w1 = edge(p[2], p[0], s) / area;
+            let w2 = edge(p[0], p[1], s) / area;
+            if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                continue;
+            }
+
+            let mut color = [0.0; 4];
+            for (i, c) in color.iter_mut().enumerate() {
+                *c = w0 * v[0].color[i] as f32
+                    + w1 * v[1].color[i] as f32
+                    + w2 * v[2].color[i] as f32;
+            }
+
+            if let Some(texture) = texture {
+                let uv_x = w0 * v[0].uv.x + w1 * v[1].uv.x + w2 * v[2].uv.x;
+                let uv_y = w0 * v[0].uv.y + w1 * v[1].uv.y + w2 * v[2].uv.y;
+                let coverage = sample(texture, uv_x, uv_y) as f32 / 255.0;
+                for c in &mut color {
+                    *c *= coverage;
+                }
+            }
+
+            let src = Color32::from_rgba_premultiplied(
+                color[0].round() as u8,
+                color[1].round() as u8,
+                color[2].round() as u8,
+                color[3].round() as u8,
+            );
+            let dst = &mut image.pixels[y * image.size[0] + x];
+            *dst = blend(*dst, src);
+        }
+    }
+}
+
+/// Twice the signed area of the triangle `a, b, c`.
+fn edge(a: Pos2, b: Pos2, c: Pos2) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+/// Nearest-neighbor sampling of the font texture.
+fn sample(texture: &FontImage, u: f32, v: f32) -> u8 {
+    if texture.width == 0 || texture.height == 0 {
+        return 255;
+    }
+    let x = ((u * texture.width as f32) as usize).min(texture.width - 1);
+    let y = ((v * texture.height as f32) as usize).min(texture.height - 1);
+    texture.pixels[y * texture.width + x]
+}
+
+/// Premultiplied "over" blending.
+fn blend(dst: Color32, src: Color32) -> Color32 {
+    let inv_alpha = 255 - src.a() as u16;
+    let mix = |d: u8, s: u8| (s as u16 + (d as u16 * inv_alpha + 127) / 255).min(255) as u8;
+    Color32::from_rgba_premultiplied(
+        mix(dst.r(), src.r()),
+        mix(dst.g(), src.g()),
+        mix(dst.b(), src.b()),
+        mix(dst.a(), src.a()),
+    )
+}
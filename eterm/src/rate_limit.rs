@@ -0,0 +1,110 @@
+//! Protects the server from clients that flood it with input. See [`crate::Server::set_input_rate_limit`].
+
+use egui::Event;
+
+/// Clients that go this many bursts over their budget are disconnected.
+const DISCONNECT_AFTER_BURSTS: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct InputRateLimit {
+    pub events_per_second: f32,
+    /// How many events can come at once, after a quiet period.
+    pub burst: f32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Verdict {
+    /// Within budget.
+    Ok,
+    /// Pointer moves were dropped to stay within budget, or close to it.
+    Limited { dropped: usize },
+    /// Way over budget, even after dropping what we could.
+    Flooding,
+}
+
+/// A token bucket: each event costs a token, and tokens come back at a steady rate.
+pub(crate) struct RateLimiter {
+    limit: InputRateLimit,
+    /// Goes negative when the client sends more than we can drop.
+    tokens: f32,
+    /// In seconds, see [`crate::clock::now`].
+    last_refill: Option<f64>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: InputRateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst,
+            last_refill: None,
+        }
+    }
+
+    /// Charge for the events that arrived at `now`, first coalescing and then dropping pointer moves if over budget.
+    pub fn limit(&mut self, now: f64, events: &mut Vec<Event>) -> Verdict {
+        if let Some(last_refill) = self.last_refill {
+            let refill = (now - last_refill) as f32 * self.limit.events_per_second;
+            self.tokens = (self.tokens + refill).min(self.limit.burst);
+        }
+        self.last_refill = Some(now);
+
+        let num_events = events.len();
+        if num_events as f32 > self.tokens {
+            crate::server::coalesce_moves(events);
+        }
+        if events.len() as f32 > self.tokens {
+            events.retain(|event| {
+                !matches!(
+                    event,
+                    Event::PointerMoved(_)
+                        | Event::Touch {
+                            phase: egui::TouchPhase::Move,
+                            ..
+                        }
+                )
+            });
+        }
+        self.tokens -= events.len() as f32;
+
+        if self.tokens < -DISCONNECT_AFTER_BURSTS * self.limit.burst {
+            Verdict::Flooding
+        } else if events.len() < num_events {
+            Verdict::Limited {
+                dropped: num_events - events.len(),
+            }
+        } else {
+            Verdict::Ok
+        }
+    }
+}
+
+#[test]
+fn test_rate_limiter() {
+    let mut limiter = RateLimiter::new(InputRateLimit {
+        events_per_second: 10.0,
+        burst: 5.0,
+    });
+    let moves = |n: usize| vec![Event::PointerMoved(egui::Pos2::ZERO); n];
+    let texts = |n: usize| vec![Event::Text("x".to_owned()); n];
+
+    assert_eq!(limiter.limit(0.0, &mut moves(5)), Verdict::Ok);
+
+    let mut events = moves(5);
+    assert_eq!(
+        limiter.limit(0.1, &mut events),
+        Verdict::Limited { dropped: 4 },
+        "coalesced"
+    );
+    assert_eq!(events.len(), 1);
+
+    let mut events = texts(3);
+    events.extend(moves(1));
+    assert_eq!(
+        limiter.limit(0.1, &mut events),
+        Verdict::Limited { dropped: 1 },
+        "dropped"
+    );
+    assert_eq!(events.len(), 3, "text is never dropped");
+
+    assert_eq!(limiter.limit(0.1, &mut texts(100)), Verdict::Flooding);
+}
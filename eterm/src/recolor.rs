@@ -0,0 +1,94 @@
+//! Changing the colors of received shapes on the client, e.g. with [`crate::Client::set_frame_hook`].
+
+use egui::epaint::{ClippedShape, Color32, Shape};
+
+/// Replace every color of the shapes (fills, strokes, text and meshes) with `f(color)`.
+///
+/// ``` no_run
+/// # let mut client = eterm::Client::new("127.0.0.1:8580".to_owned());
+/// // Dim the whole remote ui:
+/// client.set_frame_hook(|shapes| {
+///     eterm::recolor::map_colors(shapes, |color| color.linear_multiply(0.5));
+/// });
+/// ```
+pub fn map_colors(shapes: &mut [ClippedShape], mut f: impl FnMut(Color32) -> Color32) {
+    for ClippedShape(_, shape) in shapes {
+        map_shape_colors(shape, &mut f);
+    }
+}
+
+fn map_shape_colors(shape: &mut Shape, f: &mut dyn FnMut(Color32) -> Color32) {
+    match shape {
+        Shape::Noop => {}
+        Shape::Vec(shapes) => {
+            for shape in shapes {
+                map_shape_colors(shape, f);
+            }
+        }
+        Shape::Circle(circle_shape) => {
+            circle_shape.fill = f(circle_shape.fill);
+            circle_shape.stroke.color = f(circle_shape.stroke.color);
+        }
+        Shape::LineSegment { stroke, .. } => {
+            stroke.color = f(stroke.color);
+        }
+        Shape::Path(path_shape) => {
+            path_shape.fill = f(path_shape.fill);
+            path_shape.stroke.color = f(path_shape.stroke.color);
+        }
+        Shape::Rect(rect_shape) => {
+            rect_shape.fill = f(rect_shape.fill);
+            rect_shape.stroke.color = f(rect_shape.stroke.color);
+        }
+        Shape::Text(text_shape) => {
+            text_shape.underline.color = f(text_shape.underline.color);
+            if let Some(color) = &mut text_shape.override_text_color {
+                *color = f(*color);
+            }
+            // The galley is shared with the layout cache, so change a copy:
+            let galley = std::sync::Arc::make_mut(&mut text_shape.galley);
+            for row in &mut galley.rows {
+                for vertex in &mut row.visuals.mesh.vertices {
+                    vertex.color = f(vertex.color);
+                }
+            }
+        }
+        Shape::Mesh(mesh) => {
+            for vertex in &mut mesh.vertices {
+                vertex.color = f(vertex.color);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_map_colors() {
+    let rect = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(10.0, 10.0));
+    let mut shapes = vec![ClippedShape(
+        rect,
+        Shape::Vec(vec![
+            Shape::rect_filled(rect, 0.0, Color32::WHITE),
+            Shape::line_segment([rect.min, rect.max], (1.0, Color32::RED)),
+        ]),
+    )];
+
+    map_colors(&mut shapes, |color| {
+        if color == Color32::WHITE {
+            Color32::BLACK
+        } else {
+            color
+        }
+    });
+
+    match &shapes[0].1 {
+        Shape::Vec(shapes) => {
+            assert!(
+                matches!(&shapes[0], Shape::Rect(rect_shape) if rect_shape.fill == Color32::BLACK)
+            );
+            assert!(
+                matches!(&shapes[1], Shape::LineSegment { stroke, .. } if stroke.color == Color32::RED)
+            );
+        }
+        _ => panic!("Expected a Vec shape"),
+    }
+}
@@ -0,0 +1,181 @@
+//! Recordings of what a server sent, for analyzers, converters and replays.
+//!
+//! A recording starts with [`RECORDING_HEADER`], followed by one entry per packet:
+//! the time (seconds since the recording started, as a little-endian `f64`)
+//! followed by the packet, framed as on the wire (see [`crate::wire::write_packet`]).
+//!
+//! ``` no_run
+//! # fn analyze() -> anyhow::Result<()> {
+//! let file = std::io::BufReader::new(std::fs::File::open("session.eterm")?);
+//! for recorded in eterm::recording::RecordingReader::new(file)? {
+//!     let recorded = recorded?;
+//!     if let eterm::ServerToClientMessage::Frame { frame_index, .. } = recorded.message {
+//!         println!("{:.3}s: frame {}", recorded.time, frame_index);
+//!     }
+//! }
+//! # Ok(()) }
+//! ```
+
+use anyhow::Context as _;
+
+use crate::{wire, DecodeLimits, Packet, ServerToClientMessage};
+
+/// Every recording starts with this.
+pub const RECORDING_HEADER: [u8; 8] = *b"etermrec";
+
+/// Writes packets (as received from the server) with the time they were written.
+pub struct RecordingWriter<W: std::io::Write> {
+    writer: W,
+    start: std::time::Instant,
+}
+
+impl<W: std::io::Write> RecordingWriter<W> {
+    /// Writes the [`RECORDING_HEADER`]. Time starts now.
+    ///
+    /// # Errors
+    /// On I/O errors.
+    pub fn new(mut writer: W) -> anyhow::Result<Self> {
+        writer.write_all(&RECORDING_HEADER).context("write")?;
+        Ok(Self {
+            writer,
+            start: std::time::Instant::now(),
+        })
+    }
+
+    /// Append a packet, e.g. from [`crate::wire::read_packet`].
+    ///
+    /// # Errors
+    /// On I/O errors.
+    pub fn write_packet(&mut self, packet: &[u8]) -> anyhow::Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+        self.writer
+            .write_all(&time.to_le_bytes())
+            .context("write")?;
+        wire::write_packet(&mut self.writer, packet)
+    }
+
+    /// Encode (with the best compression this build supports) and append a message.
+    ///
+    /// # Errors
+    /// On I/O errors.
+    pub fn write_message(&mut self, message: &ServerToClientMessage) -> anyhow::Result<()> {
+        let packet = wire::encode_message(
+            message,
+            crate::codec::Compression::supported()[0],
+            crate::codec::DEFAULT_LEVEL,
+        )?;
+        self.write_packet(&packet)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// A packet from a recording.
+pub struct RecordedPacket {
+    /// Seconds since the recording started.
+    pub time: f64,
+    pub packet: Packet,
+}
+
+/// A message from a recording.
+pub struct RecordedMessage {
+    /// Seconds since the recording started.
+    pub time: f64,
+    pub message: ServerToClientMessage,
+}
+
+/// Iterates over the messages of a recording. See also [`Self::next_packet`].
+///
+/// Wrap files in a [`std::io::BufReader`].
+pub struct RecordingReader<R: std::io::Read> {
+    reader: R,
+    limits: DecodeLimits,
+}
+
+impl<R: std::io::Read> RecordingReader<R> {
+    /// Reads and checks the [`RECORDING_HEADER`].
+    ///
+    /// # Errors
+    /// On I/O errors, or if this is not a recording.
+    pub fn new(mut reader: R) -> anyhow::Result<Self> {
+        let mut header = [0_u8; RECORDING_HEADER.len()];
+        reader.read_exact(&mut header).context("read")?;
+        if header != RECORDING_HEADER {
+            anyhow::bail!("Not an eterm recording");
+        }
+        Ok(Self {
+            reader,
+            limits: DecodeLimits::SERVER_TO_CLIENT,
+        })
+    }
+
+    /// The next packet, without decoding it. `None` at the end of the recording.
+    ///
+    /// # Errors
+    /// On I/O errors, or if the recording is truncated or corrupt.
+    pub fn next_packet(&mut self) -> anyhow::Result<Option<RecordedPacket>> {
+        let mut time = [0_u8; 8];
+        match self.reader.read(&mut time[..1]) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(err) => return Err(err).context("read"),
+        }
+        self.reader.read_exact(&mut time[1..]).context("read")?;
+        let time = f64::from_le_bytes(time);
+        let packet = wire::read_packet(&mut self.reader, &self.limits)?
+            .context("Recording ends in the middle of an entry")?;
+        Ok(Some(RecordedPacket { time, packet }))
+    }
+}
+
+impl<R: std::io::Read> Iterator for RecordingReader<R> {
+    type Item = anyhow::Result<RecordedMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let recorded = match self.next_packet() {
+            Ok(recorded) => recorded?,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(
+            wire::decode_message(&recorded.packet, &self.limits).map(|message| RecordedMessage {
+                time: recorded.time,
+                message,
+            }),
+        )
+    }
+}
+
+#[test]
+fn test_recording() {
+    let mut writer = RecordingWriter::new(vec![]).unwrap();
+    for frame_index in 0..3 {
+        writer
+            .write_message(&ServerToClientMessage::Frame {
+                frame_index,
+                output: Default::default(),
+                clipped_net_shapes: vec![],
+                client_time: None,
+                server_time: 0.0,
+                server_frame_time: None,
+            })
+            .unwrap();
+    }
+    let bytes = writer.into_inner();
+
+    let mut last_time = 0.0;
+    let mut frame_indices = vec![];
+    for recorded in RecordingReader::new(bytes.as_slice()).unwrap() {
+        let recorded = recorded.unwrap();
+        assert!(recorded.time >= last_time);
+        last_time = recorded.time;
+        match recorded.message {
+            ServerToClientMessage::Frame { frame_index, .. } => frame_indices.push(frame_index),
+            _ => panic!("Expected a frame"),
+        }
+    }
+    assert_eq!(frame_indices, vec![0, 1, 2]);
+
+    assert!(RecordingReader::new(&b"not a recording"[..]).is_err());
+}
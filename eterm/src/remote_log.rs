@@ -0,0 +1,191 @@
+//! Forwarding the log of the server to the viewers, for debugging headless servers remotely.
+//!
+//! On the server, add [`crate::Server::log_layer`] to your `tracing_subscriber`
+//! (needs the `remote_log` feature).
+//! On the client, read what has arrived with [`crate::Client::server_log`].
+
+use std::collections::VecDeque;
+
+/// How many records the server keeps for clients that connect later,
+/// and how many records the client keeps.
+const MAX_RECORDS: usize = 1000;
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize, serde::Serialize,
+)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl From<tracing::Level> for Level {
+    fn from(level: tracing::Level) -> Self {
+        match level {
+            tracing::Level::TRACE => Self::Trace,
+            tracing::Level::DEBUG => Self::Debug,
+            tracing::Level::INFO => Self::Info,
+            tracing::Level::WARN => Self::Warn,
+            tracing::Level::ERROR => Self::Error,
+        }
+    }
+}
+
+impl std::fmt::Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Self::Trace => "TRACE",
+            Self::Debug => "DEBUG",
+            Self::Info => "INFO",
+            Self::Warn => "WARN",
+            Self::Error => "ERROR",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct LogRecord {
+    /// Seconds since the server started.
+    pub time: f64,
+    pub level: Level,
+    /// Usually the module path, e.g. `"my_server::physics"`.
+    pub target: String,
+    /// The message, followed by any other fields.
+    pub message: String,
+}
+
+/// The latest records on the server, shared with the [`LogLayer`].
+#[derive(Default)]
+pub(crate) struct LogBuffer {
+    records: VecDeque<LogRecord>,
+    /// Index of the first record in `records`, counting from the start of the server.
+    first_index: u64,
+}
+
+impl LogBuffer {
+    #[cfg_attr(not(feature = "remote_log"), allow(dead_code))]
+    pub fn push(&mut self, record: LogRecord) {
+        if self.records.len() == MAX_RECORDS {
+            self.records.pop_front();
+            self.first_index += 1;
+        }
+        self.records.push_back(record);
+    }
+
+    /// The records from index `next_index` and on, and the index after the last of them.
+    pub fn since(&self, next_index: u64) -> (Vec<LogRecord>, u64) {
+        let skip = next_index.saturating_sub(self.first_index) as usize;
+        let records = self.records.iter().skip(skip).cloned().collect();
+        (records, self.first_index + self.records.len() as u64)
+    }
+}
+
+/// The records received by a [`crate::Client`], oldest first.
+#[derive(Default)]
+pub struct ReceivedLog {
+    records: VecDeque<LogRecord>,
+    num_received: u64,
+}
+
+impl ReceivedLog {
+    pub(crate) fn extend(&mut self, records: Vec<LogRecord>) {
+        self.num_received += records.len() as u64;
+        self.records.extend(records);
+        while self.records.len() > MAX_RECORDS {
+            self.records.pop_front();
+        }
+    }
+
+    /// The latest records, oldest first.
+    pub fn records(&self) -> impl Iterator<Item = &LogRecord> {
+        self.records.iter()
+    }
+
+    /// Total number of records received. Goes up when new records arrive.
+    pub fn num_received(&self) -> u64 {
+        self.num_received
+    }
+}
+
+/// A [`tracing_subscriber::Layer`] that sends the log to all clients. See [`crate::Server::log_layer`].
+///
+/// Records from eterm itself are not sent, since sending them would log more.
+#[cfg(feature = "remote_log")]
+pub struct LogLayer {
+    pub(crate) buffer: std::sync::Arc<parking_lot::Mutex<LogBuffer>>,
+}
+
+#[cfg(feature = "remote_log")]
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for LogLayer {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        let metadata = event.metadata();
+        let target = metadata.target();
+        if target == "eterm" || target.starts_with("eterm::") {
+            return;
+        }
+
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+
+        self.buffer.lock().push(LogRecord {
+            time: crate::clock::now(),
+            level: (*metadata.level()).into(),
+            target: target.to_owned(),
+            message: visitor.message,
+        });
+    }
+}
+
+/// Formats the fields of an event as `message key=value key=value`.
+#[cfg(feature = "remote_log")]
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+#[cfg(feature = "remote_log")]
+impl tracing::field::Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        use std::fmt::Write as _;
+        if field.name() == "message" {
+            self.message.insert_str(0, &format!("{:?}", value));
+        } else {
+            write!(self.message, " {}={:?}", field.name(), value).ok();
+        }
+    }
+}
+
+#[test]
+fn test_log_buffer() {
+    let record = |message: &str| LogRecord {
+        time: 0.0,
+        level: Level::Info,
+        target: "test".to_owned(),
+        message: message.to_owned(),
+    };
+
+    let mut buffer = LogBuffer::default();
+    buffer.push(record("first"));
+    buffer.push(record("second"));
+
+    let (records, next_index) = buffer.since(0);
+    assert_eq!(records, vec![record("first"), record("second")]);
+    assert_eq!(next_index, 2);
+
+    let (records, next_index) = buffer.since(next_index);
+    assert!(records.is_empty());
+    assert_eq!(next_index, 2);
+
+    for i in 0..MAX_RECORDS {
+        buffer.push(record(&i.to_string()));
+    }
+    let (records, next_index) = buffer.since(2);
+    assert_eq!(records.len(), MAX_RECORDS, "the oldest are forgotten");
+    assert_eq!(next_index, MAX_RECORDS as u64 + 2);
+}
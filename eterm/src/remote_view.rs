@@ -0,0 +1,175 @@
+use egui::{Pos2, Rect, Response, Sense, Vec2};
+
+use crate::Client;
+
+/// Shows a remote eterm session inside an existing egui app.
+///
+/// ``` no_run
+/// # let mut client = eterm::Client::new("127.0.0.1:8505".to_owned());
+/// # let ctx = egui::CtxRef::default();
+/// # egui::CentralPanel::default().show(&ctx, |ui| {
+/// eterm::RemoteView::new(&mut client).ui(ui);
+/// # });
+/// ```
+///
+/// Text is laid out with the fonts of the host app.
+///
+/// The client receives frames in the background, so the host app needs to keep repainting
+/// for as long as the view is shown. [`RemoteView::ui`] requests that for you.
+pub struct RemoteView<'a> {
+    client: &'a mut Client,
+    desired_size: Option<Vec2>,
+}
+
+impl<'a> RemoteView<'a> {
+    pub fn new(client: &'a mut Client) -> Self {
+        Self {
+            client,
+            desired_size: None,
+        }
+    }
+
+    /// Default: all available space.
+    pub fn desired_size(mut self, desired_size: Vec2) -> Self {
+        self.desired_size = Some(desired_size);
+        self
+    }
+
+    pub fn ui(self, ui: &mut egui::Ui) -> Response {
+        let Self {
+            client,
+            desired_size,
+        } = self;
+
+        let desired_size = desired_size.unwrap_or_else(|| ui.available_size());
+        let (rect, response) = ui.allocate_exact_size(desired_size, Sense::click());
+        if response.clicked() {
+            response.request_focus();
+        }
+
+        send_input(ui, client, rect, &response);
+
+        let offset = rect.min.to_vec2();
+
+        // The remote cursor etc is state that we need to keep applying every frame,
+        // not just the frames when something new arrives.
+        let remote_state_id = response.id.with("remote_output_state");
+
+        let pixels_per_point = ui.ctx().pixels_per_point();
+        if let Some(frame) = client.update(pixels_per_point) {
+            let mut remote = frame.output;
+            crate::output::translate(&mut remote, offset);
+
+            let remote_state: RemoteOutputState = (
+                remote.cursor_icon,
+                remote.mutable_text_under_cursor,
+                remote.text_cursor_pos,
+            );
+            ui.memory().data.insert_temp(remote_state_id, remote_state);
+
+            let mut output = ui.output();
+            if remote.open_url.is_some() {
+                output.open_url = remote.open_url;
+            }
+            if !remote.copied_text.is_empty() {
+                output.copied_text = remote.copied_text;
+            }
+            output.events.append(&mut remote.events);
+        }
+
+        let remote_state = ui
+            .memory()
+            .data
+            .get_temp::<RemoteOutputState>(remote_state_id);
+        if let Some((cursor_icon, mutable_text_under_cursor, text_cursor_pos)) = remote_state {
+            let mut output = ui.output();
+            if response.hovered() {
+                output.cursor_icon = cursor_icon;
+                output.mutable_text_under_cursor |= mutable_text_under_cursor;
+            }
+            if response.has_focus() && text_cursor_pos.is_some() {
+                output.text_cursor_pos = text_cursor_pos;
+            }
+        }
+
+        let clipped_shapes = crate::net_shape::from_clipped_net_shapes(
+            ui.fonts(),
+            client.latest_clipped_net_shapes(),
+        );
+        for egui::epaint::ClippedShape(clip_rect, mut shape) in clipped_shapes {
+            shape.translate(offset);
+            ui.painter()
+                .sub_region(clip_rect.translate(offset).intersect(rect))
+                .add(shape);
+        }
+
+        if response.has_focus() {
+            ui.painter()
+                .rect_stroke(rect, 0.0, ui.visuals().selection.stroke);
+        }
+
+        ui.ctx().request_repaint(); // poll the client
+        response
+    }
+}
+
+/// `cursor_icon`, `mutable_text_under_cursor` and `text_cursor_pos` of the latest remote output.
+type RemoteOutputState = (egui::CursorIcon, bool, Option<Pos2>);
+
+fn send_input(ui: &egui::Ui, client: &Client, rect: Rect, response: &Response) {
+    let offset = rect.min.to_vec2();
+    let input = ui.input();
+
+    let mut events = vec![];
+    for event in &input.events {
+        match event {
+            egui::Event::PointerMoved(pos) => {
+                if rect.contains(*pos) {
+                    events.push(egui::Event::PointerMoved(*pos - offset));
+                }
+            }
+            egui::Event::PointerButton {
+                pos,
+                button,
+                pressed,
+                modifiers,
+            } => {
+                // Always forward releases, so the remote side doesn't think a button is stuck.
+                if rect.contains(*pos) || !*pressed {
+                    events.push(egui::Event::PointerButton {
+                        pos: *pos - offset,
+                        button: *button,
+                        pressed: *pressed,
+                        modifiers: *modifiers,
+                    });
+                }
+            }
+            egui::Event::PointerGone => events.push(egui::Event::PointerGone),
+            _ => {
+                if response.has_focus() {
+                    events.push(event.clone());
+                }
+            }
+        }
+    }
+
+    let screen_rect = Rect::from_min_size(Pos2::ZERO, rect.size());
+    let last_screen_rect_id = response.id.with("last_screen_rect");
+    let last_screen_rect = ui.memory().data.get_temp::<Rect>(last_screen_rect_id);
+
+    if events.is_empty() && last_screen_rect == Some(screen_rect) {
+        return; // Nothing new
+    }
+
+    ui.memory()
+        .data
+        .insert_temp(last_screen_rect_id, screen_rect);
+
+    client.send_input(egui::RawInput {
+        screen_rect: Some(screen_rect),
+        pixels_per_point: Some(ui.ctx().pixels_per_point()),
+        modifiers: input.modifiers,
+        events,
+        ..Default::default()
+    });
+}
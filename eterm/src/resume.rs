@@ -0,0 +1,133 @@
+//! Session resumption tokens.
+//!
+//! When a client connects, the server gives it an opaque token in [`crate::ServerToClientMessage::Hello`].
+//! If the connection drops, the client presents the token when it reconnects,
+//! and gets its old session back (same [`crate::ClientId`], same egui memory)
+//! instead of starting a new one.
+//!
+//! Tokens are encrypted and authenticated (ChaCha20-Poly1305), so clients can neither read nor forge them.
+//! Servers sharing a secret (see [`crate::Server::set_resumption_secret`]) accept each other's tokens,
+//! so a client failing over to a replica keeps its [`crate::ClientId`].
+//! The ui state itself lives in the server process though, so that does not survive a failover.
+
+use anyhow::Context as _;
+use chacha20poly1305::{
+    aead::{Aead, NewAead},
+    ChaCha20Poly1305, Key, Nonce,
+};
+
+/// Tokens older than this are not accepted.
+const TOKEN_LIFETIME_SECONDS: u64 = 24 * 60 * 60;
+
+const NONCE_LEN: usize = 12;
+
+/// What a valid token told us.
+pub(crate) struct ResumedSession {
+    pub client_id: u64,
+    /// Was the token issued by this server (rather than a replica)?
+    pub same_server: bool,
+}
+
+/// Issues and verifies resumption tokens.
+pub(crate) struct TokenKey {
+    cipher: ChaCha20Poly1305,
+    /// Unique for each [`crate::Server`], so we can tell our own tokens from a replica's.
+    server_id: u64,
+}
+
+impl TokenKey {
+    /// A key with a random secret, so only this server accepts its tokens.
+    pub fn random() -> anyhow::Result<Self> {
+        let mut secret = [0_u8; 32];
+        getrandom::getrandom(&mut secret).context("getrandom")?;
+        let mut server_id = [0_u8; 8];
+        getrandom::getrandom(&mut server_id).context("getrandom")?;
+        Ok(Self {
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&secret)),
+            server_id: u64::from_le_bytes(server_id),
+        })
+    }
+
+    pub fn set_secret(&mut self, secret: &[u8; 32]) {
+        self.cipher = ChaCha20Poly1305::new(Key::from_slice(secret));
+    }
+
+    pub fn issue(&self, client_id: u64) -> anyhow::Result<Vec<u8>> {
+        let mut plaintext = Vec::with_capacity(24);
+        plaintext.extend_from_slice(&self.server_id.to_le_bytes());
+        plaintext.extend_from_slice(&client_id.to_le_bytes());
+        plaintext.extend_from_slice(&unix_time().to_le_bytes());
+
+        let mut nonce = [0_u8; NONCE_LEN];
+        getrandom::getrandom(&mut nonce).context("getrandom")?;
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+            .map_err(|_| anyhow::format_err!("Failed to encrypt resumption token"))?;
+
+        let mut token = nonce.to_vec();
+        token.extend_from_slice(&ciphertext);
+        Ok(token)
+    }
+
+    /// `None` if the token is forged, corrupt, from a server with another secret, or too old.
+    pub fn verify(&self, token: &[u8]) -> Option<ResumedSession> {
+        if token.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = token.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .ok()?;
+        if plaintext.len() != 24 {
+            return None;
+        }
+
+        let read_u64 = |i: usize| {
+            let mut bytes = [0_u8; 8];
+            bytes.copy_from_slice(&plaintext[8 * i..8 * (i + 1)]);
+            u64::from_le_bytes(bytes)
+        };
+        let (server_id, client_id, issued_at) = (read_u64(0), read_u64(1), read_u64(2));
+
+        if unix_time().saturating_sub(issued_at) > TOKEN_LIFETIME_SECONDS {
+            return None;
+        }
+
+        Some(ResumedSession {
+            client_id,
+            same_server: server_id == self.server_id,
+        })
+    }
+}
+
+fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[test]
+fn test_resumption_token() {
+    let key = TokenKey::random().unwrap();
+    let token = key.issue(42).unwrap();
+    let session = key.verify(&token).unwrap();
+    assert_eq!(session.client_id, 42);
+    assert!(session.same_server);
+
+    let mut forged = token.clone();
+    *forged.last_mut().unwrap() ^= 1;
+    assert!(key.verify(&forged).is_none());
+
+    let other_server = TokenKey::random().unwrap();
+    assert!(other_server.verify(&token).is_none(), "different secret");
+
+    let mut replica = TokenKey::random().unwrap();
+    let mut shared_key = key;
+    shared_key.set_secret(&[7; 32]);
+    replica.set_secret(&[7; 32]);
+    let session = replica.verify(&shared_key.issue(42).unwrap()).unwrap();
+    assert_eq!(session.client_id, 42);
+    assert!(!session.same_server);
+}
@@ -0,0 +1,122 @@
+//! What each client may do, assigned by the [`crate::Server::set_authenticator`].
+//!
+//! Read the role of the client being shown with [`role`], from within [`crate::Server::show`].
+
+use crate::ClientId;
+
+/// What a client may do. Ordered from least to most trusted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Role {
+    /// Can watch, but not click, type or use a gamepad.
+    /// The server drops such input before the ui sees it.
+    Viewer,
+    /// Can use the ui.
+    Operator,
+    /// Can use the ui and [`kick`] other clients.
+    /// Everyone is an admin if there is no [`crate::Server::set_authenticator`].
+    Admin,
+}
+
+impl Default for Role {
+    fn default() -> Self {
+        Self::Admin
+    }
+}
+
+impl Role {
+    /// Can this client change anything?
+    pub fn can_control(self) -> bool {
+        self >= Self::Operator
+    }
+
+    /// Can this client [`kick`] others?
+    pub fn can_kick(self) -> bool {
+        self == Self::Admin
+    }
+}
+
+/// Remove the input a [`Role::Viewer`] may not send. Pointer moves and scrolling are kept:
+/// they only change what the client itself sees, and keep remote cursors working.
+pub(crate) fn strip_control_input(raw_input: &mut egui::RawInput) {
+    raw_input.events.retain(|event| {
+        matches!(
+            event,
+            egui::Event::PointerMoved(_) | egui::Event::PointerGone
+        )
+    });
+    raw_input.hovered_files.clear();
+    raw_input.dropped_files.clear();
+}
+
+fn role_id() -> egui::Id {
+    egui::Id::new("eterm_role")
+}
+
+fn kick_requests_id() -> egui::Id {
+    egui::Id::new("eterm_kick_requests")
+}
+
+pub(crate) fn set_role(egui_ctx: &egui::CtxRef, role: Role) {
+    egui_ctx.memory().data.insert_temp(role_id(), role);
+}
+
+/// The role of the client currently being shown, e.g. to hide buttons it can't use.
+///
+/// Call this from within the closure given to [`crate::Server::show`].
+pub fn role(egui_ctx: &egui::CtxRef) -> Role {
+    egui_ctx
+        .memory()
+        .data
+        .get_temp(role_id())
+        .unwrap_or_default()
+}
+
+/// Disconnect another client, e.g. from a "Kick" button.
+/// Ignored (with a warning) unless the client currently being shown is a [`Role::Admin`].
+///
+/// Call this from within the closure given to [`crate::Server::show`].
+pub fn kick(egui_ctx: &egui::CtxRef, client_id: ClientId) {
+    egui_ctx
+        .memory()
+        .data
+        .get_temp_mut_or_default::<Vec<ClientId>>(kick_requests_id())
+        .push(client_id);
+}
+
+/// The clients passed to [`kick`] since last call.
+pub(crate) fn take_kick_requests(egui_ctx: &egui::CtxRef) -> Vec<ClientId> {
+    std::mem::take(
+        egui_ctx
+            .memory()
+            .data
+            .get_temp_mut_or_default::<Vec<ClientId>>(kick_requests_id()),
+    )
+}
+
+#[test]
+fn test_strip_control_input() {
+    let mut raw_input = egui::RawInput {
+        events: vec![
+            egui::Event::PointerMoved(egui::pos2(1.0, 2.0)),
+            egui::Event::PointerButton {
+                pos: egui::pos2(1.0, 2.0),
+                button: egui::PointerButton::Primary,
+                pressed: true,
+                modifiers: Default::default(),
+            },
+            egui::Event::Text("rm -rf".to_owned()),
+            egui::Event::PointerGone,
+        ],
+        scroll_delta: egui::vec2(0.0, 10.0),
+        ..Default::default()
+    };
+    strip_control_input(&mut raw_input);
+    assert_eq!(
+        raw_input.events,
+        vec![
+            egui::Event::PointerMoved(egui::pos2(1.0, 2.0)),
+            egui::Event::PointerGone,
+        ]
+    );
+    assert_eq!(raw_input.scroll_delta, egui::vec2(0.0, 10.0));
+}
@@ -0,0 +1,94 @@
+//! The schema of all protocol messages, checked into `tests/schema.yaml`.
+//!
+//! It describes every type on the wire in the `serde-reflection` format,
+//! so that clients in other languages can be generated from it (e.g. with `serde-generate`).
+//!
+//! If the test fails you have changed the wire format. Check that the change is backwards compatible
+//! (the golden tests help with that) and re-generate the schema with:
+//!
+//! ``` sh
+//! ETERM_BLESS=1 cargo test -p eterm schema
+//! ```
+
+use serde_reflection::{Samples, Tracer, TracerConfig};
+
+use crate::{
+    gamepad::{GamepadAxis, GamepadButton, GamepadEvent},
+    ClientToServerMessage, ServerToClientMessage,
+};
+
+fn schema_path() -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/schema.yaml")
+}
+
+fn trace_schema() -> String {
+    let mut tracer = Tracer::new(TracerConfig::default());
+
+    // Enums are only explored fully when traced on their own:
+    tracer.trace_simple_type::<egui::Align>().unwrap();
+    tracer.trace_simple_type::<egui::CursorIcon>().unwrap();
+    tracer.trace_simple_type::<egui::Event>().unwrap();
+    tracer.trace_simple_type::<egui::FontFamily>().unwrap();
+    tracer.trace_simple_type::<egui::Key>().unwrap();
+    tracer.trace_simple_type::<egui::PointerButton>().unwrap();
+    tracer.trace_simple_type::<egui::TextStyle>().unwrap();
+    tracer.trace_simple_type::<egui::TextureId>().unwrap();
+    tracer.trace_simple_type::<egui::TouchPhase>().unwrap();
+    tracer.trace_simple_type::<egui::WidgetType>().unwrap();
+    tracer
+        .trace_simple_type::<egui::output::OutputEvent>()
+        .unwrap();
+    tracer.trace_simple_type::<GamepadAxis>().unwrap();
+    tracer.trace_simple_type::<GamepadButton>().unwrap();
+    tracer.trace_simple_type::<GamepadEvent>().unwrap();
+    tracer
+        .trace_simple_type::<crate::codec::Compression>()
+        .unwrap();
+    tracer
+        .trace_simple_type::<crate::notice::Severity>()
+        .unwrap();
+    tracer
+        .trace_simple_type::<crate::remote_log::Level>()
+        .unwrap();
+    tracer
+        .trace_simple_type::<crate::net_shape::NetShape>()
+        .unwrap();
+    tracer.trace_simple_type::<crate::GoodbyeReason>().unwrap();
+
+    let samples = Samples::new();
+    tracer
+        .trace_type::<ClientToServerMessage>(&samples)
+        .unwrap();
+    tracer
+        .trace_type::<ServerToClientMessage>(&samples)
+        .unwrap();
+
+    let registry = tracer
+        .registry()
+        .unwrap_or_else(|err| panic!("Incomplete schema, trace more types above: {}", err));
+    serde_yaml::to_string(&registry).unwrap()
+}
+
+#[test]
+fn schema() {
+    let path = schema_path();
+    let schema = trace_schema();
+
+    if std::env::var("ETERM_BLESS").is_ok() {
+        std::fs::write(&path, &schema).unwrap();
+        return;
+    }
+
+    let checked_in = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+        panic!(
+            "{:?}: {}. Generate it with ETERM_BLESS=1 cargo test -p eterm schema",
+            path, err
+        )
+    });
+    assert!(
+        checked_in == schema,
+        "The wire schema has changed. Diff {:?} against:\n{}",
+        path,
+        schema
+    );
+}
@@ -0,0 +1,124 @@
+/// Maps the remote ui onto the viewer window: `window_pos = scale * remote_pos + offset`.
+///
+/// Set with [`crate::Client::set_screen_transform`], e.g. to [`Self::letterbox`]
+/// a remote ui of a fixed size into a window of another size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScreenTransform {
+    pub scale: f32,
+    pub offset: egui::Vec2,
+}
+
+impl Default for ScreenTransform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl ScreenTransform {
+    pub const IDENTITY: Self = Self {
+        scale: 1.0,
+        offset: egui::Vec2::ZERO,
+    };
+
+    /// Scale `remote` to fit inside `window`, keeping the aspect ratio, with the rest of `window` left empty.
+    pub fn letterbox(remote: egui::Rect, window: egui::Rect) -> Self {
+        if !remote.is_positive() || !window.is_positive() {
+            return Self::IDENTITY;
+        }
+        let scale = (window.width() / remote.width()).min(window.height() / remote.height());
+        Self {
+            scale,
+            offset: window.center().to_vec2() - scale * remote.center().to_vec2(),
+        }
+    }
+
+    /// Magnify by `zoom` around `center`, which stays put.
+    pub fn zoom_around(center: egui::Pos2, zoom: f32) -> Self {
+        Self {
+            scale: zoom,
+            offset: (1.0 - zoom) * center.to_vec2(),
+        }
+    }
+
+    /// First apply `self`, then `outer`.
+    pub fn then(self, outer: Self) -> Self {
+        Self {
+            scale: outer.scale * self.scale,
+            offset: outer.scale * self.offset + outer.offset,
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    /// From remote to window coordinates.
+    pub fn transform_pos(&self, pos: egui::Pos2) -> egui::Pos2 {
+        (self.scale * pos.to_vec2()).to_pos2() + self.offset
+    }
+
+    /// From window to remote coordinates.
+    pub fn inverse_pos(&self, pos: egui::Pos2) -> egui::Pos2 {
+        ((pos - self.offset) / self.scale).to_pos2()
+    }
+
+    /// From remote to window coordinates.
+    pub fn transform_rect(&self, rect: egui::Rect) -> egui::Rect {
+        egui::Rect::from_min_max(self.transform_pos(rect.min), self.transform_pos(rect.max))
+    }
+
+    /// Move the tessellated remote ui into the window.
+    pub(crate) fn transform_meshes(&self, clipped_meshes: &mut [egui::ClippedMesh]) {
+        if self.is_identity() {
+            return;
+        }
+        for egui::ClippedMesh(clip_rect, mesh) in clipped_meshes {
+            *clip_rect = self.transform_rect(*clip_rect);
+            for vertex in &mut mesh.vertices {
+                vertex.pos = self.transform_pos(vertex.pos);
+            }
+        }
+    }
+
+    /// Move the pointer input from the window into the remote ui.
+    pub(crate) fn inverse_input(&self, raw_input: &mut egui::RawInput) {
+        if self.is_identity() {
+            return;
+        }
+        raw_input.scroll_delta = raw_input.scroll_delta / self.scale;
+        for event in &mut raw_input.events {
+            match event {
+                egui::Event::PointerMoved(pos)
+                | egui::Event::PointerButton { pos, .. }
+                | egui::Event::Touch { pos, .. } => {
+                    *pos = self.inverse_pos(*pos);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[test]
+fn test_letterbox() {
+    let remote = egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(800.0, 600.0));
+    let window = egui::Rect::from_min_size(egui::pos2(0.0, 24.0), egui::vec2(1600.0, 800.0));
+    let transform = ScreenTransform::letterbox(remote, window);
+
+    assert_eq!(transform.scale, 800.0 / 600.0);
+    let fitted = transform.transform_rect(remote);
+    assert!((fitted.center() - window.center()).length() < 1e-3);
+    assert!((fitted.height() - window.height()).abs() < 1e-3);
+    assert!(fitted.width() < window.width());
+
+    let pos = egui::pos2(123.0, 456.0);
+    assert!((transform.inverse_pos(transform.transform_pos(pos)) - pos).length() < 1e-3);
+
+    assert!(ScreenTransform::letterbox(remote, remote).is_identity());
+
+    let center = egui::pos2(400.0, 300.0);
+    let magnified = transform.then(ScreenTransform::zoom_around(center, 2.0));
+    assert_eq!(magnified.scale, 2.0 * transform.scale);
+    let remote_center = transform.inverse_pos(center);
+    assert!((magnified.transform_pos(remote_center) - center).length() < 1e-3);
+}
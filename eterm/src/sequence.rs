@@ -0,0 +1,112 @@
+//! Packet sequence numbers and acknowledgements.
+//!
+//! TCP delivers packets in order, so the sequence numbers are implicit:
+//! both sides count the packets sent and received on a connection, starting at zero.
+//! The client acknowledges what it has received with [`crate::ClientToServerMessage::Ack`],
+//! which lets the server know how many bytes are still in flight.
+//!
+//! Acknowledging is optional, so the server only keeps track once it gets the first ack.
+
+use std::collections::VecDeque;
+
+/// Don't remember more unacknowledged packets than this, in case the acks stop coming.
+const MAX_UNACKED: usize = 1024;
+
+#[derive(Default)]
+pub(crate) struct Sequencer {
+    /// Sequence number of the next packet we send.
+    next_send: u64,
+    /// Sequence number of the next packet we expect to receive.
+    next_receive: u64,
+    /// Sequence number of the first entry in `unacked`.
+    first_unacked: u64,
+    /// Sizes of the sent packets the other side has not acknowledged yet, oldest first.
+    unacked: VecDeque<usize>,
+    /// Has the other side ever acknowledged anything?
+    acks_seen: bool,
+}
+
+impl Sequencer {
+    /// Returns the sequence number of the sent packet.
+    pub fn on_send(&mut self, bytes: usize) -> u64 {
+        let sequence = self.next_send;
+        self.next_send += 1;
+        if self.acks_seen {
+            if self.unacked.len() == MAX_UNACKED {
+                self.unacked.pop_front();
+                self.first_unacked += 1;
+            }
+            self.unacked.push_back(bytes);
+        }
+        sequence
+    }
+
+    /// Returns the sequence number of the received packet.
+    pub fn on_receive(&mut self) -> u64 {
+        let sequence = self.next_receive;
+        self.next_receive += 1;
+        sequence
+    }
+
+    /// How many packets we have received, i.e. what to acknowledge.
+    pub fn num_received(&self) -> u64 {
+        self.next_receive
+    }
+
+    /// The other side has received all packets before `num_received`.
+    pub fn on_ack(&mut self, num_received: u64) {
+        if !self.acks_seen {
+            // Start keeping track from here on:
+            self.acks_seen = true;
+            self.first_unacked = self.next_send;
+            return;
+        }
+        while self.first_unacked < num_received.min(self.next_send) {
+            if self.unacked.pop_front().is_none() {
+                break;
+            }
+            self.first_unacked += 1;
+        }
+    }
+
+    /// Bytes sent but not yet acknowledged.
+    ///
+    /// `None` if the other side doesn't acknowledge.
+    pub fn bytes_in_flight(&self) -> Option<usize> {
+        if self.acks_seen {
+            Some(self.unacked.iter().sum())
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn test_sequencer() {
+    let mut sender = Sequencer::default();
+    let mut receiver = Sequencer::default();
+
+    assert_eq!(sender.on_send(100), 0);
+    assert_eq!(receiver.on_receive(), 0);
+    assert_eq!(sender.bytes_in_flight(), None, "no acks yet");
+
+    sender.on_ack(receiver.num_received());
+    assert_eq!(sender.bytes_in_flight(), Some(0));
+
+    assert_eq!(sender.on_send(10), 1);
+    assert_eq!(sender.on_send(20), 2);
+    assert_eq!(sender.on_send(30), 3);
+    assert_eq!(sender.bytes_in_flight(), Some(60));
+
+    assert_eq!(receiver.on_receive(), 1);
+    assert_eq!(receiver.on_receive(), 2);
+    sender.on_ack(receiver.num_received());
+    assert_eq!(sender.bytes_in_flight(), Some(30));
+
+    sender.on_ack(1); // late, out of date ack
+    assert_eq!(sender.bytes_in_flight(), Some(30));
+
+    assert_eq!(receiver.on_receive(), 3);
+    sender.on_ack(receiver.num_received());
+    assert_eq!(sender.bytes_in_flight(), Some(0));
+}
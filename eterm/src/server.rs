@@ -1,21 +1,288 @@
-use std::{
-    collections::HashMap,
-    net::{SocketAddr, TcpListener},
-};
+use std::{collections::HashMap, net::SocketAddr};
 
 use anyhow::Context as _;
-use egui::RawInput;
+use egui::{util::History, RawInput};
 
-use crate::{net_shape::ClippedNetShape, ClientToServerMessage};
+use crate::{gamepad::Gamepads, net_shape::ClippedNetShape, ClientToServerMessage};
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+/// Shown to web browsers connecting to the server.
+const HTTP_PAGE: &str = include_str!("http_page.html");
+
+/// The [`mio::Token`] of the listening socket. Clients use [`ClientId::token`].
+const LISTENER: mio::Token = mio::Token(0);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ClientId(u64);
 
+impl ClientId {
+    /// Unique for each client of a [`Server`].
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    fn token(self) -> mio::Token {
+        mio::Token(self.0 as usize + 1)
+    }
+}
+
+/// A bundle of settings for one client. See [`Server::set_client_profile`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    /// Full quality. The default.
+    Default,
+    /// For slow connections: at most 10 frames per second, no animations,
+    /// and positions rounded to whole points, which compresses better.
+    /// MJPEG streams are also painted without anti-aliasing.
+    LowBandwidth,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::Default
+    }
+}
+
+/// Frame cap of [`Profile::LowBandwidth`].
+const LOW_BANDWIDTH_FPS: f32 = 10.0;
+
+/// The most input events we keep for a client between two frames.
+const MAX_BUFFERED_EVENTS: usize = 1000;
+
+/// See [`Server::set_disconnected_client_retention`].
+const DEFAULT_DISCONNECTED_CLIENT_RETENTION: std::time::Duration =
+    std::time::Duration::from_secs(24 * 60 * 60);
+
+/// Notices when painting all clients takes longer than the frame budget.
+/// See [`Server::set_frame_budget`].
+struct LoadMonitor {
+    /// Seconds per [`Server::show`] we can spend on painting.
+    budget: f32,
+    /// Smoothed time spent painting in [`Server::show`], in seconds.
+    smoothed: f32,
+    overloaded: bool,
+}
+
+impl LoadMonitor {
+    fn new(budget: f32) -> Self {
+        Self {
+            budget,
+            smoothed: 0.0,
+            overloaded: false,
+        }
+    }
+
+    /// Call after painting all clients. Returns true if we are (still) overloaded.
+    fn on_show(&mut self, seconds: f32) -> bool {
+        self.smoothed = 0.9 * self.smoothed + 0.1 * seconds;
+        if !self.overloaded && self.smoothed > self.budget {
+            tracing::warn!(
+                "Painting takes {:.1} ms, over the budget of {:.1} ms. Sending fewer frames.",
+                self.smoothed * 1e3,
+                self.budget * 1e3
+            );
+            self.overloaded = true;
+        } else if self.overloaded && self.smoothed < 0.5 * self.budget {
+            tracing::info!("Painting is back within budget");
+            self.overloaded = false;
+        }
+        self.overloaded
+    }
+}
+
+/// Times the frames of a client to arrive just before its vsync.
+/// See [`ClientToServerMessage::DisplayTiming`].
+struct FramePacing {
+    /// Seconds between two vsyncs of the viewer.
+    period: f64,
+    /// Server time to send at, give or take a multiple of `period`.
+    send_phase: f64,
+    /// See [`Self::slot`].
+    last_slot: Option<i64>,
+}
+
+impl FramePacing {
+    /// Which refresh of the viewer a frame sent at `now` (server clock) will be shown at.
+    fn slot(&self, now: f64) -> i64 {
+        ((now - self.send_phase) / self.period).floor() as i64
+    }
+}
+
+/// See [`Server::set_input_filter`].
+type InputFilter = dyn FnMut(ClientId, &mut RawInput) + Send;
+
+/// See [`Server::add_ui`].
+type Ui = dyn FnMut(&egui::CtxRef, ClientId) + Send;
+
+/// See [`Server::set_audit_sink`].
+type AuditSink = dyn FnMut(&crate::audit::AuditRecord) + Send;
+
+/// See [`Server::set_frame_hook`].
+type FrameHook = dyn FnMut(&ClientInfo, &mut Vec<ClippedNetShape>) + Send;
+
+/// See [`Server::set_ui_router`].
+type UiRouter = dyn FnMut(&ClientInfo) -> Option<String> + Send;
+
+/// See [`Server::set_authenticator`].
+type Authenticator = dyn FnMut(&ClientInfo) -> Option<crate::role::Role> + Send;
+
+/// About a connected client.
+#[derive(Clone, Debug)]
+pub struct ClientInfo {
+    pub client_id: ClientId,
+    /// Where the client connected from.
+    pub addr: SocketAddr,
+    /// What the viewer would like, including its locale.
+    pub preferences: crate::ClientPreferences,
+    /// The name of the ui shown to the client, if not the one given to [`Server::show`].
+    /// See [`Server::assign_ui`].
+    pub ui: Option<String>,
+    /// What the client may do. See [`Server::set_authenticator`].
+    pub role: crate::role::Role,
+}
+
+/// What happened since the last [`Server::poll`].
+#[derive(Clone, Debug)]
+pub enum ServerEvent {
+    /// A client connected and said hello, or got its session back.
+    ClientConnected(ClientId),
+    /// Input from the client, for the next frame you paint it with [`Server::paint_client`].
+    /// Already passed through the [`Server::set_input_filter`].
+    InputReceived {
+        client_id: ClientId,
+        raw_input: RawInput,
+    },
+    ClientDisconnected(ClientId),
+}
+
+/// How long eterm spends on one client, averaged over the last few seconds.
+///
+/// All times are in seconds, and `None` if nothing has been measured recently.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientStats {
+    pub client_id: ClientId,
+    /// Running the closure given to [`Server::show`], including egui's own work.
+    pub ui_time: Option<f32>,
+    /// Converting the shapes to [`crate::net_shape::NetShape`]:s.
+    pub net_shape_time: Option<f32>,
+    /// Serializing and compressing a frame.
+    pub encode_time: Option<f32>,
+    /// Writing a frame to the socket.
+    pub send_time: Option<f32>,
+    /// Frames sent per second.
+    pub frames_per_second: Option<f32>,
+    /// The compression negotiated with this client.
+    pub compression: crate::codec::Compression,
+    /// The current compression level. Goes up when the connection can't keep up,
+    /// and down when it can, or when compressing takes too long.
+    pub compression_level: i32,
+    /// Bytes sent that the client has not acknowledged yet.
+    /// `None` if the client doesn't acknowledge what it receives.
+    pub bytes_in_flight: Option<usize>,
+}
+
+/// Per-client [`egui::util::History`]:s backing [`ClientStats`].
+struct Timings {
+    ui: History<f32>,
+    net_shapes: History<f32>,
+    encode: History<f32>,
+    send: History<f32>,
+    frames: History<()>,
+    /// Frame sizes before compression.
+    uncompressed_size: History<f32>,
+    /// Frame sizes after compression.
+    compressed_size: History<f32>,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Self {
+            ui: History::new(0..300, 2.0),
+            net_shapes: History::new(0..300, 2.0),
+            encode: History::new(0..300, 2.0),
+            send: History::new(0..300, 2.0),
+            frames: History::new(2..300, 2.0),
+            uncompressed_size: History::new(0..300, 2.0),
+            compressed_size: History::new(0..300, 2.0),
+        }
+    }
+}
+
+impl Timings {
+    fn flush(&mut self) {
+        let now = crate::clock::now();
+        self.ui.flush(now);
+        self.net_shapes.flush(now);
+        self.encode.flush(now);
+        self.send.flush(now);
+        self.frames.flush(now);
+        self.uncompressed_size.flush(now);
+        self.compressed_size.flush(now);
+    }
+}
+
 pub struct Server {
     next_client_id: u64,
-    tcp_listener: TcpListener,
+    poll: mio::Poll,
+    events: mio::Events,
+    tcp_listener: mio::net::TcpListener,
     clients: HashMap<SocketAddr, Client>,
+    /// Which client owns which socket.
+    client_addrs: HashMap<mio::Token, SocketAddr>,
     minimum_update_interval: f32,
+    minimum_frame_interval: f32,
+    mjpeg_screen_size: egui::Vec2,
+    show_remote_cursors: bool,
+    /// See [`Self::set_max_session_duration`].
+    max_session_duration: Option<std::time::Duration>,
+    /// See [`Self::set_disconnected_client_retention`].
+    disconnected_client_retention: std::time::Duration,
+    /// See [`Self::set_input_rate_limit`].
+    input_rate_limit: Option<crate::rate_limit::InputRateLimit>,
+    /// See [`Self::set_frame_budget`].
+    load_monitor: Option<LoadMonitor>,
+    /// See [`Self::set_frame_diagnostics`].
+    frame_diagnostics: bool,
+    /// See [`Self::set_font_subsetting`].
+    font_subsetting: bool,
+    /// See [`Self::set_laid_out_text`].
+    laid_out_text: bool,
+    /// See [`Self::set_catch_panics`].
+    catch_panics: bool,
+    /// See [`Self::set_visuals_memory_budget`].
+    visuals_memory_budget: Option<usize>,
+    /// See [`Self::set_telemetry_interval`].
+    telemetry_interval: Option<f32>,
+    /// See [`Self::map_port_with_upnp`].
+    #[cfg(feature = "upnp")]
+    port_mapping: Option<crate::upnp::PortMapping>,
+    /// For session resumption tokens. See [`Self::set_resumption_secret`].
+    resumption_key: crate::resume::TokenKey,
+    /// Shown once by each viewer when connecting. See [`Self::set_motd`].
+    motd: String,
+    /// Sent to each viewer when connecting. See [`Self::set_app_info`].
+    app_info: crate::AppInfo,
+    /// Shown by all viewers. See [`Self::broadcast_notice`].
+    notice: Option<crate::notice::Notice>,
+    /// See [`Self::set_input_filter`].
+    input_filter: Option<Box<InputFilter>>,
+    /// See [`Self::set_frame_hook`].
+    frame_hook: Option<Box<FrameHook>>,
+    /// See [`Self::add_ui`].
+    uis: HashMap<String, Box<Ui>>,
+    /// See [`Self::set_ui_router`].
+    ui_router: Option<Box<UiRouter>>,
+    /// See [`Self::set_authenticator`].
+    authenticator: Option<Box<Authenticator>>,
+    /// See [`Self::set_audit_sink`].
+    audit_sink: Option<Box<AuditSink>>,
+    /// See [`Self::set_packet_dump_dir`].
+    packet_dumper: Option<std::sync::Arc<crate::packet_dump::PacketDumper>>,
+    /// See [`Self::preview`].
+    preview: Option<crate::preview::ServerPreview>,
+    /// Sent to all clients. Filled by [`Self::log_layer`].
+    log: std::sync::Arc<parking_lot::Mutex<crate::remote_log::LogBuffer>>,
+    /// The connected clients as of the last [`Self::poll`].
+    polled_clients: std::collections::HashSet<ClientId>,
 }
 
 impl Server {
@@ -24,134 +291,1569 @@ impl Server {
     /// # Errors
     /// Can fail if the port is already taken.
     pub fn new(bind_addr: &str) -> anyhow::Result<Self> {
-        let tcp_listener = TcpListener::bind(bind_addr).context("binding server TCP socket")?;
+        let tcp_listener =
+            std::net::TcpListener::bind(bind_addr).context("binding server TCP socket")?;
         tcp_listener
             .set_nonblocking(true)
             .context("TCP set_nonblocking")?;
+        let mut tcp_listener = mio::net::TcpListener::from_std(tcp_listener);
+
+        let poll = mio::Poll::new().context("mio::Poll")?;
+        poll.registry()
+            .register(&mut tcp_listener, LISTENER, mio::Interest::READABLE)
+            .context("registering server TCP socket")?;
 
         Ok(Self {
             next_client_id: 0,
+            poll,
+            events: mio::Events::with_capacity(128),
             tcp_listener,
             clients: Default::default(),
+            client_addrs: Default::default(),
             minimum_update_interval: 1.0,
+            minimum_frame_interval: 0.0,
+            mjpeg_screen_size: egui::vec2(1024.0, 768.0),
+            show_remote_cursors: false,
+            max_session_duration: None,
+            disconnected_client_retention: DEFAULT_DISCONNECTED_CLIENT_RETENTION,
+            input_rate_limit: None,
+            load_monitor: None,
+            frame_diagnostics: false,
+            font_subsetting: false,
+            laid_out_text: false,
+            catch_panics: false,
+            visuals_memory_budget: None,
+            telemetry_interval: None,
+            #[cfg(feature = "upnp")]
+            port_mapping: None,
+            resumption_key: crate::resume::TokenKey::random()?,
+            motd: String::new(),
+            app_info: Default::default(),
+            notice: None,
+            input_filter: None,
+            frame_hook: None,
+            uis: Default::default(),
+            ui_router: None,
+            authenticator: None,
+            audit_sink: None,
+            packet_dumper: None,
+            preview: None,
+            log: Default::default(),
+            polled_clients: Default::default(),
         })
     }
 
+    /// The address we are listening on, e.g. to find the port after binding to port 0 in tests.
+    ///
+    /// # Errors
+    /// If the OS can't tell.
+    pub fn local_addr(&self) -> anyhow::Result<SocketAddr> {
+        self.tcp_listener.local_addr().context("local_addr")
+    }
+
+    /// Serve a client over stdin and stdout, e.g. when started over SSH
+    /// by [`crate::Client::new_command`]. See [`crate::stdio`].
+    ///
+    /// The client is connected directly, not through the port we listen on.
+    /// Others on this machine can still connect to that port, so bind the server to `127.0.0.1:0`
+    /// and turn them away with [`Self::set_authenticator`]. Keep your logging off stdout.
+    ///
+    /// # Errors
+    /// If we can't make the local connection.
+    pub fn serve_stdio(&mut self) -> anyhow::Result<crate::stdio::StdioBridge> {
+        let (ours, theirs) = crate::stdio::local_pair().context("local connection")?;
+        let client_addr = ours.peer_addr().context("peer_addr")?;
+        ours.set_nonblocking(true).context("set_nonblocking")?;
+        let client_id = self.add_client(mio::net::TcpStream::from_std(ours), client_addr)?;
+        crate::stdio::StdioBridge::start(client_id, theirs)
+    }
+
+    /// Ask the local router (with UPnP) to forward our port from the internet,
+    /// so that someone outside your network can connect without you configuring the router.
+    ///
+    /// Returns the address they should connect to. The server must be bound to
+    /// all interfaces (e.g. `0.0.0.0:8505`). The forward is removed when the server is dropped.
+    ///
+    /// There is no authentication, so anyone who finds the address can use your ui.
+    /// Only use this for things you don't mind sharing, and not for longer than needed.
+    ///
+    /// Blocks for a few seconds while looking for the router.
+    ///
+    /// # Errors
+    /// If there is no UPnP router, or it refuses.
+    #[cfg(feature = "upnp")]
+    pub fn map_port_with_upnp(&mut self) -> anyhow::Result<SocketAddr> {
+        let local_port = self.local_addr()?.port();
+        let port_mapping = crate::upnp::PortMapping::new(local_port)?;
+        let external_addr = port_mapping.external_addr();
+        self.port_mapping = Some(port_mapping);
+        Ok(external_addr.into())
+    }
+
     /// Send a new frame to each client at least this often.
     /// Default: one second.
     pub fn set_minimum_update_interval(&mut self, seconds: f32) {
         self.minimum_update_interval = seconds;
     }
 
+    /// Never send more than this many frames per second to any one client,
+    /// even if the ui keeps calling `request_repaint`.
+    ///
+    /// Input is still received right away, but is applied in the next allowed frame.
+    /// Default: no limit.
+    pub fn set_maximum_frame_rate(&mut self, frames_per_second: f32) {
+        self.minimum_frame_interval = 1.0 / frames_per_second;
+    }
+
+    /// Log how many shapes changed, in which clip rects, and the encoded size of each frame sent.
+    ///
+    /// Useful for finding the widget that keeps changing when a seemingly static ui uses a lot of bandwidth.
+    /// Default: off.
+    pub fn set_frame_diagnostics(&mut self, frame_diagnostics: bool) {
+        self.frame_diagnostics = frame_diagnostics;
+    }
+
+    /// Send only the glyphs of the characters the client has been shown so far,
+    /// instead of the whole fonts set with [`egui::Context::set_fonts`].
+    /// When new characters show up, the fonts are sent again with those added.
+    ///
+    /// This makes the first frame arrive much sooner with big fonts, e.g. for CJK.
+    /// Only TrueType fonts are subset: other fonts are sent whole.
+    /// Default: off.
+    pub fn set_font_subsetting(&mut self, font_subsetting: bool) {
+        self.font_subsetting = font_subsetting;
+    }
+
+    /// Send text laid out, as meshes of glyphs, together with the font texture they sample,
+    /// instead of as [`egui::text::LayoutJob`]:s for the client to lay out.
+    ///
+    /// The client then shows exactly what the server laid out, whatever its fonts and egui version,
+    /// at the cost of a lot more bandwidth. Needs a viewer that knows
+    /// [`crate::ServerToClientMessage::FontImage`].
+    /// Only applies to [`Self::show`], and not to clients with [`Profile::LowBandwidth`].
+    ///
+    /// What paints with fonts of its own ([`crate::RemoteView`], [`Self::preview`])
+    /// can't show the laid-out text.
+    /// Default: off.
+    pub fn set_laid_out_text(&mut self, laid_out_text: bool) {
+        self.laid_out_text = laid_out_text;
+    }
+
+    /// Catch panics in the ui closure given to [`Self::show`], instead of letting one client
+    /// (e.g. with an odd screen size) take down the whole process.
+    ///
+    /// The client it panicked for is disconnected with [`crate::GoodbyeReason::ServerError`],
+    /// and the panic is logged and sent to the [`Self::set_audit_sink`] as
+    /// [`crate::audit::AuditEvent::UiPanicked`].
+    ///
+    /// Only use this if your ui state can't be left broken halfway through a frame.
+    /// Default: off.
+    pub fn set_catch_panics(&mut self, catch_panics: bool) {
+        self.catch_panics = catch_panics;
+    }
+
+    /// To skip sending frames where nothing changed, we keep the shapes last sent to each client.
+    /// For mesh-heavy uis that can be many megabytes per client.
+    ///
+    /// When the shapes of a client take more than `bytes`, we keep only a hash of them instead.
+    /// Unchanged frames are then still skipped, but [`Self::set_frame_diagnostics`]
+    /// can't tell what changed in them.
+    /// Default: no limit.
+    pub fn set_visuals_memory_budget(&mut self, bytes: usize) {
+        self.visuals_memory_budget = Some(bytes);
+    }
+
+    /// Send each client [`crate::Telemetry`] about how its frames are encoded every `seconds`,
+    /// which the viewer shows with its other stats. Useful when tuning the compression.
+    ///
+    /// Default: off.
+    pub fn set_telemetry_interval(&mut self, seconds: f32) {
+        self.telemetry_interval = Some(seconds);
+    }
+
+    /// Protect the host loop from too many or too heavy clients:
+    /// when painting all of them in [`Self::show`] takes longer than `seconds` (on average),
+    /// all clients are served as with [`Profile::LowBandwidth`] until it is well within budget again.
+    ///
+    /// Default: no budget.
+    pub fn set_frame_budget(&mut self, seconds: f32) {
+        self.load_monitor = Some(LoadMonitor::new(seconds));
+    }
+
+    /// Are we over the [`Self::set_frame_budget`]?
+    pub fn is_overloaded(&self) -> bool {
+        self.load_monitor
+            .as_ref()
+            .map_or(false, |load_monitor| load_monitor.overloaded)
+    }
+
+    /// The screen size (in points) of clients watching the MJPEG stream at `/mjpeg`.
+    ///
+    /// Only used with the `mjpeg` feature. Default: 1024x768.
+    pub fn set_mjpeg_screen_size(&mut self, screen_size: egui::Vec2) {
+        self.mjpeg_screen_size = screen_size;
+    }
+
+    /// Show each client where the other clients are pointing,
+    /// with a colored dot and a name label.
+    ///
+    /// Useful when several people look at the same ui together. Default: off.
+    pub fn set_show_remote_cursors(&mut self, show_remote_cursors: bool) {
+        self.show_remote_cursors = show_remote_cursors;
+    }
+
+    /// Disconnect clients this long after they first connected, so forgotten viewer windows
+    /// don't keep control forever. Resuming the session doesn't restart the clock.
+    ///
+    /// The viewer won't reconnect on its own (see [`crate::GoodbyeReason::SessionExpired`]),
+    /// so the user has to start a new session. Default: no limit.
+    pub fn set_max_session_duration(&mut self, max_session_duration: std::time::Duration) {
+        self.max_session_duration = Some(max_session_duration);
+    }
+
+    /// Forget the ui state of clients that have been disconnected for this long.
+    /// Until then they can resume their session (see [`crate::ServerToClientMessage::Hello`]).
+    ///
+    /// Default: 24 hours, which is as long as a session can be resumed.
+    pub fn set_disconnected_client_retention(&mut self, retention: std::time::Duration) {
+        self.disconnected_client_retention = retention;
+    }
+
+    /// Let each client send at most this many input events per second,
+    /// with bursts of up to `burst` events.
+    ///
+    /// Pointer moves over the limit are merged or dropped.
+    /// Clients that keep sending far more than this anyway are disconnected. Default: no limit.
+    pub fn set_input_rate_limit(&mut self, events_per_second: f32, burst: usize) {
+        let limit = crate::rate_limit::InputRateLimit {
+            events_per_second,
+            burst: burst as f32,
+        };
+        self.input_rate_limit = Some(limit);
+        for client in self.clients.values_mut() {
+            client.rate_limiter = Some(crate::rate_limit::RateLimiter::new(limit));
+        }
+    }
+
+    /// The client that most recently sent us input events (clicks, key presses, gamepad buttons, …).
+    ///
+    /// Within the closure given to [`Self::show`] the input is always from the
+    /// [`ClientId`] passed to it. Use this outside of it, e.g. to find out who
+    /// changed some shared state, for permissions or audit logs.
+    pub fn last_input_source(&self) -> Option<ClientId> {
+        self.clients
+            .values()
+            .filter_map(|client| Some((client.last_user_input?, client.client_id)))
+            .max_by_key(|(time, _)| *time)
+            .map(|(_, client_id)| client_id)
+    }
+
+    /// How much time eterm spends on each connected client.
+    ///
+    /// Use this to see if eterm itself is eating into your frame budget.
+    pub fn stats(&self) -> Vec<ClientStats> {
+        self.clients
+            .values()
+            .filter(|client| client.tcp_endpoint.is_some())
+            .map(|client| {
+                let timings = &client.timings;
+                ClientStats {
+                    client_id: client.client_id,
+                    ui_time: timings.ui.average(),
+                    net_shape_time: timings.net_shapes.average(),
+                    encode_time: timings.encode.average(),
+                    send_time: timings.send.average(),
+                    frames_per_second: timings.frames.rate(),
+                    compression: client
+                        .tcp_endpoint
+                        .as_ref()
+                        .map_or(crate::codec::Compression::None, |tcp_endpoint| {
+                            tcp_endpoint.compression
+                        }),
+                    compression_level: client.level_control.level(),
+                    bytes_in_flight: client
+                        .tcp_endpoint
+                        .as_ref()
+                        .and_then(|tcp_endpoint| tcp_endpoint.sequencer.bytes_in_flight()),
+                }
+            })
+            .collect()
+    }
+
+    /// Clients that lose their connection get their session back when they reconnect,
+    /// using an encrypted token we gave them when they first connected.
+    ///
+    /// By default each server uses a random secret for these tokens.
+    /// Give replicas of the same service the same secret so that they accept each other's tokens.
+    /// A client that fails over to a replica keeps its [`ClientId`], but not its ui state.
+    pub fn set_resumption_secret(&mut self, secret: [u8; 32]) {
+        self.resumption_key.set_secret(&secret);
+    }
+
+    /// A message of the day, shown once by viewers when they connect,
+    /// e.g. a legal notice or the name of the environment ("PRODUCTION").
+    ///
+    /// Only clients that connect after this call will see the new message.
+    /// Default: none.
+    pub fn set_motd(&mut self, motd: impl Into<String>) {
+        self.motd = motd.into();
+    }
+
+    /// Name, version and icon of the served app.
+    /// Viewers use it for their window title and icon, so several viewer windows can be told apart.
+    ///
+    /// Only clients that connect after this call will see the new info.
+    /// Default: none.
+    pub fn set_app_info(&mut self, app_info: crate::AppInfo) {
+        self.app_info = app_info;
+    }
+
+    /// Inspect and change the input from each client before the ui sees it,
+    /// e.g. to block dangerous shortcuts, or to only allow some clients to point and click.
+    ///
+    /// ``` no_run
+    /// # let mut server = eterm::Server::new("0.0.0.0:8505")?;
+    /// server.set_input_filter(|client_id, raw_input| {
+    ///     if client_id.as_u64() != 0 {
+    ///         // Everyone but the first client is pointer-only:
+    ///         raw_input.events.retain(|event| {
+    ///             matches!(
+    ///                 event,
+    ///                 egui::Event::PointerMoved(_)
+    ///                     | egui::Event::PointerButton { .. }
+    ///                     | egui::Event::PointerGone
+    ///             )
+    ///         });
+    ///     }
+    /// });
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn set_input_filter(
+        &mut self,
+        input_filter: impl FnMut(ClientId, &mut RawInput) + Send + 'static,
+    ) {
+        self.input_filter = Some(Box::new(input_filter));
+    }
+
+    /// Change the shapes of each frame before it is sent to a client,
+    /// e.g. to watermark it ("READ ONLY", the user name), redact parts of it for some clients,
+    /// or collect statistics.
+    ///
+    /// ``` no_run
+    /// # let mut server = eterm::Server::new("0.0.0.0:8505")?;
+    /// use eterm::net_shape::{ClippedNetShape, NetShape};
+    /// server.set_frame_hook(|client_info, clipped_net_shapes| {
+    ///     if client_info.ui.as_deref() != Some("admin") {
+    ///         // Hide all text:
+    ///         clipped_net_shapes.retain(|ClippedNetShape(_, shape)| !matches!(shape, NetShape::Text(_)));
+    ///     }
+    /// });
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    ///
+    /// Shapes are in points. Identical frames are not sent again, so a hook that changes
+    /// every time will send a frame every time.
+    pub fn set_frame_hook(
+        &mut self,
+        frame_hook: impl FnMut(&ClientInfo, &mut Vec<ClippedNetShape>) + Send + 'static,
+    ) {
+        self.frame_hook = Some(Box::new(frame_hook));
+    }
+
+    /// Add a ui that clients can be given with [`Self::assign_ui`] or [`Self::set_ui_router`],
+    /// e.g. an admin interface.
+    ///
+    /// Clients that haven't been given one see the ui passed to [`Self::show`].
+    pub fn add_ui(
+        &mut self,
+        name: impl Into<String>,
+        do_ui: impl FnMut(&egui::CtxRef, ClientId) + Send + 'static,
+    ) {
+        self.uis.insert(name.into(), Box::new(do_ui));
+    }
+
+    /// Show this client the ui added with [`Self::add_ui`] under this name.
+    ///
+    /// Clients start out with the ui passed to [`Self::show`], or what the [`Self::set_ui_router`] picks.
+    pub fn assign_ui(&mut self, client_id: ClientId, name: impl Into<String>) {
+        let name = name.into();
+        if !self.uis.contains_key(&name) {
+            tracing::warn!("No ui called {:?}. Call Server::add_ui first.", name);
+        }
+        for client in self.clients.values_mut() {
+            if client.client_id == client_id {
+                tracing::info!("{}: showing ui {:?}", client.info(), name);
+                client.ui = Some(name.clone());
+                client.ui_routed = true;
+                client.input(RawInput::default()); // repaint
+            }
+        }
+    }
+
+    /// Pick a ui for each new client when it connects, based on its [`ClientInfo`].
+    ///
+    /// Return the name of a ui added with [`Self::add_ui`],
+    /// or `None` for the ui passed to [`Self::show`].
+    ///
+    /// ``` no_run
+    /// # let mut server = eterm::Server::new("0.0.0.0:8505")?;
+    /// server.add_ui("admin", |egui_ctx, _client_id| {
+    ///     egui::CentralPanel::default().show(egui_ctx, |ui| ui.label("Admin"));
+    /// });
+    /// server.set_ui_router(|client_info| {
+    ///     if client_info.addr.ip().is_loopback() {
+    ///         Some("admin".to_owned())
+    ///     } else {
+    ///         None
+    ///     }
+    /// });
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn set_ui_router(
+        &mut self,
+        ui_router: impl FnMut(&ClientInfo) -> Option<String> + Send + 'static,
+    ) {
+        self.ui_router = Some(Box::new(ui_router));
+    }
+
+    /// Decide what each new client may do when it says hello, based on its [`ClientInfo`].
+    ///
+    /// Return `None` to turn the client away with [`crate::GoodbyeReason::AuthFailed`].
+    /// Viewers don't send any credentials, so this can only go by e.g. the address.
+    /// Browsers watching `/mjpeg` are asked about too, when they connect.
+    ///
+    /// The role is enforced for what eterm does itself: input from a [`crate::role::Role::Viewer`]
+    /// is dropped, and only a [`crate::role::Role::Admin`] can [`crate::role::kick`].
+    /// Use [`crate::role::role`] in your ui for the rest.
+    ///
+    /// ``` no_run
+    /// # let mut server = eterm::Server::new("0.0.0.0:8505")?;
+    /// use eterm::role::Role;
+    /// server.set_authenticator(|client_info| {
+    ///     if client_info.addr.ip().is_loopback() {
+    ///         Some(Role::Admin)
+    ///     } else {
+    ///         Some(Role::Viewer)
+    ///     }
+    /// });
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    ///
+    /// Default: everyone is an admin.
+    pub fn set_authenticator(
+        &mut self,
+        authenticator: impl FnMut(&ClientInfo) -> Option<crate::role::Role> + Send + 'static,
+    ) {
+        self.authenticator = Some(Box::new(authenticator));
+    }
+
+    /// Write every packet sent and received, by all clients, to a file in `dir`.
+    /// See [`crate::packet_dump`] for how to read them.
+    ///
+    /// This is for debugging, and slow.
+    ///
+    /// # Errors
+    /// If `dir` can't be created.
+    pub fn set_packet_dump_dir(&mut self, dir: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        let packet_dumper = std::sync::Arc::new(crate::packet_dump::PacketDumper::new(
+            dir.as_ref(),
+            crate::packet_dump::Direction::ServerToClient,
+        )?);
+        for client in self.clients.values_mut() {
+            if let Some(tcp_endpoint) = &mut client.tcp_endpoint {
+                tcp_endpoint.packet_dumper = Some(packet_dumper.clone());
+            }
+        }
+        self.packet_dumper = Some(packet_dumper);
+        Ok(())
+    }
+
+    /// Keep a copy of the latest frame sent to each client, to show on the server machine.
+    /// See [`crate::preview`].
+    pub fn preview(&mut self) -> crate::preview::ServerPreview {
+        if let Some(preview) = &self.preview {
+            return preview.clone();
+        }
+        let preview = crate::preview::ServerPreview::default();
+        for client in self.clients.values_mut() {
+            if client.tcp_endpoint.is_some() {
+                preview.set_fonts(client.client_id, &client.client_font_definitions);
+            }
+            client.preview = Some(preview.clone());
+        }
+        self.preview = Some(preview.clone());
+        preview
+    }
+
+    /// Record connects, disconnects, session resumptions and a summary of the input
+    /// (clicks, key presses and text) of every client.
+    ///
+    /// ``` no_run
+    /// # let mut server = eterm::Server::new("0.0.0.0:8505")?;
+    /// server.set_audit_sink(|record| {
+    ///     tracing::info!(target: "audit", "{}", record);
+    /// });
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    ///
+    /// The input is recorded as received, before the [`Self::set_input_filter`].
+    pub fn set_audit_sink(
+        &mut self,
+        audit_sink: impl FnMut(&crate::audit::AuditRecord) + Send + 'static,
+    ) {
+        self.audit_sink = Some(Box::new(audit_sink));
+    }
+
+    /// Send the log of this process to all viewers, e.g. for debugging a headless server.
+    ///
+    /// Add the returned layer to your `tracing_subscriber`:
+    ///
+    /// ``` no_run
+    /// use tracing_subscriber::prelude::*;
+    /// let server = eterm::Server::new("0.0.0.0:8505")?;
+    /// tracing_subscriber::registry()
+    ///     .with(tracing_subscriber::fmt::layer())
+    ///     .with(server.log_layer())
+    ///     .init();
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    ///
+    /// Viewers that connect later get the latest records too.
+    #[cfg(feature = "remote_log")]
+    pub fn log_layer(&self) -> crate::remote_log::LogLayer {
+        crate::remote_log::LogLayer {
+            buffer: self.log.clone(),
+        }
+    }
+
+    /// Show a banner in all viewers, on top of the served ui, e.g. "server restarting in 30 s".
+    ///
+    /// Clients that connect later will see it too. An empty `text` removes the banner.
+    pub fn broadcast_notice(&mut self, text: impl Into<String>, severity: crate::notice::Severity) {
+        let text = text.into();
+        self.notice = if text.is_empty() {
+            None
+        } else {
+            Some(crate::notice::Notice { text, severity })
+        };
+    }
+
+    /// Use this style for the ui shown to this client,
+    /// instead of the default or what the client prefers (see [`Self::client_preferences`]).
+    pub fn set_client_style(&mut self, client_id: ClientId, style: egui::Style) {
+        for client in self.clients.values_mut() {
+            if client.client_id == client_id {
+                client.egui_ctx.set_style(style.clone());
+                client.style_overridden = true;
+                client.input(RawInput::default()); // repaint
+            }
+        }
+    }
+
+    /// Trade quality for bandwidth for this client, e.g. one on a slow connection.
+    pub fn set_client_profile(&mut self, client_id: ClientId, profile: Profile) {
+        for client in self.clients.values_mut() {
+            if client.client_id == client_id {
+                client.profile = profile;
+                client
+                    .egui_ctx
+                    .memory()
+                    .options
+                    .tessellation_options
+                    .anti_alias = profile != Profile::LowBandwidth;
+                client.input(RawInput::default()); // repaint
+            }
+        }
+    }
+
+    /// Information about a connected client, e.g. its locale.
+    ///
+    /// Returns `None` if there is no such client connected.
+    pub fn client_info(&self, client_id: ClientId) -> Option<ClientInfo> {
+        self.clients
+            .values()
+            .find(|client| client.client_id == client_id && client.tcp_endpoint.is_some())
+            .map(Client::client_info)
+    }
+
+    /// What the client would like the ui to look like.
+    ///
+    /// By default we switch each client to the dark or light theme it prefers,
+    /// unless you call [`Self::set_client_style`].
+    pub fn client_preferences(&self, client_id: ClientId) -> Option<crate::ClientPreferences> {
+        self.clients
+            .values()
+            .find(|client| client.client_id == client_id)
+            .map(|client| client.preferences.clone())
+    }
+
+    /// Paint and send a new frame to all clients in the next call to [`Self::show`],
+    /// even if none of them sent any input.
+    ///
+    /// Call this when something the ui shows has changed, e.g. a new log line.
+    pub fn request_repaint_all(&mut self) {
+        for client in self.clients.values_mut() {
+            client.input(RawInput::default());
+        }
+    }
+
+    /// Like [`Self::request_repaint_all`], but for just one client.
+    pub fn request_repaint(&mut self, client_id: ClientId) {
+        for client in self.clients.values_mut() {
+            if client.client_id == client_id {
+                client.input(RawInput::default());
+            }
+        }
+    }
+
+    /// Disconnect the client, and tell it not to come back.
+    pub fn kick(&mut self, client_id: ClientId) {
+        for client in self.clients.values_mut() {
+            if client.client_id == client_id && client.tcp_endpoint.is_some() {
+                tracing::info!("Kicking {}", client.info());
+                client.say_goodbye(crate::GoodbyeReason::Kicked);
+            }
+        }
+    }
+
     /// Call frequently (e.g. 60 times per second) with the ui you'd like to show to clients.
     ///
+    /// Clients given another ui with [`Self::assign_ui`] or [`Self::set_ui_router`] see that one instead.
+    /// To paint the clients yourself, use [`Self::poll`] instead.
+    ///
     /// # Errors
     /// Underlying TCP errors.
     pub fn show(&mut self, mut do_ui: impl FnMut(&egui::CtxRef, ClientId)) -> anyhow::Result<()> {
         self.show_dyn(&mut do_ui)
     }
 
-    fn show_dyn(&mut self, do_ui: &mut dyn FnMut(&egui::CtxRef, ClientId)) -> anyhow::Result<()> {
-        self.accept_new_clients()?;
-        self.try_receive();
+    fn show_dyn(&mut self, do_ui: &mut dyn FnMut(&egui::CtxRef, ClientId)) -> anyhow::Result<()> {
+        self.poll_sockets()?;
+        self.update_remote_cursors();
+        self.housekeeping();
+
+        let show_settings = ShowSettings {
+            minimum_update_interval: self.minimum_update_interval,
+            minimum_frame_interval: self.minimum_frame_interval,
+            overloaded: self.is_overloaded(),
+            frame_diagnostics: self.frame_diagnostics,
+            font_subsetting: self.font_subsetting,
+            laid_out_text: self.laid_out_text,
+            catch_panics: self.catch_panics,
+            visuals_memory_budget: self.visuals_memory_budget,
+        };
+        let paint_start = std::time::Instant::now();
+        let mut kicks = vec![];
+        for client in self.clients.values_mut() {
+            if client.said_hello && !client.ui_routed {
+                client.ui_routed = true;
+                if let Some(ui_router) = &mut self.ui_router {
+                    client.ui = ui_router(&client.client_info());
+                    if let Some(name) = &client.ui {
+                        tracing::info!("{}: showing ui {:?}", client.info(), name);
+                    }
+                }
+            }
+            let do_ui: &mut dyn FnMut(&egui::CtxRef, ClientId) =
+                match client.ui.as_ref().and_then(|name| self.uis.get_mut(name)) {
+                    Some(ui) => ui.as_mut(),
+                    None => &mut *do_ui,
+                };
+
+            client.send_notice(self.notice.as_ref());
+            client.send_log(&self.log.lock());
+            client.show(
+                do_ui,
+                self.input_filter.as_deref_mut(),
+                self.frame_hook.as_deref_mut(),
+                &show_settings,
+            );
+            let kick_requests = crate::role::take_kick_requests(&client.egui_ctx);
+            if !kick_requests.is_empty() {
+                if client.role.can_kick() {
+                    kicks.extend(kick_requests);
+                } else {
+                    tracing::warn!("{}: ignoring kick from a {:?}", client.info(), client.role);
+                }
+            }
+            client.send_telemetry(self.telemetry_interval);
+            client.send_file_chunks();
+            client.timings.flush();
+        }
+        if let Some(load_monitor) = &mut self.load_monitor {
+            load_monitor.on_show(paint_start.elapsed().as_secs_f32());
+        }
+        for client_id in kicks {
+            self.kick(client_id);
+        }
+        Ok(())
+    }
+
+    /// Instead of [`Self::show`], for hosts that paint the clients themselves,
+    /// e.g. only the ones that got input, or on their own schedule.
+    ///
+    /// Call frequently. Paint each client with [`Self::paint_client`],
+    /// e.g. with the shapes from running your own [`egui::CtxRef`] on the [`ServerEvent::InputReceived`].
+    ///
+    /// # Errors
+    /// Underlying TCP errors.
+    pub fn poll(&mut self) -> anyhow::Result<Vec<ServerEvent>> {
+        self.poll_sockets()?;
+        self.housekeeping();
+
+        let connected: std::collections::HashSet<ClientId> = self
+            .clients
+            .values()
+            .filter(|client| client.tcp_endpoint.is_some() && client.said_hello)
+            .map(|client| client.client_id)
+            .collect();
+
+        let mut events: Vec<ServerEvent> = connected
+            .difference(&self.polled_clients)
+            .map(|&client_id| ServerEvent::ClientConnected(client_id))
+            .collect();
+        for client in self.clients.values_mut() {
+            if !connected.contains(&client.client_id) {
+                continue;
+            }
+            if let Some(mut raw_input) = client.input.take() {
+                if let Some(input_filter) = &mut self.input_filter {
+                    input_filter(client.client_id, &mut raw_input);
+                }
+                raw_input.time = Some(client.start_time.elapsed().as_secs_f64());
+                events.push(ServerEvent::InputReceived {
+                    client_id: client.client_id,
+                    raw_input,
+                });
+            }
+            client.send_notice(self.notice.as_ref());
+            client.send_log(&self.log.lock());
+            client.send_telemetry(self.telemetry_interval);
+            client.send_file_chunks();
+        }
+        events.extend(
+            self.polled_clients
+                .difference(&connected)
+                .map(|&client_id| ServerEvent::ClientDisconnected(client_id)),
+        );
+
+        self.polled_clients = connected;
+        Ok(events)
+    }
+
+    /// Send a frame you painted yourself to a client. See [`Self::poll`].
+    ///
+    /// The viewer lays out text with the default fonts.
+    /// Like with [`Self::show`], nothing is sent if nothing changed since the last frame.
+    ///
+    /// Returns `false` if there is no such client connected.
+    pub fn paint_client(
+        &mut self,
+        client_id: ClientId,
+        output: egui::Output,
+        clipped_shapes: Vec<egui::epaint::ClippedShape>,
+    ) -> bool {
+        let frame_diagnostics = self.frame_diagnostics;
+        let visuals_memory_budget = self.visuals_memory_budget;
+        match self.clients.values_mut().find(|client| {
+            client.client_id == client_id && client.tcp_endpoint.is_some() && !client.mjpeg
+        }) {
+            Some(client) => {
+                client.paint(
+                    output,
+                    clipped_shapes,
+                    self.frame_hook.as_deref_mut(),
+                    frame_diagnostics,
+                    visuals_memory_budget,
+                );
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// What both [`Self::show`] and [`Self::poll`] need to do besides talking to the clients.
+    fn housekeeping(&mut self) {
+        self.flush_audit_log();
+        self.evict_disconnected_clients();
+        #[cfg(feature = "upnp")]
+        {
+            if let Some(port_mapping) = &mut self.port_mapping {
+                port_mapping.renew_if_needed();
+            }
+        }
+        if let Some(max_session_duration) = self.max_session_duration {
+            for client in self.clients.values_mut() {
+                if client.tcp_endpoint.is_some()
+                    && client.start_time.elapsed() > max_session_duration
+                {
+                    tracing::info!("{}: session expired", client.info());
+                    client.say_goodbye(crate::GoodbyeReason::SessionExpired);
+                }
+            }
+        }
+    }
+
+    /// See [`Self::set_disconnected_client_retention`].
+    fn evict_disconnected_clients(&mut self) {
+        let retention = self.disconnected_client_retention;
+        let client_addrs = &mut self.client_addrs;
+        self.clients.retain(|addr, client| {
+            let evict = client.disconnected_at.map_or(false, |disconnected_at| {
+                disconnected_at.elapsed() > retention
+            });
+            if evict {
+                tracing::debug!("Forgetting {}", client.info());
+                if client_addrs.get(&client.client_id.token()) == Some(addr) {
+                    client_addrs.remove(&client.client_id.token());
+                }
+            }
+            !evict
+        });
+    }
+
+    fn flush_audit_log(&mut self) {
+        for client in self.clients.values_mut() {
+            for event in client.audit_events.drain(..) {
+                if let Some(audit_sink) = &mut self.audit_sink {
+                    audit_sink(&crate::audit::AuditRecord {
+                        time: std::time::SystemTime::now(),
+                        client_id: client.client_id,
+                        addr: client.addr,
+                        event,
+                    });
+                }
+            }
+        }
+    }
+
+    fn update_remote_cursors(&mut self) {
+        let show_remote_cursors = self.show_remote_cursors;
+        let cursors: Vec<crate::presence::RemoteCursor> = self
+            .clients
+            .values()
+            .filter(|client| show_remote_cursors && client.tcp_endpoint.is_some() && !client.mjpeg)
+            .filter_map(|client| {
+                let pos = client.egui_ctx.input().pointer.hover_pos()?;
+                Some(crate::presence::RemoteCursor {
+                    client_id: client.client_id,
+                    name: format!("Client {}", client.client_id.0),
+                    pos,
+                })
+            })
+            .collect();
+
+        for client in self.clients.values_mut() {
+            let others: Vec<_> = cursors
+                .iter()
+                .filter(|cursor| cursor.client_id != client.client_id)
+                .cloned()
+                .collect();
+            if others != client.remote_cursors {
+                client.remote_cursors = others;
+                client.input(RawInput::default()); // repaint
+            }
+        }
+    }
+
+    /// Accept new clients, and receive from and send to the clients whose sockets are ready.
+    ///
+    /// non-blocking
+    fn poll_sockets(&mut self) -> anyhow::Result<()> {
+        match self
+            .poll
+            .poll(&mut self.events, Some(std::time::Duration::ZERO))
+        {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => return Ok(()),
+            Err(err) => return Err(err).context("mio poll"),
+        }
+
+        let mut has_new_clients = false;
+        for event in self.events.iter() {
+            if event.token() == LISTENER {
+                has_new_clients = true;
+                continue;
+            }
+            let client = match self
+                .client_addrs
+                .get(&event.token())
+                .and_then(|addr| self.clients.get_mut(addr))
+            {
+                Some(client) => client,
+                None => continue,
+            };
+            if event.is_writable() {
+                client.flush();
+            }
+            if event.is_readable() || event.is_read_closed() {
+                client.try_receive(
+                    self.mjpeg_screen_size,
+                    &self.motd,
+                    &self.app_info,
+                    &self.resumption_key,
+                    self.authenticator.as_deref_mut(),
+                );
+            }
+        }
+
+        if has_new_clients {
+            self.accept_new_clients()?;
+        }
+        self.resume_sessions()
+    }
+
+    /// Move new connections that presented a resumption token over to their old session.
+    fn resume_sessions(&mut self) -> anyhow::Result<()> {
+        let requests: Vec<_> = self
+            .clients
+            .iter_mut()
+            .filter_map(|(addr, client)| Some((*addr, client.resume_request.take()?)))
+            .collect();
+
+        for (addr, (session, compressions)) in requests {
+            let mut new_client = match self.clients.remove(&addr) {
+                Some(new_client) => new_client,
+                None => continue,
+            };
+            self.client_addrs.remove(&new_client.client_id.token());
+            let mut tcp_endpoint = match new_client.tcp_endpoint.take() {
+                Some(tcp_endpoint) => tcp_endpoint,
+                None => continue,
+            };
+
+            let preferences = std::mem::take(&mut new_client.preferences);
+            let role = new_client.role;
+            let audit_events = std::mem::take(&mut new_client.audit_events);
+            let old_client_id = ClientId(session.client_id);
+            let old_addr = self
+                .clients
+                .iter()
+                .find(|(_, client)| client.client_id == old_client_id)
+                .map(|(old_addr, _)| *old_addr);
+
+            let mut client = match old_addr {
+                Some(old_addr) if session.same_server => {
+                    let mut old_client = self.clients.remove(&old_addr).unwrap();
+                    old_client.disconnect(); // In case we haven't noticed the old connection is dead
+                    old_client.addr = addr;
+                    tracing::info!("{} resumed its session", old_client.info());
+                    old_client
+                }
+                None if !session.same_server => {
+                    // Failover from a replica. We don't have their ui state, but can keep the id.
+                    new_client.client_id = old_client_id;
+                    self.next_client_id = self.next_client_id.max(old_client_id.0 + 1);
+                    tracing::info!(
+                        "{} resumed a session from another server",
+                        new_client.info()
+                    );
+                    new_client
+                }
+                _ => new_client, // Unknown or ambiguous session. Start a new one.
+            };
+
+            self.poll
+                .registry()
+                .reregister(
+                    &mut tcp_endpoint.tcp_stream,
+                    client.client_id.token(),
+                    mio::Interest::READABLE | mio::Interest::WRITABLE,
+                )
+                .context("registering client TCP socket")?;
+            client.tcp_endpoint = Some(tcp_endpoint);
+            client.disconnected_at = None;
+            client.audit_events.extend(audit_events);
+            client.role = role;
+            client.set_preferences(preferences);
+            client.say_hello(
+                &compressions,
+                &self.motd,
+                &self.app_info,
+                &self.resumption_key,
+            );
+            client.input(RawInput::default()); // Send a full frame right away
+
+            // Read whatever came after the hello:
+            client.try_receive(
+                self.mjpeg_screen_size,
+                &self.motd,
+                &self.app_info,
+                &self.resumption_key,
+                self.authenticator.as_deref_mut(),
+            );
+
+            self.client_addrs.insert(client.client_id.token(), addr);
+            self.clients.insert(addr, client);
+        }
+        Ok(())
+    }
+
+    /// non-blocking
+    fn accept_new_clients(&mut self) -> anyhow::Result<()> {
+        loop {
+            match self.tcp_listener.accept() {
+                Ok((tcp_stream, client_addr)) => {
+                    let _span = profile_span!("accept", addr = %client_addr).entered();
+                    self.add_client(tcp_stream, client_addr)?;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    break; // No (more) new clients
+                }
+                Err(err) => {
+                    anyhow::bail!("eterm server TCP error: {:?}", err);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A new connection, or a client coming back.
+    fn add_client(
+        &mut self,
+        mut tcp_stream: mio::net::TcpStream,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<ClientId> {
+        // reuse existing client - especially the egui context
+        // which contains things like window positons:
+        let clients = &mut self.clients;
+        let next_client_id = &mut self.next_client_id;
+        let input_rate_limit = self.input_rate_limit;
+        let preview = self.preview.clone();
+        let client = clients.entry(client_addr).or_insert_with(|| {
+            let client_id = ClientId(*next_client_id);
+            *next_client_id += 1;
+
+            Client {
+                client_id,
+                addr: client_addr,
+                tcp_endpoint: None,
+                start_time: std::time::Instant::now(),
+                disconnected_at: None,
+                frame_index: 0,
+                egui_ctx: Default::default(),
+                input: None,
+                screen_rect: egui::Rect::EVERYTHING,
+                client_time: None,
+                last_update: None,
+                last_visuals: Default::default(),
+                last_output_state: Default::default(),
+                gamepads: Default::default(),
+                mjpeg: false,
+                file_transfers: Default::default(),
+                remote_cursors: Default::default(),
+                next_transfer_id: 0,
+                said_hello: false,
+                resume_request: None,
+                preferences: Default::default(),
+                style_overridden: false,
+                profile: Profile::Default,
+                ui: None,
+                ui_routed: false,
+                role: crate::role::Role::Viewer,
+                audit_events: Vec::new(),
+                rate_limiter: input_rate_limit.map(crate::rate_limit::RateLimiter::new),
+                pacing: None,
+                input_overflowed: false,
+                level_control: Default::default(),
+                backlogged: false,
+                notice: None,
+                next_log_record: 0,
+                last_telemetry: f64::NEG_INFINITY,
+                client_font_definitions: Default::default(),
+                client_font_chars: None,
+                fonts_ptr: 0,
+                fonts_requested: false,
+                client_font_image: None,
+                client_theme: None,
+                visible: true,
+                last_user_input: None,
+                timings: Default::default(),
+                preview,
+            }
+        });
+
+        // mio sockets are edge-triggered, so this will tell us about anything
+        // the client has already sent:
+        self.poll
+            .registry()
+            .register(
+                &mut tcp_stream,
+                client.client_id.token(),
+                mio::Interest::READABLE | mio::Interest::WRITABLE,
+            )
+            .context("registering client TCP socket")?;
+        self.client_addrs
+            .insert(client.client_id.token(), client_addr);
+        let mut tcp_endpoint = crate::TcpEndpoint::new(tcp_stream);
+        tcp_endpoint.packet_dumper = self.packet_dumper.clone();
+        client.tcp_endpoint = Some(tcp_endpoint);
+        client.disconnected_at = None;
+
+        tracing::info!("{} connected", client.info());
+        client
+            .audit_events
+            .push(crate::audit::AuditEvent::Connected);
+        Ok(client.client_id)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        for client in self.clients.values_mut() {
+            if client.tcp_endpoint.is_some() {
+                client.say_goodbye(crate::GoodbyeReason::ServerShutdown);
+            }
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+/// The settings of the [`Server`] that [`Client::show`] needs.
+struct ShowSettings {
+    minimum_update_interval: f32,
+    minimum_frame_interval: f32,
+    /// See [`Server::is_overloaded`].
+    overloaded: bool,
+    frame_diagnostics: bool,
+    font_subsetting: bool,
+    laid_out_text: bool,
+    catch_panics: bool,
+    visuals_memory_budget: Option<usize>,
+}
+
+struct Client {
+    client_id: ClientId,
+    addr: SocketAddr,
+    tcp_endpoint: Option<crate::TcpEndpoint>,
+    /// When the session started. Kept when the session is resumed.
+    start_time: std::time::Instant,
+    /// When we lost the connection, if we have. See [`Server::set_disconnected_client_retention`].
+    disconnected_at: Option<std::time::Instant>,
+    frame_index: u64,
+    egui_ctx: egui::CtxRef,
+    /// Set when there is something to do. Cleared after painting.
+    input: Option<egui::RawInput>,
+    /// The screen of the client, as of the last input with one.
+    /// Used to cull the shapes of [`Server::paint_client`].
+    screen_rect: egui::Rect,
+    /// The client time of the last input we got from them.
+    client_time: Option<f64>,
+    last_update: Option<std::time::Instant>,
+    last_visuals: LastVisuals,
+    last_output_state: crate::output::OutputState,
+    gamepads: Gamepads,
+    /// This is a web browser watching the MJPEG stream.
+    mjpeg: bool,
+    /// Files being sent with [`crate::download::send_file`].
+    file_transfers: std::collections::VecDeque<FileTransfer>,
+    next_transfer_id: u64,
+    /// Where the other clients are pointing, if [`Server::set_show_remote_cursors`].
+    remote_cursors: Vec<crate::presence::RemoteCursor>,
+    /// Set when we get [`ClientToServerMessage::Hello`]. Before that it may not even be an eterm client.
+    said_hello: bool,
+    /// What the viewer would like the ui to look like.
+    preferences: crate::ClientPreferences,
+    /// Set by [`Server::set_client_style`]. Then we ignore [`Self::preferences`].
+    style_overridden: bool,
+    /// See [`Server::set_client_profile`].
+    profile: Profile,
+    /// See [`Server::assign_ui`].
+    ui: Option<String>,
+    /// Has the [`Server::set_ui_router`] (or [`Server::assign_ui`]) picked a ui for this client?
+    ui_routed: bool,
+    /// Set by the [`Server::set_authenticator`] when the client says hello.
+    /// Until then, a [`crate::role::Role::Viewer`].
+    role: crate::role::Role,
+    /// Not yet passed on to the [`Server::set_audit_sink`].
+    audit_events: Vec<crate::audit::AuditEvent>,
+    /// See [`Server::set_input_rate_limit`].
+    rate_limiter: Option<crate::rate_limit::RateLimiter>,
+    /// Set if the viewer tells us about its vsync.
+    pacing: Option<FramePacing>,
+    /// Did we drop input since the last frame? So we only warn once.
+    input_overflowed: bool,
+    /// Picks the compression level of the frames, based on how well the connection keeps up.
+    level_control: crate::codec::LevelControl,
+    /// Did we skip a frame since the last one because the socket wasn't ready?
+    backlogged: bool,
+    /// Set when the client presents a valid resumption token for another session.
+    /// Handled by [`Server::resume_sessions`].
+    resume_request: Option<(
+        crate::resume::ResumedSession,
+        Vec<crate::codec::Compression>,
+    )>,
+    /// The last [`crate::notice::Notice`] we sent.
+    notice: Option<crate::notice::Notice>,
+    /// Index of the next [`crate::remote_log::LogRecord`] to send.
+    next_log_record: u64,
+    /// When we last sent [`crate::Telemetry`].
+    last_telemetry: f64,
+    /// What the client lays out text with. It starts with the defaults after each hello.
+    client_font_definitions: egui::FontDefinitions,
+    /// The characters of the subset of [`Self::client_font_definitions`] the client has,
+    /// or `None` if it has them all. See [`Server::set_font_subsetting`].
+    client_font_chars: Option<std::collections::BTreeSet<char>>,
+    /// Address of the [`egui::text::Fonts`] of [`Self::egui_ctx`] when we last checked them.
+    /// egui creates new `Fonts` whenever the definitions change.
+    fonts_ptr: usize,
+    /// The client sent [`ClientToServerMessage::RequestFonts`].
+    fonts_requested: bool,
+    /// [`Self::fonts_ptr`] and version of the font image we last sent.
+    /// See [`Server::set_laid_out_text`].
+    client_font_image: Option<(usize, u64)>,
+    /// The last [`crate::Theme`] we sent.
+    client_theme: Option<crate::Theme>,
+    /// `false` while the viewer window is minimized. We don't paint for hidden clients.
+    visible: bool,
+    /// When we last got input events (clicks, key presses, …) from this client.
+    last_user_input: Option<std::time::Instant>,
+    timings: Timings,
+    /// See [`Server::preview`].
+    preview: Option<crate::preview::ServerPreview>,
+}
+
+/// The shapes last sent to a client. See [`Server::set_visuals_memory_budget`].
+enum LastVisuals {
+    Shapes(Vec<ClippedNetShape>),
+    /// The shapes were too big to keep.
+    Fingerprint(u64),
+}
+
+impl Default for LastVisuals {
+    fn default() -> Self {
+        Self::Shapes(vec![])
+    }
+}
+
+impl LastVisuals {
+    fn new(shapes: Vec<ClippedNetShape>, memory_budget: Option<usize>) -> Self {
+        match memory_budget {
+            Some(budget) if crate::net_shape::memory_size(&shapes) > budget => {
+                Self::Fingerprint(crate::net_shape::fingerprint(&shapes))
+            }
+            _ => Self::Shapes(shapes),
+        }
+    }
+
+    fn is_same(&self, shapes: &[ClippedNetShape]) -> bool {
+        match self {
+            Self::Shapes(last_shapes) => last_shapes.as_slice() == shapes,
+            Self::Fingerprint(fingerprint) => *fingerprint == crate::net_shape::fingerprint(shapes),
+        }
+    }
+}
+
+struct FileTransfer {
+    transfer_id: u64,
+    bytes: std::sync::Arc<[u8]>,
+    /// How much we've sent so far.
+    offset: usize,
+}
+
+impl Client {
+    fn disconnect(&mut self) {
+        if self.tcp_endpoint.take().is_some() {
+            self.audit_events
+                .push(crate::audit::AuditEvent::Disconnected);
+            self.disconnected_at = Some(std::time::Instant::now());
+        }
+        if let Some(preview) = &self.preview {
+            preview.remove_client(self.client_id);
+        }
+        self.last_visuals = Default::default();
+        self.last_output_state = Default::default();
+        self.gamepads = Default::default();
+        self.mjpeg = false;
+        self.file_transfers.clear();
+        self.remote_cursors.clear();
+        self.said_hello = false;
+        self.role = crate::role::Role::Viewer;
+        self.resume_request = None;
+        self.level_control = Default::default();
+        self.backlogged = false;
+        self.notice = None;
+        self.visible = true;
+        self.pacing = None;
+        self.last_telemetry = f64::NEG_INFINITY;
+    }
+
+    fn set_preferences(&mut self, preferences: crate::ClientPreferences) {
+        if !self.style_overridden {
+            if let Some(dark_mode) = preferences.dark_mode {
+                self.egui_ctx.set_visuals(if dark_mode {
+                    egui::Visuals::dark()
+                } else {
+                    egui::Visuals::light()
+                });
+            }
+        }
+        self.preferences = preferences;
+        self.input(RawInput::default()); // repaint
+    }
+
+    /// Answer [`ClientToServerMessage::Hello`].
+    fn say_hello(
+        &mut self,
+        compressions: &[crate::codec::Compression],
+        motd: &str,
+        app_info: &crate::AppInfo,
+        resumption_key: &crate::resume::TokenKey,
+    ) {
+        let compression = crate::codec::Compression::negotiate(compressions);
+        tracing::info!("{}: using {:?} compression", self.info(), compression);
+        let resumption_token = resumption_key
+            .issue(self.client_id.0)
+            .unwrap_or_else(|err| {
+                tracing::warn!("Failed to issue resumption token: {:#}", err);
+                Vec::new()
+            });
+        // Send the answer uncompressed, then switch:
+        self.send_message(&crate::ServerToClientMessage::Hello {
+            compression,
+            motd: motd.to_owned(),
+            resumption_token,
+            app_info: app_info.clone(),
+        });
+        if let Some(tcp_endpoint) = &mut self.tcp_endpoint {
+            tcp_endpoint.compression = compression;
+        }
+        self.said_hello = true;
+        self.client_font_definitions = Default::default();
+        self.client_font_chars = None;
+        self.fonts_ptr = 0;
+        self.fonts_requested = false;
+        self.client_font_image = None;
+        self.client_theme = None;
+    }
 
-        for client in self.clients.values_mut() {
-            client.show(do_ui, self.minimum_update_interval);
+    /// Send the log records the client hasn't seen yet.
+    fn send_log(&mut self, log: &crate::remote_log::LogBuffer) {
+        if !self.said_hello || self.mjpeg {
+            return;
+        }
+        let (records, next_log_record) = log.since(self.next_log_record);
+        self.next_log_record = next_log_record;
+        if !records.is_empty() {
+            self.send_message(&crate::ServerToClientMessage::Log { records });
         }
-        Ok(())
     }
 
-    /// non-blocking
-    fn accept_new_clients(&mut self) -> anyhow::Result<()> {
-        loop {
-            match self.tcp_listener.accept() {
-                Ok((tcp_stream, client_addr)) => {
-                    tcp_stream
-                        .set_nonblocking(true)
-                        .context("stream.set_nonblocking")?;
-                    let tcp_endpoint = crate::TcpEndpoint { tcp_stream };
-
-                    // reuse existing client - especially the egui context
-                    // which contains things like window positons:
-                    let clients = &mut self.clients;
-                    let next_client_id = &mut self.next_client_id;
-                    let client = clients.entry(client_addr).or_insert_with(|| {
-                        let client_id = ClientId(*next_client_id);
-                        *next_client_id += 1;
-
-                        Client {
-                            client_id,
-                            addr: client_addr,
-                            tcp_endpoint: None,
-                            start_time: std::time::Instant::now(),
-                            frame_index: 0,
-                            egui_ctx: Default::default(),
-                            input: None,
-                            client_time: None,
-                            last_update: None,
-                            last_visuals: Default::default(),
-                        }
-                    });
+    /// Send [`crate::Telemetry`] if it is time to.
+    fn send_telemetry(&mut self, interval: Option<f32>) {
+        let interval = match interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        if !self.said_hello || self.mjpeg {
+            return;
+        }
+        let now = crate::clock::now();
+        if now - self.last_telemetry < interval as f64 {
+            return;
+        }
+        let compression = match &self.tcp_endpoint {
+            Some(tcp_endpoint) => tcp_endpoint.compression,
+            None => return,
+        };
+        let timings = &self.timings;
+        let telemetry = match (
+            timings.encode.average(),
+            timings.uncompressed_size.average(),
+            timings.compressed_size.average(),
+        ) {
+            (Some(encode_time), Some(uncompressed_size), Some(compressed_size)) => {
+                crate::Telemetry {
+                    encode_time,
+                    uncompressed_size,
+                    compressed_size,
+                    compression,
+                    compression_level: self.level_control.level(),
+                }
+            }
+            _ => return, // no frames sent lately
+        };
+        self.last_telemetry = now;
+        self.send_message(&crate::ServerToClientMessage::Telemetry(telemetry));
+    }
 
-                    client.tcp_endpoint = Some(tcp_endpoint);
+    /// Send the font definitions if they have changed, e.g. with [`egui::Context::set_fonts`],
+    /// or if the client lacks glyphs for the text in `clipped_net_shapes`.
+    ///
+    /// Must be sent before the frame that uses them.
+    fn send_fonts(&mut self, font_subsetting: bool, clipped_net_shapes: &[ClippedNetShape]) {
+        if !self.said_hello || self.mjpeg {
+            return;
+        }
+        let mut send = std::mem::take(&mut self.fonts_requested);
+        let fonts = self.egui_ctx.fonts();
+        let fonts_ptr = fonts as *const egui::text::Fonts as usize;
+        if fonts_ptr != self.fonts_ptr {
+            self.fonts_ptr = fonts_ptr;
+            // Unless only the pixels_per_point changed:
+            if fonts.definitions() != &self.client_font_definitions {
+                self.client_font_definitions = fonts.definitions().clone();
+                self.client_font_chars = None;
+                send = true;
+                if let Some(preview) = &self.preview {
+                    preview.set_fonts(self.client_id, &self.client_font_definitions);
+                }
+            }
+        }
 
-                    // TODO: send egui::FontDefinitions to client
+        if !font_subsetting {
+            if send || self.client_font_chars.is_some() {
+                tracing::debug!("{}: sending new fonts", self.info());
+                self.client_font_chars = None;
+                self.send_message(&crate::ServerToClientMessage::Fonts {
+                    font_definitions: self.client_font_definitions.clone(),
+                });
+            }
+            return;
+        }
 
-                    tracing::info!("{} connected", client.info());
-                }
-                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    break; // No (more) new clients
-                }
-                Err(err) => {
-                    anyhow::bail!("eterm server TCP error: {:?}", err);
-                }
+        // Once we have sent a subset, keep it up to date with the text we show:
+        if send || self.client_font_chars.is_some() {
+            let chars = self
+                .client_font_chars
+                .get_or_insert_with(crate::font_subset::base_chars);
+            let num_sent_chars = chars.len();
+            crate::font_subset::collect_chars(clipped_net_shapes, chars);
+            let num_chars = chars.len();
+            if send || num_chars > num_sent_chars {
+                let font_definitions = crate::font_subset::subset_font_definitions(
+                    &self.client_font_definitions,
+                    chars,
+                );
+                tracing::debug!(
+                    "{}: sending fonts subset to {} characters",
+                    self.info(),
+                    num_chars
+                );
+                self.send_message(&crate::ServerToClientMessage::Fonts { font_definitions });
             }
         }
-        Ok(())
     }
 
-    /// non-blocking
-    fn try_receive(&mut self) {
-        for client in self.clients.values_mut() {
-            client.try_receive();
+    /// Send the [`crate::Theme`] if it has changed.
+    fn send_theme(&mut self) {
+        if !self.said_hello || self.mjpeg {
+            return;
+        }
+        let theme = crate::Theme::from_visuals(&self.egui_ctx.style().visuals);
+        if self.client_theme != Some(theme) {
+            self.client_theme = Some(theme);
+            self.send_message(&crate::ServerToClientMessage::Theme(theme));
         }
     }
-}
 
-// ----------------------------------------------------------------------------
+    /// Send the notice if the client hasn't seen it yet.
+    fn send_notice(&mut self, notice: Option<&crate::notice::Notice>) {
+        if !self.said_hello || self.notice.as_ref() == notice {
+            return;
+        }
+        self.notice = notice.cloned();
+        let (text, severity) = match notice {
+            Some(notice) => (notice.text.clone(), notice.severity),
+            None => (String::new(), crate::notice::Severity::Info),
+        };
+        self.send_message(&crate::ServerToClientMessage::Notice { text, severity });
+    }
 
-struct Client {
-    client_id: ClientId,
-    addr: SocketAddr,
-    tcp_endpoint: Option<crate::TcpEndpoint>,
-    start_time: std::time::Instant,
-    frame_index: u64,
-    egui_ctx: egui::CtxRef,
-    /// Set when there is something to do. Cleared after painting.
-    input: Option<egui::RawInput>,
-    /// The client time of the last input we got from them.
-    client_time: Option<f64>,
-    last_update: Option<std::time::Instant>,
-    last_visuals: Vec<ClippedNetShape>,
-}
+    /// Tell the client why, then disconnect.
+    fn say_goodbye(&mut self, reason: crate::GoodbyeReason) {
+        if !self.mjpeg {
+            self.send_message(&crate::ServerToClientMessage::Goodbye { reason });
+        }
+        self.disconnect();
+    }
 
-impl Client {
-    fn disconnect(&mut self) {
-        self.tcp_endpoint = None;
-        self.last_visuals = Default::default();
+    /// Send the font image the text meshes sample, if the client doesn't have it.
+    /// See [`Server::set_laid_out_text`].
+    fn send_font_image(&mut self) {
+        if !self.said_hello {
+            return;
+        }
+        let fonts = self.egui_ctx.fonts();
+        let font_image = fonts.font_image();
+        // A new `Fonts` starts over with the versions:
+        let key = (
+            fonts as *const egui::text::Fonts as usize,
+            font_image.version,
+        );
+        if self.client_font_image != Some(key) {
+            self.client_font_image = Some(key);
+            self.send_message(&crate::ServerToClientMessage::FontImage {
+                version: font_image.version,
+                width: font_image.width,
+                height: font_image.height,
+                pixels: font_image.pixels.clone(),
+            });
+        }
     }
 
     fn show(
         &mut self,
         do_ui: &mut dyn FnMut(&egui::CtxRef, ClientId),
-        minimum_update_interval: f32,
+        input_filter: Option<&mut InputFilter>,
+        frame_hook: Option<&mut FrameHook>,
+        settings: &ShowSettings,
     ) {
-        if self.tcp_endpoint.is_none() {
-            return;
+        let ShowSettings {
+            minimum_update_interval,
+            minimum_frame_interval,
+            overloaded,
+            frame_diagnostics,
+            font_subsetting,
+            laid_out_text,
+            catch_panics,
+            visuals_memory_budget,
+        } = *settings;
+        if !self.said_hello && !self.mjpeg {
+            return; // Not authenticated yet.
+        }
+        if !self.visible {
+            return; // Input is kept until it becomes visible again.
+        }
+
+        match &self.tcp_endpoint {
+            None => return,
+            Some(tcp_endpoint) if tcp_endpoint.has_pending_output() => {
+                // Wait for the socket to become writable instead of queuing up stale frames.
+                self.backlogged = true;
+                return;
+            }
+            Some(_) => {}
+        }
+
+        // Serve lower-fidelity frames when the server is overloaded:
+        let profile = if overloaded {
+            Profile::LowBandwidth
+        } else {
+            self.profile
+        };
+        let minimum_frame_interval = match profile {
+            Profile::Default => minimum_frame_interval,
+            Profile::LowBandwidth => minimum_frame_interval.max(1.0 / LOW_BANDWIDTH_FPS),
+        };
+        if let Some(last_update) = self.last_update {
+            if last_update.elapsed().as_secs_f32() < minimum_frame_interval {
+                return; // Too soon. Any new input is kept until the next frame.
+            }
+        }
+        let now = crate::clock::now();
+        if let Some(pacing) = &self.pacing {
+            if pacing.last_slot == Some(pacing.slot(now)) {
+                return; // We already sent a frame for this vsync of the viewer.
+            }
         }
 
+        let _span = profile_span!("show", client_id = self.client_id.0).entered();
+
         let client_time = self.client_time.take();
 
+        self.input_overflowed = false;
         let mut input = match self.input.take() {
             Some(input) => input,
             None => {
@@ -168,34 +1870,115 @@ impl Client {
         };
 
         self.last_update = Some(std::time::Instant::now());
+        if let Some(pacing) = &mut self.pacing {
+            pacing.last_slot = Some(pacing.slot(now));
+        }
+
+        if let Some(input_filter) = input_filter {
+            input_filter(self.client_id, &mut input);
+        }
 
         // Ignore client time:
         input.time = Some(self.start_time.elapsed().as_secs_f64());
 
-        let (mut output, clipped_shapes) = self
-            .egui_ctx
-            .run(input, |egui_ctx| do_ui(egui_ctx, self.client_id));
+        let client_id = self.client_id;
+        let role = self.role;
+        let gamepads = &self.gamepads;
+        let remote_cursors = &self.remote_cursors;
+        if self.profile == Profile::LowBandwidth && self.egui_ctx.style().animation_time > 0.0 {
+            // Animations mean many frames. Done here, since the style may have been replaced.
+            let mut style = (*self.egui_ctx.style()).clone();
+            style.animation_time = 0.0;
+            self.egui_ctx.set_style(style);
+        }
+
+        let run_span = profile_span!("run_ui").entered();
+        let ui_start = std::time::Instant::now();
+        let run = || {
+            self.egui_ctx.run(input, |egui_ctx| {
+                crate::gamepad::set_gamepads(egui_ctx, gamepads);
+                crate::role::set_role(egui_ctx, role);
+                do_ui(egui_ctx, client_id);
+                crate::presence::paint_remote_cursors(egui_ctx, remote_cursors);
+            })
+        };
+        let (mut output, mut clipped_shapes) = if catch_panics {
+            match std::panic::catch_unwind(std::panic::AssertUnwindSafe(run)) {
+                Ok(output_and_shapes) => output_and_shapes,
+                Err(panic) => {
+                    let message = panic_message(panic.as_ref());
+                    tracing::error!("{}: the ui panicked: {}", self.info(), message);
+                    self.audit_events
+                        .push(crate::audit::AuditEvent::UiPanicked { message });
+                    self.say_goodbye(crate::GoodbyeReason::ServerError);
+                    return;
+                }
+            }
+        } else {
+            run()
+        };
+        drop(run_span);
+        let ui_time = ui_start.elapsed().as_secs_f32();
+        self.timings.ui.add(crate::clock::now(), ui_time);
+        crate::gamepad::end_frame(&mut self.gamepads);
+        self.send_theme();
+        for file in crate::download::take_outgoing_files(&self.egui_ctx) {
+            self.start_file_transfer(file);
+        }
+        for notification in crate::notification::take_notifications(&self.egui_ctx) {
+            if !self.mjpeg {
+                self.send_message(&crate::ServerToClientMessage::Notification(notification));
+            }
+        }
+
+        let mjpeg_shapes = if self.mjpeg {
+            Some(clipped_shapes.clone())
+        } else {
+            None
+        };
+
+        let laid_out_text = laid_out_text && !self.mjpeg && profile != Profile::LowBandwidth;
+        if laid_out_text {
+            crate::net_shape::text_to_meshes(&mut clipped_shapes, self.egui_ctx.fonts());
+        }
 
-        let clipped_net_shapes = crate::net_shape::to_clipped_net_shapes(clipped_shapes);
+        let has_frame_hook = frame_hook.is_some();
+        let screen_rect = self.egui_ctx.input().screen_rect();
+        let clipped_net_shapes =
+            self.to_net_shapes(clipped_shapes, screen_rect, profile, frame_hook);
+        self.send_fonts(font_subsetting, &clipped_net_shapes);
+        if laid_out_text {
+            self.send_font_image();
+        }
 
         let needs_repaint = output.needs_repaint;
-        output.needs_repaint = false; // so we can compare below
+        output.needs_repaint = false; // the client doesn't need to know
 
-        if output == Default::default() && clipped_net_shapes == self.last_visuals {
+        if !crate::output::has_news(&output, &self.last_output_state)
+            && self.last_visuals.is_same(&clipped_net_shapes)
+        {
             // No change - save bandwidth and send nothing
+        } else if let Some(mut clipped_shapes) = mjpeg_shapes {
+            if has_frame_hook {
+                // Paint what the hook left us:
+                clipped_shapes = crate::net_shape::from_clipped_net_shapes(
+                    self.egui_ctx.fonts(),
+                    &clipped_net_shapes,
+                );
+            }
+            self.update_preview(&clipped_net_shapes);
+            self.last_visuals = LastVisuals::new(clipped_net_shapes, visuals_memory_budget);
+            self.last_output_state = crate::output::OutputState::from_output(&output);
+            self.send_mjpeg_frame(clipped_shapes);
         } else {
-            let frame_index = self.frame_index;
-            self.frame_index += 1;
-
-            let message = crate::ServerToClientMessage::Frame {
-                frame_index,
+            self.send_visuals(
                 output,
-                clipped_net_shapes: clipped_net_shapes.clone(),
+                clipped_net_shapes,
                 client_time,
-            };
-
-            self.last_visuals = clipped_net_shapes;
-            self.send_message(&message);
+                Some(ui_time),
+                frame_diagnostics,
+                visuals_memory_budget,
+            );
         }
 
         if needs_repaint {
@@ -207,10 +1990,143 @@ impl Client {
         }
     }
 
+    /// Cull the shapes to the screen, quantize them if needed and run the frame hook.
+    fn to_net_shapes(
+        &mut self,
+        clipped_shapes: Vec<egui::epaint::ClippedShape>,
+        screen_rect: egui::Rect,
+        profile: Profile,
+        frame_hook: Option<&mut FrameHook>,
+    ) -> Vec<ClippedNetShape> {
+        let _span = profile_span!("to_clipped_net_shapes").entered();
+        let start = std::time::Instant::now();
+        let mut clipped_net_shapes =
+            crate::net_shape::to_clipped_net_shapes(clipped_shapes, screen_rect);
+        if profile == Profile::LowBandwidth || self.level_control.should_quantize() {
+            crate::net_shape::quantize(&mut clipped_net_shapes);
+        }
+        if let Some(frame_hook) = frame_hook {
+            frame_hook(&self.client_info(), &mut clipped_net_shapes);
+        }
+        self.timings
+            .net_shapes
+            .add(crate::clock::now(), start.elapsed().as_secs_f32());
+        clipped_net_shapes
+    }
+
+    /// Send shapes painted outside of [`Self::show`]. See [`Server::paint_client`].
+    fn paint(
+        &mut self,
+        mut output: egui::Output,
+        clipped_shapes: Vec<egui::epaint::ClippedShape>,
+        frame_hook: Option<&mut FrameHook>,
+        frame_diagnostics: bool,
+        visuals_memory_budget: Option<usize>,
+    ) {
+        if !self.said_hello {
+            return;
+        }
+        let screen_rect = self.screen_rect;
+        let profile = self.profile;
+        let clipped_net_shapes =
+            self.to_net_shapes(clipped_shapes, screen_rect, profile, frame_hook);
+        output.needs_repaint = false; // the client doesn't need to know
+        if crate::output::has_news(&output, &self.last_output_state)
+            || !self.last_visuals.is_same(&clipped_net_shapes)
+        {
+            let client_time = self.client_time.take();
+            self.send_visuals(
+                output,
+                clipped_net_shapes,
+                client_time,
+                None,
+                frame_diagnostics,
+                visuals_memory_budget,
+            );
+        }
+    }
+
+    /// Send a frame, and remember what we sent.
+    fn send_visuals(
+        &mut self,
+        output: egui::Output,
+        clipped_net_shapes: Vec<ClippedNetShape>,
+        client_time: Option<f64>,
+        server_frame_time: Option<f32>,
+        frame_diagnostics: bool,
+        visuals_memory_budget: Option<usize>,
+    ) {
+        let frame_index = self.frame_index;
+        self.frame_index += 1;
+
+        let diff = match &self.last_visuals {
+            LastVisuals::Shapes(last_shapes) if frame_diagnostics => Some(
+                crate::net_shape::FrameDiff::new(last_shapes, &clipped_net_shapes),
+            ),
+            _ => None,
+        };
+
+        self.last_output_state = crate::output::OutputState::from_output(&output);
+
+        let message = crate::ServerToClientMessage::Frame {
+            frame_index,
+            output,
+            clipped_net_shapes: clipped_net_shapes.clone(),
+            client_time,
+            server_time: crate::clock::now(),
+            server_frame_time,
+        };
+
+        self.update_preview(&clipped_net_shapes);
+        self.last_visuals = LastVisuals::new(clipped_net_shapes, visuals_memory_budget);
+        let packet_size = self.send_frame(&message);
+        if let (Some(diff), Some(packet_size)) = (diff, packet_size) {
+            tracing::info!(
+                "{}: frame {}: {}. {} bytes.",
+                self.info(),
+                frame_index,
+                diff,
+                packet_size
+            );
+        }
+    }
+
+    /// See [`Server::preview`].
+    fn update_preview(&self, clipped_net_shapes: &[ClippedNetShape]) {
+        if let Some(preview) = &self.preview {
+            let screen_rect = self.egui_ctx.input().screen_rect();
+            preview.set_frame(self.client_id, screen_rect, clipped_net_shapes);
+        }
+    }
+
+    fn client_info(&self) -> ClientInfo {
+        ClientInfo {
+            client_id: self.client_id,
+            addr: self.addr,
+            preferences: self.preferences.clone(),
+            ui: self.ui.clone(),
+            role: self.role,
+        }
+    }
+
     fn info(&self) -> String {
         format!("Client {} ({})", self.client_id.0, self.addr)
     }
 
+    /// Send what the socket wasn't ready for before.
+    fn flush(&mut self) {
+        if let Some(tcp_endpoint) = &mut self.tcp_endpoint {
+            if let Err(err) = tcp_endpoint.flush() {
+                tracing::error!(
+                    "Failed to send to {}: {}. Disconnecting.",
+                    self.info(),
+                    crate::error_display_chain(err.as_ref())
+                );
+                self.disconnect();
+            }
+        }
+    }
+
     fn send_message(&mut self, message: &impl serde::Serialize) {
         if let Some(tcp_endpoint) = &mut self.tcp_endpoint {
             match tcp_endpoint.send_message(&message) {
@@ -228,41 +2144,363 @@ impl Client {
         }
     }
 
+    /// Like [`Self::send_message`], but records how long it took.
+    ///
+    /// Returns the size of the sent packet.
+    fn send_frame(&mut self, message: &crate::ServerToClientMessage) -> Option<usize> {
+        match self.try_send_frame(message) {
+            Ok(packet_size) => packet_size,
+            Err(err) => {
+                tracing::error!(
+                    "Failed to send frame to {}: {}. Disconnecting.",
+                    self.info(),
+                    crate::error_display_chain(err.as_ref())
+                );
+                self.disconnect();
+                None
+            }
+        }
+    }
+
+    fn try_send_frame(
+        &mut self,
+        message: &crate::ServerToClientMessage,
+    ) -> anyhow::Result<Option<usize>> {
+        let tcp_endpoint = match &mut self.tcp_endpoint {
+            Some(tcp_endpoint) => tcp_endpoint,
+            None => return Ok(None),
+        };
+
+        let start = std::time::Instant::now();
+        let (packet, uncompressed_size) = {
+            let _span = profile_span!("encode").entered();
+            crate::wire::encode_message_and_size(
+                message,
+                tcp_endpoint.compression,
+                self.level_control.level(),
+            )?
+        };
+        let encode_time = start.elapsed().as_secs_f32();
+
+        let start = std::time::Instant::now();
+        {
+            let _span = profile_span!("send", bytes = packet.len()).entered();
+            tcp_endpoint.send_packet(&packet)?;
+        }
+        let send_time = start.elapsed().as_secs_f32();
+
+        let backlogged = std::mem::take(&mut self.backlogged) || tcp_endpoint.has_pending_output();
+        self.level_control.on_frame(encode_time, backlogged);
+
+        let now = crate::clock::now();
+        self.timings.encode.add(now, encode_time);
+        self.timings.send.add(now, send_time);
+        self.timings.frames.add(now, ());
+        self.timings
+            .uncompressed_size
+            .add(now, uncompressed_size as f32);
+        self.timings.compressed_size.add(now, packet.len() as f32);
+        Ok(Some(packet.len()))
+    }
+
+    fn start_file_transfer(&mut self, file: crate::download::OutgoingFile) {
+        if self.mjpeg {
+            tracing::warn!("{}: can't send files to MJPEG viewers", self.info());
+            return;
+        }
+
+        let transfer_id = self.next_transfer_id;
+        self.next_transfer_id += 1;
+
+        tracing::info!(
+            "{}: sending {:?} ({} bytes)",
+            self.info(),
+            file.file_name,
+            file.bytes.len()
+        );
+
+        self.send_message(&crate::ServerToClientMessage::FileStart {
+            transfer_id,
+            file_name: file.file_name,
+            size: file.bytes.len() as u64,
+        });
+
+        if !file.bytes.is_empty() {
+            self.file_transfers.push_back(FileTransfer {
+                transfer_id,
+                bytes: file.bytes,
+                offset: 0,
+            });
+        }
+    }
+
+    /// Send the next few chunks of pending files.
+    /// We don't send them all at once so that we don't stall frames.
+    fn send_file_chunks(&mut self) {
+        for _ in 0..crate::download::CHUNKS_PER_UPDATE {
+            match &self.tcp_endpoint {
+                Some(tcp_endpoint) if !tcp_endpoint.has_pending_output() => {}
+                _ => return,
+            }
+            let transfer = match self.file_transfers.front_mut() {
+                Some(transfer) => transfer,
+                None => return,
+            };
+
+            let end = (transfer.offset + crate::download::CHUNK_SIZE).min(transfer.bytes.len());
+            let message = crate::ServerToClientMessage::FileChunk {
+                transfer_id: transfer.transfer_id,
+                bytes: transfer.bytes[transfer.offset..end].to_vec(),
+            };
+            transfer.offset = end;
+            if transfer.offset == transfer.bytes.len() {
+                self.file_transfers.pop_front();
+            }
+
+            self.send_message(&message);
+        }
+    }
+
+    /// Queued like a packet, so [`Self::show`] skips frames while a slow browser catches up.
+    fn send_mjpeg_frame(&mut self, clipped_shapes: Vec<egui::epaint::ClippedShape>) {
+        let result = crate::mjpeg::encode_frame(&self.egui_ctx, clipped_shapes).and_then(|part| {
+            match &mut self.tcp_endpoint {
+                Some(tcp_endpoint) => tcp_endpoint.send_raw(&part),
+                None => Ok(()),
+            }
+        });
+        if let Err(err) = result {
+            tracing::error!(
+                "Failed to send MJPEG frame to {}: {}. Disconnecting.",
+                self.info(),
+                crate::error_display_chain(err.as_ref())
+            );
+            self.disconnect();
+        }
+    }
+
     /// non-blocking
-    fn try_receive(&mut self) {
+    fn try_receive(
+        &mut self,
+        mjpeg_screen_size: egui::Vec2,
+        motd: &str,
+        app_info: &crate::AppInfo,
+        resumption_key: &crate::resume::TokenKey,
+        mut authenticator: Option<&mut Authenticator>,
+    ) {
+        let _span = profile_span!("receive", client_id = self.client_id.0).entered();
         loop {
             let tcp_endpoint = match &mut self.tcp_endpoint {
                 Some(tcp_endpoint) => tcp_endpoint,
                 None => return,
             };
 
-            let message = match tcp_endpoint.try_receive_message() {
-                Ok(None) => {
-                    return;
-                }
-                Ok(Some(message)) => message,
-                Err(err) => {
-                    tracing::error!(
-                        "Failed to read from client {}: {:?}. Disconnecting.",
-                        self.info(),
-                        crate::error_display_chain(err.as_ref())
-                    );
-                    self.disconnect();
-                    return;
-                }
+            if self.mjpeg {
+                tcp_endpoint.discard_incoming(); // no input from MJPEG viewers
+                return;
+            }
+
+            // Only the first thing a client sends can be an HTTP request:
+            let http_request_path = if self.said_hello {
+                None
+            } else {
+                tcp_endpoint.peek_http_request_path()
             };
+            if let Some(path) = http_request_path {
+                // Someone pointed their browser at us.
+                tcp_endpoint.discard_incoming();
+                if cfg!(feature = "mjpeg") && path.starts_with(crate::mjpeg::PATH) {
+                    // Browsers don't say hello, so we ask the authenticator here instead:
+                    if let Some(authenticator) = &mut authenticator {
+                        match authenticator(&self.client_info()) {
+                            Some(role) => {
+                                tracing::debug!("{}: role: {:?}", self.info(), role);
+                                self.role = role;
+                            }
+                            None => {
+                                tracing::info!("{}: authentication failed", self.info());
+                                self.disconnect();
+                                return;
+                            }
+                        }
+                    }
+                    let tcp_endpoint = match &mut self.tcp_endpoint {
+                        Some(tcp_endpoint) => tcp_endpoint,
+                        None => return,
+                    };
+                    let header = crate::mjpeg::response_header();
+                    if tcp_endpoint.send_raw(header.as_bytes()).is_ok() {
+                        tracing::info!("{}: streaming MJPEG", self.info());
+                        self.mjpeg = true;
+                        self.input(RawInput {
+                            screen_rect: Some(egui::Rect::from_min_size(
+                                Default::default(),
+                                mjpeg_screen_size,
+                            )),
+                            pixels_per_point: Some(1.0),
+                            ..Default::default()
+                        });
+                        return;
+                    }
+                } else {
+                    tcp_endpoint.send_http_page(HTTP_PAGE).ok();
+                    tracing::info!("{}: responded to HTTP request", self.info());
+                }
+                self.disconnect();
+                return;
+            }
+
+            let message =
+                match tcp_endpoint.try_receive_message(&crate::DecodeLimits::CLIENT_TO_SERVER) {
+                    Ok(None) => {
+                        return;
+                    }
+                    Ok(Some(message)) => message,
+                    Err(err) => {
+                        tracing::error!(
+                            "Failed to read from client {}: {:?}. Disconnecting.",
+                            self.info(),
+                            crate::error_display_chain(err.as_ref())
+                        );
+                        self.say_goodbye(crate::GoodbyeReason::ProtocolError);
+                        return;
+                    }
+                };
 
             match message {
                 ClientToServerMessage::Input {
-                    raw_input,
+                    mut raw_input,
                     client_time,
                 } => {
+                    if let Some(rate_limiter) = &mut self.rate_limiter {
+                        match rate_limiter.limit(crate::clock::now(), &mut raw_input.events) {
+                            crate::rate_limit::Verdict::Ok => {}
+                            crate::rate_limit::Verdict::Limited { dropped } => {
+                                tracing::debug!(
+                                    "{}: over the input rate limit. Dropped {} pointer moves.",
+                                    self.info(),
+                                    dropped
+                                );
+                            }
+                            crate::rate_limit::Verdict::Flooding => {
+                                tracing::warn!(
+                                    "{}: far over the input rate limit. Disconnecting.",
+                                    self.info()
+                                );
+                                self.say_goodbye(crate::GoodbyeReason::Kicked);
+                                return;
+                            }
+                        }
+                    }
+                    if !self.role.can_control() {
+                        crate::role::strip_control_input(&mut raw_input);
+                    }
                     // eprintln!("Received new input");
+                    if !raw_input.events.is_empty() {
+                        self.last_user_input = Some(std::time::Instant::now());
+                        self.audit_events
+                            .extend(crate::audit::summarize_input(&raw_input));
+                    }
                     self.input(raw_input);
                     self.client_time = Some(client_time);
                     // keep polling for more messages
                 }
-                ClientToServerMessage::Goodbye => {
+                ClientToServerMessage::Ping { client_time } => {
+                    // Answer right away, so the round-trip is as short as possible:
+                    self.send_message(&crate::ServerToClientMessage::Pong {
+                        client_time,
+                        server_time: crate::clock::now(),
+                    });
+                }
+                ClientToServerMessage::Gamepad { events } => {
+                    if !self.role.can_control() {
+                        continue;
+                    }
+                    self.last_user_input = Some(std::time::Instant::now());
+                    for event in events {
+                        crate::gamepad::apply_event(&mut self.gamepads, event);
+                    }
+                    self.input(RawInput::default()); // repaint
+                }
+                ClientToServerMessage::Hello {
+                    compressions,
+                    resumption_token,
+                    preferences,
+                } => {
+                    self.set_preferences(preferences);
+                    let role = match &mut authenticator {
+                        Some(authenticator) => authenticator(&self.client_info()),
+                        None => Some(Default::default()),
+                    };
+                    match role {
+                        Some(role) => {
+                            tracing::debug!("{}: role: {:?}", self.info(), role);
+                            self.role = role;
+                        }
+                        None => {
+                            tracing::info!("{}: authentication failed", self.info());
+                            self.say_goodbye(crate::GoodbyeReason::AuthFailed);
+                            return;
+                        }
+                    }
+                    let session = resumption_token.map(|token| resumption_key.verify(&token));
+                    if let Some(session) = &session {
+                        self.audit_events
+                            .push(crate::audit::AuditEvent::Resumption {
+                                accepted: session.is_some(),
+                            });
+                    }
+                    let session = session.flatten();
+                    match session {
+                        Some(session) if session.client_id != self.client_id.0 => {
+                            // The server will move us over to the old session, then say hello.
+                            self.resume_request = Some((session, compressions));
+                            return;
+                        }
+                        _ => {
+                            self.say_hello(&compressions, motd, app_info, resumption_key);
+                        }
+                    }
+                }
+                ClientToServerMessage::Visibility { visible } => {
+                    tracing::debug!("{}: visible: {}", self.info(), visible);
+                    if visible && !self.visible {
+                        // Send everything again, in case the viewer threw it away:
+                        self.last_visuals = Default::default();
+                        self.last_output_state = Default::default();
+                        self.input(RawInput::default());
+                    }
+                    self.visible = visible;
+                }
+                ClientToServerMessage::Preferences(preferences) => {
+                    self.set_preferences(preferences);
+                }
+                ClientToServerMessage::DisplayTiming {
+                    refresh_rate,
+                    send_phase,
+                } => {
+                    self.pacing =
+                        if (1.0..=1000.0).contains(&refresh_rate) && send_phase.is_finite() {
+                            Some(FramePacing {
+                                period: 1.0 / refresh_rate as f64,
+                                send_phase,
+                                last_slot: self.pacing.as_ref().and_then(|pacing| pacing.last_slot),
+                            })
+                        } else {
+                            None
+                        };
+                }
+                ClientToServerMessage::RequestFonts => {
+                    tracing::debug!("{} asked for the fonts", self.info());
+                    self.fonts_requested = true; // sent with the next frame
+                }
+                ClientToServerMessage::Ack { num_received } => {
+                    if let Some(tcp_endpoint) = &mut self.tcp_endpoint {
+                        tcp_endpoint.sequencer.on_ack(num_received);
+                    }
+                }
+                ClientToServerMessage::Goodbye { reason } => {
+                    tracing::info!("{} said goodbye: {}", self.info(), reason);
                     self.disconnect();
                     return;
                 }
@@ -271,6 +2509,9 @@ impl Client {
     }
 
     fn input(&mut self, new_input: RawInput) {
+        if let Some(screen_rect) = new_input.screen_rect {
+            self.screen_rect = screen_rect;
+        }
         match &mut self.input {
             None => {
                 self.input = Some(new_input);
@@ -279,5 +2520,151 @@ impl Client {
                 existing_input.append(new_input);
             }
         }
+
+        // In case `Server::show` isn't called often enough to keep up:
+        let mut num_dropped = 0;
+        if let Some(input) = &mut self.input {
+            if input.events.len() > MAX_BUFFERED_EVENTS {
+                coalesce_moves(&mut input.events);
+                num_dropped = input.events.len().saturating_sub(MAX_BUFFERED_EVENTS);
+                input.events.drain(..num_dropped);
+            }
+        }
+        if num_dropped > 0 && !self.input_overflowed {
+            tracing::warn!(
+                "{}: too much input waiting for the next frame. Dropping the oldest events.",
+                self.info()
+            );
+            self.input_overflowed = true;
+        }
+    }
+}
+
+/// The message given to `panic!`, if any.
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "(no message)".to_owned()
+    }
+}
+
+/// Merge consecutive pointer moves (and touch moves of the same finger) into the latest one.
+pub(crate) fn coalesce_moves(events: &mut Vec<egui::Event>) {
+    use egui::{Event, TouchPhase};
+    let is_same_move = |a: &Event, b: &Event| match (a, b) {
+        (Event::PointerMoved(_), Event::PointerMoved(_)) => true,
+        (
+            Event::Touch {
+                device_id: device_a,
+                id: id_a,
+                phase: TouchPhase::Move,
+                ..
+            },
+            Event::Touch {
+                device_id: device_b,
+                id: id_b,
+                phase: TouchPhase::Move,
+                ..
+            },
+        ) => device_a == device_b && id_a == id_b,
+        _ => false,
+    };
+
+    let mut coalesced: Vec<Event> = Vec::with_capacity(events.len());
+    for event in events.drain(..) {
+        match coalesced.last_mut() {
+            Some(last) if is_same_move(last, &event) => *last = event,
+            _ => coalesced.push(event),
+        }
+    }
+    *events = coalesced;
+}
+
+#[test]
+fn test_coalesce_moves() {
+    use egui::{pos2, Event};
+    let mut events = vec![
+        Event::PointerMoved(pos2(1.0, 1.0)),
+        Event::PointerMoved(pos2(2.0, 2.0)),
+        Event::Text("a".to_owned()),
+        Event::PointerMoved(pos2(3.0, 3.0)),
+        Event::PointerMoved(pos2(4.0, 4.0)),
+        Event::PointerGone,
+    ];
+    coalesce_moves(&mut events);
+    assert_eq!(
+        events,
+        vec![
+            Event::PointerMoved(pos2(2.0, 2.0)),
+            Event::Text("a".to_owned()),
+            Event::PointerMoved(pos2(4.0, 4.0)),
+            Event::PointerGone,
+        ]
+    );
+}
+
+#[test]
+fn test_load_monitor() {
+    let mut load_monitor = LoadMonitor::new(0.010);
+    for _ in 0..100 {
+        assert!(!load_monitor.on_show(0.005));
+    }
+    let mut frames = 0;
+    while !load_monitor.on_show(0.020) {
+        frames += 1;
+        assert!(frames < 100);
+    }
+    assert!(frames > 1, "a single slow frame is not overload");
+    assert!(load_monitor.on_show(0.007), "hysteresis");
+    while load_monitor.on_show(0.001) {}
+}
+
+#[test]
+fn test_panic_message() {
+    let panic = std::panic::catch_unwind(|| panic!("odd screen size: {}", 0)).unwrap_err();
+    assert_eq!(panic_message(panic.as_ref()), "odd screen size: 0");
+    let panic = std::panic::catch_unwind(|| std::panic::panic_any(42)).unwrap_err();
+    assert_eq!(panic_message(panic.as_ref()), "(no message)");
+}
+
+#[test]
+fn test_no_frames_without_authentication() {
+    use std::io::{Read as _, Write as _};
+    use std::time::Duration;
+
+    let mut server = Server::new("127.0.0.1:0").unwrap();
+    server.set_authenticator(|_client_info| None);
+    let addr = server.local_addr().unwrap();
+    let mut silent = std::net::TcpStream::connect(addr).unwrap(); // never says hello
+    let mut browser = std::net::TcpStream::connect(addr).unwrap();
+    browser
+        .write_all(b"GET /mjpeg HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+
+    for _ in 0..10 {
+        server
+            .show(|egui_ctx, _client_id| {
+                egui::CentralPanel::default().show(egui_ctx, |ui| ui.label("Secret"));
+            })
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut received = vec![];
+    silent
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    silent.read_to_end(&mut received).ok();
+    assert!(received.is_empty(), "got {} bytes", received.len());
+
+    if cfg!(feature = "mjpeg") {
+        browser
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        browser.read_to_end(&mut received).ok();
+        assert!(received.is_empty(), "got {} bytes", received.len());
     }
 }
@@ -0,0 +1,204 @@
+//! Running a ui for a few frames without any network, and saving what a viewer would see.
+//! For golden-image tests of server uis.
+//!
+//! ``` no_run
+//! # fn test() -> anyhow::Result<()> {
+//! let options = eterm::snapshot::Options {
+//!     input: Some("tests/click_save.ron".into()),
+//!     png: Some("tests/click_save.png".into()),
+//!     ..Default::default()
+//! };
+//! eterm::snapshot::run(&options, |egui_ctx| {
+//!     egui::CentralPanel::default().show(egui_ctx, |ui| ui.button("Save"));
+//! })?;
+//! # Ok(()) }
+//! ```
+//!
+//! The input is an [`egui::RawInput`] in [RON](https://github.com/ron-rs/ron).
+//! Write one with `ron::to_string` to get all the fields.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use egui::{text::Fonts, FontDefinitions, Rect};
+
+use crate::{net_shape::ClippedNetShape, ServerToClientMessage};
+
+/// The screen when the input doesn't say.
+const DEFAULT_SCREEN_SIZE: egui::Vec2 = egui::Vec2 { x: 800.0, y: 600.0 };
+
+/// What to run, and where to save the last frame.
+pub struct Options {
+    /// A RON file with the [`egui::RawInput`] for the first frame.
+    /// The following frames get the same input, minus the events.
+    pub input: Option<PathBuf>,
+    /// Run this many frames. Some layouts need a couple to settle.
+    pub num_frames: usize,
+    /// Save the last frame as a PNG here.
+    pub png: Option<PathBuf>,
+    /// Scale factor of the PNG.
+    pub pixels_per_point: f32,
+    /// Save the last frame as a recording (see [`crate::recording`]) here,
+    /// e.g. for `eterm_inspect` or `eterm-export`.
+    pub recording: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            input: None,
+            num_frames: 3,
+            png: None,
+            pixels_per_point: 1.0,
+            recording: None,
+        }
+    }
+}
+
+/// The last frame of [`run_frames`], as it would be sent to a viewer.
+pub struct Snapshot {
+    pub screen_rect: Rect,
+    pub font_definitions: FontDefinitions,
+    pub output: egui::Output,
+    pub clipped_net_shapes: Vec<ClippedNetShape>,
+}
+
+/// Run the ui as described by `options`, save the last frame, and return it.
+///
+/// # Errors
+/// If the input can't be read, or a file can't be written.
+pub fn run(options: &Options, run_ui: impl FnMut(&egui::CtxRef)) -> anyhow::Result<Snapshot> {
+    let raw_input = match &options.input {
+        Some(path) => read_raw_input(path)?,
+        None => Default::default(),
+    };
+    let snapshot = run_frames(raw_input, options.num_frames, run_ui);
+    if let Some(path) = &options.png {
+        write_png(path, &snapshot.to_image(options.pixels_per_point))?;
+        tracing::info!("Saved {:?}", path);
+    }
+    if let Some(path) = &options.recording {
+        snapshot.write_recording(path)?;
+        tracing::info!("Saved {:?}", path);
+    }
+    Ok(snapshot)
+}
+
+/// Run the ui for `num_frames` frames (at least one), and return the last.
+///
+/// The events of `raw_input` are only given to the first frame.
+pub fn run_frames(
+    mut raw_input: egui::RawInput,
+    num_frames: usize,
+    mut run_ui: impl FnMut(&egui::CtxRef),
+) -> Snapshot {
+    let screen_rect = *raw_input
+        .screen_rect
+        .get_or_insert_with(|| Rect::from_min_size(egui::Pos2::ZERO, DEFAULT_SCREEN_SIZE));
+    let start_time = raw_input.time.unwrap_or(0.0);
+
+    let mut egui_ctx = egui::CtxRef::default();
+    let mut last_frame = None;
+    for frame_index in 0..num_frames.max(1) {
+        let mut frame_input = raw_input.clone();
+        frame_input.time = Some(start_time + frame_index as f64 * raw_input.predicted_dt as f64);
+        if frame_index > 0 {
+            frame_input.events.clear();
+        }
+        last_frame = Some(egui_ctx.run(frame_input, &mut run_ui));
+    }
+    let (output, clipped_shapes) = last_frame.unwrap_or_default();
+
+    Snapshot {
+        screen_rect,
+        font_definitions: egui_ctx.fonts().definitions().clone(),
+        output,
+        clipped_net_shapes: crate::net_shape::to_clipped_net_shapes(clipped_shapes, screen_rect),
+    }
+}
+
+impl Snapshot {
+    /// Paint the frame like the viewer would, at the size of the screen.
+    pub fn to_image(&self, pixels_per_point: f32) -> crate::raster::Image {
+        let fonts = Fonts::new(pixels_per_point, self.font_definitions.clone());
+        let clipped_shapes =
+            crate::net_shape::from_clipped_net_shapes(&fonts, &self.clipped_net_shapes);
+        let tesselator_options =
+            egui::epaint::tessellator::TessellationOptions::from_pixels_per_point(pixels_per_point);
+        let font_image = fonts.font_image();
+        let clipped_meshes = egui::epaint::tessellator::tessellate_shapes(
+            clipped_shapes,
+            tesselator_options,
+            font_image.size(),
+        );
+        let size = self.screen_rect.size() * pixels_per_point;
+        crate::raster::rasterize(
+            [size.x.round() as usize, size.y.round() as usize],
+            egui::Color32::BLACK,
+            pixels_per_point,
+            &clipped_meshes,
+            &font_image,
+        )
+    }
+
+    /// Save as a recording of a single frame.
+    ///
+    /// # Errors
+    /// If the file can't be written.
+    pub fn write_recording(&self, path: &Path) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path).with_context(|| format!("creating {:?}", path))?;
+        let mut writer = crate::recording::RecordingWriter::new(std::io::BufWriter::new(file))?;
+        if self.font_definitions != FontDefinitions::default() {
+            writer.write_message(&ServerToClientMessage::Fonts {
+                font_definitions: self.font_definitions.clone(),
+            })?;
+        }
+        writer.write_message(&ServerToClientMessage::Frame {
+            frame_index: 0,
+            output: self.output.clone(),
+            clipped_net_shapes: self.clipped_net_shapes.clone(),
+            client_time: None,
+            server_time: 0.0,
+            server_frame_time: None,
+        })?;
+        use std::io::Write as _;
+        writer.into_inner().flush().context("write")
+    }
+}
+
+fn read_raw_input(path: &Path) -> anyhow::Result<egui::RawInput> {
+    let ron = std::fs::read_to_string(path).with_context(|| format!("reading {:?}", path))?;
+    ron::from_str(&ron).with_context(|| format!("parsing {:?}", path))
+}
+
+fn write_png(path: &Path, image: &crate::raster::Image) -> anyhow::Result<()> {
+    let file = std::fs::File::create(path).with_context(|| format!("creating {:?}", path))?;
+    let mut encoder = png::Encoder::new(
+        std::io::BufWriter::new(file),
+        image.width() as u32,
+        image.height() as u32,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().context("png header")?;
+    writer
+        .write_image_data(&image.as_rgba_bytes())
+        .with_context(|| format!("writing {:?}", path))
+}
+
+#[test]
+fn test_snapshot() {
+    let raw_input = egui::RawInput {
+        screen_rect: Some(Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(200.0, 100.0),
+        )),
+        ..Default::default()
+    };
+    let snapshot = run_frames(raw_input, 2, |egui_ctx| {
+        egui::CentralPanel::default().show(egui_ctx, |ui| ui.label("Hello snapshot"));
+    });
+    assert!(!snapshot.clipped_net_shapes.is_empty());
+    let image = snapshot.to_image(2.0);
+    assert_eq!((image.width(), image.height()), (400, 200));
+}
@@ -0,0 +1,58 @@
+//! Tunnel the eterm connection over SSH by spawning the system `ssh` (OpenSSH).
+
+use anyhow::Context as _;
+
+/// An `ssh -L` port forward. The `ssh` process is killed when this is dropped.
+pub(crate) struct SshTunnel {
+    child: std::process::Child,
+    local_addr: String,
+}
+
+impl SshTunnel {
+    /// Forward a free local port to `remote_addr` (as seen from `ssh_target`).
+    ///
+    /// `ssh_target` is anything `ssh` accepts, e.g. `user@host` or a `Host` from `~/.ssh/config`.
+    /// `remote_addr` is e.g. `127.0.0.1:8505`.
+    pub fn new(ssh_target: &str, remote_addr: &str) -> anyhow::Result<Self> {
+        let local_port = free_local_port()?;
+        let local_addr = format!("127.0.0.1:{}", local_port);
+
+        tracing::info!(
+            "Forwarding {} to {} via ssh {}…",
+            local_addr,
+            remote_addr,
+            ssh_target
+        );
+
+        let child = std::process::Command::new("ssh")
+            .arg("-N") // no remote command
+            .arg("-o")
+            .arg("ExitOnForwardFailure=yes")
+            .arg("-L")
+            .arg(format!("{}:{}", local_addr, remote_addr))
+            .arg(ssh_target)
+            .stdin(std::process::Stdio::null())
+            .spawn()
+            .context("Failed to start ssh. Is OpenSSH installed?")?;
+
+        Ok(Self { child, local_addr })
+    }
+
+    /// Where to connect to reach the remote server.
+    pub fn local_addr(&self) -> &str {
+        &self.local_addr
+    }
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+fn free_local_port() -> anyhow::Result<u16> {
+    let listener =
+        std::net::TcpListener::bind("127.0.0.1:0").context("Failed to find a free local port")?;
+    Ok(listener.local_addr()?.port())
+}
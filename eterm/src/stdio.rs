@@ -0,0 +1,222 @@
+//! Speaking the eterm protocol over stdin and stdout, so a session can ride an existing
+//! SSH connection (or any other pipe) without opening a port to the outside.
+//!
+//! The server, started with e.g. `ssh user@host my-server --stdio`:
+//!
+//! ``` no_run
+//! # fn test() -> anyhow::Result<()> {
+//! // Only reachable from this machine. The client comes in over stdio.
+//! let mut server = eterm::Server::new("127.0.0.1:0")?;
+//! let stdio = server.serve_stdio()?;
+//! // Turn away anyone else on this machine:
+//! let stdio_client = stdio.client_id();
+//! server.set_authenticator(move |client_info| {
+//!     (client_info.client_id == stdio_client).then(Default::default)
+//! });
+//! while !stdio.is_closed() {
+//!     server.show(|egui_ctx, _client_id| {
+//!         egui::CentralPanel::default().show(egui_ctx, |ui| ui.label("Hello"));
+//!     })?;
+//!     std::thread::sleep(std::time::Duration::from_millis(10));
+//! }
+//! # Ok(()) }
+//! ```
+//!
+//! Anything else written to stdout breaks the protocol, so log to stderr or a file.
+//!
+//! The client: `eterm::Client::new_command("ssh user@host my-server --stdio")`.
+//!
+//! Both sides bridge the pipes to a TCP connection on the loopback interface,
+//! so everything else works as usual. Nobody else gets to connect to it.
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use parking_lot::Mutex;
+
+/// Connects stdin and stdout to the server. See [`crate::Server::serve_stdio`].
+pub struct StdioBridge {
+    client_id: crate::ClientId,
+    closed: Arc<AtomicBool>,
+}
+
+impl StdioBridge {
+    /// `stream` is connected to the server.
+    pub(crate) fn start(client_id: crate::ClientId, stream: TcpStream) -> anyhow::Result<Self> {
+        stream.set_nodelay(true).ok();
+        let closed = Arc::new(AtomicBool::new(false));
+
+        {
+            let stream = stream.try_clone().context("try_clone")?;
+            let closed = closed.clone();
+            std::thread::spawn(move || {
+                pipe(std::io::stdin(), &stream);
+                // Let the server know the client is gone:
+                stream.shutdown(Shutdown::Write).ok();
+                closed.store(true, SeqCst);
+            });
+        }
+        {
+            let closed = closed.clone();
+            std::thread::spawn(move || {
+                pipe(&stream, std::io::stdout());
+                closed.store(true, SeqCst);
+            });
+        }
+
+        Ok(Self { client_id, closed })
+    }
+
+    /// The client coming in over stdio.
+    pub fn client_id(&self) -> crate::ClientId {
+        self.client_id
+    }
+
+    /// Has either the client or the server hung up? Then it is time to exit.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(SeqCst)
+    }
+}
+
+/// Runs a command each time we connect,
+/// and connects the connection to the stdin and stdout of the command.
+///
+/// The commands still running are killed when this is dropped.
+pub(crate) struct CommandTunnel {
+    program: String,
+    args: Vec<String>,
+    children: Mutex<Vec<std::process::Child>>,
+}
+
+impl CommandTunnel {
+    /// `command` is split on whitespace, without any quoting.
+    pub fn new(command: &str) -> anyhow::Result<Self> {
+        let mut words = command.split_whitespace().map(ToOwned::to_owned);
+        let program = words.next().context("The command is empty")?;
+        Ok(Self {
+            program,
+            args: words.collect(),
+            children: Default::default(),
+        })
+    }
+
+    /// Run the command, and return a connection to it.
+    pub fn connect(&self) -> std::io::Result<TcpStream> {
+        let (ours, theirs) = local_pair()?;
+        run_command(theirs, &self.program, &self.args, &self.children)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, format!("{:#}", err)))?;
+        Ok(ours)
+    }
+}
+
+impl Drop for CommandTunnel {
+    fn drop(&mut self) {
+        for mut child in self.children.lock().drain(..) {
+            child.kill().ok();
+            child.wait().ok();
+        }
+    }
+}
+
+/// Two connected TCP streams on the loopback interface, like a `socketpair`.
+///
+/// The port is only open during this call, and we only accept the connection we made ourselves,
+/// so no one else on this machine can get in.
+pub(crate) fn local_pair() -> std::io::Result<(TcpStream, TcpStream)> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let ours = TcpStream::connect(listener.local_addr()?)?;
+    let our_addr = ours.local_addr()?;
+    loop {
+        let (theirs, peer_addr) = listener.accept()?;
+        if peer_addr == our_addr {
+            return Ok((ours, theirs));
+        }
+        tracing::warn!("Refused a connection from {} to a local pair", peer_addr);
+    }
+}
+
+fn run_command(
+    stream: TcpStream,
+    program: &str,
+    args: &[String],
+    children: &Mutex<Vec<std::process::Child>>,
+) -> anyhow::Result<()> {
+    tracing::info!("Running {} {}…", program, args.join(" "));
+    let mut child = std::process::Command::new(program)
+        .args(args)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {:?}", program))?;
+    let child_stdin = child.stdin.take().context("stdin")?;
+    let child_stdout = child.stdout.take().context("stdout")?;
+    stream.set_nodelay(true).ok();
+
+    {
+        let stream = stream.try_clone().context("try_clone")?;
+        std::thread::spawn(move || {
+            pipe(&stream, child_stdin); // The command sees its stdin close when we return
+        });
+    }
+    std::thread::spawn(move || {
+        pipe(child_stdout, &stream);
+        stream.shutdown(Shutdown::Both).ok();
+    });
+
+    // Forget the commands of earlier connections that have finished:
+    let mut children = children.lock();
+    let mut running: Vec<_> = children
+        .drain(..)
+        .filter_map(|mut child| match child.try_wait() {
+            Ok(None) => Some(child),
+            _ => None,
+        })
+        .collect();
+    running.push(child);
+    *children = running;
+    Ok(())
+}
+
+/// Copy everything from `from` to `to`, as soon as it arrives, until either side is closed.
+fn pipe(mut from: impl Read, mut to: impl Write) {
+    let mut buffer = vec![0_u8; 64 * 1024];
+    loop {
+        let num_bytes = match from.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(num_bytes) => num_bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        };
+        if to
+            .write_all(&buffer[..num_bytes])
+            .and_then(|()| to.flush())
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+#[test]
+fn test_local_pair() {
+    let (mut ours, mut theirs) = local_pair().unwrap();
+    assert_eq!(ours.local_addr().unwrap(), theirs.peer_addr().unwrap());
+    ours.write_all(b"ping").unwrap();
+    let mut ping = [0_u8; 4];
+    theirs.read_exact(&mut ping).unwrap();
+    assert_eq!(&ping, b"ping");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_command_tunnel() {
+    let tunnel = CommandTunnel::new("cat").unwrap();
+    let mut stream = tunnel.connect().unwrap();
+    stream.write_all(b"echo").unwrap();
+    let mut echo = [0_u8; 4];
+    stream.read_exact(&mut echo).unwrap();
+    assert_eq!(&echo, b"echo");
+}
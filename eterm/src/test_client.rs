@@ -0,0 +1,175 @@
+//! A client without a window, for integration tests of served uis.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+use egui::{Pos2, RawInput, Rect};
+
+use crate::Client;
+
+/// How long the `wait_for_*` functions wait by default.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Drives a served ui over the real protocol, like a viewer would, e.g. in CI:
+///
+/// ``` no_run
+/// # fn test() -> anyhow::Result<()> {
+/// let mut client = eterm::TestClient::connect("127.0.0.1:8505");
+/// client.wait_for_frame_containing("Name")?;
+/// client.click_text("Name")?;
+/// client.type_text("Ferris");
+/// client.click_text("Save")?;
+/// client.wait_for_frame_containing("Saved")?;
+/// # Ok(()) }
+/// ```
+///
+/// Text is found by looking at the text shapes the server sends,
+/// so it must be painted (and not clipped away) to be found.
+pub struct TestClient {
+    client: Client,
+    screen_rect: Rect,
+    pixels_per_point: f32,
+    timeout: Duration,
+}
+
+impl TestClient {
+    /// Connect to a server, with an 800x600 screen.
+    ///
+    /// Doesn't reconnect: once disconnected, the `wait_for_*` functions fail.
+    pub fn connect(addr: impl Into<String>) -> Self {
+        let client = Client::new(addr.into());
+        client.set_reconnect(false);
+        let test_client = Self {
+            client,
+            screen_rect: Rect::from_min_size(Pos2::ZERO, egui::vec2(800.0, 600.0)),
+            pixels_per_point: 1.0,
+            timeout: DEFAULT_TIMEOUT,
+        };
+        test_client.send_input(RawInput::default());
+        test_client
+    }
+
+    /// Size of the screen, in points.
+    pub fn set_screen_size(&mut self, screen_size: egui::Vec2) {
+        self.screen_rect = Rect::from_min_size(Pos2::ZERO, screen_size);
+        self.send_input(RawInput::default());
+    }
+
+    /// How long the `wait_for_*` functions wait before giving up. Default: ten seconds.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// The underlying client, e.g. for its [`Client::visible_text`].
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Send some input, with our screen size filled in.
+    pub fn send_input(&self, raw_input: RawInput) {
+        self.client.send_input(RawInput {
+            screen_rect: Some(self.screen_rect),
+            pixels_per_point: Some(self.pixels_per_point),
+            time: None, // server knows the time
+            ..raw_input
+        });
+    }
+
+    /// Move the pointer to `pos` and click the primary button there.
+    pub fn click_at(&self, pos: Pos2) {
+        let button = |pressed| egui::Event::PointerButton {
+            pos,
+            button: egui::PointerButton::Primary,
+            pressed,
+            modifiers: Default::default(),
+        };
+        // Separate frames, so the ui sees the button go down and then up:
+        self.send_input(RawInput {
+            events: vec![egui::Event::PointerMoved(pos), button(true)],
+            ..Default::default()
+        });
+        self.send_input(RawInput {
+            events: vec![button(false)],
+            ..Default::default()
+        });
+    }
+
+    /// Click the middle of the first text shape containing `text`, waiting for it to show up.
+    ///
+    /// # Errors
+    /// If the text doesn't show up in time.
+    pub fn click_text(&mut self, text: &str) -> anyhow::Result<()> {
+        let rect = self.wait_for_frame_containing(text)?;
+        self.click_at(rect.center());
+        Ok(())
+    }
+
+    /// Type into whatever has keyboard focus.
+    pub fn type_text(&self, text: &str) {
+        self.send_input(RawInput {
+            events: vec![egui::Event::Text(text.to_owned())],
+            ..Default::default()
+        });
+    }
+
+    /// Press and release a key.
+    pub fn press_key(&self, key: egui::Key, modifiers: egui::Modifiers) {
+        let key_event = |pressed| egui::Event::Key {
+            key,
+            pressed,
+            modifiers,
+        };
+        self.send_input(RawInput {
+            events: vec![key_event(true), key_event(false)],
+            ..Default::default()
+        });
+    }
+
+    /// Where the first text shape containing `text` is in the latest frame, if any.
+    pub fn find_text(&self, text: &str) -> Option<Rect> {
+        let visible_text = self.client.visible_text();
+        let found = visible_text.find(text).next()?;
+        Some(found.rect)
+    }
+
+    /// Wait for the next frame from the server.
+    ///
+    /// # Errors
+    /// If no frame arrives in time, or we are disconnected.
+    pub fn wait_for_frame(&mut self) -> anyhow::Result<crate::EguiFrame> {
+        self.wait_for(|_, frame| frame.take())
+            .context("waiting for a frame")
+    }
+
+    /// Wait until the server has painted `text`, and return where.
+    ///
+    /// # Errors
+    /// If the text doesn't show up in time, or we are disconnected.
+    pub fn wait_for_frame_containing(&mut self, text: &str) -> anyhow::Result<Rect> {
+        self.wait_for(|client, _| client.find_text(text))
+            .with_context(|| format!("waiting for {:?}", text))
+    }
+
+    /// Keep updating until `f` returns something, which it is given each new frame to look at.
+    fn wait_for<R>(
+        &mut self,
+        mut f: impl FnMut(&Self, &mut Option<crate::EguiFrame>) -> Option<R>,
+    ) -> anyhow::Result<R> {
+        let start = Instant::now();
+        loop {
+            let mut frame = self.client.update(self.pixels_per_point);
+            if let Some(result) = f(self, &mut frame) {
+                return Ok(result);
+            }
+            if let Some(reason) = self.client.disconnect_reason() {
+                if self.client.has_given_up() {
+                    anyhow::bail!("Disconnected: {}", reason);
+                }
+            }
+            if start.elapsed() > self.timeout {
+                anyhow::bail!("Timed out after {:.1} s", self.timeout.as_secs_f32());
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+}
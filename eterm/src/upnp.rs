@@ -0,0 +1,100 @@
+//! Ask the router to forward a port to us with UPnP, see [`crate::Server::map_port_with_upnp`].
+
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+
+/// Routers forget the mapping after this long, in case we crash.
+const LEASE_DURATION: Duration = Duration::from_secs(600);
+
+/// A TCP port forward on the router, removed when dropped.
+pub(crate) struct PortMapping {
+    gateway: igd::Gateway,
+    local_addr: SocketAddrV4,
+    external_addr: SocketAddrV4,
+    /// When we last asked the router to (re-)add the mapping.
+    last_renewed: Instant,
+}
+
+impl PortMapping {
+    /// Forward the same port number on the router to `local_port` on this machine.
+    ///
+    /// Blocks while looking for the router, for a few seconds at most.
+    pub fn new(local_port: u16) -> anyhow::Result<Self> {
+        let gateway =
+            igd::search_gateway(Default::default()).context("Failed to find a UPnP router")?;
+        let local_ip = local_ip_towards(gateway.addr)?;
+        let external_ip = gateway
+            .get_external_ip()
+            .context("Failed to get the external IP from the router")?;
+
+        let mapping = Self {
+            gateway,
+            local_addr: SocketAddrV4::new(local_ip, local_port),
+            external_addr: SocketAddrV4::new(external_ip, local_port),
+            last_renewed: Instant::now(),
+        };
+        mapping.add()?;
+        tracing::info!(
+            "The router forwards {} to {}",
+            mapping.external_addr,
+            mapping.local_addr
+        );
+        Ok(mapping)
+    }
+
+    /// Where to connect from the outside.
+    pub fn external_addr(&self) -> SocketAddrV4 {
+        self.external_addr
+    }
+
+    /// Re-add the mapping before the lease runs out.
+    pub fn renew_if_needed(&mut self) {
+        if self.last_renewed.elapsed() > LEASE_DURATION / 2 {
+            self.last_renewed = Instant::now();
+            if let Err(err) = self.add() {
+                tracing::warn!("{}", crate::error_display_chain(err.as_ref()));
+            }
+        }
+    }
+
+    fn add(&self) -> anyhow::Result<()> {
+        self.gateway
+            .add_port(
+                igd::PortMappingProtocol::TCP,
+                self.external_addr.port(),
+                self.local_addr,
+                LEASE_DURATION.as_secs() as u32,
+                "eterm",
+            )
+            .with_context(|| {
+                format!(
+                    "The router refused to forward port {}",
+                    self.external_addr.port()
+                )
+            })
+    }
+}
+
+impl Drop for PortMapping {
+    fn drop(&mut self) {
+        if let Err(err) = self
+            .gateway
+            .remove_port(igd::PortMappingProtocol::TCP, self.external_addr.port())
+        {
+            tracing::warn!("Failed to remove the port mapping from the router: {}", err);
+        }
+    }
+}
+
+/// Our address on the network of the router.
+fn local_ip_towards(gateway: SocketAddrV4) -> anyhow::Result<Ipv4Addr> {
+    // Connecting a UDP socket sends nothing, but picks the interface:
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0").context("UDP bind")?;
+    socket.connect(gateway).context("UDP connect")?;
+    match socket.local_addr().context("local_addr")? {
+        std::net::SocketAddr::V4(addr) => Ok(*addr.ip()),
+        std::net::SocketAddr::V6(addr) => anyhow::bail!("Expected an IPv4 address, got {}", addr),
+    }
+}
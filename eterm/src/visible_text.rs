@@ -0,0 +1,130 @@
+//! The text a served ui currently shows, for monitoring scripts and automation.
+//!
+//! ``` no_run
+//! # let mut client = eterm::Client::new("127.0.0.1:8505".to_owned());
+//! client.update(1.0);
+//! let text = client.visible_text();
+//! if !text.contains("All systems nominal") {
+//!     eprintln!("Alert! The dashboard says: {:?}", text.all_text());
+//! }
+//! ```
+
+use egui::{epaint, Rect};
+
+use crate::net_shape::{ClippedNetShape, NetShape};
+
+/// One text shape of a frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VisibleText {
+    pub text: String,
+    /// Where the text is painted, in the coordinates of the remote ui.
+    pub rect: Rect,
+}
+
+/// All the text of a frame, in the order it is painted. See [`crate::Client::visible_text`].
+#[derive(Clone, Debug, Default)]
+pub struct TextIndex {
+    texts: Vec<VisibleText>,
+    /// [`Self::texts`] in lowercase, for [`Self::find_ignore_case`].
+    lowercase: Vec<String>,
+}
+
+impl TextIndex {
+    pub fn new(texts: Vec<VisibleText>) -> Self {
+        let lowercase = texts.iter().map(|text| text.text.to_lowercase()).collect();
+        Self { texts, lowercase }
+    }
+
+    /// The text shapes that aren't clipped away entirely.
+    pub fn from_clipped_shapes(clipped_shapes: &[epaint::ClippedShape]) -> Self {
+        let texts = clipped_shapes
+            .iter()
+            .filter_map(|epaint::ClippedShape(clip_rect, shape)| match shape {
+                epaint::Shape::Text(text_shape) => {
+                    let rect = Rect::from_min_size(text_shape.pos, text_shape.galley.size());
+                    clip_rect.intersects(rect).then(|| VisibleText {
+                        text: text_shape.galley.text().to_owned(),
+                        rect,
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+        Self::new(texts)
+    }
+
+    /// The text shapes that aren't clipped away entirely, laid out with `fonts`.
+    pub(crate) fn from_clipped_net_shapes(
+        fonts: &epaint::text::Fonts,
+        clipped_net_shapes: &[ClippedNetShape],
+    ) -> Self {
+        let texts = clipped_net_shapes
+            .iter()
+            .filter_map(|ClippedNetShape(clip_rect, shape)| match shape {
+                NetShape::Text(text_shape) => {
+                    let galley = fonts.layout_job(text_shape.job.clone());
+                    let rect = Rect::from_min_size(text_shape.pos, galley.size());
+                    clip_rect.intersects(rect).then(|| VisibleText {
+                        text: galley.text().to_owned(),
+                        rect,
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+        Self::new(texts)
+    }
+
+    pub fn texts(&self) -> &[VisibleText] {
+        &self.texts
+    }
+
+    /// All the text, one text shape per line.
+    pub fn all_text(&self) -> String {
+        let texts: Vec<&str> = self.texts.iter().map(|text| text.text.as_str()).collect();
+        texts.join("\n")
+    }
+
+    /// Is `needle` part of any text shape?
+    pub fn contains(&self, needle: &str) -> bool {
+        self.find(needle).next().is_some()
+    }
+
+    /// The text shapes containing `needle`.
+    pub fn find<'a>(&'a self, needle: &'a str) -> impl Iterator<Item = &'a VisibleText> + 'a {
+        self.texts
+            .iter()
+            .filter(move |text| text.text.contains(needle))
+    }
+
+    /// Like [`Self::find`], but ignoring case.
+    pub fn find_ignore_case<'a>(
+        &'a self,
+        needle: &str,
+    ) -> impl Iterator<Item = &'a VisibleText> + 'a {
+        let needle = needle.to_lowercase();
+        self.texts
+            .iter()
+            .zip(&self.lowercase)
+            .filter(move |(_, lowercase)| lowercase.contains(&needle))
+            .map(|(text, _)| text)
+    }
+}
+
+#[test]
+fn test_text_index() {
+    let text_at = |text: &str, y: f32| VisibleText {
+        text: text.to_owned(),
+        rect: Rect::from_min_size(egui::pos2(0.0, y), egui::vec2(100.0, 10.0)),
+    };
+    let index = TextIndex::new(vec![text_at("Name: Ferris", 0.0), text_at("Save", 20.0)]);
+
+    assert!(index.contains("Ferris"));
+    assert!(!index.contains("ferris"));
+    assert_eq!(
+        index.find_ignore_case("FERRIS").collect::<Vec<_>>(),
+        vec![&index.texts()[0]]
+    );
+    assert_eq!(index.find("Save").next().unwrap().rect.min.y, 20.0);
+    assert_eq!(index.all_text(), "Name: Ferris\nSave");
+}
@@ -0,0 +1,195 @@
+//! The eterm wire format, for building relays, recorders and protocol analyzers.
+//!
+//! A message ([`crate::ClientToServerMessage`] or [`crate::ServerToClientMessage`])
+//! is serialized with `bincode` and then compressed into a packet, see [`encode_message`].
+//! The first byte of each packet says how it was compressed (see [`crate::codec`]).
+//!
+//! On the connection, each packet is prefixed by [`PROTOCOL_HEADER`]
+//! and the length of the packet as a little-endian `u32`, see [`write_packet`].
+//!
+//! ``` no_run
+//! # fn relay(mut from: std::net::TcpStream, mut to: std::net::TcpStream) -> anyhow::Result<()> {
+//! let limits = eterm::DecodeLimits::SERVER_TO_CLIENT;
+//! while let Some(packet) = eterm::wire::read_packet(&mut from, &limits)? {
+//!     let message: eterm::ServerToClientMessage = eterm::wire::decode_message(&packet, &limits)?;
+//!     // … inspect or record the message …
+//!     eterm::wire::write_packet(&mut to, &packet)?;
+//! }
+//! # Ok(()) }
+//! ```
+
+use anyhow::Context as _;
+
+use crate::{codec, DecodeLimits, Packet};
+
+/// All packets are prefixed with this.
+///
+/// b"eterm", major, minor, patch
+pub const PROTOCOL_HEADER: [u8; 8] = [b'e', b't', b'e', b'r', b'm', 0, 0, 2];
+
+/// [`PROTOCOL_HEADER`] followed by the packet length.
+pub(crate) const FRAME_HEADER_LEN: usize = PROTOCOL_HEADER.len() + 4;
+
+/// Serialize and compress a message into a packet.
+///
+/// Use [`codec::DEFAULT_LEVEL`] unless you know better.
+/// The other side must be able to decode the `compression`; [`codec::Compression::None`] always works.
+///
+/// # Errors
+/// If the message can't be serialized, or the compression is not compiled in.
+pub fn encode_message<M: ?Sized + serde::Serialize>(
+    message: &M,
+    compression: codec::Compression,
+    level: i32,
+) -> anyhow::Result<Packet> {
+    encode_message_and_size(message, compression, level).map(|(packet, _)| packet)
+}
+
+/// Like [`encode_message`], but also returns the size of the message before compression.
+pub(crate) fn encode_message_and_size<M: ?Sized + serde::Serialize>(
+    message: &M,
+    compression: codec::Compression,
+    level: i32,
+) -> anyhow::Result<(Packet, usize)> {
+    use bincode::Options as _;
+
+    let bincoded = bincode::options().serialize(message).context("bincode")?;
+    let compressed = codec::compress(compression, level, &bincoded).context("compress")?;
+    Ok((compressed.into(), bincoded.len()))
+}
+
+/// How many bytes a message, or part of one (e.g. a single [`crate::net_shape::NetShape`]),
+/// takes up before compression.
+///
+/// # Errors
+/// If it can't be serialized.
+pub fn serialized_size<M: ?Sized + serde::Serialize>(message: &M) -> anyhow::Result<usize> {
+    use bincode::Options as _;
+    let size = bincode::options()
+        .serialized_size(message)
+        .context("bincode")?;
+    Ok(size as usize)
+}
+
+/// Decompress and deserialize a packet from an untrusted source.
+///
+/// # Errors
+/// If the packet is malformed or breaks any of the `limits`.
+pub fn decode_message<M: serde::de::DeserializeOwned>(
+    packet: &[u8],
+    limits: &DecodeLimits,
+) -> anyhow::Result<M> {
+    use bincode::Options as _;
+
+    anyhow::ensure!(
+        packet.len() <= limits.max_packet_size,
+        "Refusing packet of {:.1} MB",
+        packet.len() as f32 * 1e-6
+    );
+
+    let bincoded = codec::decompress(packet, limits.max_decompressed_size).context("decompress")?;
+
+    let message = bincode::options()
+        .with_limit(limits.max_decompressed_size as u64)
+        .deserialize(&bincoded)
+        .context("bincode")?;
+
+    Ok(message)
+}
+
+/// Append the framed packet to `out`.
+pub(crate) fn frame_packet(packet: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(&PROTOCOL_HEADER);
+    out.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+    out.extend_from_slice(packet);
+}
+
+/// Check the frame header and return the length of the packet that follows.
+pub(crate) fn parse_frame_header(
+    header: &[u8; FRAME_HEADER_LEN],
+    limits: &DecodeLimits,
+) -> anyhow::Result<usize> {
+    let protocol = &header[..PROTOCOL_HEADER.len()];
+    let length = &header[PROTOCOL_HEADER.len()..];
+    let length = u32::from_le_bytes([length[0], length[1], length[2], length[3]]) as usize;
+
+    if protocol[0..5] != PROTOCOL_HEADER[0..5] {
+        anyhow::bail!("The other side is not eterm");
+    }
+
+    if protocol != PROTOCOL_HEADER {
+        return Err(crate::VersionMismatch {
+            theirs: [protocol[5], protocol[6], protocol[7]],
+        }
+        .into());
+    }
+
+    if length > limits.max_packet_size {
+        anyhow::bail!("Refusing packet of {:.1} MB", length as f32 * 1e-6);
+    }
+
+    Ok(length)
+}
+
+/// Write one packet (from [`encode_message`] or [`read_packet`]) with its frame header.
+///
+/// # Errors
+/// On I/O errors.
+pub fn write_packet(writer: &mut impl std::io::Write, packet: &[u8]) -> anyhow::Result<()> {
+    let mut framed = Vec::with_capacity(FRAME_HEADER_LEN + packet.len());
+    frame_packet(packet, &mut framed);
+    writer.write_all(&framed).context("write")
+}
+
+/// Read one packet, blocking until all of it has arrived.
+///
+/// Returns `None` if the stream ends before a new packet starts.
+///
+/// # Errors
+/// On I/O errors, if the other side is not eterm or uses another version of it,
+/// or if the packet is larger than `limits` allows.
+pub fn read_packet(
+    reader: &mut impl std::io::Read,
+    limits: &DecodeLimits,
+) -> anyhow::Result<Option<Packet>> {
+    let mut header = [0_u8; FRAME_HEADER_LEN];
+    match reader.read(&mut header[..1]) {
+        Ok(0) => return Ok(None),
+        Ok(_) => {}
+        Err(err) => return Err(err).context("read"),
+    }
+    reader.read_exact(&mut header[1..]).context("read")?;
+    let length = parse_frame_header(&header, limits)?;
+
+    let mut packet = vec![0_u8; length];
+    reader.read_exact(&mut packet).context("read")?;
+    Ok(Some(packet.into()))
+}
+
+#[test]
+fn test_read_write_packet() {
+    let message = crate::ClientToServerMessage::Ping { client_time: 12.5 };
+    let packet = encode_message(&message, codec::Compression::None, codec::DEFAULT_LEVEL).unwrap();
+    assert_eq!(packet.len(), 1 + serialized_size(&message).unwrap()); // + the compression byte
+
+    let mut stream = vec![];
+    write_packet(&mut stream, &packet).unwrap();
+    write_packet(&mut stream, &packet).unwrap();
+
+    let limits = DecodeLimits::CLIENT_TO_SERVER;
+    let mut reader = stream.as_slice();
+    for _ in 0..2 {
+        let read = read_packet(&mut reader, &limits).unwrap().unwrap();
+        assert_eq!(&*read, &*packet);
+        match decode_message(&read, &limits).unwrap() {
+            crate::ClientToServerMessage::Ping { client_time } => assert_eq!(client_time, 12.5),
+            _ => panic!("Expected a ping"),
+        }
+    }
+    assert!(read_packet(&mut reader, &limits).unwrap().is_none());
+
+    let mut wrong_version = stream.clone();
+    wrong_version[PROTOCOL_HEADER.len() - 1] += 1;
+    let err = read_packet(&mut wrong_version.as_slice(), &limits).unwrap_err();
+    assert!(err.downcast_ref::<crate::VersionMismatch>().is_some());
+}
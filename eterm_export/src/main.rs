@@ -0,0 +1,424 @@
+//! Convert a recording (see [`eterm::recording`]) to PNG files, an animated GIF or an MP4,
+//! so a session can be shared with people who don't have the viewer.
+//!
+//! ``` sh
+//! eterm-export session.eterm --output frames/
+//! eterm-export session.eterm --output session.gif            # with the `gif` feature
+//! eterm-export session.eterm --output session.mp4 --fps 30   # needs `ffmpeg` installed
+//! ```
+
+#![forbid(unsafe_code)]
+#![warn(
+    clippy::all,
+    clippy::await_holding_lock,
+    clippy::char_lit_as_u8,
+    clippy::checked_conversions,
+    clippy::dbg_macro,
+    clippy::debug_assert_with_mut_call,
+    clippy::doc_markdown,
+    clippy::empty_enum,
+    clippy::enum_glob_use,
+    clippy::exit,
+    clippy::expl_impl_clone_on_copy,
+    clippy::explicit_deref_methods,
+    clippy::explicit_into_iter_loop,
+    clippy::fallible_impl_from,
+    clippy::filter_map_next,
+    clippy::float_cmp_const,
+    clippy::fn_params_excessive_bools,
+    clippy::if_let_mutex,
+    clippy::imprecise_flops,
+    clippy::inefficient_to_string,
+    clippy::invalid_upcast_comparisons,
+    clippy::large_types_passed_by_value,
+    clippy::let_unit_value,
+    clippy::linkedlist,
+    clippy::lossy_float_literal,
+    clippy::macro_use_imports,
+    clippy::manual_ok_or,
+    clippy::map_err_ignore,
+    clippy::map_flatten,
+    clippy::match_on_vec_items,
+    clippy::match_same_arms,
+    clippy::match_wildcard_for_single_variants,
+    clippy::mem_forget,
+    clippy::mismatched_target_os,
+    clippy::missing_errors_doc,
+    clippy::missing_safety_doc,
+    clippy::mut_mut,
+    clippy::mutex_integer,
+    clippy::needless_borrow,
+    clippy::needless_continue,
+    clippy::needless_pass_by_value,
+    clippy::option_option,
+    clippy::path_buf_push_overwrite,
+    clippy::ptr_as_ptr,
+    clippy::ref_option_ref,
+    clippy::rest_pat_in_fully_bound_structs,
+    clippy::same_functions_in_if_condition,
+    clippy::string_add_assign,
+    clippy::string_add,
+    clippy::string_lit_as_bytes,
+    clippy::string_to_string,
+    clippy::todo,
+    clippy::trait_duplication_in_bounds,
+    clippy::unimplemented,
+    clippy::unnested_or_patterns,
+    clippy::unused_self,
+    clippy::useless_transmute,
+    clippy::verbose_file_reads,
+    clippy::zero_sized_map_values,
+    future_incompatible,
+    missing_crate_level_docs,
+    nonstandard_style,
+    rust_2018_idioms
+)]
+#![allow(clippy::float_cmp)]
+#![allow(clippy::manual_range_contains)]
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use egui::text::Fonts;
+use eterm::ServerToClientMessage;
+
+/// How long to show the last frame of the recording, in seconds.
+const LAST_FRAME_DURATION: f64 = 1.0;
+
+/// Convert an eterm recording to PNG files, a GIF or a video.
+#[derive(argh::FromArgs)]
+struct Arguments {
+    /// the recording to convert.
+    #[argh(positional)]
+    recording: PathBuf,
+
+    /// a directory to save one PNG file per frame in,
+    /// or a `.gif` file (needs the `gif` feature),
+    /// or any other file for `ffmpeg` to write, e.g. `.mp4`.
+    #[argh(option)]
+    output: PathBuf,
+
+    /// screen width (in points) of the viewer that made the recording.
+    #[argh(option, default = "800.0")]
+    width: f32,
+
+    /// screen height (in points) of the viewer that made the recording.
+    #[argh(option, default = "600.0")]
+    height: f32,
+
+    /// scale factor of the exported frames.
+    #[argh(option, default = "1.0")]
+    pixels_per_point: f32,
+
+    /// frames per second of videos made with `ffmpeg`.
+    #[argh(option, default = "30.0")]
+    fps: f32,
+}
+
+fn main() -> anyhow::Result<()> {
+    // Log to stdout (if you run with `RUST_LOG=debug`).
+    tracing_subscriber::fmt::init();
+
+    let opt: Arguments = argh::from_env();
+    if opt.fps.is_nan() || opt.fps <= 0.0 {
+        anyhow::bail!("--fps must be positive");
+    }
+
+    let file = std::fs::File::open(&opt.recording)
+        .with_context(|| format!("opening {:?}", opt.recording))?;
+    let recording = eterm::recording::RecordingReader::new(std::io::BufReader::new(file))
+        .with_context(|| format!("reading {:?}", opt.recording))?;
+
+    let mut renderer = Renderer::new(egui::vec2(opt.width, opt.height), opt.pixels_per_point);
+    let mut sink = Sink::create(&opt.output, renderer.size_in_pixels, opt.fps)?;
+
+    // We only know how long to show a frame once the next one arrives:
+    let mut pending: Option<(f64, eterm::raster::Image)> = None;
+    let mut start_time = None;
+    let mut num_frames = 0;
+
+    for recorded in recording {
+        let recorded = recorded.with_context(|| format!("reading {:?}", opt.recording))?;
+        match recorded.message {
+            ServerToClientMessage::Fonts { font_definitions } => {
+                renderer.set_font_definitions(font_definitions);
+            }
+            ServerToClientMessage::Hello { .. } => {
+                // The server sends its fonts again if they are not the defaults:
+                renderer.set_font_definitions(Default::default());
+                renderer.server_font_image = None;
+            }
+            ServerToClientMessage::FontImage {
+                version,
+                width,
+                height,
+                pixels,
+            } => {
+                renderer.server_font_image = Some(std::sync::Arc::new(egui::FontImage {
+                    version,
+                    width,
+                    height,
+                    pixels,
+                }));
+            }
+            ServerToClientMessage::Frame {
+                clipped_net_shapes, ..
+            } => {
+                let start_time = *start_time.get_or_insert(recorded.time);
+                let time = recorded.time - start_time;
+                if let Some((_, image)) = pending.take() {
+                    sink.add_frame(&image, time)?;
+                }
+                pending = Some((time, renderer.render(&clipped_net_shapes)));
+                num_frames += 1;
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((time, image)) = pending {
+        sink.add_frame(&image, time + LAST_FRAME_DURATION)?;
+    }
+    sink.finish()?;
+
+    if num_frames == 0 {
+        anyhow::bail!("There are no frames in {:?}", opt.recording);
+    }
+    tracing::info!("Exported {} frames to {:?}", num_frames, opt.output);
+    Ok(())
+}
+
+/// Turns the shapes of frames into images, like the viewer would.
+struct Renderer {
+    fonts: Fonts,
+    /// The text meshes sample this instead, if the server sent laid-out text.
+    server_font_image: Option<std::sync::Arc<egui::FontImage>>,
+    pixels_per_point: f32,
+    size_in_pixels: [usize; 2],
+}
+
+impl Renderer {
+    fn new(screen_size: egui::Vec2, pixels_per_point: f32) -> Self {
+        Self {
+            fonts: Fonts::new(pixels_per_point, Default::default()),
+            server_font_image: None,
+            pixels_per_point,
+            size_in_pixels: [
+                (screen_size.x * pixels_per_point).round() as usize,
+                (screen_size.y * pixels_per_point).round() as usize,
+            ],
+        }
+    }
+
+    fn set_font_definitions(&mut self, font_definitions: egui::FontDefinitions) {
+        if self.fonts.definitions() != &font_definitions {
+            self.fonts = Fonts::new(self.pixels_per_point, font_definitions);
+        }
+    }
+
+    fn render(
+        &mut self,
+        clipped_net_shapes: &[eterm::net_shape::ClippedNetShape],
+    ) -> eterm::raster::Image {
+        let clipped_shapes =
+            eterm::net_shape::from_clipped_net_shapes(&self.fonts, clipped_net_shapes);
+        let tesselator_options =
+            egui::epaint::tessellator::TessellationOptions::from_pixels_per_point(
+                self.pixels_per_point,
+            );
+        let font_image = self
+            .server_font_image
+            .clone()
+            .unwrap_or_else(|| self.fonts.font_image());
+        let clipped_meshes = egui::epaint::tessellator::tessellate_shapes(
+            clipped_shapes,
+            tesselator_options,
+            font_image.size(),
+        );
+        let image = eterm::raster::rasterize(
+            self.size_in_pixels,
+            egui::Color32::BLACK,
+            self.pixels_per_point,
+            &clipped_meshes,
+            &font_image,
+        );
+        self.fonts.end_frame(); // make sure to evict galley cache
+        image
+    }
+}
+
+/// Where the frames go.
+enum Sink {
+    /// One PNG file per frame, numbered from zero.
+    Png {
+        output_dir: PathBuf,
+        num_written: usize,
+    },
+    #[cfg(feature = "gif")]
+    Gif {
+        encoder: gif::Encoder<std::io::BufWriter<std::fs::File>>,
+        /// In hundredths of a second, which is what GIF frame delays are in.
+        centiseconds_written: u64,
+    },
+    /// Raw frames piped to the `ffmpeg` command.
+    Ffmpeg {
+        ffmpeg: std::process::Child,
+        fps: f64,
+        /// Frames are repeated to keep a constant frame rate.
+        num_written: u64,
+    },
+}
+
+impl Sink {
+    fn create(path: &Path, size_in_pixels: [usize; 2], fps: f32) -> anyhow::Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            None => {
+                std::fs::create_dir_all(path).with_context(|| format!("creating {:?}", path))?;
+                Ok(Self::Png {
+                    output_dir: path.to_owned(),
+                    num_written: 0,
+                })
+            }
+            #[cfg(feature = "gif")]
+            Some("gif") => {
+                let file =
+                    std::fs::File::create(path).with_context(|| format!("creating {:?}", path))?;
+                let mut encoder = gif::Encoder::new(
+                    std::io::BufWriter::new(file),
+                    size_in_pixels[0] as u16,
+                    size_in_pixels[1] as u16,
+                    &[],
+                )
+                .context("gif")?;
+                encoder.set_repeat(gif::Repeat::Infinite).context("gif")?;
+                Ok(Self::Gif {
+                    encoder,
+                    centiseconds_written: 0,
+                })
+            }
+            #[cfg(not(feature = "gif"))]
+            Some("gif") => {
+                anyhow::bail!("Exporting to GIF needs the `gif` feature of eterm_export")
+            }
+            Some(_) => {
+                let [width, height] = size_in_pixels;
+                let ffmpeg = std::process::Command::new("ffmpeg")
+                    .args(&["-loglevel", "error", "-y"])
+                    .args(&["-f", "rawvideo", "-pixel_format", "rgba"])
+                    .arg("-video_size")
+                    .arg(format!("{}x{}", width, height))
+                    .arg("-framerate")
+                    .arg(fps.to_string())
+                    .args(&["-i", "-"])
+                    // Most players want yuv420p, which needs an even size:
+                    .args(&[
+                        "-vf",
+                        "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+                        "-pix_fmt",
+                        "yuv420p",
+                    ])
+                    .arg(path)
+                    .stdin(std::process::Stdio::piped())
+                    .spawn()
+                    .context("Failed to run ffmpeg. Is it installed?")?;
+                Ok(Self::Ffmpeg {
+                    ffmpeg,
+                    fps: fps as f64,
+                    num_written: 0,
+                })
+            }
+        }
+    }
+
+    /// Add a frame, shown until `end_time` (in seconds), when the next one starts.
+    fn add_frame(&mut self, image: &eterm::raster::Image, end_time: f64) -> anyhow::Result<()> {
+        match self {
+            Self::Png {
+                output_dir,
+                num_written,
+            } => {
+                let path = output_dir.join(format!("frame_{:06}.png", num_written));
+                *num_written += 1;
+                write_png(&path, image)
+            }
+            #[cfg(feature = "gif")]
+            Self::Gif {
+                encoder,
+                centiseconds_written,
+            } => {
+                // Round the end times rather than the durations, so we don't drift:
+                let end = (end_time * 100.0).round() as u64;
+                if end <= *centiseconds_written {
+                    return Ok(()); // Replaced by the next frame before it would be shown
+                }
+                let mut rgba = image.as_rgba_bytes();
+                let mut frame = gif::Frame::from_rgba_speed(
+                    image.width() as u16,
+                    image.height() as u16,
+                    &mut rgba,
+                    10,
+                );
+                frame.delay = (end - *centiseconds_written).min(u16::MAX as u64) as u16;
+                *centiseconds_written = end;
+                encoder.write_frame(&frame).context("gif")
+            }
+            Self::Ffmpeg {
+                ffmpeg,
+                fps,
+                num_written,
+            } => {
+                use std::io::Write as _;
+                let end = (end_time * *fps).round() as u64;
+                let stdin = ffmpeg.stdin.as_mut().context("ffmpeg stdin")?;
+                let rgba = image.as_rgba_bytes();
+                while *num_written < end {
+                    stdin
+                        .write_all(&rgba)
+                        .context("Failed to write to ffmpeg")?;
+                    *num_written += 1;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        match self {
+            Self::Png { .. } => Ok(()),
+            #[cfg(feature = "gif")]
+            Self::Gif { encoder, .. } => {
+                drop(encoder); // writes the end of the file
+                Ok(())
+            }
+            Self::Ffmpeg { mut ffmpeg, .. } => {
+                drop(ffmpeg.stdin.take()); // end of input
+                let status = ffmpeg.wait().context("ffmpeg")?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    anyhow::bail!("ffmpeg failed: {}", status)
+                }
+            }
+        }
+    }
+}
+
+fn write_png(path: &Path, image: &eterm::raster::Image) -> anyhow::Result<()> {
+    let file = std::fs::File::create(path).with_context(|| format!("creating {:?}", path))?;
+    let mut encoder = png::Encoder::new(
+        std::io::BufWriter::new(file),
+        image.width() as u32,
+        image.height() as u32,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().context("png header")?;
+    writer
+        .write_image_data(&image.as_rgba_bytes())
+        .context("png data")?;
+    Ok(())
+}
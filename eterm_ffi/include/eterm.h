@@ -0,0 +1,158 @@
+#ifndef ETERM_H
+#define ETERM_H
+
+/* Generated by cbindgen from eterm_ffi/src/lib.rs - do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum EtermKey {
+  ETERM_KEY_ENTER,
+  ETERM_KEY_ESCAPE,
+  ETERM_KEY_TAB,
+  ETERM_KEY_BACKSPACE,
+  ETERM_KEY_DELETE,
+  ETERM_KEY_INSERT,
+  ETERM_KEY_HOME,
+  ETERM_KEY_END,
+  ETERM_KEY_PAGE_UP,
+  ETERM_KEY_PAGE_DOWN,
+  ETERM_KEY_ARROW_LEFT,
+  ETERM_KEY_ARROW_RIGHT,
+  ETERM_KEY_ARROW_UP,
+  ETERM_KEY_ARROW_DOWN,
+} EtermKey;
+
+typedef enum EtermPointerButton {
+  ETERM_POINTER_BUTTON_PRIMARY,
+  ETERM_POINTER_BUTTON_SECONDARY,
+  ETERM_POINTER_BUTTON_MIDDLE,
+} EtermPointerButton;
+
+/**
+ * Opaque handle to an eterm client.
+ */
+typedef struct EtermClient EtermClient;
+
+/**
+ * Same memory layout as `epaint::Vertex`.
+ */
+typedef struct EtermVertex {
+  /**
+   * Logical pixel coordinates (points).
+   */
+  float pos[2];
+  /**
+   * Normalized texture coordinates.
+   */
+  float uv[2];
+  /**
+   * sRGBA with premultiplied alpha.
+   */
+  uint8_t color[4];
+} EtermVertex;
+
+/**
+ * One triangle mesh to paint.
+ *
+ * The pointers are valid until the next call to [`eterm_client_update`] or [`eterm_client_free`].
+ */
+typedef struct EtermMesh {
+  /**
+   * `[min_x, min_y, max_x, max_y]` in points.
+   */
+  float clip_rect[4];
+  /**
+   * 0 means the font texture.
+   * Anything else is a user texture, which you need to provide yourself.
+   */
+  uint64_t texture_id;
+  const struct EtermVertex *vertices;
+  uintptr_t num_vertices;
+  const uint32_t *indices;
+  uintptr_t num_indices;
+} EtermMesh;
+
+/**
+ * The font texture: one byte of coverage (white with alpha) per pixel.
+ */
+typedef struct EtermFontImage {
+  /**
+   * Changes when the contents change.
+   */
+  uint64_t version;
+  uintptr_t width;
+  uintptr_t height;
+  /**
+   * `width * height` bytes, valid until the next call to [`eterm_client_update`].
+   */
+  const uint8_t *pixels;
+} EtermFontImage;
+
+/**
+ * Connection statistics.
+ */
+typedef struct EtermStats {
+  bool connected;
+  /**
+   * Downstream bandwidth.
+   */
+  float bytes_per_second;
+  /**
+   * Zero if unknown.
+   */
+  float average_frame_packet_size;
+  /**
+   * Round-trip time in seconds. Negative if unknown.
+   */
+  float latency;
+  /**
+   * Zero if unknown.
+   */
+  float adaptive_fps;
+} EtermStats;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+struct EtermClient *eterm_client_new(const char *addr);
+
+void eterm_client_free(struct EtermClient *client);
+
+void eterm_client_set_screen_size(struct EtermClient *client,
+                                  float width_points,
+                                  float height_points,
+                                  float pixels_per_point);
+
+void eterm_client_push_pointer_moved(struct EtermClient *client, float x, float y);
+
+void eterm_client_push_pointer_button(struct EtermClient *client,
+                                      float x,
+                                      float y,
+                                      enum EtermPointerButton button,
+                                      bool pressed);
+
+void eterm_client_push_pointer_gone(struct EtermClient *client);
+
+void eterm_client_push_text(struct EtermClient *client, const char *utf8);
+
+void eterm_client_push_key(struct EtermClient *client, enum EtermKey key, bool pressed);
+
+bool eterm_client_update(struct EtermClient *client);
+
+uintptr_t eterm_client_num_meshes(const struct EtermClient *client);
+
+struct EtermMesh eterm_client_mesh(const struct EtermClient *client, uintptr_t index);
+
+struct EtermFontImage eterm_client_font_image(const struct EtermClient *client);
+
+struct EtermStats eterm_client_stats(const struct EtermClient *client);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* ETERM_H */
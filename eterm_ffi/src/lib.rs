@@ -0,0 +1,438 @@
+//! C API for [`eterm::Client`], so that non-Rust engines can embed an eterm viewer.
+//!
+//! The header is in `include/eterm.h`. After changing the API, regenerate it with
+//! `cbindgen --config eterm_ffi/cbindgen.toml --crate eterm_ffi --output eterm_ffi/include/eterm.h`
+//! (CI checks that it is up to date).
+//!
+//! Usage from C:
+//!
+//! ``` c
+//! EtermClient* client = eterm_client_new("127.0.0.1:8505");
+//! eterm_client_set_screen_size(client, 800.0f, 600.0f, 1.0f);
+//! while (running) {
+//!     eterm_client_push_pointer_moved(client, x, y);
+//!     if (eterm_client_update(client)) {
+//!         for (size_t i = 0; i < eterm_client_num_meshes(client); ++i) {
+//!             EtermMesh mesh = eterm_client_mesh(client, i);
+//!             // upload and draw mesh.vertices / mesh.indices, clipped to mesh.clip_rect
+//!         }
+//!     }
+//! }
+//! eterm_client_free(client);
+//! ```
+
+#![warn(
+    clippy::all,
+    clippy::missing_safety_doc,
+    future_incompatible,
+    nonstandard_style,
+    rust_2018_idioms
+)]
+
+use std::{
+    ffi::CStr,
+    os::raw::{c_char, c_float},
+    sync::Arc,
+};
+
+/// Opaque handle to an eterm client.
+pub struct EtermClient {
+    client: eterm::Client,
+    screen_size: egui::Vec2,
+    pixels_per_point: f32,
+    pending_input: egui::RawInput,
+    has_pending_input: bool,
+    clipped_meshes: Vec<egui::ClippedMesh>,
+    font_image: Option<Arc<egui::FontImage>>,
+}
+
+/// Same memory layout as `epaint::Vertex`.
+#[repr(C)]
+pub struct EtermVertex {
+    /// Logical pixel coordinates (points).
+    pub pos: [c_float; 2],
+    /// Normalized texture coordinates.
+    pub uv: [c_float; 2],
+    /// sRGBA with premultiplied alpha.
+    pub color: [u8; 4],
+}
+
+// `eterm_client_mesh` hands out `epaint::Vertex`:s as `EtermVertex`:s:
+const _: [(); std::mem::size_of::<egui::epaint::Vertex>()] =
+    [(); std::mem::size_of::<EtermVertex>()];
+
+/// One triangle mesh to paint.
+///
+/// The pointers are valid until the next call to [`eterm_client_update`] or [`eterm_client_free`].
+#[repr(C)]
+pub struct EtermMesh {
+    /// `[min_x, min_y, max_x, max_y]` in points.
+    pub clip_rect: [c_float; 4],
+    /// 0 means the font texture.
+    /// Anything else is a user texture, which you need to provide yourself.
+    pub texture_id: u64,
+    pub vertices: *const EtermVertex,
+    pub num_vertices: usize,
+    pub indices: *const u32,
+    pub num_indices: usize,
+}
+
+/// The font texture: one byte of coverage (white with alpha) per pixel.
+#[repr(C)]
+pub struct EtermFontImage {
+    /// Changes when the contents change.
+    pub version: u64,
+    pub width: usize,
+    pub height: usize,
+    /// `width * height` bytes, valid until the next call to [`eterm_client_update`].
+    pub pixels: *const u8,
+}
+
+/// Connection statistics.
+#[repr(C)]
+pub struct EtermStats {
+    pub connected: bool,
+    /// Downstream bandwidth.
+    pub bytes_per_second: c_float,
+    /// Zero if unknown.
+    pub average_frame_packet_size: c_float,
+    /// Round-trip time in seconds. Negative if unknown.
+    pub latency: c_float,
+    /// Zero if unknown.
+    pub adaptive_fps: c_float,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum EtermPointerButton {
+    Primary,
+    Secondary,
+    Middle,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum EtermKey {
+    Enter,
+    Escape,
+    Tab,
+    Backspace,
+    Delete,
+    Insert,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    ArrowLeft,
+    ArrowRight,
+    ArrowUp,
+    ArrowDown,
+}
+
+impl EtermMesh {
+    const EMPTY: Self = Self {
+        clip_rect: [0.0; 4],
+        texture_id: 0,
+        vertices: std::ptr::null(),
+        num_vertices: 0,
+        indices: std::ptr::null(),
+        num_indices: 0,
+    };
+}
+
+impl EtermFontImage {
+    const EMPTY: Self = Self {
+        version: 0,
+        width: 0,
+        height: 0,
+        pixels: std::ptr::null(),
+    };
+}
+
+impl EtermStats {
+    const DISCONNECTED: Self = Self {
+        connected: false,
+        bytes_per_second: 0.0,
+        average_frame_packet_size: 0.0,
+        latency: -1.0,
+        adaptive_fps: 0.0,
+    };
+}
+
+/// Unwinding into C is undefined behavior, so we return `fallback` on panic instead.
+fn catch_panic<R>(fallback: R, f: impl FnOnce() -> R) -> R {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_or(fallback)
+}
+
+impl EtermClient {
+    fn push_event(&mut self, event: egui::Event) {
+        self.pending_input.events.push(event);
+        self.has_pending_input = true;
+    }
+}
+
+/// Connect to an eterm server, e.g. `"127.0.0.1:8505"`.
+///
+/// Returns null if `addr` is not valid UTF-8.
+/// Like all functions here, it returns null, `false` or zeroes if something panics.
+/// Free the client with [`eterm_client_free`].
+///
+/// # Safety
+/// `addr` must be a valid, nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn eterm_client_new(addr: *const c_char) -> *mut EtermClient {
+    catch_panic(std::ptr::null_mut(), || {
+        if addr.is_null() {
+            return std::ptr::null_mut();
+        }
+        let addr = match CStr::from_ptr(addr).to_str() {
+            Ok(addr) => addr.to_owned(),
+            Err(_) => return std::ptr::null_mut(),
+        };
+        Box::into_raw(Box::new(EtermClient {
+            client: eterm::Client::new(addr),
+            screen_size: egui::vec2(800.0, 600.0),
+            pixels_per_point: 1.0,
+            pending_input: Default::default(),
+            has_pending_input: true,
+            clipped_meshes: Default::default(),
+            font_image: None,
+        }))
+    })
+}
+
+/// Disconnect and free the client.
+///
+/// # Safety
+/// `client` must come from [`eterm_client_new`] (or be null), and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn eterm_client_free(client: *mut EtermClient) {
+    catch_panic((), || {
+        if !client.is_null() {
+            drop(Box::from_raw(client));
+        }
+    })
+}
+
+/// Set the size of the area you paint the remote ui in.
+///
+/// # Safety
+/// `client` must be a valid pointer from [`eterm_client_new`].
+#[no_mangle]
+pub unsafe extern "C" fn eterm_client_set_screen_size(
+    client: *mut EtermClient,
+    width_points: c_float,
+    height_points: c_float,
+    pixels_per_point: c_float,
+) {
+    catch_panic((), || {
+        let client = &mut *client;
+        client.screen_size = egui::vec2(width_points, height_points);
+        client.pixels_per_point = pixels_per_point;
+        client.has_pending_input = true;
+    })
+}
+
+/// # Safety
+/// `client` must be a valid pointer from [`eterm_client_new`].
+#[no_mangle]
+pub unsafe extern "C" fn eterm_client_push_pointer_moved(
+    client: *mut EtermClient,
+    x: c_float,
+    y: c_float,
+) {
+    catch_panic((), || {
+        (*client).push_event(egui::Event::PointerMoved(egui::pos2(x, y)));
+    })
+}
+
+/// # Safety
+/// `client` must be a valid pointer from [`eterm_client_new`].
+#[no_mangle]
+pub unsafe extern "C" fn eterm_client_push_pointer_button(
+    client: *mut EtermClient,
+    x: c_float,
+    y: c_float,
+    button: EtermPointerButton,
+    pressed: bool,
+) {
+    catch_panic((), || {
+        let button = match button {
+            EtermPointerButton::Primary => egui::PointerButton::Primary,
+            EtermPointerButton::Secondary => egui::PointerButton::Secondary,
+            EtermPointerButton::Middle => egui::PointerButton::Middle,
+        };
+        (*client).push_event(egui::Event::PointerButton {
+            pos: egui::pos2(x, y),
+            button,
+            pressed,
+            modifiers: Default::default(),
+        });
+    })
+}
+
+/// The pointer left the area.
+///
+/// # Safety
+/// `client` must be a valid pointer from [`eterm_client_new`].
+#[no_mangle]
+pub unsafe extern "C" fn eterm_client_push_pointer_gone(client: *mut EtermClient) {
+    catch_panic((), || {
+        (*client).push_event(egui::Event::PointerGone);
+    })
+}
+
+/// Typed text (not including enter/return).
+///
+/// # Safety
+/// `client` must be a valid pointer from [`eterm_client_new`],
+/// and `utf8` a valid, nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn eterm_client_push_text(client: *mut EtermClient, utf8: *const c_char) {
+    catch_panic((), || {
+        if utf8.is_null() {
+            return;
+        }
+        let text = CStr::from_ptr(utf8).to_string_lossy().into_owned();
+        (*client).push_event(egui::Event::Text(text));
+    })
+}
+
+/// # Safety
+/// `client` must be a valid pointer from [`eterm_client_new`].
+#[no_mangle]
+pub unsafe extern "C" fn eterm_client_push_key(
+    client: *mut EtermClient,
+    key: EtermKey,
+    pressed: bool,
+) {
+    catch_panic((), || {
+        let key = match key {
+            EtermKey::Enter => egui::Key::Enter,
+            EtermKey::Escape => egui::Key::Escape,
+            EtermKey::Tab => egui::Key::Tab,
+            EtermKey::Backspace => egui::Key::Backspace,
+            EtermKey::Delete => egui::Key::Delete,
+            EtermKey::Insert => egui::Key::Insert,
+            EtermKey::Home => egui::Key::Home,
+            EtermKey::End => egui::Key::End,
+            EtermKey::PageUp => egui::Key::PageUp,
+            EtermKey::PageDown => egui::Key::PageDown,
+            EtermKey::ArrowLeft => egui::Key::ArrowLeft,
+            EtermKey::ArrowRight => egui::Key::ArrowRight,
+            EtermKey::ArrowUp => egui::Key::ArrowUp,
+            EtermKey::ArrowDown => egui::Key::ArrowDown,
+        };
+        (*client).push_event(egui::Event::Key {
+            key,
+            pressed,
+            modifiers: Default::default(),
+        });
+    })
+}
+
+/// Send pending input and receive new frames.
+///
+/// Returns `true` if there is a new frame to paint.
+///
+/// # Safety
+/// `client` must be a valid pointer from [`eterm_client_new`].
+#[no_mangle]
+pub unsafe extern "C" fn eterm_client_update(client: *mut EtermClient) -> bool {
+    catch_panic(false, || {
+        let client = &mut *client;
+
+        if client.has_pending_input {
+            let mut input = std::mem::take(&mut client.pending_input);
+            input.screen_rect = Some(egui::Rect::from_min_size(
+                Default::default(),
+                client.screen_size,
+            ));
+            input.pixels_per_point = Some(client.pixels_per_point);
+            client.client.send_input(input);
+            client.has_pending_input = false;
+        }
+
+        let new_frame = client.client.update(client.pixels_per_point);
+        client.font_image = Some(client.client.font_image());
+        match new_frame {
+            Some(frame) => {
+                client.clipped_meshes = frame.clipped_meshes;
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Number of meshes in the latest frame.
+///
+/// # Safety
+/// `client` must be a valid pointer from [`eterm_client_new`].
+#[no_mangle]
+pub unsafe extern "C" fn eterm_client_num_meshes(client: *const EtermClient) -> usize {
+    catch_panic(0, || (*client).clipped_meshes.len())
+}
+
+/// Mesh `index` of the latest frame. Paint them in order.
+///
+/// # Safety
+/// `client` must be a valid pointer from [`eterm_client_new`],
+/// and `index` less than [`eterm_client_num_meshes`].
+#[no_mangle]
+pub unsafe extern "C" fn eterm_client_mesh(client: *const EtermClient, index: usize) -> EtermMesh {
+    catch_panic(EtermMesh::EMPTY, || {
+        let egui::ClippedMesh(clip_rect, mesh) = &(*client).clipped_meshes[index];
+        EtermMesh {
+            clip_rect: [
+                clip_rect.min.x,
+                clip_rect.min.y,
+                clip_rect.max.x,
+                clip_rect.max.y,
+            ],
+            texture_id: match mesh.texture_id {
+                egui::TextureId::Egui => 0,
+                egui::TextureId::User(id) => id,
+            },
+            vertices: mesh.vertices.as_ptr().cast::<EtermVertex>(),
+            num_vertices: mesh.vertices.len(),
+            indices: mesh.indices.as_ptr(),
+            num_indices: mesh.indices.len(),
+        }
+    })
+}
+
+/// The font texture used by meshes with `texture_id == 0`.
+///
+/// All zeroes before the first call to [`eterm_client_update`].
+///
+/// # Safety
+/// `client` must be a valid pointer from [`eterm_client_new`].
+#[no_mangle]
+pub unsafe extern "C" fn eterm_client_font_image(client: *const EtermClient) -> EtermFontImage {
+    catch_panic(EtermFontImage::EMPTY, || match &(*client).font_image {
+        Some(font_image) => EtermFontImage {
+            version: font_image.version,
+            width: font_image.width,
+            height: font_image.height,
+            pixels: font_image.pixels.as_ptr(),
+        },
+        None => EtermFontImage::EMPTY,
+    })
+}
+
+/// # Safety
+/// `client` must be a valid pointer from [`eterm_client_new`].
+#[no_mangle]
+pub unsafe extern "C" fn eterm_client_stats(client: *const EtermClient) -> EtermStats {
+    catch_panic(EtermStats::DISCONNECTED, || {
+        let client = &(*client).client;
+        EtermStats {
+            connected: client.is_connected(),
+            bytes_per_second: client.bytes_per_second(),
+            average_frame_packet_size: client.average_frame_packet_size().unwrap_or(0.0),
+            latency: client.latency().unwrap_or(-1.0),
+            adaptive_fps: client.adaptive_fps().unwrap_or(0.0),
+        }
+    })
+}
@@ -0,0 +1,318 @@
+//! Loading the frames of a recording, and the ui for stepping through them.
+
+use std::path::Path;
+
+use anyhow::Context as _;
+use egui::{epaint, Color32, Rect};
+use eterm::net_shape::{ClippedNetShape, NetShape};
+
+/// Outline of the shape selected in the list.
+const SELECTED_COLOR: Color32 = Color32::from_rgb(255, 60, 60);
+
+/// Outline of the shapes that differ from the previous frame.
+const CHANGED_COLOR: Color32 = Color32::from_rgb(240, 190, 0);
+
+/// How much of the text of a text shape to show in the list.
+const MAX_TEXT_CHARS: usize = 40;
+
+/// A [`eterm::ServerToClientMessage::Frame`] from the recording.
+pub struct RecordedFrame {
+    /// Seconds since the recording started.
+    time: f64,
+    frame_index: u64,
+    /// Size of the (compressed) packet, as sent.
+    packet_size: usize,
+    clipped_net_shapes: Vec<ClippedNetShape>,
+    /// The size of each shape before compression.
+    shape_sizes: Vec<usize>,
+}
+
+/// Read all the frames of a recording.
+pub fn load(path: &Path) -> anyhow::Result<Vec<RecordedFrame>> {
+    let file = std::fs::File::open(path).with_context(|| format!("opening {:?}", path))?;
+    let mut reader = eterm::recording::RecordingReader::new(std::io::BufReader::new(file))
+        .with_context(|| format!("reading {:?}", path))?;
+    let limits = eterm::DecodeLimits::SERVER_TO_CLIENT;
+
+    let mut frames = vec![];
+    while let Some(recorded) = reader
+        .next_packet()
+        .with_context(|| format!("reading {:?}", path))?
+    {
+        let message = eterm::wire::decode_message(&recorded.packet, &limits)
+            .with_context(|| format!("decoding the packet at {:.3} s", recorded.time))?;
+        if let eterm::ServerToClientMessage::Frame {
+            frame_index,
+            clipped_net_shapes,
+            ..
+        } = message
+        {
+            let shape_sizes = clipped_net_shapes
+                .iter()
+                .map(eterm::wire::serialized_size)
+                .collect::<anyhow::Result<_>>()?;
+            frames.push(RecordedFrame {
+                time: recorded.time,
+                frame_index,
+                packet_size: recorded.packet.len(),
+                clipped_net_shapes,
+                shape_sizes,
+            });
+        }
+    }
+    Ok(frames)
+}
+
+pub struct Inspector {
+    frames: Vec<RecordedFrame>,
+    /// Index into [`Self::frames`].
+    current: usize,
+    /// Index into the shapes of the current frame.
+    selected_shape: Option<usize>,
+    only_changed: bool,
+    largest_first: bool,
+    outline_changed: bool,
+}
+
+impl Inspector {
+    /// `frames` must not be empty.
+    pub fn new(frames: Vec<RecordedFrame>) -> Self {
+        Self {
+            frames,
+            current: 0,
+            selected_shape: None,
+            only_changed: false,
+            largest_first: false,
+            outline_changed: true,
+        }
+    }
+
+    pub fn ui(&mut self, egui_ctx: &egui::CtxRef) {
+        if egui_ctx.input().key_pressed(egui::Key::ArrowLeft) {
+            self.current = self.current.saturating_sub(1);
+        }
+        if egui_ctx.input().key_pressed(egui::Key::ArrowRight) {
+            self.current = (self.current + 1).min(self.frames.len() - 1);
+        }
+
+        egui::TopBottomPanel::top("frame_bar").show(egui_ctx, |ui| {
+            self.frame_bar(ui);
+        });
+        egui::SidePanel::left("shape_list")
+            .default_width(420.0)
+            .show(egui_ctx, |ui| {
+                self.shape_list(ui);
+            });
+        egui::CentralPanel::default().show(egui_ctx, |ui| {
+            self.preview(ui);
+        });
+    }
+
+    /// For each shape of the current frame: is it different from the shape at the same
+    /// position in the previous frame? This is what the server compares too.
+    fn changed_shapes(&self) -> Vec<bool> {
+        let previous: &[ClippedNetShape] = match self.current.checked_sub(1) {
+            Some(previous) => &self.frames[previous].clipped_net_shapes,
+            None => &[],
+        };
+        self.frames[self.current]
+            .clipped_net_shapes
+            .iter()
+            .enumerate()
+            .map(|(i, shape)| previous.get(i) != Some(shape))
+            .collect()
+    }
+
+    fn frame_bar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("⏴").on_hover_text("Previous frame (←)").clicked() {
+                self.current = self.current.saturating_sub(1);
+            }
+            if ui.button("⏵").on_hover_text("Next frame (→)").clicked() {
+                self.current = (self.current + 1).min(self.frames.len() - 1);
+            }
+            ui.add(egui::Slider::new(&mut self.current, 0..=self.frames.len() - 1).text("frame"));
+        });
+
+        let changed = self.changed_shapes();
+        let frame = &self.frames[self.current];
+        let num_removed = match self.current.checked_sub(1) {
+            Some(previous) => self.frames[previous]
+                .clipped_net_shapes
+                .len()
+                .saturating_sub(frame.clipped_net_shapes.len()),
+            None => 0,
+        };
+        let num_changed = changed.iter().filter(|changed| **changed).count() + num_removed;
+        let changed_size: usize = frame
+            .shape_sizes
+            .iter()
+            .zip(&changed)
+            .filter(|(_, changed)| **changed)
+            .map(|(size, _)| size)
+            .sum();
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "Frame {} at {:.3} s",
+                frame.frame_index, frame.time
+            ));
+            ui.separator();
+            ui.label(format!("{:.1} kB sent", frame.packet_size as f32 * 1e-3))
+                .on_hover_text("The size of the packet, after compression");
+            ui.separator();
+            ui.label(format!(
+                "{:.1} kB of shapes",
+                frame.shape_sizes.iter().sum::<usize>() as f32 * 1e-3
+            ))
+            .on_hover_text("The size of the shapes, before compression");
+            ui.separator();
+            ui.label(format!(
+                "{}/{} shapes changed ({:.1} kB)",
+                num_changed,
+                frame.clipped_net_shapes.len(),
+                changed_size as f32 * 1e-3
+            ))
+            .on_hover_text("Compared to the previous frame, including removed shapes");
+        });
+    }
+
+    fn shape_list(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.only_changed, "Only changed");
+            ui.checkbox(&mut self.largest_first, "Largest first");
+            ui.checkbox(&mut self.outline_changed, "Outline changed");
+        });
+
+        let changed = self.changed_shapes();
+        let frame = &self.frames[self.current];
+        if let Some(i) = self.selected_shape {
+            if let Some(ClippedNetShape(clip_rect, shape)) = frame.clipped_net_shapes.get(i) {
+                ui.separator();
+                ui.label(format!("Shape {}: {}", i, describe(shape)));
+                ui.label(format!(
+                    "{} bytes, {}",
+                    frame.shape_sizes[i],
+                    if changed[i] { "changed" } else { "unchanged" }
+                ));
+                ui.label(format!("Clip rect: {:?}", clip_rect));
+            }
+        }
+        ui.separator();
+
+        let mut rows: Vec<usize> = (0..frame.clipped_net_shapes.len())
+            .filter(|&i| !self.only_changed || changed[i])
+            .collect();
+        if self.largest_first {
+            rows.sort_by_key(|&i| std::cmp::Reverse(frame.shape_sizes[i]));
+        }
+
+        let row_height = ui.spacing().interact_size.y;
+        egui::ScrollArea::vertical().show_rows(ui, row_height, rows.len(), |ui, row_range| {
+            for &i in &rows[row_range] {
+                let text = format!(
+                    "{} {:>5}  {:>6} B  {}",
+                    if changed[i] { "●" } else { " " },
+                    i,
+                    frame.shape_sizes[i],
+                    describe(&frame.clipped_net_shapes[i].1)
+                );
+                let is_selected = self.selected_shape == Some(i);
+                if ui.selectable_label(is_selected, text).clicked() {
+                    self.selected_shape = if is_selected { None } else { Some(i) };
+                }
+            }
+        });
+    }
+
+    /// Paint the frame as the viewer would, though with our own fonts.
+    fn preview(&mut self, ui: &mut egui::Ui) {
+        let changed = self.changed_shapes();
+        let frame = &self.frames[self.current];
+        let clipped_shapes =
+            eterm::net_shape::from_clipped_net_shapes(ui.fonts(), &frame.clipped_net_shapes);
+        let bounds: Vec<Rect> = clipped_shapes
+            .iter()
+            .map(|epaint::ClippedShape(_, shape)| bounding_rect(shape))
+            .collect();
+        let size = bounds
+            .iter()
+            .fold(Rect::NOTHING, |union, rect| union.union(*rect))
+            .max
+            .to_vec2()
+            .max(egui::Vec2::ZERO);
+
+        egui::ScrollArea::both().show(ui, |ui| {
+            let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+            let offset = rect.min.to_vec2();
+            let painter = ui.painter();
+            painter.rect_filled(rect, 0.0, Color32::BLACK);
+
+            for epaint::ClippedShape(clip_rect, mut shape) in clipped_shapes {
+                shape.translate(offset);
+                let mut shape_painter = painter.clone();
+                shape_painter
+                    .set_clip_rect(clip_rect.translate(offset).intersect(painter.clip_rect()));
+                shape_painter.add(shape);
+            }
+
+            if self.outline_changed {
+                for (shape_bounds, _) in
+                    bounds.iter().zip(&changed).filter(|(_, changed)| **changed)
+                {
+                    painter.rect_stroke(shape_bounds.translate(offset), 0.0, (1.0, CHANGED_COLOR));
+                }
+            }
+            if let Some(selected) = self.selected_shape.and_then(|i| bounds.get(i)) {
+                painter.rect_stroke(
+                    selected.translate(offset).expand(1.0),
+                    0.0,
+                    (2.0, SELECTED_COLOR),
+                );
+            }
+
+            if response.clicked() {
+                // Select the topmost shape under the pointer:
+                if let Some(pos) = response.interact_pointer_pos() {
+                    self.selected_shape = bounds
+                        .iter()
+                        .rposition(|shape_bounds| shape_bounds.translate(offset).contains(pos));
+                }
+            }
+        });
+    }
+}
+
+fn describe(shape: &NetShape) -> String {
+    match shape {
+        NetShape::Circle(circle) => format!("circle, radius {:.1}", circle.radius),
+        NetShape::LineSegment { .. } => "line segment".to_owned(),
+        NetShape::Path(path) => format!("path, {} points", path.points.len()),
+        NetShape::Rect(_) => "rect".to_owned(),
+        NetShape::Text(text) => {
+            let mut shown: String = text.job.text.chars().take(MAX_TEXT_CHARS).collect();
+            if shown.len() < text.job.text.len() {
+                shown += "…";
+            }
+            format!("text {:?}", shown)
+        }
+        NetShape::Mesh(mesh) => format!("mesh, {} triangles", mesh.indices.len() / 3),
+        NetShape::WhiteMesh(mesh) => format!("white mesh, {} triangles", mesh.indices.len() / 3),
+    }
+}
+
+fn bounding_rect(shape: &epaint::Shape) -> Rect {
+    match shape {
+        epaint::Shape::Circle(circle) => Rect::from_center_size(
+            circle.center,
+            egui::Vec2::splat(2.0 * circle.radius + circle.stroke.width),
+        ),
+        epaint::Shape::LineSegment { points, stroke } => {
+            Rect::from_two_pos(points[0], points[1]).expand(stroke.width / 2.0)
+        }
+        epaint::Shape::Path(path) => path.bounding_rect(),
+        epaint::Shape::Rect(rect) => rect.bounding_rect(),
+        epaint::Shape::Text(text) => text.bounding_rect(),
+        epaint::Shape::Mesh(mesh) => mesh.calc_bounds(),
+        _ => Rect::NOTHING, // not made from net shapes
+    }
+}
@@ -0,0 +1,176 @@
+//! Step through a recording (see [`eterm::recording`]) frame by frame,
+//! to see what each frame sends and what changed since the previous one:
+//!
+//! ``` sh
+//! eterm-inspect session.eterm
+//! ```
+
+#![forbid(unsafe_code)]
+#![warn(
+    clippy::all,
+    clippy::await_holding_lock,
+    clippy::char_lit_as_u8,
+    clippy::checked_conversions,
+    clippy::dbg_macro,
+    clippy::debug_assert_with_mut_call,
+    clippy::doc_markdown,
+    clippy::empty_enum,
+    clippy::enum_glob_use,
+    clippy::exit,
+    clippy::expl_impl_clone_on_copy,
+    clippy::explicit_deref_methods,
+    clippy::explicit_into_iter_loop,
+    clippy::fallible_impl_from,
+    clippy::filter_map_next,
+    clippy::float_cmp_const,
+    clippy::fn_params_excessive_bools,
+    clippy::if_let_mutex,
+    clippy::imprecise_flops,
+    clippy::inefficient_to_string,
+    clippy::invalid_upcast_comparisons,
+    clippy::large_types_passed_by_value,
+    clippy::let_unit_value,
+    clippy::linkedlist,
+    clippy::lossy_float_literal,
+    clippy::macro_use_imports,
+    clippy::manual_ok_or,
+    clippy::map_err_ignore,
+    clippy::map_flatten,
+    clippy::match_on_vec_items,
+    clippy::match_same_arms,
+    clippy::match_wildcard_for_single_variants,
+    clippy::mem_forget,
+    clippy::mismatched_target_os,
+    clippy::missing_errors_doc,
+    clippy::missing_safety_doc,
+    clippy::mut_mut,
+    clippy::mutex_integer,
+    clippy::needless_borrow,
+    clippy::needless_continue,
+    clippy::needless_pass_by_value,
+    clippy::option_option,
+    clippy::path_buf_push_overwrite,
+    clippy::ptr_as_ptr,
+    clippy::ref_option_ref,
+    clippy::rest_pat_in_fully_bound_structs,
+    clippy::same_functions_in_if_condition,
+    clippy::string_add_assign,
+    clippy::string_add,
+    clippy::string_lit_as_bytes,
+    clippy::string_to_string,
+    clippy::todo,
+    clippy::trait_duplication_in_bounds,
+    clippy::unimplemented,
+    clippy::unnested_or_patterns,
+    clippy::unused_self,
+    clippy::useless_transmute,
+    clippy::verbose_file_reads,
+    clippy::zero_sized_map_values,
+    future_incompatible,
+    missing_crate_level_docs,
+    nonstandard_style,
+    rust_2018_idioms
+)]
+#![allow(clippy::float_cmp)]
+#![allow(clippy::manual_range_contains)]
+
+mod inspector;
+
+use std::path::PathBuf;
+
+use glium::glutin;
+
+/// Step through an eterm recording frame by frame.
+#[derive(argh::FromArgs)]
+struct Arguments {
+    /// the recording to open.
+    #[argh(positional)]
+    recording: PathBuf,
+}
+
+fn main() -> anyhow::Result<()> {
+    // Log to stdout (if you run with `RUST_LOG=debug`).
+    tracing_subscriber::fmt::init();
+
+    let opt: Arguments = argh::from_env();
+    let frames = inspector::load(&opt.recording)?;
+    if frames.is_empty() {
+        anyhow::bail!("There are no frames in {:?}", opt.recording);
+    }
+    let mut inspector = inspector::Inspector::new(frames);
+
+    let event_loop = glutin::event_loop::EventLoop::new();
+    let title = format!("{} - eterm inspector", opt.recording.display());
+    let display = create_display(&event_loop, &title);
+    let mut egui_glium = egui_glium::EguiGlium::new(&display);
+
+    event_loop.run(move |event, _, control_flow| {
+        let mut redraw = || {
+            let raw_input = egui_glium
+                .egui_winit
+                .take_egui_input(display.gl_window().window());
+            let (egui_output, clipped_shapes) = egui_glium
+                .egui_ctx
+                .run(raw_input, |egui_ctx| inspector.ui(egui_ctx));
+            let needs_repaint = egui_output.needs_repaint;
+            egui_glium.egui_winit.handle_output(
+                display.gl_window().window(),
+                &egui_glium.egui_ctx,
+                egui_output,
+            );
+
+            use glium::Surface as _;
+            let mut target = display.draw();
+            target.clear_color(0.0, 0.0, 0.0, 1.0);
+            egui_glium.paint(&display, &mut target, clipped_shapes);
+            target.finish().unwrap();
+
+            *control_flow = if needs_repaint {
+                display.gl_window().window().request_redraw();
+                glutin::event_loop::ControlFlow::Poll
+            } else {
+                glutin::event_loop::ControlFlow::Wait
+            };
+        };
+
+        match event {
+            // Platform-dependent event handlers to workaround a winit bug
+            // See: https://github.com/rust-windowing/winit/issues/987
+            // See: https://github.com/rust-windowing/winit/issues/1619
+            glutin::event::Event::RedrawEventsCleared if cfg!(windows) => redraw(),
+            glutin::event::Event::RedrawRequested(_) if !cfg!(windows) => redraw(),
+
+            glutin::event::Event::WindowEvent { event, .. } => {
+                use glutin::event::WindowEvent;
+                if matches!(event, WindowEvent::CloseRequested | WindowEvent::Destroyed) {
+                    *control_flow = glutin::event_loop::ControlFlow::Exit;
+                }
+
+                egui_glium.on_event(&event);
+
+                display.gl_window().window().request_redraw();
+            }
+
+            _ => (),
+        }
+    });
+}
+
+fn create_display(event_loop: &glutin::event_loop::EventLoop<()>, title: &str) -> glium::Display {
+    let window_builder = glutin::window::WindowBuilder::new()
+        .with_resizable(true)
+        .with_inner_size(glutin::dpi::LogicalSize {
+            width: 1280.0,
+            height: 800.0,
+        })
+        .with_title(title);
+
+    let context_builder = glutin::ContextBuilder::new()
+        .with_depth_buffer(0)
+        .with_double_buffer(Some(true))
+        .with_srgb(true)
+        .with_stencil_buffer(0)
+        .with_vsync(true);
+
+    glium::Display::new(window_builder, context_builder, event_loop).unwrap()
+}
@@ -0,0 +1,197 @@
+//! Python bindings for [`eterm::Server`].
+//!
+//! ``` python
+//! import time
+//! import eterm_py
+//!
+//! server = eterm_py.Server("0.0.0.0:8505")
+//! loss = 0.5
+//!
+//! def ui(ui, client_id):
+//!     ui.heading("Training")
+//!     ui.label(f"loss: {loss:.3f}")
+//!     if ui.button("Stop"):
+//!         ...
+//!
+//! while True:
+//!     server.show(ui)
+//!     time.sleep(1 / 60)
+//! ```
+//!
+//! Build with [maturin](https://github.com/PyO3/maturin): `maturin develop -m eterm_py/Cargo.toml`.
+
+#![warn(clippy::all, future_incompatible, nonstandard_style, rust_2018_idioms)]
+
+use pyo3::{exceptions::PyRuntimeError, prelude::*};
+
+/// An eterm server. Connect to it with `eterm_viewer`.
+#[pyclass(unsendable, name = "Server")]
+struct PyServer {
+    server: eterm::Server,
+}
+
+#[pymethods]
+impl PyServer {
+    /// Start listening for connections on this addr (e.g. "0.0.0.0:8505").
+    #[new]
+    fn new(bind_addr: &str) -> PyResult<Self> {
+        let server = eterm::Server::new(bind_addr).map_err(to_py_err)?;
+        Ok(Self { server })
+    }
+
+    /// Send a new frame to each client at least this often.
+    fn set_minimum_update_interval(&mut self, seconds: f32) {
+        self.server.set_minimum_update_interval(seconds);
+    }
+
+    /// Never send more than this many frames per second to any one client.
+    fn set_maximum_frame_rate(&mut self, frames_per_second: f32) {
+        self.server.set_maximum_frame_rate(frames_per_second);
+    }
+
+    /// Send a new frame to all clients in the next call to `show`, e.g. when some data changed.
+    fn request_repaint_all(&mut self) {
+        self.server.request_repaint_all();
+    }
+
+    /// Call frequently (e.g. 60 times per second).
+    ///
+    /// `callback(ui, client_id)` is called once for each client that needs a new frame.
+    fn show(&mut self, py: Python<'_>, callback: PyObject) -> PyResult<()> {
+        let mut callback_error = None;
+
+        self.server
+            .show(|egui_ctx, client_id| {
+                if callback_error.is_some() {
+                    return;
+                }
+                egui::CentralPanel::default().show(egui_ctx, |ui| {
+                    let max_rect = ui.available_rect_before_wrap();
+                    let layout = *ui.layout();
+                    let result = call_with_ui(py, ui, max_rect, layout, |py_ui| {
+                        callback.call1(py, (py_ui, client_id.as_u64()))
+                    });
+                    if let Err(err) = result {
+                        callback_error = Some(err);
+                    }
+                });
+            })
+            .map_err(to_py_err)?;
+
+        match callback_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Run `call` with a Python [`PyUi`] that paints into a child of `ui`.
+fn call_with_ui(
+    py: Python<'_>,
+    ui: &mut egui::Ui,
+    max_rect: egui::Rect,
+    layout: egui::Layout,
+    call: impl FnOnce(Py<PyUi>) -> PyResult<PyObject>,
+) -> PyResult<()> {
+    let child_ui = ui.child_ui(max_rect, layout);
+    let py_ui = Py::new(py, PyUi { ui: Some(child_ui) })?;
+    let result = call(py_ui.clone_ref(py));
+
+    // Take back the ui so Python can't use it after we return:
+    if let Some(child_ui) = py_ui.borrow_mut(py).ui.take() {
+        ui.allocate_space(child_ui.min_rect().size());
+    }
+
+    result.map(|_| ())
+}
+
+/// Passed to the `show` callback. Only valid during the callback.
+#[pyclass(unsendable, name = "Ui")]
+struct PyUi {
+    ui: Option<egui::Ui>,
+}
+
+impl PyUi {
+    fn ui(&mut self) -> PyResult<&mut egui::Ui> {
+        self.ui
+            .as_mut()
+            .ok_or_else(|| PyRuntimeError::new_err("The Ui can only be used inside the callback"))
+    }
+}
+
+#[pymethods]
+impl PyUi {
+    fn label(&mut self, text: &str) -> PyResult<()> {
+        self.ui()?.label(text);
+        Ok(())
+    }
+
+    fn heading(&mut self, text: &str) -> PyResult<()> {
+        self.ui()?.heading(text);
+        Ok(())
+    }
+
+    fn monospace(&mut self, text: &str) -> PyResult<()> {
+        self.ui()?.monospace(text);
+        Ok(())
+    }
+
+    fn separator(&mut self) -> PyResult<()> {
+        self.ui()?.separator();
+        Ok(())
+    }
+
+    /// Returns `True` if clicked.
+    fn button(&mut self, text: &str) -> PyResult<bool> {
+        Ok(self.ui()?.button(text).clicked())
+    }
+
+    /// Returns the new value.
+    fn checkbox(&mut self, text: &str, checked: bool) -> PyResult<bool> {
+        let mut checked = checked;
+        self.ui()?.checkbox(&mut checked, text);
+        Ok(checked)
+    }
+
+    /// Returns the new value.
+    fn slider(&mut self, text: &str, value: f64, min: f64, max: f64) -> PyResult<f64> {
+        let mut value = value;
+        self.ui()?
+            .add(egui::Slider::new(&mut value, min..=max).text(text));
+        Ok(value)
+    }
+
+    /// Returns the new text.
+    fn text_edit(&mut self, text: &str) -> PyResult<String> {
+        let mut text = text.to_owned();
+        self.ui()?.text_edit_singleline(&mut text);
+        Ok(text)
+    }
+
+    /// `fraction` is in the range `[0, 1]`.
+    fn progress_bar(&mut self, fraction: f32) -> PyResult<()> {
+        self.ui()?
+            .add(egui::ProgressBar::new(fraction).show_percentage());
+        Ok(())
+    }
+
+    /// Lay out the widgets added by `callback(ui)` left-to-right.
+    fn horizontal(&mut self, py: Python<'_>, callback: PyObject) -> PyResult<()> {
+        let ui = self.ui()?;
+        let max_rect = ui.available_rect_before_wrap();
+        call_with_ui(py, ui, max_rect, egui::Layout::left_to_right(), |py_ui| {
+            callback.call1(py, (py_ui,))
+        })
+    }
+}
+
+fn to_py_err(err: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(format!("{:#}", err))
+}
+
+#[pymodule]
+fn eterm_py(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyServer>()?;
+    m.add_class::<PyUi>()?;
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use eterm::gamepad::{GamepadAxis, GamepadButton, GamepadEvent, GamepadId};
+
+/// Reads the local gamepads so we can forward them to the server.
+pub struct GamepadInput {
+    gilrs: gilrs::Gilrs,
+}
+
+impl GamepadInput {
+    /// Returns `None` if gamepad support is unavailable on this system.
+    pub fn new() -> Option<Self> {
+        match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(Self { gilrs }),
+            Err(err) => {
+                tracing::warn!("Failed to initialize gamepad support: {}", err);
+                None
+            }
+        }
+    }
+
+    /// All events since last call.
+    pub fn events(&mut self) -> Vec<GamepadEvent> {
+        let mut events = vec![];
+        while let Some(gilrs::Event { id, event, .. }) = self.gilrs.next_event() {
+            let gamepad_id = GamepadId(Into::<usize>::into(id) as u32);
+            let event = match event {
+                gilrs::EventType::Connected => GamepadEvent::Connected {
+                    id: gamepad_id,
+                    name: self.gilrs.gamepad(id).name().to_owned(),
+                },
+                gilrs::EventType::Disconnected => GamepadEvent::Disconnected { id: gamepad_id },
+                gilrs::EventType::ButtonPressed(button, _) => match convert_button(button) {
+                    Some(button) => GamepadEvent::Button {
+                        id: gamepad_id,
+                        button,
+                        pressed: true,
+                    },
+                    None => continue,
+                },
+                gilrs::EventType::ButtonReleased(button, _) => match convert_button(button) {
+                    Some(button) => GamepadEvent::Button {
+                        id: gamepad_id,
+                        button,
+                        pressed: false,
+                    },
+                    None => continue,
+                },
+                gilrs::EventType::AxisChanged(axis, value, _) => match convert_axis(axis) {
+                    Some(axis) => GamepadEvent::Axis {
+                        id: gamepad_id,
+                        axis,
+                        value,
+                    },
+                    None => continue,
+                },
+                gilrs::EventType::ButtonRepeated(..)
+                | gilrs::EventType::ButtonChanged(..)
+                | gilrs::EventType::Dropped => continue,
+            };
+            events.push(event);
+        }
+        events
+    }
+}
+
+fn convert_button(button: gilrs::Button) -> Option<GamepadButton> {
+    Some(match button {
+        gilrs::Button::South => GamepadButton::South,
+        gilrs::Button::East => GamepadButton::East,
+        gilrs::Button::North => GamepadButton::North,
+        gilrs::Button::West => GamepadButton::West,
+        gilrs::Button::LeftTrigger => GamepadButton::LeftTrigger,
+        gilrs::Button::LeftTrigger2 => GamepadButton::LeftTrigger2,
+        gilrs::Button::RightTrigger => GamepadButton::RightTrigger,
+        gilrs::Button::RightTrigger2 => GamepadButton::RightTrigger2,
+        gilrs::Button::Select => GamepadButton::Select,
+        gilrs::Button::Start => GamepadButton::Start,
+        gilrs::Button::Mode => GamepadButton::Mode,
+        gilrs::Button::LeftThumb => GamepadButton::LeftThumb,
+        gilrs::Button::RightThumb => GamepadButton::RightThumb,
+        gilrs::Button::DPadUp => GamepadButton::DPadUp,
+        gilrs::Button::DPadDown => GamepadButton::DPadDown,
+        gilrs::Button::DPadLeft => GamepadButton::DPadLeft,
+        gilrs::Button::DPadRight => GamepadButton::DPadRight,
+        gilrs::Button::C | gilrs::Button::Z | gilrs::Button::Unknown => return None,
+    })
+}
+
+fn convert_axis(axis: gilrs::Axis) -> Option<GamepadAxis> {
+    Some(match axis {
+        gilrs::Axis::LeftStickX => GamepadAxis::LeftStickX,
+        gilrs::Axis::LeftStickY => GamepadAxis::LeftStickY,
+        gilrs::Axis::RightStickX => GamepadAxis::RightStickX,
+        gilrs::Axis::RightStickY => GamepadAxis::RightStickY,
+        gilrs::Axis::LeftZ => GamepadAxis::LeftZ,
+        gilrs::Axis::RightZ => GamepadAxis::RightZ,
+        gilrs::Axis::DPadX | gilrs::Axis::DPadY | gilrs::Axis::Unknown => return None,
+    })
+}
@@ -0,0 +1,135 @@
+//! Connect to a server without a window, and save the received frames as PNG files.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+use eterm::EguiFrame;
+
+/// Give up if nothing has happened for this long.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct HeadlessOptions {
+    pub output_dir: PathBuf,
+    /// Size in points.
+    pub screen_size: egui::Vec2,
+    pub pixels_per_point: f32,
+    /// Stop after saving this many frames.
+    pub num_frames: usize,
+    /// Each input is sent after the previous frame has been saved.
+    pub input_script: Vec<egui::RawInput>,
+    /// Exit as soon as we are disconnected, instead of waiting for a reconnect.
+    pub exit_on_disconnect: bool,
+}
+
+/// Load a RON file containing a list of [`egui::RawInput`].
+pub fn load_input_script(path: &Path) -> anyhow::Result<Vec<egui::RawInput>> {
+    let ron = std::fs::read_to_string(path).with_context(|| format!("reading {:?}", path))?;
+    ron::from_str(&ron).with_context(|| format!("parsing {:?}", path))
+}
+
+pub fn run(mut client: eterm::Client, options: HeadlessOptions) -> anyhow::Result<()> {
+    let HeadlessOptions {
+        output_dir,
+        screen_size,
+        pixels_per_point,
+        num_frames,
+        input_script,
+        exit_on_disconnect,
+    } = options;
+
+    std::fs::create_dir_all(&output_dir).with_context(|| format!("creating {:?}", output_dir))?;
+
+    let screen_rect = egui::Rect::from_min_size(Default::default(), screen_size);
+    let size_in_pixels = [
+        (screen_size.x * pixels_per_point).round() as usize,
+        (screen_size.y * pixels_per_point).round() as usize,
+    ];
+
+    let mut input_script = input_script.into_iter();
+    let send_input = |client: &eterm::Client, input: egui::RawInput| {
+        client.send_input(egui::RawInput {
+            screen_rect: Some(screen_rect),
+            pixels_per_point: Some(pixels_per_point),
+            time: None, // server knows the time
+            ..input
+        });
+    };
+
+    send_input(&client, Default::default());
+
+    let mut last_progress = Instant::now();
+    let mut num_saved = 0;
+    while num_saved < num_frames {
+        if last_progress.elapsed() > TIMEOUT {
+            anyhow::bail!(
+                "Timed out after saving {} of {} frames from {}",
+                num_saved,
+                num_frames,
+                client.addr()
+            );
+        }
+
+        let new_frame = client.update(pixels_per_point);
+
+        if exit_on_disconnect || client.has_given_up() {
+            if let Some(reason) = client.disconnect_reason() {
+                crate::exit_disconnected(&client, reason);
+            }
+        }
+
+        if let Some(motd) = client.take_motd() {
+            tracing::info!("Message of the day: {}", motd);
+        }
+
+        for file in client.take_received_files() {
+            let path = output_dir.join(&file.file_name);
+            std::fs::write(&path, &file.bytes).with_context(|| format!("writing {:?}", path))?;
+            tracing::info!("Saved {:?}", path);
+        }
+
+        if let Some(EguiFrame {
+            frame_index,
+            clipped_meshes,
+            ..
+        }) = new_frame
+        {
+            let image = eterm::raster::rasterize(
+                size_in_pixels,
+                egui::Color32::BLACK,
+                pixels_per_point,
+                &clipped_meshes,
+                &client.font_image(),
+            );
+            let path = output_dir.join(format!("frame_{:06}.png", frame_index));
+            write_png(&path, &image)?;
+            tracing::info!("Saved {:?}", path);
+            num_saved += 1;
+            last_progress = Instant::now();
+
+            if let Some(input) = input_script.next() {
+                send_input(&client, input);
+            }
+        } else {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    Ok(())
+}
+
+fn write_png(path: &Path, image: &eterm::raster::Image) -> anyhow::Result<()> {
+    let file = std::fs::File::create(path).with_context(|| format!("creating {:?}", path))?;
+    let mut encoder = png::Encoder::new(
+        std::io::BufWriter::new(file),
+        image.width() as u32,
+        image.height() as u32,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().context("png header")?;
+    writer
+        .write_image_data(&image.as_rgba_bytes())
+        .context("png data")?;
+    Ok(())
+}
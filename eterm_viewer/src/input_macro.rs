@@ -0,0 +1,86 @@
+//! Recording the input we send to the server (`--record-input`) and playing it back later (`--play-input`),
+//! e.g. for smoke tests and repeatable demos.
+//!
+//! The file has one line per sent [`egui::RawInput`]: a RON tuple of the time
+//! (in seconds since the recording started) and the input.
+
+use std::collections::VecDeque;
+use std::io::{BufRead as _, Write as _};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+
+pub struct InputRecorder {
+    file: std::fs::File,
+    start: Instant,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::create(path).with_context(|| format!("creating {:?}", path))?;
+        Ok(Self {
+            file,
+            start: Instant::now(),
+        })
+    }
+
+    /// Each input is written right away, so nothing is lost if the viewer is killed.
+    pub fn record(&mut self, input: &egui::RawInput) -> anyhow::Result<()> {
+        let time = self.start.elapsed().as_secs_f64();
+        let line = ron::to_string(&(time, input)).context("ron")?;
+        writeln!(self.file, "{}", line).context("write")
+    }
+}
+
+pub struct InputPlayer {
+    /// Oldest first.
+    inputs: VecDeque<(f64, egui::RawInput)>,
+    start: Instant,
+}
+
+impl InputPlayer {
+    /// Playback starts right away.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path).with_context(|| format!("opening {:?}", path))?;
+        let mut inputs = VecDeque::new();
+        for (line_nr, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line.with_context(|| format!("reading {:?}", path))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let timed_input = ron::from_str(&line)
+                .with_context(|| format!("parsing {:?}, line {}", path, line_nr + 1))?;
+            inputs.push_back(timed_input);
+        }
+        Ok(Self {
+            inputs,
+            start: Instant::now(),
+        })
+    }
+
+    /// The inputs that are due by now, oldest first.
+    pub fn take_due(&mut self) -> Vec<egui::RawInput> {
+        let now = self.start.elapsed().as_secs_f64();
+        let mut due = vec![];
+        while let Some((time, _)) = self.inputs.front() {
+            if *time > now {
+                break;
+            }
+            if let Some((_, input)) = self.inputs.pop_front() {
+                due.push(input);
+            }
+        }
+        due
+    }
+
+    /// When the next input is due, or `None` when we are done.
+    pub fn next_due(&self) -> Option<Instant> {
+        let (time, _) = self.inputs.front()?;
+        Some(self.start + Duration::from_secs_f64(time.max(0.0)))
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
@@ -67,6 +67,12 @@
 #![allow(clippy::float_cmp)]
 #![allow(clippy::manual_range_contains)]
 
+mod gamepad;
+mod headless;
+mod input_macro;
+mod settings;
+mod tui;
+
 use eterm::EguiFrame;
 use glium::glutin;
 
@@ -77,6 +83,12 @@ const TOP_BAR_HEIGHT: f32 = 24.0;
 /// Repaint every so often to check connection status etc.
 const MIN_REPAINT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
 
+/// How much holding down Alt magnifies the remote ui around the mouse pointer.
+const MAGNIFIER_ZOOM: f32 = 2.5;
+
+/// How often we check for new gamepad input.
+const GAMEPAD_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(16);
+
 /// eterm viewer viewer.
 ///
 /// Connects to an eterm server somewhere.
@@ -85,55 +97,297 @@ struct Arguments {
     /// which server to connect to, e.g. `127.0.0.1:8505`.
     #[argh(option)]
     url: String,
-}
 
-fn main() {
-    // Log to stdout (if you run with `RUST_LOG=debug`).
-    tracing_subscriber::fmt::init();
+    /// connect through an SSH tunnel to this host (e.g. `user@example.com`).
+    /// `--url` is then the address of the server as seen from that host.
+    #[argh(option)]
+    ssh: Option<String>,
+
+    /// give up instead of reconnecting when the connection fails or is lost.
+    #[argh(switch)]
+    no_reconnect: bool,
+
+    /// exit as soon as we are disconnected from the server.
+    /// The exit code says why: 2 = connection refused, 3 = version mismatch,
+    /// 4 = authentication failed, 5 = other disconnects.
+    #[argh(switch)]
+    exit_on_disconnect: bool,
+
+    /// ask the server for a light theme (default: dark).
+    #[argh(switch)]
+    light: bool,
+
+    /// experimental: show the remote ui in the terminal instead of opening a window.
+    #[argh(switch)]
+    tui: bool,
+
+    /// don't open a window: save the received frames as PNG files and exit.
+    #[argh(switch)]
+    headless: bool,
+
+    /// where to save the frames in `--headless` mode.
+    #[argh(option, default = "\"eterm_frames\".into()")]
+    output: std::path::PathBuf,
+
+    /// how many frames to save in `--headless` mode.
+    #[argh(option, default = "1")]
+    frames: usize,
+
+    /// a RON file with a list of `egui::RawInput` to send in `--headless` mode, one per frame.
+    #[argh(option)]
+    input_script: Option<std::path::PathBuf>,
+
+    /// screen width (in points) in `--headless` mode.
+    #[argh(option, default = "800.0")]
+    width: f32,
+
+    /// screen height (in points) in `--headless` mode.
+    #[argh(option, default = "600.0")]
+    height: f32,
+
+    /// lay out the remote ui for this width (in points) and scale it to fit the window.
+    #[argh(option)]
+    remote_width: Option<f32>,
+
+    /// lay out the remote ui for this height (in points) and scale it to fit the window.
+    #[argh(option)]
+    remote_height: Option<f32>,
+
+    /// keep the window above all other windows, e.g. to monitor something while using another app.
+    #[argh(switch)]
+    always_on_top: bool,
+
+    /// open the window without a title bar and borders.
+    #[argh(switch)]
+    borderless: bool,
+
+    /// repaint at most this many times per second, to save battery.
+    #[argh(option)]
+    max_fps: Option<f32>,
 
+    /// save the input we send to the server to this file, to play it back later with `--play-input`.
+    #[argh(option)]
+    record_input: Option<std::path::PathBuf>,
+
+    /// send the input saved with `--record-input` to the server, with its original timing.
+    /// Our own input is ignored until it has all been sent.
+    #[argh(option)]
+    play_input: Option<std::path::PathBuf>,
+
+    /// override the detected scale factor (pixels per point). Defaults to 1 in `--headless` mode.
+    /// Use Ctrl +/- to zoom and Ctrl 0 to reset while running.
+    #[argh(option)]
+    pixels_per_point: Option<f32>,
+
+    /// write every packet sent and received to a file in this directory, for debugging.
+    /// Decode them with `cargo run -p eterm --example decode_packet`.
+    #[argh(option)]
+    dump_packets: Option<std::path::PathBuf>,
+
+    /// the most verbose level to log: `error`, `warn`, `info` (default), `debug` or `trace`.
+    #[argh(option, default = "tracing::Level::INFO")]
+    log_level: tracing::Level,
+
+    /// append the log to this file instead of writing it to stdout.
+    #[argh(option)]
+    log_file: Option<std::path::PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
     let opt: Arguments = argh::from_env();
-    let mut client = eterm::Client::new(opt.url);
+    init_logging(&opt)?;
+
+    let mut client = match &opt.ssh {
+        Some(ssh_target) => eterm::Client::new_via_ssh(ssh_target, &opt.url)?,
+        None => eterm::Client::new(opt.url.clone()),
+    };
+    if let Some(dir) = &opt.dump_packets {
+        client.set_packet_dump_dir(dir)?;
+    }
+    client.set_preferences(eterm::ClientPreferences {
+        dark_mode: Some(!opt.light),
+        ..client.preferences()
+    });
+    client.set_reconnect(!opt.no_reconnect);
+
+    if opt.headless {
+        return run_headless(client, &opt);
+    }
+    if opt.tui {
+        return tui::run(client);
+    }
+
+    let mut settings = settings::Settings::load();
 
     let event_loop = glutin::event_loop::EventLoop::with_user_event();
-    let display = create_display(&event_loop);
+    let display = create_display(&event_loop, &settings, &opt);
+    settings.apply_to_window(display.gl_window().window());
+
+    let event_loop_proxy = event_loop.create_proxy();
+    client.set_wake_up_callback(move || {
+        event_loop_proxy.send_event(()).ok();
+    });
 
     let mut egui_glium = egui_glium::EguiGlium::new(&display);
+    if opt.light {
+        egui_glium.egui_ctx.set_visuals(egui::Visuals::light());
+    }
+
+    let mut gamepad_input = gamepad::GamepadInput::new();
+
+    let mut input_recorder = opt
+        .record_input
+        .as_deref()
+        .map(input_macro::InputRecorder::create)
+        .transpose()?;
+    let mut input_player = opt
+        .play_input
+        .as_deref()
+        .map(input_macro::InputPlayer::load)
+        .transpose()?;
 
     let mut last_sent_input = None;
 
     let mut latest_eterm_meshes = Default::default();
 
+    // Applied to the window when it changes:
+    let mut app_info: Option<eterm::AppInfo> = None;
+
+    // Shown until the user dismisses it:
+    let mut motd: Option<String> = None;
+
+    let mut show_server_log = settings.show_server_log;
+
+    // The remote ui is dimmed while we are disconnected, so it doesn't look like it responds:
+    let mut dimmed = false;
+
+    // Set by `--pixels-per-point` or the zoom keys:
+    let mut pixels_per_point_override = opt.pixels_per_point;
+    let mut modifiers = glutin::event::ModifiersState::default();
+
+    let exit_on_disconnect = opt.exit_on_disconnect;
+    let (remote_width, remote_height) = (opt.remote_width, opt.remote_height);
+
+    let refresh_rate = monitor_refresh_rate(display.gl_window().window());
+
     let mut needs_repaint = true;
     let mut last_repaint = std::time::Instant::now();
+    let min_repaint_interval = opt
+        .max_fps
+        .filter(|max_fps| *max_fps > 0.0)
+        .map(|max_fps| std::time::Duration::from_secs_f32(1.0 / max_fps));
 
     event_loop.run(move |event, _, control_flow| {
         let mut redraw = || {
+            if let Some(pixels_per_point) = pixels_per_point_override {
+                // Re-applied every frame, or a `ScaleFactorChanged` would undo it.
+                egui_glium.egui_winit.set_pixels_per_point(pixels_per_point);
+            }
             let raw_input = egui_glium
                 .egui_winit
                 .take_egui_input(display.gl_window().window());
 
+            // Hold Alt to magnify the remote ui around the pointer:
+            let magnifier_center = if modifiers.alt() {
+                egui_glium.egui_ctx.input().pointer.hover_pos()
+            } else {
+                None
+            };
+
+            let mut screen_transform = eterm::ScreenTransform::IDENTITY;
             let mut sent_input = raw_input.clone();
             sent_input.time = None; // server knows the time
             if let Some(screen_rect) = &mut sent_input.screen_rect {
                 screen_rect.min.y += TOP_BAR_HEIGHT;
                 screen_rect.max.y = screen_rect.max.y.max(screen_rect.min.y);
+
+                if remote_width.is_some() || remote_height.is_some() {
+                    // Letterbox a remote ui of a fixed size into the window:
+                    let window_rect = *screen_rect;
+                    *screen_rect = egui::Rect::from_min_size(
+                        window_rect.min,
+                        egui::vec2(
+                            remote_width.unwrap_or_else(|| window_rect.width()),
+                            remote_height.unwrap_or_else(|| window_rect.height()),
+                        ),
+                    );
+                    screen_transform = eterm::ScreenTransform::letterbox(*screen_rect, window_rect);
+                }
             }
+            if let Some(center) = magnifier_center {
+                screen_transform = screen_transform
+                    .then(eterm::ScreenTransform::zoom_around(center, MAGNIFIER_ZOOM));
+            }
+            client.set_screen_transform(screen_transform);
 
-            if last_sent_input.as_ref() != Some(&sent_input) {
+            if let Some(player) = &mut input_player {
+                for input in player.take_due() {
+                    client.send_input(input);
+                    needs_repaint = true;
+                }
+                if player.is_done() {
+                    tracing::info!("Finished playing back the recorded input");
+                    input_player = None;
+                }
+            } else if last_sent_input.as_ref() != Some(&sent_input) {
                 client.send_input(sent_input.clone());
+                if let Some(recorder) = &mut input_recorder {
+                    if let Err(err) = recorder.record(&sent_input) {
+                        tracing::error!("Failed to record input: {}", err);
+                        input_recorder = None;
+                    }
+                }
                 last_sent_input = Some(sent_input);
                 needs_repaint = true;
             }
 
+            if let Some(gamepad_input) = &mut gamepad_input {
+                client.send_gamepad_events(gamepad_input.events());
+            }
+
+            if dimmed == client.is_connected() {
+                dimmed = !dimmed;
+                if dimmed {
+                    client.set_frame_hook(|shapes| {
+                        eterm::recolor::map_colors(shapes, |color| color.linear_multiply(0.4));
+                    });
+                } else {
+                    client.clear_frame_hook();
+                }
+            }
+
             let pixels_per_point = egui_glium.egui_winit.pixels_per_point();
-            if let Some(frame) = client.update(pixels_per_point) {
+            let old_notice = client.notice().cloned();
+            let old_num_log_records = client.server_log().num_received();
+            // Lay out the text of the magnified ui at a higher resolution, so it stays sharp:
+            let remote_pixels_per_point = if magnifier_center.is_some() {
+                MAGNIFIER_ZOOM * pixels_per_point
+            } else {
+                pixels_per_point
+            };
+            let new_frame = client.update(remote_pixels_per_point);
+            needs_repaint |= client.notice() != old_notice.as_ref();
+            needs_repaint |=
+                show_server_log && client.server_log().num_received() != old_num_log_records;
+            if exit_on_disconnect {
+                if let Some(reason) = client.disconnect_reason() {
+                    exit_disconnected(&client, reason);
+                }
+            }
+            if let Some(frame) = new_frame {
                 // We got something new from the server!
                 let EguiFrame {
                     frame_index: _,
-                    output,
+                    mut output,
                     clipped_meshes,
                 } = frame;
 
+                // The remote ui is painted below our top bar:
+                if let Some(text_cursor_pos) = &mut output.text_cursor_pos {
+                    *text_cursor_pos = client.screen_transform().transform_pos(*text_cursor_pos);
+                    text_cursor_pos.y += TOP_BAR_HEIGHT;
+                }
+
                 egui_glium.egui_winit.handle_output(
                     display.gl_window().window(),
                     &egui_glium.egui_ctx,
@@ -144,14 +398,37 @@ fn main() {
                 needs_repaint = true;
             }
 
-            if needs_repaint || last_repaint.elapsed() > MIN_REPAINT_INTERVAL {
+            for file in client.take_received_files() {
+                save_received_file(file);
+            }
+
+            for notification in client.take_notifications() {
+                show_notification(display.gl_window().window(), &notification);
+            }
+
+            if client.app_info() != app_info.as_ref() {
+                app_info = client.app_info().cloned();
+                if let Some(app_info) = &app_info {
+                    set_window_app_info(display.gl_window().window(), app_info);
+                }
+            }
+
+            if let Some(new_motd) = client.take_motd() {
+                motd = Some(new_motd);
+                needs_repaint = true;
+            }
+
+            let too_soon = min_repaint_interval
+                .map_or(false, |min_interval| last_repaint.elapsed() < min_interval);
+            if (needs_repaint && !too_soon) || last_repaint.elapsed() > MIN_REPAINT_INTERVAL {
                 needs_repaint = false;
                 last_repaint = std::time::Instant::now();
 
                 // paint the eterm viewer ui:
-                let (egui_output, clipped_shapes) = egui_glium
-                    .egui_ctx
-                    .run(raw_input, |egui_ctx| client_gui(egui_ctx, &client));
+                let (egui_output, clipped_shapes) =
+                    egui_glium.egui_ctx.run(raw_input, |egui_ctx| {
+                        client_gui(egui_ctx, &client, &mut motd, &mut show_server_log)
+                    });
 
                 needs_repaint |= egui_output.needs_repaint;
                 egui_glium.egui_winit.handle_output(
@@ -177,12 +454,31 @@ fn main() {
                 egui_glium.paint(&display, &mut target, clipped_shapes);
 
                 target.finish().unwrap();
+                if let Some(refresh_rate) = refresh_rate {
+                    // With vsync on, `finish` returns right after the vsync:
+                    client.on_vsync(refresh_rate);
+                }
             }
 
-            std::thread::sleep(std::time::Duration::from_millis(10));
+            let mut next_wake_up = last_repaint + MIN_REPAINT_INTERVAL;
+            if needs_repaint {
+                match min_repaint_interval {
+                    Some(min_interval) if last_repaint.elapsed() < min_interval => {
+                        // Repaint when the `--max-fps` allows:
+                        next_wake_up = next_wake_up.min(last_repaint + min_interval);
+                    }
+                    _ => display.gl_window().window().request_redraw(),
+                }
+            }
 
-            display.gl_window().window().request_redraw();
-            *control_flow = glutin::event_loop::ControlFlow::Wait;
+            if gamepad_input.is_some() {
+                // gilrs can't wake us up, so we need to poll it:
+                next_wake_up = next_wake_up.min(std::time::Instant::now() + GAMEPAD_POLL_INTERVAL);
+            }
+            if let Some(next_due) = input_player.as_ref().and_then(|player| player.next_due()) {
+                next_wake_up = next_wake_up.min(next_due);
+            }
+            *control_flow = glutin::event_loop::ControlFlow::WaitUntil(next_wake_up);
         };
 
         match event {
@@ -192,11 +488,60 @@ fn main() {
             glutin::event::Event::RedrawEventsCleared if cfg!(windows) => redraw(),
             glutin::event::Event::RedrawRequested(_) if !cfg!(windows) => redraw(),
 
+            glutin::event::Event::UserEvent(()) => {
+                // The network thread received something
+                display.gl_window().window().request_redraw();
+            }
+            glutin::event::Event::NewEvents(glutin::event::StartCause::ResumeTimeReached {
+                ..
+            }) => {
+                display.gl_window().window().request_redraw();
+            }
+
             glutin::event::Event::WindowEvent { event, .. } => {
                 use glutin::event::WindowEvent;
                 if matches!(event, WindowEvent::CloseRequested | WindowEvent::Destroyed) {
                     *control_flow = glium::glutin::event_loop::ControlFlow::Exit;
                 }
+                if matches!(event, WindowEvent::CloseRequested) {
+                    settings.update_from_window(display.gl_window().window());
+                    settings.show_server_log = show_server_log;
+                    settings.save();
+                }
+                if let WindowEvent::Resized(size) = &event {
+                    // On some platforms a minimized window is resized to zero.
+                    client.set_visible(size.width > 0 && size.height > 0);
+                }
+                if let WindowEvent::ModifiersChanged(new_modifiers) = &event {
+                    modifiers = *new_modifiers;
+                }
+                if let WindowEvent::KeyboardInput { input, .. } = &event {
+                    if input.state == glutin::event::ElementState::Pressed
+                        && (modifiers.ctrl() || modifiers.logo())
+                    {
+                        use glutin::event::VirtualKeyCode;
+                        let current = pixels_per_point_override
+                            .unwrap_or_else(|| egui_glium.egui_winit.pixels_per_point());
+                        match input.virtual_keycode {
+                            Some(
+                                VirtualKeyCode::Equals
+                                | VirtualKeyCode::Plus
+                                | VirtualKeyCode::NumpadAdd,
+                            ) => {
+                                pixels_per_point_override = Some(zoom(current, ZOOM_STEP));
+                            }
+                            Some(VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract) => {
+                                pixels_per_point_override = Some(zoom(current, 1.0 / ZOOM_STEP));
+                            }
+                            Some(VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0) => {
+                                pixels_per_point_override = None;
+                                let native = display.gl_window().window().scale_factor() as f32;
+                                egui_glium.egui_winit.set_pixels_per_point(native);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
 
                 egui_glium.on_event(&event);
 
@@ -208,14 +553,135 @@ fn main() {
     });
 }
 
-fn create_display(event_loop: &glutin::event_loop::EventLoop<()>) -> glium::Display {
+/// Log to stdout, or to the `--log-file`.
+fn init_logging(opt: &Arguments) -> anyhow::Result<()> {
+    use anyhow::Context as _;
+    let subscriber = tracing_subscriber::fmt().with_max_level(opt.log_level);
+    match &opt.log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("opening {:?}", path))?;
+            subscriber
+                .with_ansi(false)
+                .with_writer(std::sync::Mutex::new(file))
+                .init();
+        }
+        None => subscriber.init(),
+    }
+    Ok(())
+}
+
+/// The highest refresh rate of the monitor at its current resolution, in Hz.
+fn monitor_refresh_rate(window: &glutin::window::Window) -> Option<f32> {
+    let monitor = window.current_monitor()?;
+    let size = monitor.size();
+    monitor
+        .video_modes()
+        .filter(|mode| mode.size() == size)
+        .map(|mode| mode.refresh_rate())
+        .max()
+        .map(f32::from)
+}
+
+/// Name the window (and set its icon) after what the server is serving.
+fn set_window_app_info(window: &glutin::window::Window, app_info: &eterm::AppInfo) {
+    window.set_title(&app_info.window_title());
+    if let Some(icon) = &app_info.icon {
+        match glutin::window::Icon::from_rgba(icon.rgba.clone(), icon.width, icon.height) {
+            Ok(icon) => window.set_window_icon(Some(icon)),
+            Err(err) => tracing::warn!("Bad icon from server: {}", err),
+        }
+    }
+}
+
+/// Exit with a code telling scripts why we were disconnected. See `--exit-on-disconnect`.
+#[allow(clippy::exit)]
+fn exit_disconnected(client: &eterm::Client, reason: eterm::DisconnectReason) -> ! {
+    use eterm::{DisconnectReason, GoodbyeReason};
+    eprintln!("Disconnected from {}: {}", client.addr(), reason);
+    let code = match reason {
+        DisconnectReason::ConnectionRefused => 2,
+        DisconnectReason::VersionMismatch => 3,
+        DisconnectReason::Goodbye(GoodbyeReason::AuthFailed) => 4,
+        DisconnectReason::Goodbye(_) | DisconnectReason::ConnectionLost => 5,
+    };
+    std::process::exit(code)
+}
+
+/// How much each Ctrl +/- zooms.
+const ZOOM_STEP: f32 = 1.1;
+
+fn zoom(pixels_per_point: f32, factor: f32) -> f32 {
+    (pixels_per_point * factor).clamp(0.5, 5.0)
+}
+
+/// Ask the user where to save a file sent by the server.
+fn save_received_file(file: eterm::download::ReceivedFile) {
+    if let Some(path) = rfd::FileDialog::new()
+        .set_file_name(&file.file_name)
+        .save_file()
+    {
+        match std::fs::write(&path, &file.bytes) {
+            Ok(()) => tracing::info!("Saved {:?}", path),
+            Err(err) => tracing::error!("Failed to save {:?}: {}", path, err),
+        }
+    }
+}
+
+fn show_notification(
+    window: &glutin::window::Window,
+    notification: &eterm::notification::Notification,
+) {
+    tracing::info!("Notification: {} {}", notification.title, notification.body);
+
+    let mut desktop_notification = notify_rust::Notification::new();
+    desktop_notification
+        .appname("eterm viewer")
+        .summary(&notification.title)
+        .body(&notification.body);
+    if notification.sound {
+        desktop_notification.sound_name("message-new-instant");
+    }
+    if let Err(err) = desktop_notification.show() {
+        tracing::warn!("Failed to show desktop notification: {}", err);
+    }
+
+    // Flash the taskbar entry (or bounce the dock icon) if we're not focused:
+    window.request_user_attention(Some(glutin::window::UserAttentionType::Informational));
+}
+
+fn run_headless(client: eterm::Client, opt: &Arguments) -> anyhow::Result<()> {
+    let input_script = match &opt.input_script {
+        Some(path) => headless::load_input_script(path)?,
+        None => vec![],
+    };
+    headless::run(
+        client,
+        headless::HeadlessOptions {
+            output_dir: opt.output.clone(),
+            screen_size: egui::vec2(opt.width, opt.height),
+            pixels_per_point: opt.pixels_per_point.unwrap_or(1.0),
+            num_frames: opt.frames,
+            input_script,
+            exit_on_disconnect: opt.exit_on_disconnect,
+        },
+    )
+}
+
+fn create_display(
+    event_loop: &glutin::event_loop::EventLoop<()>,
+    settings: &settings::Settings,
+    opt: &Arguments,
+) -> glium::Display {
     let window_builder = glutin::window::WindowBuilder::new()
         .with_resizable(true)
-        .with_inner_size(glutin::dpi::LogicalSize {
-            width: 800.0,
-            height: 600.0,
-        })
+        .with_always_on_top(opt.always_on_top)
+        .with_decorations(!opt.borderless)
         .with_title("eterm viewer");
+    let window_builder = settings.apply_to_builder(window_builder);
 
     let context_builder = glutin::ContextBuilder::new()
         .with_depth_buffer(0)
@@ -227,15 +693,27 @@ fn create_display(event_loop: &glutin::event_loop::EventLoop<()>) -> glium::Disp
     glium::Display::new(window_builder, context_builder, event_loop).unwrap()
 }
 
-fn client_gui(ctx: &egui::CtxRef, client: &eterm::Client) {
-    // Chose a theme that sets us apart from the server:
-    let mut visuals = ctx.style().visuals.clone();
-    let panel_background = if visuals.dark_mode {
-        egui::Color32::from_rgb(55, 0, 105)
-    } else {
-        egui::Color32::from_rgb(255, 240, 0)
+fn client_gui(
+    ctx: &egui::CtxRef,
+    client: &eterm::Client,
+    motd: &mut Option<String>,
+    show_server_log: &mut bool,
+) {
+    let visuals = match client.theme() {
+        // Match the served ui:
+        Some(theme) => theme.to_visuals(),
+        None => {
+            // Chose a theme that sets us apart from the server:
+            let mut visuals = ctx.style().visuals.clone();
+            let panel_background = if visuals.dark_mode {
+                egui::Color32::from_rgb(55, 0, 105)
+            } else {
+                egui::Color32::from_rgb(255, 240, 0)
+            };
+            visuals.widgets.noninteractive.bg_fill = panel_background;
+            visuals
+        }
     };
-    visuals.widgets.noninteractive.bg_fill = panel_background;
     ctx.set_visuals(visuals);
 
     let height = TOP_BAR_HEIGHT - 4.0; // add some breathing room
@@ -245,8 +723,71 @@ fn client_gui(ctx: &egui::CtxRef, client: &eterm::Client) {
         .show(ctx, |ui| {
             ui.horizontal(|ui| {
                 client_info_bar(ui, client);
+                if client.server_log().num_received() > 0 {
+                    ui.separator();
+                    ui.checkbox(show_server_log, "Server log");
+                }
             });
         });
+
+    egui::Window::new("Server log")
+        .open(show_server_log)
+        .default_size([600.0, 300.0])
+        .show(ctx, |ui| {
+            server_log_ui(ui, client.server_log());
+        });
+
+    if let Some(notice) = client.notice() {
+        egui::Area::new("eterm_notice")
+            .order(egui::Order::Foreground)
+            .anchor(
+                egui::Align2::CENTER_TOP,
+                egui::vec2(0.0, TOP_BAR_HEIGHT + 8.0),
+            )
+            .show(ctx, |ui| {
+                notice.ui(ui);
+            });
+    }
+
+    if let Some(text) = motd {
+        let mut dismissed = false;
+        egui::Window::new("Message of the day")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(text.as_str());
+                if ui.button("OK").clicked() {
+                    dismissed = true;
+                }
+            });
+        if dismissed {
+            *motd = None;
+        }
+    }
+}
+
+fn server_log_ui(ui: &mut egui::Ui, log: &eterm::remote_log::ReceivedLog) {
+    use eterm::remote_log::Level;
+    egui::ScrollArea::vertical()
+        .stick_to_bottom()
+        .show(ui, |ui| {
+            for record in log.records() {
+                let color = match record.level {
+                    Level::Trace | Level::Debug => ui.visuals().weak_text_color(),
+                    Level::Info => ui.visuals().text_color(),
+                    Level::Warn => egui::Color32::from_rgb(240, 190, 0),
+                    Level::Error => egui::Color32::from_rgb(230, 60, 60),
+                };
+                ui.colored_label(
+                    color,
+                    format!(
+                        "{:9.3} {:5} {}: {}",
+                        record.time, record.level, record.target, record.message
+                    ),
+                );
+            }
+        });
 }
 
 fn client_info_bar(ui: &mut egui::Ui, client: &eterm::Client) {
@@ -265,12 +806,52 @@ fn client_info_bar(ui: &mut egui::Ui, client: &eterm::Client) {
         ui.add_sized(
             [16.0, ui.available_height()],
             egui::Label::new(format!("{:.0}", fps)),
+        )
+        .on_hover_text("Frames received per second");
+        ui.label("server FPS:");
+        let server_fps = client.server_fps().unwrap_or(0.0);
+        ui.add_sized(
+            [16.0, ui.available_height()],
+            egui::Label::new(format!("{:.0}", server_fps)),
+        )
+        .on_hover_text(
+            "Frames produced by the server per second. \
+            If this is higher than the adaptive FPS, the network is the bottleneck.",
         );
         ui.separator();
         match client.latency() {
             Some(latency) => ui.label(format!("latency: {:.0} ms", latency * 1e3)),
             None => ui.label("latency: "),
         };
+        if let Some(delay) = client.downstream_delay() {
+            ui.separator();
+            ui.label(format!("downstream: {:.0} ms", delay * 1e3))
+                .on_hover_text("One-way delay from server to viewer, using synchronized clocks");
+        }
+        if let Some(frame_time) = client.server_frame_time() {
+            ui.separator();
+            ui.label(format!("server ui: {:.1} ms", frame_time * 1e3))
+                .on_hover_text("Time the server spends running the ui for each frame");
+        }
+        if let Some(telemetry) = client.telemetry() {
+            ui.separator();
+            ui.label(format!(
+                "{:?} {}: {:.1} → {:.1} kB in {:.1} ms",
+                telemetry.compression,
+                telemetry.compression_level,
+                telemetry.uncompressed_size * 1e-3,
+                telemetry.compressed_size * 1e-3,
+                telemetry.encode_time * 1e3
+            ))
+            .on_hover_text(
+                "How the server compresses each frame: codec and level, \
+                size before and after compression, and the time it takes",
+            );
+        }
+    } else if let Some(reason) = client.disconnect_reason().filter(|reason| {
+        client.has_given_up() || matches!(reason, eterm::DisconnectReason::Goodbye(_))
+    }) {
+        ui.label(format!("Disconnected from {}: {}", client.addr(), reason));
     } else {
         ui.label(format!("Connecting to {}…", client.addr()));
     }
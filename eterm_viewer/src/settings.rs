@@ -0,0 +1,92 @@
+//! What the viewer remembers between runs, like eframe apps do.
+
+use std::path::PathBuf;
+
+use anyhow::Context as _;
+use glium::glutin;
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Inner size of the window when not maximized, in logical pixels.
+    pub window_size: Option<[f64; 2]>,
+    /// Outer position of the window, in physical pixels.
+    pub window_pos: Option<[i32; 2]>,
+    pub maximized: bool,
+    pub show_server_log: bool,
+}
+
+impl Settings {
+    /// The defaults if there is nothing saved, or it can't be read.
+    pub fn load() -> Self {
+        let path = match settings_path() {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(ron) => match ron::from_str(&ron) {
+                Ok(settings) => settings,
+                Err(err) => {
+                    tracing::warn!("Failed to parse {:?}: {}", path, err);
+                    Self::default()
+                }
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(err) => {
+                tracing::warn!("Failed to read {:?}: {}", path, err);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = self.try_save() {
+            tracing::warn!("Failed to save the viewer settings: {:#}", err);
+        }
+    }
+
+    fn try_save(&self) -> anyhow::Result<()> {
+        let path = settings_path().context("no config directory")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).with_context(|| format!("creating {:?}", dir))?;
+        }
+        let ron = ron::ser::to_string_pretty(self, Default::default()).context("ron")?;
+        std::fs::write(&path, ron).with_context(|| format!("writing {:?}", path))
+    }
+
+    /// Apply to a window that is about to be created.
+    pub fn apply_to_builder(
+        &self,
+        window_builder: glutin::window::WindowBuilder,
+    ) -> glutin::window::WindowBuilder {
+        let [width, height] = self.window_size.unwrap_or([800.0, 600.0]);
+        window_builder
+            .with_inner_size(glutin::dpi::LogicalSize { width, height })
+            .with_maximized(self.maximized)
+    }
+
+    /// Apply what can only be set once the window exists.
+    pub fn apply_to_window(&self, window: &glutin::window::Window) {
+        if let Some([x, y]) = self.window_pos {
+            window.set_outer_position(glutin::dpi::PhysicalPosition { x, y });
+        }
+    }
+
+    /// Remember how the window looks now.
+    pub fn update_from_window(&mut self, window: &glutin::window::Window) {
+        self.maximized = window.is_maximized();
+        if !self.maximized {
+            // Keep the size to go back to when un-maximizing.
+            let size = window.inner_size().to_logical::<f64>(window.scale_factor());
+            self.window_size = Some([size.width, size.height]);
+            if let Ok(pos) = window.outer_position() {
+                self.window_pos = Some([pos.x, pos.y]);
+            }
+        }
+    }
+}
+
+fn settings_path() -> Option<PathBuf> {
+    let project_dirs = directories_next::ProjectDirs::from("", "", "eterm_viewer")?;
+    Some(project_dirs.config_dir().join("settings.ron"))
+}
@@ -0,0 +1,284 @@
+//! Experimental viewer that paints the remote ui into the terminal.
+//!
+//! Only text and filled rectangles are shown, snapped to the character grid.
+//! Good enough to glance at a server over plain SSH.
+
+use std::io::Write as _;
+use std::time::Duration;
+
+use crossterm::{
+    cursor, event, execute, queue, style,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use egui::{epaint, Color32, Pos2, Rect};
+
+/// How many points one character cell covers.
+const CELL_SIZE: egui::Vec2 = egui::Vec2 { x: 8.0, y: 16.0 };
+
+#[derive(Clone, Copy, PartialEq)]
+struct Cell {
+    chr: char,
+    fg: Color32,
+    bg: Color32,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            chr: ' ',
+            fg: Color32::WHITE,
+            bg: Color32::BLACK,
+        }
+    }
+}
+
+/// Run until the user presses Ctrl-C.
+pub fn run(mut client: eterm::Client) -> anyhow::Result<()> {
+    let mut stdout = std::io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        event::EnableMouseCapture,
+        cursor::Hide
+    )?;
+
+    let result = run_loop(&mut client, &mut stdout);
+
+    execute!(
+        stdout,
+        style::ResetColor,
+        cursor::Show,
+        event::DisableMouseCapture,
+        LeaveAlternateScreen
+    )?;
+    terminal::disable_raw_mode()?;
+
+    result
+}
+
+fn run_loop(client: &mut eterm::Client, stdout: &mut std::io::Stdout) -> anyhow::Result<()> {
+    let (mut columns, mut rows) = terminal::size()?;
+    let mut screen_changed = true;
+    let mut events = vec![];
+
+    loop {
+        let mut timeout = Duration::from_millis(20);
+        while event::poll(timeout)? {
+            timeout = Duration::ZERO;
+            match event::read()? {
+                event::Event::Key(event::KeyEvent {
+                    code: event::KeyCode::Char('c'),
+                    modifiers,
+                }) if modifiers.contains(event::KeyModifiers::CONTROL) => {
+                    return Ok(());
+                }
+                event::Event::Key(key) => convert_key(key, &mut events),
+                event::Event::Mouse(mouse) => convert_mouse(mouse, &mut events),
+                event::Event::Resize(new_columns, new_rows) => {
+                    columns = new_columns;
+                    rows = new_rows;
+                    screen_changed = true;
+                }
+            }
+        }
+
+        if screen_changed || !events.is_empty() {
+            let screen_size = egui::vec2(columns as f32 * CELL_SIZE.x, rows as f32 * CELL_SIZE.y);
+            client.send_input(egui::RawInput {
+                screen_rect: Some(Rect::from_min_size(Pos2::ZERO, screen_size)),
+                pixels_per_point: Some(1.0),
+                events: std::mem::take(&mut events),
+                ..Default::default()
+            });
+        }
+
+        let new_frame = client.update(1.0);
+
+        if !client.take_notifications().is_empty() {
+            // The closest thing to a notification we have in a terminal:
+            stdout.write_all(b"\x07")?;
+        }
+
+        if new_frame.is_some() || screen_changed {
+            let grid = paint_grid(&client.latest_clipped_shapes(), columns, rows);
+            draw_grid(stdout, &grid, columns)?;
+        }
+        screen_changed = false;
+    }
+}
+
+fn cell_center(column: u16, row: u16) -> Pos2 {
+    egui::pos2(
+        (column as f32 + 0.5) * CELL_SIZE.x,
+        (row as f32 + 0.5) * CELL_SIZE.y,
+    )
+}
+
+fn convert_key(key: event::KeyEvent, events: &mut Vec<egui::Event>) {
+    let modifiers = egui::Modifiers {
+        alt: key.modifiers.contains(event::KeyModifiers::ALT),
+        ctrl: key.modifiers.contains(event::KeyModifiers::CONTROL),
+        shift: key.modifiers.contains(event::KeyModifiers::SHIFT),
+        mac_cmd: false,
+        command: key.modifiers.contains(event::KeyModifiers::CONTROL),
+    };
+
+    let key = match key.code {
+        event::KeyCode::Char(chr) => {
+            if modifiers.ctrl || modifiers.alt {
+                return;
+            }
+            events.push(egui::Event::Text(chr.to_string()));
+            return;
+        }
+        event::KeyCode::Backspace => egui::Key::Backspace,
+        event::KeyCode::Enter => egui::Key::Enter,
+        event::KeyCode::Left => egui::Key::ArrowLeft,
+        event::KeyCode::Right => egui::Key::ArrowRight,
+        event::KeyCode::Up => egui::Key::ArrowUp,
+        event::KeyCode::Down => egui::Key::ArrowDown,
+        event::KeyCode::Home => egui::Key::Home,
+        event::KeyCode::End => egui::Key::End,
+        event::KeyCode::PageUp => egui::Key::PageUp,
+        event::KeyCode::PageDown => egui::Key::PageDown,
+        event::KeyCode::Tab | event::KeyCode::BackTab => egui::Key::Tab,
+        event::KeyCode::Delete => egui::Key::Delete,
+        event::KeyCode::Insert => egui::Key::Insert,
+        event::KeyCode::Esc => egui::Key::Escape,
+        _ => return,
+    };
+
+    // Terminals don't report key releases:
+    for pressed in [true, false] {
+        events.push(egui::Event::Key {
+            key,
+            pressed,
+            modifiers,
+        });
+    }
+}
+
+fn convert_mouse(mouse: event::MouseEvent, events: &mut Vec<egui::Event>) {
+    let pos = cell_center(mouse.column, mouse.row);
+    let button = |button| match button {
+        event::MouseButton::Left => egui::PointerButton::Primary,
+        event::MouseButton::Right => egui::PointerButton::Secondary,
+        event::MouseButton::Middle => egui::PointerButton::Middle,
+    };
+
+    events.push(egui::Event::PointerMoved(pos));
+    match mouse.kind {
+        event::MouseEventKind::Down(pressed_button) => {
+            events.push(egui::Event::PointerButton {
+                pos,
+                button: button(pressed_button),
+                pressed: true,
+                modifiers: Default::default(),
+            });
+        }
+        event::MouseEventKind::Up(released_button) => {
+            events.push(egui::Event::PointerButton {
+                pos,
+                button: button(released_button),
+                pressed: false,
+                modifiers: Default::default(),
+            });
+        }
+        _ => {}
+    }
+}
+
+// ----------------------------------------------------------------------------
+
+fn paint_grid(clipped_shapes: &[epaint::ClippedShape], columns: u16, rows: u16) -> Vec<Cell> {
+    let mut grid = vec![Cell::default(); columns as usize * rows as usize];
+    for epaint::ClippedShape(clip_rect, shape) in clipped_shapes {
+        paint_shape(&mut grid, columns, rows, *clip_rect, shape);
+    }
+    grid
+}
+
+fn paint_shape(grid: &mut [Cell], columns: u16, rows: u16, clip_rect: Rect, shape: &epaint::Shape) {
+    match shape {
+        epaint::Shape::Vec(shapes) => {
+            for shape in shapes {
+                paint_shape(grid, columns, rows, clip_rect, shape);
+            }
+        }
+        epaint::Shape::Rect(rect_shape) => {
+            if rect_shape.fill.a() == 0 {
+                return;
+            }
+            let rect = rect_shape.rect.intersect(clip_rect);
+            for row in 0..rows {
+                for column in 0..columns {
+                    if rect.contains(cell_center(column, row)) {
+                        let cell = &mut grid[row as usize * columns as usize + column as usize];
+                        cell.bg = blend(cell.bg, rect_shape.fill);
+                    }
+                }
+            }
+        }
+        epaint::Shape::Text(text_shape) => {
+            let galley = &text_shape.galley;
+            for glyph in galley.rows.iter().flat_map(|row| &row.glyphs) {
+                let pos = text_shape.pos + glyph.logical_rect().center().to_vec2();
+                if !clip_rect.contains(pos) || pos.x < 0.0 || pos.y < 0.0 {
+                    continue;
+                }
+                let column = (pos.x / CELL_SIZE.x) as usize;
+                let row = (pos.y / CELL_SIZE.y) as usize;
+                if column < columns as usize && row < rows as usize && !glyph.chr.is_whitespace() {
+                    let cell = &mut grid[row * columns as usize + column];
+                    cell.chr = glyph.chr;
+                    cell.fg = text_shape.override_text_color.unwrap_or_else(|| {
+                        galley.job.sections[glyph.section_index as usize]
+                            .format
+                            .color
+                    });
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Premultiplied "over" blending.
+fn blend(dst: Color32, src: Color32) -> Color32 {
+    let inv_alpha = 1.0 - src.a() as f32 / 255.0;
+    let mix = |d: u8, s: u8| (s as f32 + d as f32 * inv_alpha).round().min(255.0) as u8;
+    Color32::from_rgb(
+        mix(dst.r(), src.r()),
+        mix(dst.g(), src.g()),
+        mix(dst.b(), src.b()),
+    )
+}
+
+fn to_crossterm(color: Color32) -> style::Color {
+    style::Color::Rgb {
+        r: color.r(),
+        g: color.g(),
+        b: color.b(),
+    }
+}
+
+fn draw_grid(stdout: &mut std::io::Stdout, grid: &[Cell], columns: u16) -> anyhow::Result<()> {
+    let mut current_colors = None;
+    for (row, cells) in grid.chunks(columns.max(1) as usize).enumerate() {
+        queue!(stdout, cursor::MoveTo(0, row as u16))?;
+        for cell in cells {
+            if current_colors != Some((cell.fg, cell.bg)) {
+                queue!(
+                    stdout,
+                    style::SetForegroundColor(to_crossterm(cell.fg)),
+                    style::SetBackgroundColor(to_crossterm(cell.bg))
+                )?;
+                current_colors = Some((cell.fg, cell.bg));
+            }
+            queue!(stdout, style::Print(cell.chr))?;
+        }
+    }
+    stdout.flush()?;
+    Ok(())
+}